@@ -0,0 +1,163 @@
+use crate::timeout::deadline;
+use crate::{
+	CancellableWaitError, CancelledError, Futex, Private, Scope, TimedCancellableWaitError,
+	Timeout, WakeMask,
+};
+use std::sync::atomic::Ordering::{Acquire, Release, SeqCst};
+use std::time::Duration;
+
+/// Set in the state once the token is cancelled. The other bits count the waits in progress.
+const CANCELLED: u32 = 1 << 31;
+
+/// A token to cancel waits on a futex from another thread.
+///
+/// Threads wait on the futex through the token, with
+/// [`wait`][CancelToken::wait] or one of its timed variants. These wait
+/// like [`Futex::wait_bitset`], using the bitset of the token, so they are
+/// woken up by any [`wake`][Futex::wake] call, as well as by
+/// [`wake_bitset`][Futex::wake_bitset] calls matching the bitset.
+///
+/// [`cancel`][CancelToken::cancel] marks the token as cancelled, and wakes
+/// up the waits using it with `wake_bitset`, after which they return
+/// [`Cancelled`][CancellableWaitError::Cancelled] instead of waiting out
+/// their timeout. Give each token its own bit, with
+/// [`with_bitset`][CancelToken::with_bitset], to only interrupt the waiters
+/// that use that token, rather than every waiter of the futex.
+pub struct CancelToken<'a, S: Scope> {
+	futex: &'a Futex<S>,
+	bitset: WakeMask,
+	state: Futex<Private>,
+}
+
+impl<'a, S: Scope> CancelToken<'a, S> {
+	/// Create a token for waits on `futex`, using [`WakeMask::ALL`].
+	///
+	/// Cancelling this token wakes up all waiters of the futex.
+	#[inline]
+	pub const fn new(futex: &'a Futex<S>) -> Self {
+		Self::with_bitset(futex, WakeMask::ALL)
+	}
+
+	/// Create a token for waits on `futex`, using the given bitset.
+	///
+	/// Cancelling this token only wakes up waiters with a matching bitset.
+	#[inline]
+	pub const fn with_bitset(futex: &'a Futex<S>, bitset: WakeMask) -> Self {
+		Self {
+			futex,
+			bitset,
+			state: Futex::new(0),
+		}
+	}
+
+	/// The futex this token is for.
+	#[inline]
+	pub fn futex(&self) -> &'a Futex<S> {
+		self.futex
+	}
+
+	/// The bitset used by waits through this token.
+	#[inline]
+	pub fn bitset(&self) -> WakeMask {
+		self.bitset
+	}
+
+	/// Check whether the token was cancelled.
+	#[inline]
+	pub fn is_cancelled(&self) -> bool {
+		self.state.value.load(Acquire) & CANCELLED != 0
+	}
+
+	/// Make the token usable again after it was cancelled.
+	#[inline]
+	pub fn reset(&mut self) {
+		*self.state.value.get_mut() = 0;
+	}
+
+	/// Cancel all current and future waits through this token.
+	///
+	/// This blocks until all waits in progress have returned.
+	pub fn cancel(&self) {
+		let mut state = self.state.value.fetch_or(CANCELLED, SeqCst) | CANCELLED;
+		while state != CANCELLED {
+			// A waiter might be about to go to sleep, so keep waking until it returns.
+			self.futex.wake_bitset(u32::MAX, self.bitset);
+			let _ = self.state.wait_for(state, Duration::from_millis(1));
+			state = self.state.value.load(Acquire);
+		}
+	}
+
+	/// Wait until the futex is awoken by a matching `wake` call, or until the token is cancelled.
+	///
+	/// The thread will only be sent to sleep if the futex's value matches the
+	/// expected value. Otherwise, it returns directly with
+	/// [`CancellableWaitError::WrongValue`]. If the token is cancelled, it
+	/// returns with [`CancellableWaitError::Cancelled`], even if the futex
+	/// was woken up at the same time.
+	pub fn wait(&self, expected_value: u32) -> Result<(), CancellableWaitError> {
+		self.run(|| Ok(self.futex.wait_bitset(expected_value, self.bitset)?))
+	}
+
+	/// Wait until the futex is awoken by a matching `wake` call, until the
+	/// timeout expires, or until the token is cancelled.
+	///
+	/// See [`wait`][CancelToken::wait].
+	pub fn wait_until(
+		&self,
+		expected_value: u32,
+		timeout: impl Timeout,
+	) -> Result<(), TimedCancellableWaitError> {
+		self.run(|| {
+			Ok(self
+				.futex
+				.wait_bitset_until(expected_value, self.bitset, timeout)?)
+		})
+	}
+
+	/// Wait until the futex is awoken by a matching `wake` call, until the
+	/// timeout expires, or until the token is cancelled.
+	///
+	/// See [`wait`][CancelToken::wait].
+	pub fn wait_for(
+		&self,
+		expected_value: u32,
+		timeout: Duration,
+	) -> Result<(), TimedCancellableWaitError> {
+		match deadline(timeout) {
+			Some(deadline) => self.wait_until(expected_value, deadline),
+			None => Ok(self.wait(expected_value)?),
+		}
+	}
+
+	/// Run a wait, registered as in progress, unless the token is cancelled.
+	fn run<E: From<CancelledError>>(&self, f: impl FnOnce() -> Result<(), E>) -> Result<(), E> {
+		if self.state.value.fetch_add(1, SeqCst) & CANCELLED != 0 {
+			self.done();
+			return Err(CancelledError::Cancelled.into());
+		}
+		let r = f();
+		if self.done() {
+			return Err(CancelledError::Cancelled.into());
+		}
+		r
+	}
+
+	/// Unregister a wait in progress, and return whether the token was cancelled.
+	fn done(&self) -> bool {
+		let state = self.state.value.fetch_sub(1, Release) - 1;
+		if state == CANCELLED {
+			self.state.wake(1);
+		}
+		state & CANCELLED != 0
+	}
+}
+
+impl<S: Scope> std::fmt::Debug for CancelToken<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("CancelToken")
+			.field("futex", &self.futex)
+			.field("bitset", &self.bitset)
+			.field("cancelled", &self.is_cancelled())
+			.finish()
+	}
+}
@@ -0,0 +1,139 @@
+use crate::{AsFutex, Futex, Private, Scope, Shared, TimedWaitError, Timeout, WaitError, WakeMask};
+use std::sync::atomic::AtomicU32;
+use std::time::Duration;
+
+/// [`Private`] or [`Shared`], chosen at runtime.
+///
+/// This is for code that only learns at runtime whether its futexes are
+/// shared with other processes, such as a library that is handed memory
+/// that might or might not be mapped in another process.
+/// [`DynScope::futex`] uses an [`AtomicU32`] as a futex of this scope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DynScope {
+	Private,
+	Shared,
+}
+
+impl DynScope {
+	/// The runtime equivalent of the [`Scope`] `S`.
+	#[inline]
+	pub fn of<S: Scope>() -> Self {
+		if S::futex_flag() == 0 {
+			DynScope::Shared
+		} else {
+			DynScope::Private
+		}
+	}
+
+	/// `FUTEX_PRIVATE_FLAG` for [`DynScope::Private`], or zero for [`DynScope::Shared`].
+	#[inline]
+	pub fn futex_flag(self) -> i32 {
+		match self {
+			DynScope::Private => Private::futex_flag(),
+			DynScope::Shared => Shared::futex_flag(),
+		}
+	}
+
+	/// Use `value` as a futex of this scope.
+	#[inline]
+	pub fn futex(self, value: &AtomicU32) -> DynFutex<'_> {
+		match self {
+			DynScope::Private => DynFutex::Private(value.as_futex()),
+			DynScope::Shared => DynFutex::Shared(value.as_futex()),
+		}
+	}
+}
+
+/// A reference to a [`Futex`] of a [`DynScope`].
+///
+/// This forwards the most common futex operations to the futex of the
+/// right scope. Match on it to use any of the others.
+#[derive(Clone, Copy, Debug)]
+pub enum DynFutex<'a> {
+	Private(&'a Futex<Private>),
+	Shared(&'a Futex<Shared>),
+}
+
+impl<'a> DynFutex<'a> {
+	/// The scope of this futex.
+	#[inline]
+	pub fn scope(self) -> DynScope {
+		match self {
+			DynFutex::Private(_) => DynScope::Private,
+			DynFutex::Shared(_) => DynScope::Shared,
+		}
+	}
+
+	/// The value of the futex.
+	#[inline]
+	pub fn value(self) -> &'a AtomicU32 {
+		match self {
+			DynFutex::Private(f) => &f.value,
+			DynFutex::Shared(f) => &f.value,
+		}
+	}
+
+	/// See [`Futex::wait`].
+	#[inline]
+	pub fn wait(self, expected_value: u32) -> Result<(), WaitError> {
+		match self {
+			DynFutex::Private(f) => f.wait(expected_value),
+			DynFutex::Shared(f) => f.wait(expected_value),
+		}
+	}
+
+	/// See [`Futex::wait_for`].
+	#[inline]
+	pub fn wait_for(self, expected_value: u32, timeout: Duration) -> Result<(), TimedWaitError> {
+		match self {
+			DynFutex::Private(f) => f.wait_for(expected_value, timeout),
+			DynFutex::Shared(f) => f.wait_for(expected_value, timeout),
+		}
+	}
+
+	/// See [`Futex::wait_bitset_until`]. This waits with [`WakeMask::ALL`].
+	#[inline]
+	pub fn wait_until(
+		self,
+		expected_value: u32,
+		timeout: impl Timeout,
+	) -> Result<(), TimedWaitError> {
+		match self {
+			DynFutex::Private(f) => f.wait_bitset_until(expected_value, WakeMask::ALL, timeout),
+			DynFutex::Shared(f) => f.wait_bitset_until(expected_value, WakeMask::ALL, timeout),
+		}
+	}
+
+	/// See [`Futex::wake`].
+	#[inline]
+	pub fn wake(self, n: u32) -> usize {
+		match self {
+			DynFutex::Private(f) => f.wake(n),
+			DynFutex::Shared(f) => f.wake(n),
+		}
+	}
+
+	/// See [`Futex::wake_one`].
+	#[inline]
+	pub fn wake_one(self) -> bool {
+		self.wake(1) > 0
+	}
+
+	/// See [`Futex::wake_all`].
+	#[inline]
+	pub fn wake_all(self) -> usize {
+		self.wake(u32::MAX)
+	}
+}
+
+impl<'a> From<&'a Futex<Private>> for DynFutex<'a> {
+	fn from(futex: &'a Futex<Private>) -> Self {
+		DynFutex::Private(futex)
+	}
+}
+
+impl<'a> From<&'a Futex<Shared>> for DynFutex<'a> {
+	fn from(futex: &'a Futex<Shared>) -> Self {
+		DynFutex::Shared(futex)
+	}
+}
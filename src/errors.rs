@@ -4,6 +4,24 @@ pub enum WrongValueError {
 	WrongValue,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FaultError {
+	/// The futex is not (or no longer) mapped in the address space of the process.
+	Fault,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AlignmentError {
+	/// The futex address is not aligned to 4 bytes.
+	Misaligned,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimedOutError {
+	/// The timeout expired before the operation completed.
+	TimedOut,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum WaitError {
 	/// The futex value did not match the expected value.
@@ -22,20 +40,56 @@ pub enum TimedWaitError {
 	TimedOut,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimedWrongValueError {
+	/// The futex value did not match the expected value.
+	WrongValue,
+	/// The timeout expired before the operation completed.
+	TimedOut,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TryAgainError {
 	/// The futex owner thread is about to exit, or the futex value did not match the expected value.
 	TryAgain,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LockError {
+	/// The futex owner thread is about to exit, but has not yet handled the internal state cleanup. Try again.
+	TryAgain,
+	/// The futex is already locked by the calling thread.
+	Deadlock,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TimedLockError {
 	/// The futex owner thread is about to exit, but has not yet handled the internal state cleanup. Try again.
 	TryAgain,
+	/// The futex is already locked by the calling thread.
+	Deadlock,
 	/// The timeout expired before the operation completed.
 	TimedOut,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnlockError {
+	/// The futex is not owned by the calling thread.
+	NotOwner,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NotRecoverableError {
+	/// A previous owner died while holding the lock, and the protected state was never made consistent again.
+	NotRecoverable,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PidNamespaceError {
+	/// The process is in a different PID namespace, so thread ids stored in a [`PiFutex`][crate::PiFutex] are meaningless to it.
+	Mismatch,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TimedRequeueError {
 	/// The futex value did not match the expected value.
@@ -48,12 +102,202 @@ pub enum TimedRequeueError {
 pub enum RequeuePiError {
 	/// The futex value did not match the expected value, or the thread was woken up without being requeued to the [`PiFutex`][crate::PiFutex] first.
 	TryAgain,
+	/// The operation was interrupted by a signal.
+	Interrupted,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum TimedRequeuePiError {
 	/// The futex value did not match the expected value, or the thread was woken up without being requeued to the [`PiFutex`][crate::PiFutex] first.
 	TryAgain,
+	/// The operation was interrupted by a signal.
+	Interrupted,
 	/// The timeout expired before the operation completed.
 	TimedOut,
 }
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CancelledError {
+	/// The operation was cancelled through a [`CancelToken`][crate::CancelToken].
+	Cancelled,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CancellableWaitError {
+	/// The futex value did not match the expected value.
+	WrongValue,
+	/// The operation was interrupted by a signal.
+	Interrupted,
+	/// The operation was cancelled through a [`CancelToken`][crate::CancelToken].
+	Cancelled,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimedCancellableWaitError {
+	/// The futex value did not match the expected value.
+	WrongValue,
+	/// The operation was interrupted by a signal.
+	Interrupted,
+	/// The timeout expired before the operation completed.
+	TimedOut,
+	/// The operation was cancelled through a [`CancelToken`][crate::CancelToken].
+	Cancelled,
+}
+
+macro_rules! impl_error {
+	($($name:ident { $($variant:ident => $errno:ident, $msg:literal,)* })*) => {
+		$(
+			impl std::fmt::Display for $name {
+				fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+					f.write_str(match self {
+						$(Self::$variant => $msg,)*
+					})
+				}
+			}
+
+			impl std::error::Error for $name {}
+
+			/// Converts to an [`io::Error`][std::io::Error] with the
+			/// [`ErrorKind`][std::io::ErrorKind] of the corresponding `errno` value.
+			impl From<$name> for std::io::Error {
+				fn from(e: $name) -> Self {
+					let errno = match e {
+						$($name::$variant => libc::$errno,)*
+					};
+					Self::new(Self::from_raw_os_error(errno).kind(), e)
+				}
+			}
+		)*
+	};
+}
+
+impl_error! {
+	WrongValueError {
+		WrongValue => EAGAIN, "futex value did not match the expected value",
+	}
+	FaultError {
+		Fault => EFAULT, "futex address is not mapped",
+	}
+	AlignmentError {
+		Misaligned => EINVAL, "futex address is not aligned to 4 bytes",
+	}
+	TimedOutError {
+		TimedOut => ETIMEDOUT, "futex operation timed out",
+	}
+	WaitError {
+		WrongValue => EAGAIN, "futex value did not match the expected value",
+		Interrupted => EINTR, "futex operation was interrupted by a signal",
+	}
+	TimedWaitError {
+		WrongValue => EAGAIN, "futex value did not match the expected value",
+		Interrupted => EINTR, "futex operation was interrupted by a signal",
+		TimedOut => ETIMEDOUT, "futex operation timed out",
+	}
+	TimedWrongValueError {
+		WrongValue => EAGAIN, "futex value did not match the expected value",
+		TimedOut => ETIMEDOUT, "futex operation timed out",
+	}
+	TryAgainError {
+		TryAgain => EAGAIN, "futex operation must be tried again",
+	}
+	LockError {
+		TryAgain => EAGAIN, "futex operation must be tried again",
+		Deadlock => EDEADLK, "futex is already locked by the calling thread",
+	}
+	TimedLockError {
+		TryAgain => EAGAIN, "futex operation must be tried again",
+		Deadlock => EDEADLK, "futex is already locked by the calling thread",
+		TimedOut => ETIMEDOUT, "futex operation timed out",
+	}
+	UnlockError {
+		NotOwner => EPERM, "futex is not owned by the calling thread",
+	}
+	NotRecoverableError {
+		NotRecoverable => ENOTRECOVERABLE, "lock is not recoverable",
+	}
+	PidNamespaceError {
+		Mismatch => ESRCH, "process is in a different PID namespace",
+	}
+	TimedRequeueError {
+		WrongValue => EAGAIN, "futex value did not match the expected value",
+		TimedOut => ETIMEDOUT, "futex operation timed out",
+	}
+	RequeuePiError {
+		TryAgain => EAGAIN, "futex operation must be tried again",
+		Interrupted => EINTR, "futex operation was interrupted by a signal",
+	}
+	TimedRequeuePiError {
+		TryAgain => EAGAIN, "futex operation must be tried again",
+		Interrupted => EINTR, "futex operation was interrupted by a signal",
+		TimedOut => ETIMEDOUT, "futex operation timed out",
+	}
+	CancelledError {
+		Cancelled => ECANCELED, "futex operation was cancelled",
+	}
+	CancellableWaitError {
+		WrongValue => EAGAIN, "futex value did not match the expected value",
+		Interrupted => EINTR, "futex operation was interrupted by a signal",
+		Cancelled => ECANCELED, "futex operation was cancelled",
+	}
+	TimedCancellableWaitError {
+		WrongValue => EAGAIN, "futex value did not match the expected value",
+		Interrupted => EINTR, "futex operation was interrupted by a signal",
+		TimedOut => ETIMEDOUT, "futex operation timed out",
+		Cancelled => ECANCELED, "futex operation was cancelled",
+	}
+}
+
+macro_rules! impl_subset {
+	($($subset:ident => $superset:ident { $($variant:ident),* })*) => {
+		$(
+			impl From<$subset> for $superset {
+				#[inline]
+				fn from(e: $subset) -> Self {
+					match e {
+						$($subset::$variant => Self::$variant,)*
+					}
+				}
+			}
+
+			impl std::convert::TryFrom<$superset> for $subset {
+				type Error = $superset;
+				#[inline]
+				fn try_from(e: $superset) -> Result<Self, $superset> {
+					match e {
+						$($superset::$variant => Ok(Self::$variant),)*
+						#[allow(unreachable_patterns)]
+						e => Err(e),
+					}
+				}
+			}
+		)*
+	};
+}
+
+impl_subset! {
+	WrongValueError => WaitError { WrongValue }
+	WrongValueError => TimedWaitError { WrongValue }
+	WrongValueError => TimedRequeueError { WrongValue }
+	WrongValueError => TimedWrongValueError { WrongValue }
+	WaitError => TimedWaitError { WrongValue, Interrupted }
+	TimedOutError => TimedWaitError { TimedOut }
+	TimedOutError => TimedWrongValueError { TimedOut }
+	TimedOutError => TimedLockError { TimedOut }
+	TimedOutError => TimedRequeueError { TimedOut }
+	TimedOutError => TimedRequeuePiError { TimedOut }
+	TimedWrongValueError => TimedWaitError { WrongValue, TimedOut }
+	TryAgainError => LockError { TryAgain }
+	TryAgainError => TimedLockError { TryAgain }
+	LockError => TimedLockError { TryAgain, Deadlock }
+	TryAgainError => RequeuePiError { TryAgain }
+	TryAgainError => TimedRequeuePiError { TryAgain }
+	RequeuePiError => TimedRequeuePiError { TryAgain, Interrupted }
+	WrongValueError => CancellableWaitError { WrongValue }
+	WrongValueError => TimedCancellableWaitError { WrongValue }
+	TimedOutError => TimedCancellableWaitError { TimedOut }
+	CancelledError => CancellableWaitError { Cancelled }
+	CancelledError => TimedCancellableWaitError { Cancelled }
+	WaitError => CancellableWaitError { WrongValue, Interrupted }
+	TimedWaitError => TimedCancellableWaitError { WrongValue, Interrupted, TimedOut }
+	CancellableWaitError => TimedCancellableWaitError { WrongValue, Interrupted, Cancelled }
+}
@@ -0,0 +1,128 @@
+use crate::{CachePadded, Futex, Private, Scope, TimedWaitError, WaitError};
+use std::ops::{Bound, Index, RangeBounds};
+use std::time::Duration;
+
+/// A fixed-size array of futexes, each on its own cache line.
+///
+/// Useful for blocking per slot, such as in a ring buffer or a slab
+/// allocator, without allocating every [`Futex`] separately.
+pub struct FutexVec<S = Private> {
+	futexes: Box<[CachePadded<Futex<S>>]>,
+}
+
+impl<S> FutexVec<S> {
+	/// Create `len` futexes, all with the same initial value.
+	pub fn new(len: usize, value: u32) -> Self {
+		Self {
+			futexes: (0..len)
+				.map(|_| CachePadded::new(Futex::new(value)))
+				.collect(),
+		}
+	}
+
+	/// The number of futexes.
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.futexes.len()
+	}
+
+	/// Returns true if there are no futexes.
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.futexes.is_empty()
+	}
+
+	/// Get the futex at index `i`, or `None` if out of bounds.
+	#[inline]
+	pub fn get(&self, i: usize) -> Option<&Futex<S>> {
+		self.futexes.get(i).map(|f| &**f)
+	}
+
+	/// Iterate over all futexes.
+	#[inline]
+	pub fn iter(&self) -> impl Iterator<Item = &Futex<S>> + '_ {
+		self.futexes.iter().map(|f| &**f)
+	}
+}
+
+impl<S: Scope> FutexVec<S> {
+	/// Wait on the futex at index `i`. See [`Futex::wait`].
+	///
+	/// # Panics
+	///
+	/// Panics if `i` is out of bounds.
+	#[inline]
+	pub fn wait(&self, i: usize, expected_value: u32) -> Result<(), WaitError> {
+		self[i].wait(expected_value)
+	}
+
+	/// Wait on the futex at index `i`, or until the timeout expires. See [`Futex::wait_for`].
+	///
+	/// # Panics
+	///
+	/// Panics if `i` is out of bounds.
+	#[inline]
+	pub fn wait_for(
+		&self,
+		i: usize,
+		expected_value: u32,
+		timeout: Duration,
+	) -> Result<(), TimedWaitError> {
+		self[i].wait_for(expected_value, timeout)
+	}
+
+	/// Wake up `n` waiters of the futex at index `i`.
+	///
+	/// Returns the number of waiters that were woken up.
+	///
+	/// # Panics
+	///
+	/// Panics if `i` is out of bounds.
+	#[inline]
+	pub fn wake_index(&self, i: usize, n: u32) -> usize {
+		self[i].wake(n)
+	}
+
+	/// Wake up all waiters of all futexes in the given range of indices.
+	///
+	/// This takes one syscall per futex.
+	///
+	/// Returns the total number of waiters that were woken up.
+	///
+	/// # Panics
+	///
+	/// Panics if the range is out of bounds.
+	pub fn wake_range(&self, range: impl RangeBounds<usize>) -> usize {
+		let start = match range.start_bound() {
+			Bound::Included(&i) => i,
+			Bound::Excluded(&i) => i + 1,
+			Bound::Unbounded => 0,
+		};
+		let end = match range.end_bound() {
+			Bound::Included(&i) => i + 1,
+			Bound::Excluded(&i) => i,
+			Bound::Unbounded => self.len(),
+		};
+		self.futexes[start..end]
+			.iter()
+			.map(|f| f.wake(u32::MAX))
+			.sum()
+	}
+}
+
+impl<S> Index<usize> for FutexVec<S> {
+	type Output = Futex<S>;
+	#[inline]
+	fn index(&self, i: usize) -> &Futex<S> {
+		&self.futexes[i]
+	}
+}
+
+impl<S> std::fmt::Debug for FutexVec<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("FutexVec")
+			.field("scope", &std::any::type_name::<S>())
+			.field("values", &self.iter().map(|f| &f.value).collect::<Vec<_>>())
+			.finish()
+	}
+}
@@ -0,0 +1,300 @@
+//! Waiting on futexes from async code.
+//!
+//! On Linux 6.7 and later, the waits of an [`AsyncFutex`] are submitted to
+//! io_uring as `IORING_OP_FUTEX_WAIT` operations, and a single thread reaps
+//! their completions and wakes the [`Waker`] of the waiting tasks.
+//!
+//! On older kernels, or when io_uring is disabled, the waits are performed by
+//! a small pool of helper threads instead, shared by all futexes. Each
+//! pending wait then occupies one helper thread. Helper threads are spawned
+//! when needed, and exit again after being idle for a while.
+//!
+//! This allows async code to wait on futexes that are woken up by regular
+//! [`Futex::wake`] calls, possibly from another process through a
+//! [`Shared`][crate::Shared] futex, without blocking the threads of the executor.
+//!
+//! An [`AsyncMutex`] can be locked by both async tasks and regular threads,
+//! and an [`AsyncSemaphore`] shares its permits with a regular
+//! [`Semaphore`][crate::sync::Semaphore].
+//!
+//! [`select`] waits on several futexes at once, resolving to the one that
+//! was woken up.
+//!
+//! A [`FutexWaker`] wakes a registered [`Waker`] when a futex is woken up,
+//! for futures that are written by hand.
+//!
+//! With the `async-io` feature, [`AsyncFutex::wait_async_io`] waits through
+//! the reactor of the `async-io` crate instead, as used by `smol`.
+
+mod mutex;
+mod pool;
+#[cfg(feature = "async-io")]
+mod reactor;
+mod select;
+mod semaphore;
+mod uring;
+mod waker;
+
+pub use self::mutex::{AsyncMutex, AsyncMutexGuard, Lock};
+#[cfg(feature = "async-io")]
+pub use self::reactor::WaitAsyncIo;
+pub use self::select::{select, select_until, Select};
+pub use self::semaphore::{Acquire, AsyncSemaphore};
+pub use self::waker::FutexWaker;
+
+use self::pool::{Request, WRONG_VALUE};
+use crate::{Futex, Private, Scope, WaitvEntry, WrongValueError};
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// Start waiting on a futex through io_uring, or on a helper thread if that's not supported.
+///
+/// The futex must stay valid until the request is cancelled or finished.
+unsafe fn submit<S: Scope>(futex: &Futex<S>, expected: u32, waker: Waker) -> Arc<Request> {
+	let ring = uring::ring();
+	let entry = WaitvEntry::new(futex, expected);
+	let request = Arc::new(Request::new(&[entry], None, waker, ring.is_some()));
+	match ring {
+		Some(ring) => ring.submit(request.clone()),
+		None => pool::submit(request.clone()),
+	}
+	request
+}
+
+/// A [`Futex`] that can be waited on asynchronously.
+///
+/// This dereferences to the [`Futex`] it wraps, so all the regular
+/// operations, such as [`wake`][Futex::wake], are available on it.
+#[repr(transparent)]
+pub struct AsyncFutex<S = Private> {
+	futex: Futex<S>,
+}
+
+impl<S> AsyncFutex<S> {
+	/// Create a new [`AsyncFutex`] with an initial value.
+	#[inline]
+	pub const fn new(value: u32) -> Self {
+		Self {
+			futex: Futex::new(value),
+		}
+	}
+
+	/// Use an existing [`Futex`] as an [`AsyncFutex`].
+	#[inline]
+	pub fn from_futex(futex: &Futex<S>) -> &Self {
+		unsafe { &*(futex as *const Futex<S> as *const Self) }
+	}
+}
+
+impl<S: Scope> AsyncFutex<S> {
+	/// Wait until this futex is awoken by a `wake` call.
+	///
+	/// The returned future resolves right away with an error if the futex
+	/// does not have the expected value. Otherwise, it resolves once the
+	/// futex is woken up, which might also happen spuriously.
+	///
+	/// Dropping the future before it resolves stops the wait. Without io_uring,
+	/// if a helper thread is already blocked on the futex, this wakes up all
+	/// waiters of the futex to release it, which they might observe as a
+	/// spurious wake up. Forgetting a pending future (with [`std::mem::forget`]) keeps the
+	/// wait (and possibly a helper thread) blocked on the futex until it is woken up.
+	#[inline]
+	pub fn wait(&self, expected_value: u32) -> Wait<'_, S> {
+		Wait {
+			futex: &self.futex,
+			expected_value,
+			request: None,
+		}
+	}
+
+	/// Wait until this futex is awoken by a `wake` call, through the reactor of `async-io`.
+	///
+	/// This works like [`wait`][AsyncFutex::wait], except the thread that
+	/// finishes the wait doesn't wake the task directly, but signals an
+	/// eventfd that is registered with the `async-io` reactor, which then
+	/// wakes the task. This way, wake ups are delivered through the reactor like any other I/O
+	/// event, no matter which executor polls the future. If the eventfd cannot
+	/// be created or registered, the task is woken directly instead.
+	#[cfg(feature = "async-io")]
+	#[inline]
+	pub fn wait_async_io(&self, expected_value: u32) -> WaitAsyncIo<'_, S> {
+		WaitAsyncIo::new(&self.futex, expected_value)
+	}
+}
+
+impl<S> Deref for AsyncFutex<S> {
+	type Target = Futex<S>;
+	#[inline]
+	fn deref(&self) -> &Futex<S> {
+		&self.futex
+	}
+}
+
+impl<S> From<u32> for AsyncFutex<S> {
+	#[inline]
+	fn from(value: u32) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<S> Default for AsyncFutex<S> {
+	#[inline]
+	fn default() -> Self {
+		Self::new(0)
+	}
+}
+
+impl<S> std::fmt::Debug for AsyncFutex<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("AsyncFutex")
+			.field("scope", &std::any::type_name::<S>())
+			.field("value", &self.futex.value)
+			.finish()
+	}
+}
+
+/// The future returned by [`AsyncFutex::wait`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Wait<'a, S: Scope> {
+	futex: &'a Futex<S>,
+	expected_value: u32,
+	request: Option<Arc<Request>>,
+}
+
+impl<S: Scope> Future for Wait<'_, S> {
+	type Output = Result<(), WrongValueError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		let result = match &this.request {
+			Some(request) => request.register(cx.waker()),
+			None => {
+				if this.futex.value.load(Relaxed) != this.expected_value {
+					return Poll::Ready(Err(WrongValueError::WrongValue));
+				}
+				this.request =
+					Some(unsafe { submit(this.futex, this.expected_value, cx.waker().clone()) });
+				return Poll::Pending;
+			}
+		};
+		match result {
+			None => Poll::Pending,
+			Some(WRONG_VALUE) => Poll::Ready(Err(WrongValueError::WrongValue)),
+			Some(_) => Poll::Ready(Ok(())),
+		}
+	}
+}
+
+impl<S: Scope> Drop for Wait<'_, S> {
+	fn drop(&mut self) {
+		if let Some(request) = &self.request {
+			request.cancel();
+		}
+	}
+}
+
+impl<S: Scope> std::fmt::Debug for Wait<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Wait")
+			.field("futex", &self.futex)
+			.field("expected_value", &self.expected_value)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::AsyncFutex;
+	use crate::{Private, WrongValueError};
+	use std::future::Future;
+	use std::pin::Pin;
+	use std::sync::Arc;
+	use std::task::{Context, Poll, Wake, Waker};
+	use std::thread::{self, Thread};
+	use std::time::Duration;
+
+	struct Unpark(Thread);
+
+	impl Wake for Unpark {
+		fn wake(self: Arc<Self>) {
+			self.0.unpark();
+		}
+	}
+
+	fn waker() -> Waker {
+		Arc::new(Unpark(thread::current())).into()
+	}
+
+	/// Poll the future once, with a waker that unparks the current thread.
+	pub(crate) fn poll_once<F: Future + Unpin>(f: &mut F) -> Poll<F::Output> {
+		Pin::new(f).poll(&mut Context::from_waker(&waker()))
+	}
+
+	/// Run the future to completion on the current thread.
+	pub(crate) fn block_on<F: Future>(f: F) -> F::Output {
+		let mut f = Box::pin(f);
+		loop {
+			if let Poll::Ready(output) = poll_once(&mut f) {
+				return output;
+			}
+			thread::park();
+		}
+	}
+
+	#[test]
+	fn wrong_value() {
+		let futex = AsyncFutex::<Private>::new(1);
+		assert_eq!(block_on(futex.wait(0)), Err(WrongValueError::WrongValue));
+	}
+
+	#[test]
+	fn woken_by_another_thread() {
+		let futex = AsyncFutex::<Private>::new(0);
+		thread::scope(|s| {
+			let waiter = s.spawn(|| block_on(futex.wait(0)));
+			// The wait is submitted asynchronously, so keep waking until it's done.
+			while !waiter.is_finished() {
+				futex.wake(1);
+				thread::sleep(Duration::from_millis(1));
+			}
+			assert_eq!(waiter.join().unwrap(), Ok(()));
+		});
+	}
+
+	#[test]
+	fn many_pending_waits() {
+		let futex = AsyncFutex::<Private>::new(0);
+		let mut waits: Vec<_> = (0..16).map(|_| futex.wait(0)).collect();
+		for wait in &mut waits {
+			assert!(poll_once(wait).is_pending());
+		}
+		while !waits.is_empty() {
+			futex.wake(u32::MAX);
+			waits.retain_mut(|wait| poll_once(wait).is_pending());
+			thread::sleep(Duration::from_millis(1));
+		}
+	}
+
+	#[test]
+	fn dropping_a_pending_wait() {
+		let futex = AsyncFutex::<Private>::new(0);
+		for _ in 0..100 {
+			let mut wait = futex.wait(0);
+			assert!(poll_once(&mut wait).is_pending());
+			drop(wait);
+		}
+		// The dropped waits don't keep anything blocked on the futex.
+		thread::scope(|s| {
+			let waiter = s.spawn(|| block_on(futex.wait(0)));
+			while !waiter.is_finished() {
+				futex.wake(1);
+				thread::sleep(Duration::from_millis(1));
+			}
+			assert_eq!(waiter.join().unwrap(), Ok(()));
+		});
+	}
+}
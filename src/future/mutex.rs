@@ -0,0 +1,277 @@
+use super::{AsyncFutex, Wait};
+use crate::sync::{RawMutex, CONTENDED, UNLOCKED};
+use crate::{Private, Scope};
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::atomic::Ordering::Acquire;
+use std::task::{Context, Poll};
+
+/// A mutual exclusion primitive protecting data of type `T`, that can be locked from async code.
+///
+/// The lock is a single futex word, using the same three-state protocol as
+/// [`sync::Mutex`][crate::sync::Mutex]. Async tasks that find it locked
+/// wait on the futex through an [`AsyncFutex`], without blocking the
+/// executor, while regular threads can take the same lock with
+/// [`lock_blocking`][AsyncMutex::lock_blocking]. Unlocking wakes up one
+/// waiter, whether it is a task or a thread.
+///
+/// Unlike `sync::Mutex`, this mutex is not poisoned by panics, and its
+/// guard can be held across an `.await`, and sent to another thread.
+///
+/// An `AsyncMutex<T, Shared>` can be used between processes, if it is
+/// placed in shared memory.
+#[repr(C)]
+pub struct AsyncMutex<T: ?Sized, S = Private> {
+	raw: RawMutex<S>,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send, S> Send for AsyncMutex<T, S> {}
+unsafe impl<T: ?Sized + Send, S> Sync for AsyncMutex<T, S> {}
+
+/// The lock of an [`AsyncMutex`]. The mutex is unlocked when this guard is dropped.
+#[must_use = "if unused the AsyncMutex will immediately unlock"]
+pub struct AsyncMutexGuard<'a, T: ?Sized, S: Scope = Private> {
+	mutex: &'a AsyncMutex<T, S>,
+}
+
+unsafe impl<T: ?Sized + Sync, S: Scope> Sync for AsyncMutexGuard<'_, T, S> {}
+
+impl<T, S> AsyncMutex<T, S> {
+	/// Create a new unlocked mutex containing `value`.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self {
+			raw: RawMutex::new(),
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the mutex, returning the data it protected.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.data.into_inner()
+	}
+}
+
+impl<T: ?Sized, S> AsyncMutex<T, S> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the mutex.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		self.data.get_mut()
+	}
+}
+
+impl<T: ?Sized, S: Scope> AsyncMutex<T, S> {
+	/// Lock the mutex, waiting asynchronously until it is available.
+	#[inline]
+	pub fn lock(&self) -> Lock<'_, T, S> {
+		Lock {
+			mutex: self,
+			wait: None,
+			contended: false,
+			done: false,
+		}
+	}
+
+	/// Lock the mutex, blocking the calling thread until it is available.
+	///
+	/// Locking a mutex that is already locked by the calling thread (or
+	/// task) results in a deadlock.
+	#[inline]
+	pub fn lock_blocking(&self) -> AsyncMutexGuard<'_, T, S> {
+		self.raw.lock();
+		AsyncMutexGuard { mutex: self }
+	}
+
+	/// Lock the mutex if it is not locked, without blocking.
+	#[inline]
+	pub fn try_lock(&self) -> Option<AsyncMutexGuard<'_, T, S>> {
+		if self.raw.try_lock() {
+			Some(AsyncMutexGuard { mutex: self })
+		} else {
+			None
+		}
+	}
+
+	fn futex(&self) -> &AsyncFutex<S> {
+		AsyncFutex::from_futex(&self.raw.futex)
+	}
+}
+
+/// The future returned by [`AsyncMutex::lock`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Lock<'a, T: ?Sized, S: Scope> {
+	mutex: &'a AsyncMutex<T, S>,
+	wait: Option<Wait<'a, S>>,
+	/// Whether we waited before, such that the lock must be taken as contended.
+	contended: bool,
+	done: bool,
+}
+
+impl<'a, T: ?Sized, S: Scope> Future for Lock<'a, T, S> {
+	type Output = AsyncMutexGuard<'a, T, S>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		let raw = &this.mutex.raw;
+		loop {
+			if let Some(wait) = &mut this.wait {
+				if Pin::new(wait).poll(cx).is_pending() {
+					return Poll::Pending;
+				}
+				this.wait = None;
+			}
+			let locked = if this.contended {
+				// Other tasks or threads might still be waiting, so keep it marked as contended.
+				raw.futex.value.swap(CONTENDED, Acquire) == UNLOCKED
+			} else {
+				raw.try_lock() || raw.futex.value.swap(CONTENDED, Acquire) == UNLOCKED
+			};
+			if locked {
+				this.done = true;
+				return Poll::Ready(AsyncMutexGuard { mutex: this.mutex });
+			}
+			this.contended = true;
+			this.wait = Some(this.mutex.futex().wait(CONTENDED));
+		}
+	}
+}
+
+impl<T: ?Sized, S: Scope> Drop for Lock<'_, T, S> {
+	fn drop(&mut self) {
+		// Drop the wait first, such that its helper thread is done.
+		self.wait = None;
+		// We might have consumed a wake up meant for taking the lock. Pass it on.
+		// The mutex might have been unlocked (or even locked again without
+		// contention) since, so its state doesn't tell whether anyone is still waiting.
+		if self.contended && !self.done {
+			self.mutex.raw.futex.wake(1);
+		}
+	}
+}
+
+impl<T: ?Sized, S: Scope> std::fmt::Debug for Lock<'_, T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Lock").finish_non_exhaustive()
+	}
+}
+
+impl<T: ?Sized, S: Scope> Deref for AsyncMutexGuard<'_, T, S> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> DerefMut for AsyncMutexGuard<'_, T, S> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Drop for AsyncMutexGuard<'_, T, S> {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { self.mutex.raw.unlock() }
+	}
+}
+
+impl<T: Default, S> Default for AsyncMutex<T, S> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T, S> From<T> for AsyncMutex<T, S> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for AsyncMutex<T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("AsyncMutex");
+		d.field("scope", &std::any::type_name::<S>());
+		match self.try_lock() {
+			Some(guard) => d.field("data", &&*guard),
+			None => d.field("data", &format_args!("<locked>")),
+		};
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for AsyncMutexGuard<'_, T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::AsyncMutex;
+	use crate::future::tests::{block_on, poll_once};
+	use crate::{Private, Shared};
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn tasks_and_threads() {
+		let mutex = AsyncMutex::<u64, Shared>::new(0);
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					block_on(async {
+						for _ in 0..1000 {
+							*mutex.lock().await += 1;
+						}
+					})
+				});
+				s.spawn(|| {
+					for _ in 0..1000 {
+						*mutex.lock_blocking() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(mutex.into_inner(), 8000);
+	}
+
+	#[test]
+	fn try_lock() {
+		let mutex = AsyncMutex::<u32, Private>::new(0);
+		let guard = mutex.try_lock().unwrap();
+		assert!(mutex.try_lock().is_none());
+		let mut lock = mutex.lock();
+		assert!(poll_once(&mut lock).is_pending());
+		drop(guard);
+		let guard = block_on(lock);
+		assert!(mutex.try_lock().is_none());
+		drop(guard);
+		assert!(mutex.try_lock().is_some());
+	}
+
+	#[test]
+	fn dropped_lock_passes_on_the_wake_up() {
+		let mutex = AsyncMutex::<u32, Private>::new(0);
+		let guard = mutex.lock_blocking();
+		thread::scope(|s| {
+			let waiter = s.spawn(|| *mutex.lock_blocking() += 1);
+			let mut lock = mutex.lock();
+			assert!(poll_once(&mut lock).is_pending());
+			thread::sleep(Duration::from_millis(10));
+			drop(guard);
+			// The wake up might have gone to the pending lock, which then gives up.
+			thread::sleep(Duration::from_millis(10));
+			drop(lock);
+			waiter.join().unwrap();
+		});
+		assert_eq!(*mutex.try_lock().unwrap(), 1);
+	}
+}
@@ -0,0 +1,263 @@
+//! The helper threads that block on futexes on behalf of futures.
+//!
+//! A future that has to wait submits a [`Request`] to the pool. An idle
+//! helper thread picks it up, blocks on the futex, and wakes the future's
+//! [`Waker`] when the wait ends. Helper threads are spawned when there are
+//! more requests than idle threads, since a wait can take arbitrarily long,
+//! and exit after being idle for a while.
+
+use super::uring;
+use crate::sync::{Condvar, Mutex};
+use crate::sys::{Error, FutexCall};
+use crate::waitv::waitv;
+use crate::{Futex, Private, WaitvEntry};
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::{Arc, PoisonError};
+use std::task::Waker;
+use std::time::Duration;
+
+/// The request is queued, and no helper thread picked it up yet.
+const PENDING: u32 = 0;
+/// A helper thread is waiting on the futex.
+const WAITING: u32 = 1;
+/// The wait ended, because a futex was woken up (or spuriously).
+pub(crate) const WOKEN: u32 = 2;
+/// A futex did not have the expected value.
+pub(crate) const WRONG_VALUE: u32 = 3;
+/// The future was dropped before a helper thread picked up the request.
+const CANCELLED: u32 = 4;
+/// The timeout expired.
+pub(crate) const TIMED_OUT: u32 = 5;
+/// Set in addition to `WAITING` when the future was dropped during the wait.
+const CANCEL: u32 = 8;
+
+/// How long an idle helper thread waits for a new request before exiting.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A wait on one or more futexes, shared between a future and the helper
+/// thread (or io_uring) that performs it.
+pub(crate) struct Request {
+	/// The futexes to wait on. Only the first one, if io_uring is used.
+	entries: Box<[WaitvEntry<'static>]>,
+	/// An absolute timeout, from [`Timeout::as_timespec`][crate::Timeout::as_timespec].
+	timeout: Option<(i32, libc::timespec)>,
+	/// Whether the wait was submitted to io_uring, rather than a helper thread.
+	uring: bool,
+	state: Futex<Private>,
+	/// The index of the futex that was woken up.
+	index: AtomicU32,
+	waker: Mutex<Option<Waker>>,
+}
+
+// The futex pointers are only used while the future that submitted the request is alive.
+unsafe impl Send for Request {}
+unsafe impl Sync for Request {}
+
+impl Request {
+	/// The futexes must stay valid until [`cancel`][Request::cancel] is called,
+	/// or until the state is no longer `PENDING` or `WAITING`.
+	pub(crate) unsafe fn new(
+		entries: &[WaitvEntry],
+		timeout: Option<(i32, libc::timespec)>,
+		waker: Waker,
+		uring: bool,
+	) -> Self {
+		Self {
+			entries: entries
+				.iter()
+				.map(|e| std::mem::transmute::<WaitvEntry, WaitvEntry<'static>>(*e))
+				.collect(),
+			timeout,
+			uring,
+			state: Futex::new(PENDING),
+			index: AtomicU32::new(0),
+			waker: Mutex::new(Some(waker)),
+		}
+	}
+
+	/// The futex of a single wait, as submitted to io_uring.
+	pub(super) fn entry(&self) -> &WaitvEntry<'static> {
+		&self.entries[0]
+	}
+
+	/// The outcome of the wait: `WOKEN`, `WRONG_VALUE` or `TIMED_OUT`, or `None` if it didn't end yet.
+	pub(crate) fn result(&self) -> Option<u32> {
+		match self.state.value.load(Acquire) {
+			s @ (WOKEN | WRONG_VALUE | TIMED_OUT) => Some(s),
+			_ => None,
+		}
+	}
+
+	/// The index of the futex that was woken up, once the result is `WOKEN`.
+	pub(crate) fn index(&self) -> usize {
+		self.index.load(Relaxed) as usize
+	}
+
+	/// Replace the waker to be woken when the wait ends.
+	///
+	/// Returns the outcome instead, if the wait already ended.
+	pub(crate) fn register(&self, waker: &Waker) -> Option<u32> {
+		let mut w = self.waker.lock().unwrap_or_else(PoisonError::into_inner);
+		if !matches!(&*w, Some(w) if w.will_wake(waker)) {
+			*w = Some(waker.clone());
+		}
+		drop(w);
+		// The helper thread takes the waker after setting the state, so we can't miss it.
+		self.result()
+	}
+
+	/// Stop the wait, and make sure no helper thread uses the futex anymore.
+	///
+	/// This wakes up all waiters of the futexes if a helper thread is blocked on them,
+	/// and blocks until that thread has woken up.
+	pub(crate) fn cancel(&self) {
+		match self
+			.state
+			.value
+			.compare_exchange(PENDING, CANCELLED, Relaxed, Acquire)
+		{
+			Ok(_) => return,
+			Err(WAITING) => {}
+			Err(_) => return,
+		}
+		self.state.value.fetch_or(CANCEL, Relaxed);
+		if self.uring {
+			uring::cancel(self);
+			while self.state.value.load(Acquire) == WAITING | CANCEL {
+				let _ = self.state.wait(WAITING | CANCEL);
+			}
+			return;
+		}
+		loop {
+			// The helper thread might not be asleep yet, so keep waking until it reports back.
+			for entry in &*self.entries {
+				unsafe {
+					let _ = FutexCall::new()
+						.futex_op(libc::FUTEX_WAKE + entry.futex_flag())
+						.uaddr(entry.futex_ptr())
+						.val(i32::MAX as u32)
+						.call();
+				}
+			}
+			if self.state.value.load(Acquire) != WAITING | CANCEL {
+				return;
+			}
+			let _ = self
+				.state
+				.wait_for(WAITING | CANCEL, Duration::from_millis(1));
+		}
+	}
+
+	/// Mark the wait as started, unless it was cancelled.
+	pub(super) fn start(&self) -> bool {
+		self.state
+			.value
+			.compare_exchange(PENDING, WAITING, Relaxed, Relaxed)
+			.is_ok()
+	}
+
+	/// Report the outcome of the wait, and wake the future.
+	///
+	/// After this, the futex is never used again.
+	pub(super) fn finish(&self, result: u32) {
+		if self.state.value.swap(result, Release) & CANCEL != 0 {
+			self.state.wake(1);
+		}
+		let waker = self
+			.waker
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.take();
+		if let Some(waker) = waker {
+			waker.wake();
+		}
+	}
+
+	/// Perform the wait, on a helper thread.
+	fn serve(&self) {
+		if !self.start() {
+			// Cancelled.
+			return;
+		}
+		let result = loop {
+			let r = match (&*self.entries, &self.timeout) {
+				// A single futex without a timeout doesn't need futex_waitv.
+				([entry], None) => unsafe {
+					FutexCall::new()
+						.futex_op(libc::FUTEX_WAIT + entry.futex_flag())
+						.uaddr(entry.futex_ptr())
+						.val(entry.expected_value())
+						.call()
+						.map(|_| 0)
+				},
+				(entries, timeout) => unsafe { waitv(entries, *timeout) },
+			};
+			match r {
+				Ok(i) => {
+					self.index.store(i as u32, Relaxed);
+					break WOKEN;
+				}
+				Err(Error(libc::EAGAIN)) => break WRONG_VALUE,
+				Err(Error(libc::ETIMEDOUT)) => break TIMED_OUT,
+				Err(Error(libc::EINTR)) if self.state.value.load(Relaxed) & CANCEL == 0 => continue,
+				// Anything else, including errors, counts as a (spurious) wake up.
+				Err(_) => break WOKEN,
+			}
+		};
+		self.finish(result);
+	}
+}
+
+struct Queue {
+	requests: VecDeque<Arc<Request>>,
+	/// The number of helper threads waiting for a request.
+	idle: usize,
+}
+
+static QUEUE: Mutex<Queue> = Mutex::new(Queue {
+	requests: VecDeque::new(),
+	idle: 0,
+});
+
+static AVAILABLE: Condvar = Condvar::new();
+
+/// Hand a request to a helper thread, spawning one if none are idle.
+pub(crate) fn submit(request: Arc<Request>) {
+	let mut queue = QUEUE.lock().unwrap_or_else(PoisonError::into_inner);
+	queue.requests.push_back(request);
+	let spawn = queue.requests.len() > queue.idle;
+	drop(queue);
+	if spawn {
+		std::thread::Builder::new()
+			.name("futex-waiter".into())
+			.spawn(run)
+			.expect("failed to spawn futex waiter thread");
+	} else {
+		AVAILABLE.notify_one();
+	}
+}
+
+/// The main loop of a helper thread.
+fn run() {
+	loop {
+		let mut queue = QUEUE.lock().unwrap_or_else(PoisonError::into_inner);
+		let request = loop {
+			if let Some(request) = queue.requests.pop_front() {
+				break request;
+			}
+			queue.idle += 1;
+			let (q, timeout) = AVAILABLE
+				.wait_timeout(queue, IDLE_TIMEOUT)
+				.unwrap_or_else(PoisonError::into_inner);
+			queue = q;
+			queue.idle -= 1;
+			if timeout.timed_out() && queue.requests.is_empty() {
+				return;
+			}
+		};
+		drop(queue);
+		request.serve();
+	}
+}
@@ -0,0 +1,134 @@
+//! Waiting through the reactor of `async-io`.
+
+use super::pool::{Request, WRONG_VALUE};
+use crate::{Futex, Scope, WrongValueError};
+use async_io::Async;
+use std::future::Future;
+use std::io;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::pin::Pin;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+
+/// An eventfd that becomes readable when a wait finishes.
+struct EventFd(OwnedFd);
+
+impl EventFd {
+	fn new() -> io::Result<Self> {
+		let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+		if fd < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(Self(unsafe { OwnedFd::from_raw_fd(fd) }))
+	}
+}
+
+impl AsFd for EventFd {
+	#[inline]
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.0.as_fd()
+	}
+}
+
+impl Wake for EventFd {
+	fn wake(self: Arc<Self>) {
+		self.wake_by_ref();
+	}
+
+	fn wake_by_ref(self: &Arc<Self>) {
+		let one = 1u64;
+		unsafe { libc::write(self.0.as_raw_fd(), &one as *const u64 as *const _, 8) };
+	}
+}
+
+/// The future returned by [`AsyncFutex::wait_async_io`][super::AsyncFutex::wait_async_io].
+#[must_use = "futures do nothing unless polled"]
+pub struct WaitAsyncIo<'a, S: Scope> {
+	futex: &'a Futex<S>,
+	expected_value: u32,
+	request: Option<Arc<Request>>,
+	/// The eventfd signalled when the wait finishes, unless it couldn't be set up.
+	event: Option<Async<Arc<EventFd>>>,
+}
+
+impl<'a, S: Scope> WaitAsyncIo<'a, S> {
+	pub(super) fn new(futex: &'a Futex<S>, expected_value: u32) -> Self {
+		Self {
+			futex,
+			expected_value,
+			request: None,
+			event: None,
+		}
+	}
+
+	fn submit(&mut self, cx: &mut Context) -> Arc<Request> {
+		let event = EventFd::new()
+			.map(Arc::new)
+			.and_then(|e| Ok((Async::new(e.clone())?, e)));
+		// Without a registered eventfd, the task is woken directly.
+		let waker = match event {
+			Ok((event, e)) => {
+				self.event = Some(event);
+				Waker::from(e)
+			}
+			Err(_) => cx.waker().clone(),
+		};
+		let request = unsafe { super::submit(self.futex, self.expected_value, waker) };
+		self.request = Some(request.clone());
+		request
+	}
+}
+
+impl<S: Scope> Future for WaitAsyncIo<'_, S> {
+	type Output = Result<(), WrongValueError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		let request = match &this.request {
+			Some(request) => request.clone(),
+			None => {
+				if this.futex.value.load(Relaxed) != this.expected_value {
+					return Poll::Ready(Err(WrongValueError::WrongValue));
+				}
+				this.submit(cx)
+			}
+		};
+		let result = match &this.event {
+			Some(event) => loop {
+				if let Some(result) = request.result() {
+					break result;
+				}
+				if event.poll_readable(cx).is_pending() {
+					return Poll::Pending;
+				}
+			},
+			None => match request.register(cx.waker()) {
+				Some(result) => result,
+				None => return Poll::Pending,
+			},
+		};
+		if result == WRONG_VALUE {
+			Poll::Ready(Err(WrongValueError::WrongValue))
+		} else {
+			Poll::Ready(Ok(()))
+		}
+	}
+}
+
+impl<S: Scope> Drop for WaitAsyncIo<'_, S> {
+	fn drop(&mut self) {
+		if let Some(request) = &self.request {
+			request.cancel();
+		}
+	}
+}
+
+impl<S: Scope> std::fmt::Debug for WaitAsyncIo<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("WaitAsyncIo")
+			.field("futex", &self.futex)
+			.field("expected_value", &self.expected_value)
+			.finish_non_exhaustive()
+	}
+}
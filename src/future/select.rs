@@ -0,0 +1,106 @@
+use super::pool::{self, Request, TIMED_OUT, WRONG_VALUE};
+use crate::{TimedWrongValueError, Timeout, WaitvEntry, WAITV_MAX};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Wait until any of the futexes is awoken by a `wake` call.
+///
+/// The returned future resolves to the index of the futex that was woken
+/// up, or right away to [`TimedWrongValueError::WrongValue`] if any of the
+/// futexes does not have its expected value. Like [`wait_any`][crate::wait_any],
+/// this requires Linux 5.16, for `futex_waitv`.
+///
+/// The wait is performed by a helper thread, so each pending `select`
+/// occupies one. Dropping the future before it resolves stops the wait,
+/// waking up all waiters of the futexes, which they might observe as a
+/// spurious wake up.
+///
+/// # Panics
+///
+/// Panics if `entries` is empty or has more than [`WAITV_MAX`] entries.
+#[inline]
+pub fn select<'a>(entries: &'a [WaitvEntry<'a>]) -> Select<'a> {
+	Select::new(entries, None)
+}
+
+/// Wait until any of the futexes is awoken by a `wake` call, or until the timeout expires.
+///
+/// See [`select`].
+#[inline]
+pub fn select_until<'a>(entries: &'a [WaitvEntry<'a>], timeout: impl Timeout) -> Select<'a> {
+	Select::new(entries, Some(timeout.as_timespec()))
+}
+
+/// The future returned by [`select`] and [`select_until`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Select<'a> {
+	entries: &'a [WaitvEntry<'a>],
+	timeout: Option<(i32, libc::timespec)>,
+	request: Option<Arc<Request>>,
+}
+
+impl<'a> Select<'a> {
+	fn new(entries: &'a [WaitvEntry<'a>], timeout: Option<(i32, libc::timespec)>) -> Self {
+		assert!(
+			!entries.is_empty() && entries.len() <= WAITV_MAX,
+			"select needs between 1 and {} futexes",
+			WAITV_MAX
+		);
+		Self {
+			entries,
+			timeout,
+			request: None,
+		}
+	}
+}
+
+impl Future for Select<'_> {
+	type Output = Result<usize, TimedWrongValueError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		let result = match &this.request {
+			Some(request) => request.register(cx.waker()),
+			None => {
+				let wrong_value = this
+					.entries
+					.iter()
+					.any(|e| unsafe { &*e.futex_ptr() }.load(Relaxed) != e.expected_value());
+				if wrong_value {
+					return Poll::Ready(Err(TimedWrongValueError::WrongValue));
+				}
+				let request = Arc::new(unsafe {
+					Request::new(this.entries, this.timeout, cx.waker().clone(), false)
+				});
+				pool::submit(request.clone());
+				this.request = Some(request);
+				return Poll::Pending;
+			}
+		};
+		match result {
+			None => Poll::Pending,
+			Some(WRONG_VALUE) => Poll::Ready(Err(TimedWrongValueError::WrongValue)),
+			Some(TIMED_OUT) => Poll::Ready(Err(TimedWrongValueError::TimedOut)),
+			Some(_) => Poll::Ready(Ok(this.request.as_ref().unwrap().index())),
+		}
+	}
+}
+
+impl Drop for Select<'_> {
+	fn drop(&mut self) {
+		if let Some(request) = &self.request {
+			request.cancel();
+		}
+	}
+}
+
+impl std::fmt::Debug for Select<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Select")
+			.field("entries", &self.entries)
+			.finish_non_exhaustive()
+	}
+}
@@ -0,0 +1,222 @@
+use super::{AsyncFutex, Wait};
+use crate::sync::{Semaphore, MANY};
+use crate::{Private, Scope};
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::atomic::Ordering::{self, Relaxed, SeqCst};
+use std::task::{Context, Poll};
+
+/// A counting semaphore, of which permits can be acquired from async code.
+///
+/// This is a [`Semaphore`] with asynchronous [`acquire`][AsyncSemaphore::acquire]
+/// methods. It has the same state, and dereferences to the `Semaphore`, so
+/// permits can be released by regular threads (or other processes) and
+/// acquired by async tasks, and the other way around.
+/// [`from_semaphore`][AsyncSemaphore::from_semaphore] gives async access to
+/// an existing `Semaphore`.
+///
+/// # Between processes
+///
+/// An `AsyncSemaphore<Shared>` can be used between processes, if it is placed
+/// in shared memory. Just like a `Semaphore`, one with zero permits consists
+/// of only zero bytes.
+#[repr(transparent)]
+pub struct AsyncSemaphore<S = Private> {
+	semaphore: Semaphore<S>,
+}
+
+impl<S> AsyncSemaphore<S> {
+	/// Create a new semaphore with the given number of available permits.
+	#[inline]
+	pub const fn new(permits: u32) -> Self {
+		Self {
+			semaphore: Semaphore::new(permits),
+		}
+	}
+
+	/// Use an existing [`Semaphore`] as an [`AsyncSemaphore`].
+	#[inline]
+	pub fn from_semaphore(semaphore: &Semaphore<S>) -> &Self {
+		unsafe { &*(semaphore as *const Semaphore<S> as *const Self) }
+	}
+}
+
+impl<S: Scope> AsyncSemaphore<S> {
+	/// Acquire a permit, waiting asynchronously until one is available.
+	#[inline]
+	pub fn acquire(&self) -> Acquire<'_, S> {
+		self.acquire_many(1)
+	}
+
+	/// Acquire `n` permits at once, waiting asynchronously until enough are available.
+	#[inline]
+	pub fn acquire_many(&self, n: u32) -> Acquire<'_, S> {
+		Acquire {
+			semaphore: &self.semaphore,
+			n,
+			waiting: false,
+			wait: None,
+		}
+	}
+}
+
+impl<S> Deref for AsyncSemaphore<S> {
+	type Target = Semaphore<S>;
+	#[inline]
+	fn deref(&self) -> &Semaphore<S> {
+		&self.semaphore
+	}
+}
+
+impl<S> std::fmt::Debug for AsyncSemaphore<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("AsyncSemaphore")
+			.field("scope", &std::any::type_name::<S>())
+			.field("permits", &self.available_permits())
+			.finish()
+	}
+}
+
+/// The future returned by [`AsyncSemaphore::acquire`] and [`AsyncSemaphore::acquire_many`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Acquire<'a, S: Scope> {
+	semaphore: &'a Semaphore<S>,
+	n: u32,
+	/// Whether we're counted as a waiter.
+	waiting: bool,
+	wait: Option<Wait<'a, S>>,
+}
+
+impl<S: Scope> Acquire<'_, S> {
+	/// The amount added to the waiter count of the semaphore.
+	fn weight(&self) -> u32 {
+		if self.n == 1 {
+			1
+		} else {
+			MANY
+		}
+	}
+}
+
+impl<S: Scope> Future for Acquire<'_, S> {
+	type Output = ();
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+		let this = self.get_mut();
+		let permits = &this.semaphore.permits;
+		loop {
+			if let Some(wait) = &mut this.wait {
+				if Pin::new(wait).poll(cx).is_pending() {
+					return Poll::Pending;
+				}
+				this.wait = None;
+			}
+			if !this.waiting {
+				if this.semaphore.try_acquire_many(this.n) {
+					return Poll::Ready(());
+				}
+				this.semaphore.waiters.fetch_add(this.weight(), SeqCst);
+				this.waiting = true;
+			}
+			let p = permits.value.load(SeqCst);
+			if p >= this.n {
+				if permits
+					.value
+					.compare_exchange(p, p - this.n, Ordering::Acquire, Relaxed)
+					.is_ok()
+				{
+					this.semaphore.waiters.fetch_sub(this.weight(), Relaxed);
+					this.waiting = false;
+					return Poll::Ready(());
+				}
+				continue;
+			}
+			this.wait = Some(AsyncFutex::from_futex(permits).wait(p));
+		}
+	}
+}
+
+impl<S: Scope> Drop for Acquire<'_, S> {
+	fn drop(&mut self) {
+		if self.waiting {
+			// Drop the wait first, such that its helper thread is done.
+			self.wait = None;
+			self.semaphore.waiters.fetch_sub(self.weight(), Relaxed);
+			// We might have consumed a wake up for permits we'll never take. Pass it on.
+			if self.semaphore.available_permits() > 0 {
+				self.semaphore.permits.wake(1);
+			}
+		}
+	}
+}
+
+impl<S: Scope> std::fmt::Debug for Acquire<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Acquire")
+			.field("n", &self.n)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::AsyncSemaphore;
+	use crate::future::tests::{block_on, poll_once};
+	use crate::sync::Semaphore;
+	use crate::Private;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn released_by_threads_acquired_by_tasks() {
+		let semaphore = AsyncSemaphore::<Private>::new(0);
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					block_on(async {
+						for _ in 0..1000 {
+							semaphore.acquire().await;
+						}
+					})
+				});
+				s.spawn(|| {
+					for _ in 0..1000 {
+						semaphore.release();
+					}
+				});
+			}
+		});
+		assert_eq!(semaphore.available_permits(), 0);
+	}
+
+	#[test]
+	fn acquire_many_waits_for_enough_permits() {
+		let semaphore = Semaphore::<Private>::new(0);
+		let mut acquire = AsyncSemaphore::from_semaphore(&semaphore).acquire_many(3);
+		assert!(poll_once(&mut acquire).is_pending());
+		semaphore.release_many(2);
+		thread::sleep(Duration::from_millis(10));
+		assert!(poll_once(&mut acquire).is_pending());
+		semaphore.release();
+		block_on(acquire);
+		assert_eq!(semaphore.available_permits(), 0);
+	}
+
+	#[test]
+	fn dropped_acquire_passes_on_the_wake_up() {
+		let semaphore = Semaphore::<Private>::new(0);
+		thread::scope(|s| {
+			let waiter = s.spawn(|| semaphore.acquire());
+			let mut acquire = AsyncSemaphore::from_semaphore(&semaphore).acquire();
+			assert!(poll_once(&mut acquire).is_pending());
+			thread::sleep(Duration::from_millis(10));
+			semaphore.release();
+			// The wake up might have gone to the pending acquire, which then gives up.
+			thread::sleep(Duration::from_millis(10));
+			drop(acquire);
+			waiter.join().unwrap();
+		});
+		assert_eq!(semaphore.available_permits(), 0);
+	}
+}
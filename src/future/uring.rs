@@ -0,0 +1,399 @@
+//! Waiting on futexes through io_uring.
+//!
+//! Since Linux 6.7, io_uring can wait on a futex with `IORING_OP_FUTEX_WAIT`,
+//! completing the wait with a completion queue entry when the futex is woken
+//! up. All waits are submitted to a single ring, and a single thread reaps
+//! the completions and wakes the futures, instead of one helper thread per
+//! pending wait.
+//!
+//! If io_uring isn't available, or doesn't support futexes, or the ring was
+//! set up by the parent of a forked process, [`ring`] returns `None`, and the
+//! waits go to the helper threads of the [pool][super::pool] instead.
+
+use super::pool::{Request, WOKEN, WRONG_VALUE};
+use crate::sync::{Mutex, Once, OnceCell};
+use std::mem::size_of;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr::null_mut;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+
+const IORING_OP_ASYNC_CANCEL: u8 = 14;
+const IORING_OP_FUTEX_WAIT: u8 = 51;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+const IORING_FEAT_SINGLE_MMAP: u32 = 1;
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_SQES: libc::off_t = 0x1000_0000;
+const FUTEX2_SIZE_U32: u32 = 0x02;
+const FUTEX_BITSET_MATCH_ANY: u64 = 0xFFFF_FFFF;
+
+/// The number of submission queue entries.
+const ENTRIES: u32 = 256;
+
+/// `struct io_sqring_offsets`.
+#[repr(C)]
+#[derive(Default)]
+struct SqOffsets {
+	head: u32,
+	tail: u32,
+	ring_mask: u32,
+	ring_entries: u32,
+	flags: u32,
+	dropped: u32,
+	array: u32,
+	resv1: u32,
+	user_addr: u64,
+}
+
+/// `struct io_cqring_offsets`.
+#[repr(C)]
+#[derive(Default)]
+struct CqOffsets {
+	head: u32,
+	tail: u32,
+	ring_mask: u32,
+	ring_entries: u32,
+	overflow: u32,
+	cqes: u32,
+	flags: u32,
+	resv1: u32,
+	user_addr: u64,
+}
+
+/// `struct io_uring_params`.
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+	sq_entries: u32,
+	cq_entries: u32,
+	flags: u32,
+	sq_thread_cpu: u32,
+	sq_thread_idle: u32,
+	features: u32,
+	wq_fd: u32,
+	resv: [u32; 3],
+	sq_off: SqOffsets,
+	cq_off: CqOffsets,
+}
+
+/// `struct io_uring_sqe`, with the fields as used by the futex operations.
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+	opcode: u8,
+	flags: u8,
+	ioprio: u16,
+	/// The `FUTEX2_*` flags.
+	fd: i32,
+	/// The expected value.
+	addr2: u64,
+	/// The futex, or the `user_data` of the operation to cancel.
+	addr: u64,
+	len: u32,
+	op_flags: u32,
+	user_data: u64,
+	buf_index: u16,
+	personality: u16,
+	file_index: u32,
+	/// The bitset.
+	addr3: u64,
+	_pad: u64,
+}
+
+/// `struct io_uring_cqe`.
+#[repr(C)]
+struct Cqe {
+	user_data: u64,
+	res: i32,
+	flags: u32,
+}
+
+const _: () = assert!(size_of::<Params>() == 120 && size_of::<Sqe>() == 64);
+
+/// The submission side of the ring.
+struct Sq {
+	head: *const AtomicU32,
+	tail: *const AtomicU32,
+	mask: u32,
+	sqes: *mut Sqe,
+}
+
+/// An io_uring instance used only for futex waits.
+pub(crate) struct Ring {
+	fd: OwnedFd,
+	/// The process that set up the ring. A forked child can't use it,
+	/// as the completion thread doesn't exist there.
+	pid: u32,
+	ring: (*mut libc::c_void, usize),
+	sqes: (*mut libc::c_void, usize),
+	sq: Mutex<Sq>,
+	cq_head: *const AtomicU32,
+	cq_tail: *const AtomicU32,
+	cq_mask: u32,
+	cqes: *const Cqe,
+}
+
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+static RING: OnceCell<Option<Ring>> = OnceCell::new();
+static COMPLETER: Once = Once::new();
+
+/// The ring to submit futex waits to, if io_uring supports them.
+pub(crate) fn ring() -> Option<&'static Ring> {
+	let ring = RING
+		.get_or_init(|| unsafe { Ring::setup() })
+		.as_ref()
+		.filter(|ring| ring.pid == std::process::id())?;
+	COMPLETER.call_once(|| {
+		std::thread::Builder::new()
+			.name("futex-uring".into())
+			.spawn(move || ring.complete())
+			.expect("failed to spawn futex completion thread");
+	});
+	Some(ring)
+}
+
+/// Cancel a wait that was submitted to the ring.
+///
+/// The request is finished once the kernel reports back, either because the
+/// wait was cancelled or because it already completed.
+pub(crate) fn cancel(request: &Request) {
+	let ring = RING.get().and_then(Option::as_ref).unwrap();
+	loop {
+		let sqe = Sqe {
+			opcode: IORING_OP_ASYNC_CANCEL,
+			addr: request as *const Request as u64,
+			..Sqe::default()
+		};
+		if ring.push(sqe) {
+			return;
+		}
+		std::thread::sleep(Duration::from_millis(1));
+	}
+}
+
+impl Ring {
+	unsafe fn setup() -> Option<Self> {
+		let mut params = Params::default();
+		let fd = libc::syscall(libc::SYS_io_uring_setup, ENTRIES, &mut params);
+		if fd < 0 {
+			return None;
+		}
+		let fd = OwnedFd::from_raw_fd(fd as i32);
+		// Every kernel with futex support also maps both rings at once.
+		if params.features & IORING_FEAT_SINGLE_MMAP == 0 {
+			return None;
+		}
+		let map = |len: usize, offset: libc::off_t| {
+			let ptr = libc::mmap(
+				null_mut(),
+				len,
+				libc::PROT_READ | libc::PROT_WRITE,
+				libc::MAP_SHARED | libc::MAP_POPULATE,
+				fd.as_raw_fd(),
+				offset,
+			);
+			if ptr == libc::MAP_FAILED {
+				None
+			} else {
+				Some((ptr, len))
+			}
+		};
+		let ring_len = (params.sq_off.array as usize + params.sq_entries as usize * 4)
+			.max(params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<Cqe>());
+		let ring = map(ring_len, IORING_OFF_SQ_RING)?;
+		let sqes = match map(
+			params.sq_entries as usize * size_of::<Sqe>(),
+			IORING_OFF_SQES,
+		) {
+			Some(sqes) => sqes,
+			None => {
+				libc::munmap(ring.0, ring.1);
+				return None;
+			}
+		};
+		let at = |offset: u32| ring.0.cast::<u8>().add(offset as usize);
+		// Use the submission queue entries in order.
+		let array = at(params.sq_off.array).cast::<u32>();
+		for i in 0..params.sq_entries {
+			*array.add(i as usize) = i;
+		}
+		let ring = Self {
+			fd,
+			pid: std::process::id(),
+			ring,
+			sqes,
+			sq: Mutex::new(Sq {
+				head: at(params.sq_off.head).cast(),
+				tail: at(params.sq_off.tail).cast(),
+				mask: *at(params.sq_off.ring_mask).cast::<u32>(),
+				sqes: sqes.0.cast(),
+			}),
+			cq_head: at(params.cq_off.head).cast(),
+			cq_tail: at(params.cq_off.tail).cast(),
+			cq_mask: *at(params.cq_off.ring_mask).cast::<u32>(),
+			cqes: at(params.cq_off.cqes).cast(),
+		};
+		if ring.probe() {
+			Some(ring)
+		} else {
+			None
+		}
+	}
+
+	/// Check whether the kernel supports futex waits, by waiting on a futex
+	/// with the wrong value, before the completion thread exists.
+	unsafe fn probe(&self) -> bool {
+		let futex = AtomicU32::new(0);
+		let sqe = Sqe {
+			opcode: IORING_OP_FUTEX_WAIT,
+			fd: (FUTEX2_SIZE_U32 | libc::FUTEX_PRIVATE_FLAG as u32) as i32,
+			addr: &futex as *const AtomicU32 as u64,
+			addr2: 1,
+			addr3: FUTEX_BITSET_MATCH_ANY,
+			..Sqe::default()
+		};
+		if !self.push(sqe) {
+			return false;
+		}
+		loop {
+			let r = libc::syscall(
+				libc::SYS_io_uring_enter,
+				self.fd.as_raw_fd(),
+				0,
+				1,
+				IORING_ENTER_GETEVENTS,
+				null_mut::<libc::c_void>(),
+				0,
+			);
+			if r >= 0 {
+				break;
+			}
+			if *libc::__errno_location() != libc::EINTR {
+				return false;
+			}
+		}
+		let head = (*self.cq_head).load(Relaxed);
+		if (*self.cq_tail).load(Acquire) == head {
+			return false;
+		}
+		let res = (*self.cqes.add((head & self.cq_mask) as usize)).res;
+		(*self.cq_head).store(head.wrapping_add(1), Release);
+		res == -libc::EAGAIN
+	}
+
+	/// Submit a wait on a futex.
+	///
+	/// The request is finished by the completion thread.
+	pub(crate) fn submit(&self, request: Arc<Request>) {
+		if !request.start() {
+			return;
+		}
+		let sqe = Sqe {
+			opcode: IORING_OP_FUTEX_WAIT,
+			fd: (FUTEX2_SIZE_U32 | request.entry().futex_flag() as u32) as i32,
+			addr: request.entry().futex_ptr() as u64,
+			addr2: request.entry().expected_value() as u64,
+			addr3: FUTEX_BITSET_MATCH_ANY,
+			user_data: Arc::as_ptr(&request) as u64,
+			..Sqe::default()
+		};
+		let request = Arc::into_raw(request);
+		if !self.push(sqe) {
+			// The ring is full. Report a spurious wake up, so the future tries again.
+			let request = unsafe { Arc::from_raw(request) };
+			request.finish(WOKEN);
+		}
+	}
+
+	/// Put an entry in the submission queue, and submit it.
+	///
+	/// Returns false if the queue is full.
+	fn push(&self, sqe: Sqe) -> bool {
+		let sq = self.sq.lock().unwrap_or_else(PoisonError::into_inner);
+		unsafe {
+			let tail = (*sq.tail).load(Relaxed);
+			if tail.wrapping_sub((*sq.head).load(Acquire)) > sq.mask {
+				self.enter(&sq);
+				if tail.wrapping_sub((*sq.head).load(Acquire)) > sq.mask {
+					return false;
+				}
+			}
+			sq.sqes.add((tail & sq.mask) as usize).write(sqe);
+			(*sq.tail).store(tail.wrapping_add(1), Release);
+			self.enter(&sq);
+		}
+		true
+	}
+
+	/// Submit everything in the submission queue that the kernel didn't consume yet.
+	unsafe fn enter(&self, sq: &Sq) {
+		loop {
+			let n = (*sq.tail)
+				.load(Relaxed)
+				.wrapping_sub((*sq.head).load(Acquire));
+			if n == 0 {
+				return;
+			}
+			let r = libc::syscall(
+				libc::SYS_io_uring_enter,
+				self.fd.as_raw_fd(),
+				n,
+				0,
+				0,
+				null_mut::<libc::c_void>(),
+				0,
+			);
+			// Entries that couldn't be submitted are submitted with the next one.
+			if r < 0 && *libc::__errno_location() != libc::EINTR {
+				return;
+			}
+		}
+	}
+
+	/// The main loop of the completion thread.
+	fn complete(&self) {
+		loop {
+			unsafe {
+				libc::syscall(
+					libc::SYS_io_uring_enter,
+					self.fd.as_raw_fd(),
+					0,
+					1,
+					IORING_ENTER_GETEVENTS,
+					null_mut::<libc::c_void>(),
+					0,
+				);
+				let mut head = (*self.cq_head).load(Relaxed);
+				let tail = (*self.cq_tail).load(Acquire);
+				while head != tail {
+					let cqe = &*self.cqes.add((head & self.cq_mask) as usize);
+					// Cancellations don't carry a request.
+					if cqe.user_data != 0 {
+						let request = Arc::from_raw(cqe.user_data as *const Request);
+						// A wait that was cancelled, or failed, counts as a (spurious) wake up.
+						request.finish(if cqe.res == -libc::EAGAIN {
+							WRONG_VALUE
+						} else {
+							WOKEN
+						});
+					}
+					head = head.wrapping_add(1);
+				}
+				(*self.cq_head).store(head, Release);
+			}
+		}
+	}
+}
+
+impl Drop for Ring {
+	fn drop(&mut self) {
+		unsafe {
+			libc::munmap(self.sqes.0, self.sqes.1);
+			libc::munmap(self.ring.0, self.ring.1);
+		}
+	}
+}
@@ -0,0 +1,101 @@
+use super::pool::Request;
+use crate::sync::Mutex;
+use crate::{Futex, Scope, WrongValueError};
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Arc, PoisonError};
+use std::task::Waker;
+
+/// Wakes a registered [`Waker`] when a futex is woken up.
+///
+/// This is the building block of [`AsyncFutex::wait`][super::AsyncFutex::wait],
+/// for use in hand written futures, or in types that are waited on by both
+/// threads and async tasks: the threads wait on the futex as usual, and the
+/// tasks register their waker here while the futex has the value they are
+/// waiting to change. Any regular [`Futex::wake`] then also wakes the task.
+///
+/// Like an `AtomicWaker`, only the most recently registered waker is woken.
+/// It is woken once, after which a new waker must be registered to be
+/// woken again.
+pub struct FutexWaker<'a, S: Scope> {
+	futex: &'a Futex<S>,
+	request: Mutex<Option<(u32, Arc<Request>)>>,
+}
+
+impl<'a, S: Scope> FutexWaker<'a, S> {
+	/// Create a `FutexWaker` for the given futex, without any registered waker.
+	#[inline]
+	pub const fn new(futex: &'a Futex<S>) -> Self {
+		Self {
+			futex,
+			request: Mutex::new(None),
+		}
+	}
+
+	/// The futex this waker is for.
+	#[inline]
+	pub fn futex(&self) -> &'a Futex<S> {
+		self.futex
+	}
+
+	/// Register `waker` to be woken when the futex is woken up, as long as
+	/// it has the expected value.
+	///
+	/// This replaces any previously registered waker. If the futex does not
+	/// have the expected value, nothing is registered, and an error is
+	/// returned, such that the caller can check its condition again instead
+	/// of waiting. The waker might also be woken spuriously.
+	pub fn register(&self, expected_value: u32, waker: &Waker) -> Result<(), WrongValueError> {
+		let mut request = self.request.lock().unwrap_or_else(PoisonError::into_inner);
+		if let Some((expected, r)) = &*request {
+			// Keep waiting, if the wait is still pending.
+			if *expected == expected_value && r.register(waker).is_none() {
+				return Ok(());
+			}
+			r.cancel();
+			*request = None;
+		}
+		if self.futex.value.load(Relaxed) != expected_value {
+			return Err(WrongValueError::WrongValue);
+		}
+		*request = Some((expected_value, unsafe {
+			super::submit(self.futex, expected_value, waker.clone())
+		}));
+		Ok(())
+	}
+
+	/// Remove the registered waker, if any, without waking it.
+	pub fn unregister(&self) {
+		let request = self
+			.request
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.take();
+		if let Some((_, request)) = request {
+			request.cancel();
+		}
+	}
+
+	/// Check whether the registered wait ended: the futex was woken up (or
+	/// no longer had the expected value) since the last
+	/// [`register`][FutexWaker::register] call.
+	///
+	/// Returns false if no waker is registered.
+	pub fn is_woken(&self) -> bool {
+		let request = self.request.lock().unwrap_or_else(PoisonError::into_inner);
+		matches!(&*request, Some((_, r)) if r.result().is_some())
+	}
+}
+
+impl<S: Scope> Drop for FutexWaker<'_, S> {
+	fn drop(&mut self) {
+		self.unregister();
+	}
+}
+
+impl<S: Scope> std::fmt::Debug for FutexWaker<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("FutexWaker")
+			.field("futex", &self.futex)
+			.finish_non_exhaustive()
+	}
+}
@@ -11,31 +11,94 @@
 //! operations Linux can apply to them.
 //!
 //! Existing [`AtomicU32`]s can be used as futexes through [`AsFutex`]
-//! without changing their type.
+//! without changing their type, and a [`FutexVec`] holds many futexes
+//! next to each other, each on its own cache line.
+//! [`wait_any`] waits on several futexes at once.
+//! A [`DynScope`] picks between [`Private`] and [`Shared`] at runtime.
+//!
+//! The [`sync`] module provides higher level synchronization primitives,
+//! such as a [`Mutex`][sync::Mutex], built on top of these futexes.
+//! The [`parking`] module allows threads to block on arbitrary addresses instead.
+//! The [`shm`] module provides memory to share [`Shared`] futexes between processes.
+//! The [`sched`] module sets the real-time priorities that [`PiFutex`] is meant for.
+//! The [`future`] module allows async code to wait on futexes.
+//!
+//! With the `bytemuck` or `zerocopy` feature, [`Futex`] and [`PiFutex`]
+//! implement the traits of those crates for types that can be cast from
+//! (zeroed) bytes, such that they can be part of structs that are cast
+//! from a mapped byte buffer.
+//!
+//! With the `async-io` feature, [`AsyncFutex`][future::AsyncFutex] can wait
+//! through the reactor of the `async-io` crate, for use with `smol`.
+//!
+//! With the `proptest` feature, the types of the [`op`] module implement
+//! `proptest`'s `Arbitrary`, for property testing code that uses
+//! [`wake_op`][Futex::wake_op].
+//!
+//! With the `testutil` feature, the `testutil` module has helpers for
+//! testing [`Shared`] futexes with forked child processes.
 
+mod cancel;
+mod dyn_scope;
 mod errors;
+mod futex_vec;
+mod options;
+mod padded;
+mod pi;
 mod scope;
 mod sys;
 mod timeout;
+mod waitv;
+mod wake_mask;
 
+pub mod future;
 pub mod op;
+pub mod parking;
+pub mod sched;
+pub mod shm;
+pub mod sync;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
 
 use op::OpAndCmp;
+use options::WaitTimeout;
 use std::marker::PhantomData;
+use std::ptr::null;
 use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
 use std::time::Duration;
-use sys::{Error, FutexCall};
-use timeout::as_timespec;
+use sys::{count, Error, FutexCall};
+use timeout::{as_timespec, deadline};
 
+pub use cancel::CancelToken;
+pub use dyn_scope::{DynFutex, DynScope};
 pub use errors::*;
+pub use futex_vec::FutexVec;
+pub use options::{WaitOptions, WakeOptions};
+pub use padded::CachePadded;
+pub use pi::{current_tid, Acquired, PiLockGuard, PiState, PidNamespace};
 pub use scope::{Private, Scope, Shared};
 pub use timeout::Timeout;
+pub use waitv::{wait_any, wait_any_until, WaitvEntry, WAITV_MAX};
+pub use wake_mask::WakeMask;
 
 /// A Linux-specific fast user-space locking primitive.
 ///
 /// `Futex<Private>` may only be used from the same address space (the same
 /// process) and is faster than a `Futex<Shared>`, which may be used accross
 /// address spaces (processes).
+///
+/// # Layout
+///
+/// A `Futex` is exactly the 32-bit word the kernel operates on: it has the
+/// size and alignment of a `u32` (and of a C `int`), for any scope. This is
+/// checked at compile time. C code that is part of the same application can
+/// wait and wake on the same word, through [`Futex::as_ptr`] and
+/// [`Futex::from_c_word`].
+#[cfg_attr(
+	feature = "zerocopy",
+	derive(zerocopy::FromBytes, zerocopy::IntoBytes, zerocopy::KnownLayout)
+)]
 #[repr(transparent)]
 pub struct Futex<Scope> {
 	pub value: AtomicU32,
@@ -52,38 +115,79 @@ pub struct Futex<Scope> {
 /// See the *Priority-inheritance futexes* section of [the Linux futex man
 /// page](http://man7.org/linux/man-pages/man2/futex.2.html) for details.
 ///
+/// Without contention, the futex can be locked and unlocked entirely in
+/// user space, by atomically changing its value from zero to the thread id
+/// given by [`current_tid`], and back. Only when that fails is it necessary
+/// to call [`lock_pi`][PiFutex::lock_pi] or [`unlock_pi`][PiFutex::unlock_pi].
+///
 /// `PiFutex<Private>` may only be used from the same address space (the same
 /// process) and is faster than a `PiFutex<Shared>`, which may be used accross
-/// address spaces (processes).
+/// address spaces (processes). As the value contains a thread id, all those
+/// processes must be in the same PID namespace. See [`PidNamespace`].
+///
+/// Like a [`Futex`], a `PiFutex` has the size and alignment of a `u32`.
+#[cfg_attr(
+	feature = "zerocopy",
+	derive(zerocopy::FromBytes, zerocopy::IntoBytes, zerocopy::KnownLayout)
+)]
 #[repr(transparent)]
 pub struct PiFutex<Scope> {
 	pub value: AtomicU32,
 	phantom: PhantomData<Scope>,
 }
 
+// The kernel, C code, and `from_ptr` all rely on a futex being exactly one 32-bit word.
+const _: () = {
+	use std::mem::{align_of, size_of};
+	assert!(size_of::<Futex<Private>>() == 4 && align_of::<Futex<Private>>() == 4);
+	assert!(size_of::<Futex<Shared>>() == 4 && align_of::<Futex<Shared>>() == 4);
+	assert!(size_of::<PiFutex<Private>>() == 4 && align_of::<PiFutex<Private>>() == 4);
+	assert!(size_of::<PiFutex<Shared>>() == 4 && align_of::<PiFutex<Shared>>() == 4);
+	assert!(size_of::<std::os::raw::c_int>() == 4);
+};
+
 /// Use any [`AtomicU32`] as [`Futex`] or [`PiFutex`].
 ///
-/// This also allows you to convert between a [`Futex`] and a [`PiFutex`] or
-/// between [`Private`] and [`Shared`] futexes if you ever need that, as they
-/// expose their internal [`AtomicU32`] through `.value`.
+/// The kernel compares raw 32-bit words, and all values and expected
+/// values of this crate are `u32`, so an existing `AtomicU32` can be used
+/// as a futex directly, without any casts.
+///
+/// This also allows you to convert between a [`Futex`] and a [`PiFutex`] if
+/// you ever need that, as they expose their internal [`AtomicU32`] through
+/// `.value`. To convert between [`Private`] and [`Shared`], use
+/// [`Futex::as_shared`] and [`Futex::as_private`].
 pub trait AsFutex<S> {
+	/// Use this atomic as a [`Futex`], without changing its value.
+	#[must_use]
 	fn as_futex(&self) -> &Futex<S>;
+	/// Use this atomic as a [`PiFutex`], without changing its value.
+	#[must_use]
 	fn as_pi_futex(&self) -> &PiFutex<S>;
 }
 
 impl<S> AsFutex<S> for AtomicU32 {
-	#[must_use]
 	#[inline]
 	fn as_futex(&self) -> &Futex<S> {
 		unsafe { std::mem::transmute(self) }
 	}
 	#[inline]
-	#[must_use]
 	fn as_pi_futex(&self) -> &PiFutex<S> {
 		unsafe { std::mem::transmute(self) }
 	}
 }
 
+#[cfg(feature = "bytemuck")]
+unsafe impl<S> bytemuck::Zeroable for Futex<S> {}
+
+#[cfg(feature = "bytemuck")]
+unsafe impl<S> bytemuck::Zeroable for PiFutex<S> {}
+
+#[cfg(feature = "bytemuck")]
+unsafe impl<S> bytemuck::TransparentWrapper<AtomicU32> for Futex<S> {}
+
+#[cfg(feature = "bytemuck")]
+unsafe impl<S> bytemuck::TransparentWrapper<AtomicU32> for PiFutex<S> {}
+
 impl<S> Futex<S> {
 	/// Create a new [`Futex`] with an initial value.
 	#[inline]
@@ -93,6 +197,84 @@ impl<S> Futex<S> {
 			phantom: PhantomData,
 		}
 	}
+
+	/// Use the 32-bit integer at `ptr` as a [`Futex`].
+	///
+	/// This is useful for futexes at arbitrary addresses, such as in a mapped
+	/// file or in memory owned by C code. Use [`Futex::try_from_ptr`] to
+	/// check the alignment first.
+	///
+	/// # Safety
+	///
+	/// `ptr` must be aligned to 4 bytes, and valid for reads and writes for
+	/// all of `'a`. During that time, it must only be accessed through
+	/// atomic operations, not through non-atomic reads or writes.
+	#[inline]
+	pub unsafe fn from_ptr<'a>(ptr: *mut u32) -> &'a Self {
+		&*ptr.cast::<Self>()
+	}
+
+	/// Use the 32-bit integer at `ptr` as a [`Futex`], if it is aligned to 4 bytes.
+	///
+	/// # Safety
+	///
+	/// Other than the alignment, the same requirements as for
+	/// [`Futex::from_ptr`] apply.
+	#[inline]
+	pub unsafe fn try_from_ptr<'a>(ptr: *mut u32) -> Result<&'a Self, AlignmentError> {
+		if ptr as usize & 3 != 0 {
+			return Err(AlignmentError::Misaligned);
+		}
+		Ok(Self::from_ptr(ptr))
+	}
+
+	/// Use a C `int` at `ptr` as a [`Futex`].
+	///
+	/// This is for a futex word that is defined by C code, such as an `int`
+	/// or `_Atomic int` field of a struct shared with Rust.
+	///
+	/// # Safety
+	///
+	/// The same requirements as for [`Futex::from_ptr`] apply. The C code
+	/// must only access the word through atomic operations.
+	#[inline]
+	pub unsafe fn from_c_word<'a>(ptr: *mut i32) -> &'a Self {
+		Self::from_ptr(ptr.cast())
+	}
+
+	/// A pointer to the futex word, as a C `int *`.
+	///
+	/// C code can use it to wait and wake on the same futex, for example with
+	/// `syscall(SYS_futex, ...)`, as long as it only accesses the word through
+	/// atomic operations, and uses the same [`Scope`].
+	#[inline]
+	pub fn as_ptr(&self) -> *mut i32 {
+		self.value.as_ptr().cast()
+	}
+
+	/// Use a mutable reference to a `u32` as a [`Futex`].
+	///
+	/// No atomic operations or system calls are necessary to access the value
+	/// through the returned reference, since it is exclusive.
+	#[inline]
+	pub fn from_mut(value: &mut u32) -> &mut Self {
+		unsafe { &mut *(value as *mut u32).cast::<Self>() }
+	}
+
+	/// Get a mutable reference to the value of the futex.
+	///
+	/// This requires exclusive access, so no other threads can be waiting on
+	/// the futex or accessing it at the same time.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut u32 {
+		self.value.get_mut()
+	}
+
+	/// Consume the futex, returning its value.
+	#[inline]
+	pub fn into_inner(self) -> u32 {
+		self.value.into_inner()
+	}
 }
 
 impl<S> PiFutex<S> {
@@ -105,6 +287,84 @@ impl<S> PiFutex<S> {
 		}
 	}
 
+	/// Use the 32-bit integer at `ptr` as a [`PiFutex`].
+	///
+	/// This is useful for futexes at arbitrary addresses, such as in a mapped
+	/// file or in memory owned by C code. Use [`PiFutex::try_from_ptr`] to
+	/// check the alignment first.
+	///
+	/// # Safety
+	///
+	/// `ptr` must be aligned to 4 bytes, and valid for reads and writes for
+	/// all of `'a`. During that time, it must only be accessed through
+	/// atomic operations, not through non-atomic reads or writes.
+	#[inline]
+	pub unsafe fn from_ptr<'a>(ptr: *mut u32) -> &'a Self {
+		&*ptr.cast::<Self>()
+	}
+
+	/// Use the 32-bit integer at `ptr` as a [`PiFutex`], if it is aligned to 4 bytes.
+	///
+	/// # Safety
+	///
+	/// Other than the alignment, the same requirements as for
+	/// [`PiFutex::from_ptr`] apply.
+	#[inline]
+	pub unsafe fn try_from_ptr<'a>(ptr: *mut u32) -> Result<&'a Self, AlignmentError> {
+		if ptr as usize & 3 != 0 {
+			return Err(AlignmentError::Misaligned);
+		}
+		Ok(Self::from_ptr(ptr))
+	}
+
+	/// Use a C `int` at `ptr` as a [`PiFutex`].
+	///
+	/// This is for a futex word that is defined by C code, such as an `int`
+	/// or `_Atomic int` field of a struct shared with Rust.
+	///
+	/// # Safety
+	///
+	/// The same requirements as for [`PiFutex::from_ptr`] apply. The C code
+	/// must only access the word through atomic operations.
+	#[inline]
+	pub unsafe fn from_c_word<'a>(ptr: *mut i32) -> &'a Self {
+		Self::from_ptr(ptr.cast())
+	}
+
+	/// A pointer to the futex word, as a C `int *`.
+	///
+	/// C code can use it to wait and wake on the same futex, for example with
+	/// `syscall(SYS_futex, ...)`, as long as it only accesses the word through
+	/// atomic operations, and uses the same [`Scope`].
+	#[inline]
+	pub fn as_ptr(&self) -> *mut i32 {
+		self.value.as_ptr().cast()
+	}
+
+	/// Use a mutable reference to a `u32` as a [`PiFutex`].
+	///
+	/// No atomic operations or system calls are necessary to access the value
+	/// through the returned reference, since it is exclusive.
+	#[inline]
+	pub fn from_mut(value: &mut u32) -> &mut Self {
+		unsafe { &mut *(value as *mut u32).cast::<Self>() }
+	}
+
+	/// Get a mutable reference to the value of the futex.
+	///
+	/// This requires exclusive access, so no other threads can be waiting on
+	/// the futex or accessing it at the same time.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut u32 {
+		self.value.get_mut()
+	}
+
+	/// Consume the futex, returning its value.
+	#[inline]
+	pub fn into_inner(self) -> u32 {
+		self.value.into_inner()
+	}
+
 	/// The `FUTEX_WAITERS` bit that indicates there are threads waiting.
 	pub const WAITERS: u32 = 0x8000_0000;
 
@@ -113,6 +373,25 @@ impl<S> PiFutex<S> {
 
 	/// The bits that are used for storing the thread id (`FUTEX_TID_MASK`).
 	pub const TID_MASK: u32 = 0x3fffffff;
+
+	/// Decode the current value of the futex into its owner and flags.
+	///
+	/// This is only a snapshot: other threads might lock or unlock the futex
+	/// right after this returns.
+	#[inline]
+	pub fn state(&self) -> PiState {
+		PiState::from_raw(self.value.load(Relaxed))
+	}
+
+	/// Check the value of a futex the calling thread just locked.
+	#[inline]
+	fn acquired(&self) -> Acquired {
+		if self.value.load(Relaxed) & Self::OWNER_DIED != 0 {
+			Acquired::OwnerDied
+		} else {
+			Acquired::Clean
+		}
+	}
 }
 
 impl<S> Default for Futex<S> {
@@ -127,6 +406,56 @@ impl<S> Default for PiFutex<S> {
 	}
 }
 
+impl Futex<Private> {
+	/// Use this futex as a [`Shared`] futex.
+	///
+	/// The kernel treats private and shared operations on the same word as
+	/// operations on different futexes: a shared `wake` does not wake up a
+	/// private waiter, and the other way around. All waiters and wakers of
+	/// the word must agree on its scope.
+	#[inline]
+	pub fn as_shared(&self) -> &Futex<Shared> {
+		self.value.as_futex()
+	}
+}
+
+impl Futex<Shared> {
+	/// Use this futex as a [`Private`] futex.
+	///
+	/// This is only useful if the futex is not used by other processes. Like
+	/// with [`as_shared`][Futex::as_shared], all waiters and wakers of the
+	/// word must agree on its scope.
+	#[inline]
+	pub fn as_private(&self) -> &Futex<Private> {
+		self.value.as_futex()
+	}
+}
+
+impl PiFutex<Private> {
+	/// Use this futex as a [`Shared`] futex.
+	///
+	/// The kernel treats private and shared operations on the same word as
+	/// operations on different futexes: a shared `wake` does not wake up a
+	/// private waiter, and the other way around. All waiters and wakers of
+	/// the word must agree on its scope.
+	#[inline]
+	pub fn as_shared(&self) -> &PiFutex<Shared> {
+		self.value.as_pi_futex()
+	}
+}
+
+impl PiFutex<Shared> {
+	/// Use this futex as a [`Private`] futex.
+	///
+	/// This is only useful if the futex is not used by other processes. Like
+	/// with [`as_shared`][PiFutex::as_shared], all waiters and wakers of the
+	/// word must agree on its scope.
+	#[inline]
+	pub fn as_private(&self) -> &PiFutex<Private> {
+		self.value.as_pi_futex()
+	}
+}
+
 impl<S: Scope> Futex<S> {
 	/// Wait until this futex is awoken by a `wake` call.
 	///
@@ -149,13 +478,28 @@ impl<S: Scope> Futex<S> {
 		}
 	}
 
+	/// Wait until this futex is awoken by a `wake` call, retrying when interrupted by a signal.
+	///
+	/// The thread will only be sent to sleep if the futex's value matches the
+	/// expected value. Otherwise, it returns directly with [`WrongValueError::WrongValue`].
+	#[inline]
+	pub fn wait_uninterruptible(&self, expected_value: u32) -> Result<(), WrongValueError> {
+		loop {
+			match self.wait(expected_value) {
+				Err(WaitError::Interrupted) => continue,
+				Err(WaitError::WrongValue) => return Err(WrongValueError::WrongValue),
+				Ok(()) => return Ok(()),
+			}
+		}
+	}
+
 	/// Wait until this futex is awoken by a `wake` call, or until the timeout expires.
 	///
 	/// The thread will only be sent to sleep if the futex's value matches the
 	/// expected value. Otherwise, it returns directly with [`TimedWaitError::WrongValue`].
 	///
 	/// If you want an absolute point in time as timeout, use
-	/// [`wait_bitset_until`][Futex::wait_bitset_until] instead, using a bitset of `!0`.
+	/// [`wait_bitset_until`][Futex::wait_bitset_until] instead, using [`WakeMask::ALL`].
 	#[inline]
 	pub fn wait_for(&self, expected_value: u32, timeout: Duration) -> Result<(), TimedWaitError> {
 		let timeout = as_timespec(timeout);
@@ -176,41 +520,231 @@ impl<S: Scope> Futex<S> {
 		}
 	}
 
+	/// Wait until this futex is awoken by a `wake` call, or until the timeout expires,
+	/// retrying when interrupted by a signal.
+	///
+	/// The timeout is converted to a deadline before waiting, such that
+	/// retrying after a signal does not extend the total waiting time.
+	///
+	/// The thread will only be sent to sleep if the futex's value matches the
+	/// expected value. Otherwise, it returns directly with [`TimedWrongValueError::WrongValue`].
+	#[inline]
+	pub fn wait_for_uninterruptible(
+		&self,
+		expected_value: u32,
+		timeout: Duration,
+	) -> Result<(), TimedWrongValueError> {
+		match deadline(timeout) {
+			Some(deadline) => {
+				self.wait_bitset_until_uninterruptible(expected_value, WakeMask::ALL, deadline)
+			}
+			None => Ok(self.wait_uninterruptible(expected_value)?),
+		}
+	}
+
+	/// Wait for as long as the condition holds for the value of this futex.
+	///
+	/// This loads the value (using [`Acquire`] ordering), and if `condition`
+	/// returns true for it, waits with that value as the expected value.
+	/// Spurious wake-ups, signals, and the value changing right before
+	/// going to sleep are all handled by checking the condition again.
+	///
+	/// Returns the value for which the condition no longer held.
+	#[inline]
+	pub fn wait_while(&self, mut condition: impl FnMut(u32) -> bool) -> u32 {
+		loop {
+			let value = self.value.load(Acquire);
+			if !condition(value) {
+				return value;
+			}
+			let _ = self.wait(value);
+		}
+	}
+
+	/// Wait for as long as the condition holds for the value of this futex, or until the timeout expires.
+	///
+	/// See [`wait_while`][Futex::wait_while].
+	#[inline]
+	pub fn wait_while_for(
+		&self,
+		timeout: Duration,
+		condition: impl FnMut(u32) -> bool,
+	) -> Result<u32, TimedOutError> {
+		match deadline(timeout) {
+			Some(deadline) => self.wait_while_until(deadline, condition),
+			None => Ok(self.wait_while(condition)),
+		}
+	}
+
+	/// Wait for as long as the condition holds for the value of this futex, or until the deadline.
+	///
+	/// See [`wait_while`][Futex::wait_while].
+	#[inline]
+	pub fn wait_while_until(
+		&self,
+		timeout: impl Timeout + Copy,
+		mut condition: impl FnMut(u32) -> bool,
+	) -> Result<u32, TimedOutError> {
+		loop {
+			let value = self.value.load(Acquire);
+			if !condition(value) {
+				return Ok(value);
+			}
+			if let Err(TimedWaitError::TimedOut) =
+				self.wait_bitset_until(value, WakeMask::ALL, timeout)
+			{
+				return Err(TimedOutError::TimedOut);
+			}
+		}
+	}
+
 	/// Wake up `n` waiters.
 	///
+	/// The kernel wakes up at most `i32::MAX` waiters at once, which is as
+	/// good as all of them. Larger counts, like `u32::MAX`, are capped to that.
+	///
 	/// Returns the number of waiters that were woken up.
 	#[inline]
-	pub fn wake(&self, n: i32) -> i32 {
+	pub fn wake(&self, n: u32) -> usize {
+		match self.try_wake(n) {
+			Err(FaultError::Fault) => Error(libc::EFAULT).panic("FUTEX_WAKE"),
+			Ok(v) => v,
+		}
+	}
+
+	/// Wake up one waiter.
+	///
+	/// Returns whether a waiter was woken up.
+	#[inline]
+	pub fn wake_one(&self) -> bool {
+		self.wake(1) > 0
+	}
+
+	/// Wake up all waiters.
+	///
+	/// Returns the number of waiters that were woken up.
+	#[inline]
+	pub fn wake_all(&self) -> usize {
+		self.wake(u32::MAX)
+	}
+
+	/// Wake up `n` waiters, without panicking if the futex is no longer mapped.
+	///
+	/// This is useful for a [`Futex<Shared>`] in a mapping that another
+	/// process might have already unmapped.
+	///
+	/// Returns the number of waiters that were woken up.
+	#[inline]
+	pub fn try_wake(&self, n: u32) -> Result<usize, FaultError> {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_WAKE + S::futex_flag())
 				.uaddr(&self.value)
-				.val(n as u32)
+				.val(count(n))
 				.call()
 		};
 		match r {
+			Err(Error(libc::EFAULT)) => Err(FaultError::Fault),
 			Err(e) => e.panic("FUTEX_WAKE"),
-			Ok(v) => v,
+			Ok(v) => Ok(v as usize),
+		}
+	}
+
+	/// Store a new value, and then wake up `n` waiters.
+	///
+	/// The value is stored using [`Release`] ordering, such that woken
+	/// waiters loading the value using [`Acquire`] ordering also see
+	/// everything that happened before this call.
+	///
+	/// Returns the number of waiters that were woken up.
+	#[inline]
+	pub fn store_and_wake(&self, value: u32, n: u32) -> usize {
+		self.value.store(value, Release);
+		self.wake(n)
+	}
+
+	/// Replace the value, and then wake up `n` waiters.
+	///
+	/// The value is swapped using [`AcqRel`] ordering.
+	/// See [`store_and_wake`][Futex::store_and_wake].
+	///
+	/// Returns the previous value.
+	#[inline]
+	pub fn swap_and_wake(&self, value: u32, n: u32) -> u32 {
+		let previous = self.value.swap(value, AcqRel);
+		self.wake(n);
+		previous
+	}
+
+	/// Replace the value if it matches `current`, and if so, wake up `n` waiters.
+	///
+	/// The value is exchanged using [`AcqRel`] ordering, or loaded using
+	/// [`Acquire`] ordering if it didn't match.
+	/// Nobody is woken up if the value did not match.
+	/// See [`store_and_wake`][Futex::store_and_wake].
+	///
+	/// Returns the previous value, like [`AtomicU32::compare_exchange`].
+	#[inline]
+	pub fn compare_exchange_and_wake(&self, current: u32, new: u32, n: u32) -> Result<u32, u32> {
+		let previous = self.value.compare_exchange(current, new, AcqRel, Acquire)?;
+		self.wake(n);
+		Ok(previous)
+	}
+
+	/// Add to the value, and then wake up `n` waiters if `wake_if` returns true for the previous value.
+	///
+	/// This is the pattern of a semaphore or reference counter, that only
+	/// needs to wake up waiters when the value crosses some threshold, such as
+	/// `wake_if = |old| old == 0` when releasing the first permit.
+	///
+	/// The value is modified using [`AcqRel`] ordering, wrapping around on overflow.
+	/// See [`store_and_wake`][Futex::store_and_wake].
+	///
+	/// Returns the previous value.
+	#[inline]
+	pub fn fetch_add_and_wake(&self, value: u32, n: u32, wake_if: impl FnOnce(u32) -> bool) -> u32 {
+		let previous = self.value.fetch_add(value, AcqRel);
+		if wake_if(previous) {
+			self.wake(n);
 		}
+		previous
+	}
+
+	/// Subtract from the value, and then wake up `n` waiters if `wake_if` returns true for the previous value.
+	///
+	/// For example, the last thread to leave a group can wake up the threads
+	/// waiting for it to be empty, with `wake_if = |old| old == 1`.
+	///
+	/// The value is modified using [`AcqRel`] ordering, wrapping around on overflow.
+	/// See [`fetch_add_and_wake`][Futex::fetch_add_and_wake].
+	///
+	/// Returns the previous value.
+	#[inline]
+	pub fn fetch_sub_and_wake(&self, value: u32, n: u32, wake_if: impl FnOnce(u32) -> bool) -> u32 {
+		let previous = self.value.fetch_sub(value, AcqRel);
+		if wake_if(previous) {
+			self.wake(n);
+		}
+		previous
 	}
 
 	/// Wake up `n_wake` waiters, and requeue up to `n_requeue` waiters to another futex.
 	///
 	/// Returns the number of waiters that were woken up.
 	#[inline]
-	pub fn requeue(&self, n_wake: i32, to: &Futex<S>, n_requeue: i32) -> i32 {
+	pub fn requeue(&self, n_wake: u32, to: &Futex<S>, n_requeue: u32) -> usize {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_REQUEUE + S::futex_flag())
 				.uaddr(&self.value)
 				.uaddr2(&to.value)
-				.val(n_wake as u32)
-				.val2(n_requeue as u32)
+				.val(count(n_wake))
+				.val2(count(n_requeue))
 				.call()
 		};
 		match r {
 			Err(e) => e.panic("FUTEX_REQUEUE"),
-			Ok(v) => v,
+			Ok(v) => v as usize,
 		}
 	}
 
@@ -224,27 +758,54 @@ impl<S: Scope> Futex<S> {
 	pub fn cmp_requeue(
 		&self,
 		expected_value: u32,
-		n_wake: i32,
+		n_wake: u32,
 		to: &Futex<S>,
-		n_requeue: i32,
-	) -> Result<i32, WrongValueError> {
+		n_requeue: u32,
+	) -> Result<usize, WrongValueError> {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_CMP_REQUEUE + S::futex_flag())
 				.uaddr(&self.value)
 				.uaddr2(&to.value)
-				.val(n_wake as u32)
-				.val2(n_requeue as u32)
+				.val(count(n_wake))
+				.val2(count(n_requeue))
 				.val3(expected_value)
 				.call()
 		};
 		match r {
 			Err(Error(libc::EAGAIN)) => Err(WrongValueError::WrongValue),
 			Err(e) => e.panic("FUTEX_CMP_REQUEUE"),
-			Ok(v) => Ok(v),
+			Ok(v) => Ok(v as usize),
 		}
 	}
 
+	/// Wake up one waiter, and requeue all other waiters to another futex.
+	///
+	/// This is what a condition variable needs to notify all its waiters,
+	/// without waking them all up just to have them block on the mutex again.
+	///
+	/// Returns whether a waiter was woken up.
+	#[inline]
+	pub fn requeue_all(&self, to: &Futex<S>) -> bool {
+		self.requeue(1, to, u32::MAX) > 0
+	}
+
+	/// Wake up one waiter, and requeue all other waiters to another futex.
+	///
+	/// The operation will only execute if the futex's value matches the
+	/// expected value. Otherwise, it returns directly with a [`WrongValueError`].
+	/// See [`requeue_all`][Futex::requeue_all].
+	///
+	/// Returns the total number of waiters that were woken up or requeued to the other futex.
+	#[inline]
+	pub fn cmp_requeue_all(
+		&self,
+		expected_value: u32,
+		to: &Futex<S>,
+	) -> Result<usize, WrongValueError> {
+		self.cmp_requeue(expected_value, 1, to, u32::MAX)
+	}
+
 	/// Wait until this futex is awoken by a `wake` call matching a bitset.
 	///
 	/// - Calls to [`wake`][Futex::wake] will match any bitset.
@@ -253,13 +814,13 @@ impl<S: Scope> Futex<S> {
 	/// The thread will only be sent to sleep if the futex's value matches the
 	/// expected value. Otherwise, it returns directly with [`WaitError::WrongValue`].
 	#[inline]
-	pub fn wait_bitset(&self, expected_value: u32, bitset: u32) -> Result<(), WaitError> {
+	pub fn wait_bitset(&self, expected_value: u32, bitset: WakeMask) -> Result<(), WaitError> {
 		let r = unsafe {
 			FutexCall::new()
 				.uaddr(&self.value)
 				.futex_op(libc::FUTEX_WAIT_BITSET + S::futex_flag())
 				.val(expected_value)
-				.val3(bitset)
+				.val3(bitset.bits())
 				.call()
 		};
 		match r {
@@ -270,6 +831,24 @@ impl<S: Scope> Futex<S> {
 		}
 	}
 
+	/// Wait until this futex is awoken by a `wake` call matching a bitset, retrying when interrupted by a signal.
+	///
+	/// See [`wait_bitset`][Futex::wait_bitset].
+	#[inline]
+	pub fn wait_bitset_uninterruptible(
+		&self,
+		expected_value: u32,
+		bitset: WakeMask,
+	) -> Result<(), WrongValueError> {
+		loop {
+			match self.wait_bitset(expected_value, bitset) {
+				Err(WaitError::Interrupted) => continue,
+				Err(WaitError::WrongValue) => return Err(WrongValueError::WrongValue),
+				Ok(()) => return Ok(()),
+			}
+		}
+	}
+
 	/// Wait until this futex is awoken by a `wake` call matching a bitset, or until the timeout expires.
 	///
 	/// - Calls to [`wake`][Futex::wake] will match any bitset.
@@ -281,7 +860,7 @@ impl<S: Scope> Futex<S> {
 	pub fn wait_bitset_until(
 		&self,
 		expected_value: u32,
-		bitset: u32,
+		bitset: WakeMask,
 		timeout: impl Timeout,
 	) -> Result<(), TimedWaitError> {
 		let timeout = timeout.as_timespec();
@@ -290,7 +869,7 @@ impl<S: Scope> Futex<S> {
 				.uaddr(&self.value)
 				.futex_op(libc::FUTEX_WAIT_BITSET + timeout.0 + S::futex_flag())
 				.val(expected_value)
-				.val3(bitset)
+				.val3(bitset.bits())
 				.timeout(&timeout.1)
 				.call()
 		};
@@ -303,6 +882,73 @@ impl<S: Scope> Futex<S> {
 		}
 	}
 
+	/// Wait until this futex is awoken by a `wake` call matching a bitset, or until the timeout expires,
+	/// retrying when interrupted by a signal.
+	///
+	/// See [`wait_bitset_until`][Futex::wait_bitset_until].
+	#[inline]
+	pub fn wait_bitset_until_uninterruptible(
+		&self,
+		expected_value: u32,
+		bitset: WakeMask,
+		timeout: impl Timeout + Copy,
+	) -> Result<(), TimedWrongValueError> {
+		loop {
+			match self.wait_bitset_until(expected_value, bitset, timeout) {
+				Err(TimedWaitError::Interrupted) => continue,
+				Err(TimedWaitError::WrongValue) => return Err(TimedWrongValueError::WrongValue),
+				Err(TimedWaitError::TimedOut) => return Err(TimedWrongValueError::TimedOut),
+				Ok(()) => return Ok(()),
+			}
+		}
+	}
+
+	/// Wait until this futex is awoken by a `wake` call, with the given [options][WaitOptions].
+	///
+	/// This can express all combinations of [`wait`][Futex::wait],
+	/// [`wait_for`][Futex::wait_for], [`wait_bitset`][Futex::wait_bitset] and
+	/// [`wait_bitset_until`][Futex::wait_bitset_until].
+	///
+	/// The thread will only be sent to sleep if the futex's value matches the
+	/// expected value. Otherwise, it returns directly with [`TimedWaitError::WrongValue`].
+	/// If no timeout or deadline was set, [`TimedWaitError::TimedOut`] is never returned.
+	#[inline]
+	pub fn wait_with(&self, options: WaitOptions) -> Result<(), TimedWaitError> {
+		let (op, timeout) = match options.timeout {
+			None => (libc::FUTEX_WAIT_BITSET, None),
+			Some(WaitTimeout::Relative(d)) if options.bitset == WakeMask::ALL => {
+				(libc::FUTEX_WAIT, Some(as_timespec(d)))
+			}
+			Some(WaitTimeout::Relative(d)) => match deadline(d) {
+				Some(deadline) => {
+					let (clock, timespec) = deadline.as_timespec();
+					(libc::FUTEX_WAIT_BITSET + clock, Some(timespec))
+				}
+				None => (libc::FUTEX_WAIT_BITSET, None),
+			},
+			Some(WaitTimeout::Absolute(clock, timespec)) => {
+				(libc::FUTEX_WAIT_BITSET + clock, Some(timespec))
+			}
+		};
+		let r = unsafe {
+			FutexCall::new()
+				.uaddr(&self.value)
+				.futex_op(op + S::futex_flag())
+				.val(options.expected_value)
+				.val3(options.bitset.bits())
+				.timeout(timeout.as_ref().map_or(null(), |t| t))
+				.call()
+		};
+		match r {
+			Err(Error(libc::EAGAIN)) => Err(TimedWaitError::WrongValue),
+			Err(Error(libc::EINTR)) => Err(TimedWaitError::Interrupted),
+			Err(Error(libc::ETIMEDOUT)) => Err(TimedWaitError::TimedOut),
+			Err(e) if op == libc::FUTEX_WAIT => e.panic("FUTEX_WAIT"),
+			Err(e) => e.panic("FUTEX_WAIT_BITSET"),
+			Ok(_) => Ok(()),
+		}
+	}
+
 	/// Wake up `n` waiters matching a bitset.
 	///
 	/// - Waiters waiting using [`wait`][Futex::wait] are always woken up,
@@ -312,18 +958,55 @@ impl<S: Scope> Futex<S> {
 	///
 	/// Returns the number of waiters that were woken up.
 	#[inline]
-	pub fn wake_bitset(&self, n: i32, bitset: u32) -> i32 {
+	pub fn wake_bitset(&self, n: u32, bitset: WakeMask) -> usize {
+		match self.try_wake_bitset(n, bitset) {
+			Err(FaultError::Fault) => Error(libc::EFAULT).panic("FUTEX_WAKE_BITSET"),
+			Ok(v) => v,
+		}
+	}
+
+	/// Wake up `n` waiters matching a bitset, without panicking if the futex is no longer mapped.
+	///
+	/// See [`wake_bitset`][Futex::wake_bitset] and [`try_wake`][Futex::try_wake].
+	#[inline]
+	pub fn try_wake_bitset(&self, n: u32, bitset: WakeMask) -> Result<usize, FaultError> {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_WAKE_BITSET + S::futex_flag())
 				.uaddr(&self.value)
-				.val(n as u32)
-				.val3(bitset)
+				.val(count(n))
+				.val3(bitset.bits())
 				.call()
 		};
 		match r {
+			Err(Error(libc::EFAULT)) => Err(FaultError::Fault),
 			Err(e) => e.panic("FUTEX_WAKE_BITSET"),
-			Ok(v) => v,
+			Ok(v) => Ok(v as usize),
+		}
+	}
+
+	/// Wake up waiters of this futex, with the given [options][WakeOptions].
+	///
+	/// This can express all of [`wake`][Futex::wake],
+	/// [`wake_bitset`][Futex::wake_bitset] and [`wake_op`][Futex::wake_op].
+	///
+	/// Returns the total number of waiters that were woken up, on either futex.
+	///
+	/// # Panics
+	///
+	/// Panics if both a bitset and an op are set, since the kernel has no operation to do both.
+	#[inline]
+	pub fn wake_with(&self, options: WakeOptions<S>) -> usize {
+		match options.op {
+			None if options.bitset == WakeMask::ALL => self.wake(options.count),
+			None => self.wake_bitset(options.count, options.bitset),
+			Some((second, op, n2)) => {
+				assert!(
+					options.bitset == WakeMask::ALL,
+					"cannot wake with both a bitset and an op"
+				);
+				self.wake_op(options.count, second, op, n2)
+			}
 		}
 	}
 
@@ -335,20 +1018,20 @@ impl<S: Scope> Futex<S> {
 	///
 	/// Returns the total number of waiters that were woken up on either futex.
 	#[inline]
-	pub fn wake_op(&self, n: i32, second: &Futex<S>, op: OpAndCmp, n2: i32) -> i32 {
+	pub fn wake_op(&self, n: u32, second: &Futex<S>, op: OpAndCmp, n2: u32) -> usize {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_WAKE_OP + S::futex_flag())
 				.uaddr(&self.value)
 				.uaddr2(&second.value)
-				.val(n as u32)
-				.val2(n2 as u32)
+				.val(count(n))
+				.val2(count(n2))
 				.val3(op.raw_bits())
 				.call()
 		};
 		match r {
 			Err(e) => e.panic("FUTEX_WAKE_OP"),
-			Ok(v) => v,
+			Ok(v) => v as usize,
 		}
 	}
 
@@ -364,24 +1047,24 @@ impl<S: Scope> Futex<S> {
 	#[inline]
 	pub fn cmp_requeue_pi(
 		&self,
-		expected_value: i32,
+		expected_value: u32,
 		to: &PiFutex<S>,
-		n_requeue: i32,
-	) -> Result<i32, TryAgainError> {
+		n_requeue: u32,
+	) -> Result<usize, TryAgainError> {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_CMP_REQUEUE_PI + S::futex_flag())
 				.uaddr(&self.value)
 				.uaddr2(&to.value)
 				.val(1)
-				.val2(n_requeue as u32)
-				.val3(expected_value as u32)
+				.val2(count(n_requeue))
+				.val3(expected_value)
 				.call()
 		};
 		match r {
 			Err(Error(libc::EAGAIN)) => Err(TryAgainError::TryAgain),
 			Err(e) => e.panic("FUTEX_CMP_REQUEUE_PI"),
-			Ok(v) => Ok(v),
+			Ok(v) => Ok(v as usize),
 		}
 	}
 
@@ -392,6 +1075,9 @@ impl<S: Scope> Futex<S> {
 	///
 	/// A call to [`wake`][Futex::wake] (or [`wake_bitset`][Futex::wake_bitset]) will
 	/// wake this thread without requeueing. This results in an [`RequeuePiError::TryAgain`].
+	///
+	/// A signal can interrupt the wait before the thread is requeued. This
+	/// results in an [`RequeuePiError::Interrupted`].
 	#[inline]
 	pub fn wait_requeue_pi(
 		&self,
@@ -408,6 +1094,7 @@ impl<S: Scope> Futex<S> {
 		};
 		match r {
 			Err(Error(libc::EAGAIN)) => Err(RequeuePiError::TryAgain),
+			Err(Error(libc::EINTR)) => Err(RequeuePiError::Interrupted),
 			Err(e) => e.panic("FUTEX_WAIT_REQUEUE_PI"),
 			Ok(_) => Ok(()),
 		}
@@ -420,6 +1107,9 @@ impl<S: Scope> Futex<S> {
 	///
 	/// A call to [`wake`][Futex::wake] (or [`wake_bitset`][Futex::wake_bitset]) will
 	/// wake this thread without requeueing. This results in an [`TimedRequeuePiError::TryAgain`].
+	///
+	/// A signal can interrupt the wait before the thread is requeued. This
+	/// results in an [`TimedRequeuePiError::Interrupted`].
 	#[inline]
 	pub fn wait_requeue_pi_until(
 		&self,
@@ -439,6 +1129,7 @@ impl<S: Scope> Futex<S> {
 		};
 		match r {
 			Err(Error(libc::EAGAIN)) => Err(TimedRequeuePiError::TryAgain),
+			Err(Error(libc::EINTR)) => Err(TimedRequeuePiError::Interrupted),
 			Err(Error(libc::ETIMEDOUT)) => Err(TimedRequeuePiError::TimedOut),
 			Err(e) => e.panic("FUTEX_WAIT_REQUEUE_PI"),
 			Ok(_) => Ok(()),
@@ -448,8 +1139,16 @@ impl<S: Scope> Futex<S> {
 
 impl<S: Scope> PiFutex<S> {
 	/// See `FUTEX_LOCK_PI` in the [Linux futex man page](http://man7.org/linux/man-pages/man2/futex.2.html).
+	///
+	/// Returns [`Acquired::OwnerDied`] if the previous owner died while holding the lock.
+	///
+	/// # Panics
+	///
+	/// Panics with `ESRCH` if the futex holds the thread id of a thread that
+	/// no longer exists, without [`OWNER_DIED`][Self::OWNER_DIED] set. That
+	/// happens when the owner exits without the futex being on its robust list.
 	#[inline]
-	pub fn lock_pi(&self) -> Result<(), TryAgainError> {
+	pub fn lock_pi(&self) -> Result<Acquired, LockError> {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_LOCK_PI + S::futex_flag())
@@ -457,15 +1156,49 @@ impl<S: Scope> PiFutex<S> {
 				.call()
 		};
 		match r {
-			Err(Error(libc::EAGAIN)) => Err(TryAgainError::TryAgain),
+			Err(Error(libc::EAGAIN)) => Err(LockError::TryAgain),
+			Err(Error(libc::EDEADLK)) => Err(LockError::Deadlock),
 			Err(e) => e.panic("FUTEX_LOCK_PI"),
-			Ok(_) => Ok(()),
+			Ok(_) => Ok(self.acquired()),
 		}
 	}
 
+	/// Lock the futex in user space if it becomes unlocked within `spins`
+	/// attempts, and otherwise fall back to [`lock_pi`][PiFutex::lock_pi].
+	///
+	/// Each attempt checks whether the futex is unlocked, and if so,
+	/// atomically stores the thread id of the calling thread in it, without
+	/// entering the kernel. For short critical sections, this avoids the
+	/// latency of `FUTEX_LOCK_PI` when the owner is about to unlock. Spinning
+	/// stops early once other threads are waiting in the kernel.
+	///
+	/// Returns [`Acquired::OwnerDied`] if the previous owner died while holding the lock.
+	pub fn lock_pi_spin(&self, spins: u32) -> Result<Acquired, LockError> {
+		let tid = pi::current_tid();
+		for _ in 0..spins {
+			let v = self.value.load(Relaxed);
+			if v == 0 {
+				if self
+					.value
+					.compare_exchange_weak(0, tid, Acquire, Relaxed)
+					.is_ok()
+				{
+					return Ok(Acquired::Clean);
+				}
+			} else if v & Self::TID_MASK == tid || v & (Self::WAITERS | Self::OWNER_DIED) != 0 {
+				// Leave deadlocks, waiters, and dead owners to the kernel.
+				break;
+			}
+			std::hint::spin_loop();
+		}
+		self.lock_pi()
+	}
+
 	/// See `FUTEX_LOCK_PI` in the [Linux futex man page](http://man7.org/linux/man-pages/man2/futex.2.html).
+	///
+	/// Returns [`Acquired::OwnerDied`] if the previous owner died while holding the lock.
 	#[inline]
-	pub fn lock_pi_until(&self, timeout: impl Timeout) -> Result<(), TimedLockError> {
+	pub fn lock_pi_until(&self, timeout: impl Timeout) -> Result<Acquired, TimedLockError> {
 		let (clock, timespec) = timeout.as_timespec();
 		let op = if clock == libc::FUTEX_CLOCK_REALTIME {
 			libc::FUTEX_LOCK_PI
@@ -483,15 +1216,18 @@ impl<S: Scope> PiFutex<S> {
 		match r {
 			Err(Error(libc::EAGAIN)) => Err(TimedLockError::TryAgain),
 			Err(Error(libc::ETIMEDOUT)) => Err(TimedLockError::TimedOut),
+			Err(Error(libc::EDEADLK)) => Err(TimedLockError::Deadlock),
 			Err(e) if op == libc::FUTEX_LOCK_PI2 => e.panic("FUTEX_LOCK_PI2"),
 			Err(e) => e.panic("FUTEX_LOCK_PI"),
-			Ok(_) => Ok(()),
+			Ok(_) => Ok(self.acquired()),
 		}
 	}
 
 	/// See `FUTEX_TRYLOCK_PI` in the [Linux futex man page](http://man7.org/linux/man-pages/man2/futex.2.html).
+	///
+	/// Returns [`Acquired::OwnerDied`] if the previous owner died while holding the lock.
 	#[inline]
-	pub fn trylock_pi(&self) -> Result<(), TryAgainError> {
+	pub fn trylock_pi(&self) -> Result<Acquired, LockError> {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_TRYLOCK_PI + S::futex_flag())
@@ -499,25 +1235,50 @@ impl<S: Scope> PiFutex<S> {
 				.call()
 		};
 		match r {
-			Err(Error(libc::EAGAIN)) => Err(TryAgainError::TryAgain),
-			Err(e) => e.panic("FUTEX_LOCK_PI"),
-			Ok(_) => Ok(()),
+			Err(Error(libc::EAGAIN)) => Err(LockError::TryAgain),
+			Err(Error(libc::EDEADLK)) => Err(LockError::Deadlock),
+			Err(e) => e.panic("FUTEX_TRYLOCK_PI"),
+			Ok(_) => Ok(self.acquired()),
 		}
 	}
 
 	/// See `FUTEX_UNLOCK_PI` in the [Linux futex man page](http://man7.org/linux/man-pages/man2/futex.2.html).
+	///
+	/// Returns [`UnlockError::NotOwner`] if the futex is not owned by the calling thread.
 	#[inline]
-	pub fn unlock_pi(&self) {
+	pub fn unlock_pi(&self) -> Result<(), UnlockError> {
 		let r = unsafe {
 			FutexCall::new()
 				.futex_op(libc::FUTEX_UNLOCK_PI + S::futex_flag())
 				.uaddr(&self.value)
 				.call()
 		};
-		if let Err(e) = r {
-			e.panic("FUTEX_UNLOCK_PI");
+		match r {
+			Err(Error(libc::EPERM)) => Err(UnlockError::NotOwner),
+			Err(e) => e.panic("FUTEX_UNLOCK_PI"),
+			Ok(_) => Ok(()),
 		}
 	}
+
+	/// Like [`lock_pi`][PiFutex::lock_pi], but returns a guard that unlocks the futex when dropped.
+	///
+	/// The guard can't be sent to another thread, since only the thread that
+	/// locked the futex may unlock it.
+	#[inline]
+	pub fn lock_pi_guard(&self) -> Result<PiLockGuard<'_, S>, LockError> {
+		let acquired = self.lock_pi()?;
+		Ok(PiLockGuard::new(self, acquired))
+	}
+
+	/// Like [`trylock_pi`][PiFutex::trylock_pi], but returns a guard that unlocks the futex when dropped.
+	///
+	/// The guard can't be sent to another thread, since only the thread that
+	/// locked the futex may unlock it.
+	#[inline]
+	pub fn trylock_pi_guard(&self) -> Result<PiLockGuard<'_, S>, LockError> {
+		let acquired = self.trylock_pi()?;
+		Ok(PiLockGuard::new(self, acquired))
+	}
 }
 
 impl<S> std::fmt::Debug for Futex<S> {
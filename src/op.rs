@@ -1,4 +1,7 @@
 //! Arguments to the [`wake_op`][crate::Futex::wake_op] function.
+//!
+//! These can be written as `Op::assign(0) + Cmp::eq(1)`, or with the
+//! [`wake_op!`][crate::wake_op] macro as `wake_op!(*second = 0; if old == 1)`.
 
 /// The operation [`wake_op`][crate::Futex::wake_op] applies to the second futex.
 ///
@@ -6,155 +9,293 @@
 /// example: `Op::assign(1) + Cmp::eq(0)`
 ///
 /// The argument to any operation must be below `1 << 12` (= 4096).
+/// The [`wake_op!`][crate::wake_op] macro checks this at compile time.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Op {
 	bits: u32,
 }
 
+/// The kind of an [`Op`], named after its constructor.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum OpKind {
+	/// [`Op::assign`]
+	Assign,
+	/// [`Op::add`]
+	Add,
+	/// [`Op::or`]
+	Or,
+	/// [`Op::and_not`]
+	AndNot,
+	/// [`Op::xor`]
+	Xor,
+	/// [`Op::assign_bit`]
+	AssignBit,
+	/// [`Op::add_bit`]
+	AddBit,
+	/// [`Op::set_bit`]
+	SetBit,
+	/// [`Op::clear_bit`]
+	ClearBit,
+	/// [`Op::toggle_bit`]
+	ToggleBit,
+}
+
 impl Op {
 	/// Assign the argument to the futex value: `value = arg`
 	#[inline]
-	pub fn assign(arg: u32) -> Self {
+	pub const fn assign(arg: u32) -> Self {
 		Self::new(0, arg)
 	}
 
 	/// Add the argument to the futex value: `value += arg`
 	#[inline]
-	pub fn add(arg: u32) -> Self {
+	pub const fn add(arg: u32) -> Self {
 		Self::new(1, arg)
 	}
 
 	/// Bitwise-or the futex value with the argument: `value |= arg`
 	#[inline]
-	pub fn or(arg: u32) -> Self {
+	pub const fn or(arg: u32) -> Self {
 		Self::new(2, arg)
 	}
 
 	/// Bitwise-and the futex value with the bitwise complement of the argument: `value &= !arg`
 	#[inline]
-	pub fn and_not(arg: u32) -> Self {
+	pub const fn and_not(arg: u32) -> Self {
 		Self::new(3, arg)
 	}
 
 	/// Xor the futex value with the argument: `value ^= arg`
 	#[inline]
-	pub fn xor(arg: u32) -> Self {
+	pub const fn xor(arg: u32) -> Self {
 		Self::new(4, arg)
 	}
 
 	/// Assign `1 << bit` to the futex value: `value = 1 << bit`
 	#[inline]
-	pub fn assign_bit(bit: u32) -> Self {
+	pub const fn assign_bit(bit: u32) -> Self {
 		Self::new(8, bit)
 	}
 
 	/// Add `1 << bit` to the futex value: `value += 1 << bit`
 	#[inline]
-	pub fn add_bit(bit: u32) -> Self {
+	pub const fn add_bit(bit: u32) -> Self {
 		Self::new(9, bit)
 	}
 
 	/// Set the `bit`th bit of the futex value: `value |= 1 << bit`
 	#[inline]
-	pub fn set_bit(bit: u32) -> Self {
+	pub const fn set_bit(bit: u32) -> Self {
 		Self::new(10, bit)
 	}
 
 	/// Clear the `bit`th bit of the futex value: `value &= !(1 << bit)`
 	#[inline]
-	pub fn clear_bit(bit: u32) -> Self {
+	pub const fn clear_bit(bit: u32) -> Self {
 		Self::new(11, bit)
 	}
 
 	/// Toggle the `bit`th bit of the futex value: `value ^= 1 << bit`
 	#[inline]
-	pub fn toggle_bit(bit: u32) -> Self {
+	pub const fn toggle_bit(bit: u32) -> Self {
 		Self::new(12, bit)
 	}
 
+	/// The kind of operation.
 	#[inline]
-	fn new(op: u32, value: u32) -> Self {
-		if value >= 1 << 12 {
-			panic!("Value too large: {}", value);
+	pub const fn kind(self) -> OpKind {
+		match self.bits >> 28 {
+			0 => OpKind::Assign,
+			1 => OpKind::Add,
+			2 => OpKind::Or,
+			3 => OpKind::AndNot,
+			4 => OpKind::Xor,
+			8 => OpKind::AssignBit,
+			9 => OpKind::AddBit,
+			10 => OpKind::SetBit,
+			11 => OpKind::ClearBit,
+			12 => OpKind::ToggleBit,
+			_ => unreachable!(),
 		}
+	}
+
+	/// The argument of the operation, or the bit number for the bit operations.
+	#[inline]
+	pub const fn arg(self) -> u32 {
+		self.bits >> 12 & 0xFFF
+	}
+
+	#[inline]
+	const fn new(op: u32, value: u32) -> Self {
+		assert!(value < 1 << 12, "wake_op argument too large");
 		Self {
 			bits: value << 12 | op << 28,
 		}
 	}
 }
 
+/// The encodings of the operations and the names of their constructors.
+const OPS: [(u32, &str); 10] = [
+	(0, "assign"),
+	(1, "add"),
+	(2, "or"),
+	(3, "and_not"),
+	(4, "xor"),
+	(8, "assign_bit"),
+	(9, "add_bit"),
+	(10, "set_bit"),
+	(11, "clear_bit"),
+	(12, "toggle_bit"),
+];
+
+/// The encodings of the comparisons and the names of their constructors.
+const CMPS: [(u32, &str); 6] = [
+	(0, "eq"),
+	(1, "ne"),
+	(2, "lt"),
+	(3, "le"),
+	(4, "gt"),
+	(5, "ge"),
+];
+
+/// The name of the constructor with the given encoding.
+fn name(names: &[(u32, &'static str)], encoding: u32) -> &'static str {
+	match names.iter().find(|&&(e, _)| e == encoding) {
+		Some(&(_, name)) => name,
+		None => "invalid",
+	}
+}
+
+/// Parse `Type::name(arg)` into the encoding of the name and the argument.
+fn parse(s: &str, prefix: &str, names: &[(u32, &str)]) -> Result<(u32, u32), ParseOpError> {
+	let s = s.trim();
+	let s = s.strip_prefix(prefix).ok_or(ParseOpError::Syntax)?;
+	let s = s.strip_prefix("::").ok_or(ParseOpError::Syntax)?;
+	let s = s.strip_suffix(')').ok_or(ParseOpError::Syntax)?;
+	let (name, arg) = s.split_once('(').ok_or(ParseOpError::Syntax)?;
+	let encoding = match names.iter().find(|&&(_, n)| n == name) {
+		Some(&(e, _)) => e,
+		None => return Err(ParseOpError::UnknownName),
+	};
+	let arg: u32 = arg.trim().parse().map_err(|_| ParseOpError::Syntax)?;
+	if arg >= 1 << 12 {
+		return Err(ParseOpError::TooLarge);
+	}
+	Ok((encoding, arg))
+}
+
 impl std::fmt::Debug for Op {
 	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-		let op = match self.bits >> 28 {
-			0 => "assign",
-			1 => "add",
-			2 => "or",
-			3 => "and_not",
-			4 => "xor",
-			8 => "assign_bit",
-			9 => "add_bit",
-			10 => "set_bit",
-			11 => "clear_bit",
-			12 => "toggle_bit",
-			_ => "invalid",
-		};
+		let op = name(&OPS, self.bits >> 28);
 		write!(f, "Op::{}({})", op, self.bits >> 12 & 0xFFF)
 	}
 }
 
+/// The same as the [`Debug`][std::fmt::Debug] form, such as `Op::add(1)`.
+impl std::fmt::Display for Op {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(self, f)
+	}
+}
+
+/// Parses the [`Display`][std::fmt::Display] form, such as `Op::add(1)`.
+impl std::str::FromStr for Op {
+	type Err = ParseOpError;
+	fn from_str(s: &str) -> Result<Self, ParseOpError> {
+		let (op, arg) = parse(s, "Op", &OPS)?;
+		Ok(Self::new(op, arg))
+	}
+}
+
 /// The comparison [`wake_op`][crate::Futex::wake_op] applies to the old value of the second futex.
 ///
 /// A [`Cmp`] must be combined with an [`Op`] by using the plus operator. For
 /// example: `Op::assign(1) + Cmp::eq(0)`
 ///
 /// The argument to any comparison must be below `1 << 12` (= 4096).
+/// The [`wake_op!`][crate::wake_op] macro checks this at compile time.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Cmp {
 	bits: u32,
 }
 
+/// The kind of a [`Cmp`], named after its constructor.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum CmpKind {
+	/// [`Cmp::eq`]
+	Eq,
+	/// [`Cmp::ne`]
+	Ne,
+	/// [`Cmp::lt`]
+	Lt,
+	/// [`Cmp::le`]
+	Le,
+	/// [`Cmp::gt`]
+	Gt,
+	/// [`Cmp::ge`]
+	Ge,
+}
+
 impl Cmp {
 	/// Check if the old value of the futex equals this value.
 	#[inline]
-	pub fn eq(value: u32) -> Self {
+	pub const fn eq(value: u32) -> Self {
 		Self::new(0, value)
 	}
 
 	/// Check if the old value of the futex does not equal this value.
 	#[inline]
-	pub fn ne(value: u32) -> Self {
+	pub const fn ne(value: u32) -> Self {
 		Self::new(1, value)
 	}
 
 	/// Check if the old value of the futex is less than this value.
 	#[inline]
-	pub fn lt(value: u32) -> Self {
+	pub const fn lt(value: u32) -> Self {
 		Self::new(2, value)
 	}
 
 	/// Check if the old value of the futex is less than or equal to this value.
 	#[inline]
-	pub fn le(value: u32) -> Self {
+	pub const fn le(value: u32) -> Self {
 		Self::new(3, value)
 	}
 
 	/// Check if the old value of the futex is greater than this value.
 	#[inline]
-	pub fn gt(value: u32) -> Self {
+	pub const fn gt(value: u32) -> Self {
 		Self::new(4, value)
 	}
 
 	/// Check if the old value of the futex is greater than or equal to this value.
 	#[inline]
-	pub fn ge(value: u32) -> Self {
+	pub const fn ge(value: u32) -> Self {
 		Self::new(5, value)
 	}
 
+	/// The kind of comparison.
 	#[inline]
-	fn new(op: u32, value: u32) -> Self {
-		if value >= 1 << 12 {
-			panic!("Value too large: {}", value);
+	pub const fn kind(self) -> CmpKind {
+		match self.bits >> 24 {
+			0 => CmpKind::Eq,
+			1 => CmpKind::Ne,
+			2 => CmpKind::Lt,
+			3 => CmpKind::Le,
+			4 => CmpKind::Gt,
+			5 => CmpKind::Ge,
+			_ => unreachable!(),
 		}
+	}
+
+	/// The value the old value of the futex is compared to.
+	#[inline]
+	pub const fn arg(self) -> u32 {
+		self.bits & 0xFFF
+	}
+
+	#[inline]
+	const fn new(op: u32, value: u32) -> Self {
+		assert!(value < 1 << 12, "wake_op argument too large");
 		Self {
 			bits: value | op << 24,
 		}
@@ -163,19 +304,27 @@ impl Cmp {
 
 impl std::fmt::Debug for Cmp {
 	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-		let op = match self.bits >> 24 & 0xF {
-			0 => "eq",
-			1 => "ne",
-			2 => "lt",
-			3 => "le",
-			4 => "gt",
-			5 => "ge",
-			_ => "invalid",
-		};
+		let op = name(&CMPS, self.bits >> 24 & 0xF);
 		write!(f, "Cmp::{}({})", op, self.bits & 0xFFF)
 	}
 }
 
+/// The same as the [`Debug`][std::fmt::Debug] form, such as `Cmp::eq(0)`.
+impl std::fmt::Display for Cmp {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(self, f)
+	}
+}
+
+/// Parses the [`Display`][std::fmt::Display] form, such as `Cmp::eq(0)`.
+impl std::str::FromStr for Cmp {
+	type Err = ParseOpError;
+	fn from_str(s: &str) -> Result<Self, ParseOpError> {
+		let (cmp, arg) = parse(s, "Cmp", &CMPS)?;
+		Ok(Self::new(cmp, arg))
+	}
+}
+
 /// The operation and comparison [`wake_op`][crate::Futex::wake_op] applies to the second futex.
 ///
 /// See [`Op`] and [`Cmp`].
@@ -188,6 +337,14 @@ pub struct OpAndCmp {
 }
 
 impl OpAndCmp {
+	/// Combine an [`Op`] and a [`Cmp`], like `op + cmp`, but usable in constants.
+	#[inline]
+	pub const fn new(op: Op, cmp: Cmp) -> Self {
+		Self {
+			bits: op.bits | cmp.bits,
+		}
+	}
+
 	#[inline]
 	pub const fn from_raw_bits(bits: u32) -> Self {
 		Self { bits }
@@ -197,15 +354,35 @@ impl OpAndCmp {
 	pub const fn raw_bits(self) -> u32 {
 		self.bits
 	}
+
+	/// Split this back into its [`Op`] and [`Cmp`].
+	///
+	/// Returns `None` if the bits do not encode a valid operation and
+	/// comparison, which is only possible through [`OpAndCmp::from_raw_bits`].
+	#[inline]
+	pub const fn split(self) -> Option<(Op, Cmp)> {
+		let op = self.bits >> 28;
+		let cmp = self.bits >> 24 & 0xF;
+		if (op > 4 && op < 8) || op > 12 || cmp > 5 {
+			return None;
+		}
+		Some((
+			Op {
+				bits: self.bits & 0xF0FF_F000,
+			},
+			Cmp {
+				bits: self.bits & 0x0F00_0FFF,
+			},
+		))
+	}
 }
 
 impl std::ops::Add<Cmp> for Op {
 	type Output = OpAndCmp;
 	#[inline]
+	#[allow(clippy::suspicious_arithmetic_impl)]
 	fn add(self, cmp: Cmp) -> OpAndCmp {
-		OpAndCmp {
-			bits: self.bits | cmp.bits,
-		}
+		OpAndCmp::new(self, cmp)
 	}
 }
 
@@ -219,3 +396,254 @@ impl std::fmt::Debug for OpAndCmp {
 		)
 	}
 }
+
+/// The same as the [`Debug`][std::fmt::Debug] form, such as `Op::add(1) + Cmp::eq(0)`.
+///
+/// Encodings that are not valid for the kernel are shown with `invalid` as
+/// their name, which cannot be parsed back.
+impl std::fmt::Display for OpAndCmp {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(self, f)
+	}
+}
+
+/// Parses the [`Display`][std::fmt::Display] form, such as `Op::add(1) + Cmp::eq(0)`.
+///
+/// Whitespace around the names and arguments is ignored.
+impl std::str::FromStr for OpAndCmp {
+	type Err = ParseOpError;
+	fn from_str(s: &str) -> Result<Self, ParseOpError> {
+		let (op, cmp) = s.split_once('+').ok_or(ParseOpError::Syntax)?;
+		Ok(op.parse::<Op>()? + cmp.parse::<Cmp>()?)
+	}
+}
+
+/// The ways parsing an [`Op`], [`Cmp`] or [`OpAndCmp`] can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParseOpError {
+	/// The text is not of the form `Op::name(arg) + Cmp::name(arg)`.
+	Syntax,
+	/// The name is not one of the constructors of [`Op`] or [`Cmp`].
+	UnknownName,
+	/// The argument is not below `1 << 12` (= 4096).
+	TooLarge,
+}
+
+impl std::fmt::Display for ParseOpError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Syntax => "invalid wake_op syntax",
+			Self::UnknownName => "unknown wake_op operation or comparison",
+			Self::TooLarge => "wake_op argument too large",
+		})
+	}
+}
+
+impl std::error::Error for ParseOpError {}
+
+/// Generates any operation, with an argument below `1 << 12`, or a bit below 32.
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for Op {
+	type Parameters = ();
+	type Strategy = proptest::strategy::Map<
+		(std::ops::Range<usize>, std::ops::Range<u32>),
+		fn((usize, u32)) -> Self,
+	>;
+	fn arbitrary_with(_: ()) -> Self::Strategy {
+		use proptest::strategy::Strategy;
+		(0..OPS.len(), 0..1 << 12).prop_map(|(i, arg)| {
+			let op = OPS[i].0;
+			// The kernel only shifts by up to 31 bits.
+			Op::new(op, if op >= 8 { arg % 32 } else { arg })
+		})
+	}
+}
+
+/// Generates any comparison, with an argument below `1 << 12`.
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for Cmp {
+	type Parameters = ();
+	type Strategy = proptest::strategy::Map<
+		(std::ops::Range<u32>, std::ops::Range<u32>),
+		fn((u32, u32)) -> Self,
+	>;
+	fn arbitrary_with(_: ()) -> Self::Strategy {
+		use proptest::strategy::Strategy;
+		(0..6, 0..1 << 12).prop_map(|(cmp, arg)| Cmp::new(cmp, arg))
+	}
+}
+
+/// Generates any combination of an arbitrary [`Op`] and [`Cmp`].
+#[cfg(feature = "proptest")]
+impl proptest::arbitrary::Arbitrary for OpAndCmp {
+	type Parameters = ();
+	type Strategy = proptest::strategy::Map<
+		(
+			<Op as proptest::arbitrary::Arbitrary>::Strategy,
+			<Cmp as proptest::arbitrary::Arbitrary>::Strategy,
+		),
+		fn((Op, Cmp)) -> Self,
+	>;
+	fn arbitrary_with(_: ()) -> Self::Strategy {
+		use proptest::arbitrary::any;
+		use proptest::strategy::Strategy;
+		(any::<Op>(), any::<Cmp>()).prop_map(|(op, cmp)| op + cmp)
+	}
+}
+
+/// Write the [`OpAndCmp`] of a [`wake_op`][crate::Futex::wake_op] call as the operation it performs.
+///
+/// The operation on the second futex is written as an assignment to
+/// `*second` (any name can be used), followed by the comparison of the old
+/// value of that futex, written as `if old`:
+///
+/// - `*second = arg`, `+= arg`, `|= arg`, `&= !arg` or `^= arg`,
+///   for [`Op::assign`], [`Op::add`], [`Op::or`], [`Op::and_not`] and [`Op::xor`];
+/// - `*second = 1 << bit`, `+= 1 << bit`, `|= 1 << bit`, `&= !(1 << bit)` or `^= 1 << bit`,
+///   for [`Op::assign_bit`] and the other bit operations;
+/// - `if old == value`, `!=`, `<`, `<=`, `>` or `>=`, for [`Cmp::eq`] and the other comparisons.
+///
+/// For example, `wake_op!(*second |= 1 << 3; if old == 0)`.
+///
+/// The arguments must be constant expressions. The result is a constant, so
+/// an argument that is too large is a compilation error instead of a panic.
+#[macro_export]
+macro_rules! wake_op {
+	(*$second:ident = 1 << $bit:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::assign_bit($bit), $($cmp)+)
+	};
+	(*$second:ident += 1 << $bit:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::add_bit($bit), $($cmp)+)
+	};
+	(*$second:ident |= 1 << $bit:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::set_bit($bit), $($cmp)+)
+	};
+	(*$second:ident &= !(1 << $bit:expr); if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::clear_bit($bit), $($cmp)+)
+	};
+	(*$second:ident ^= 1 << $bit:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::toggle_bit($bit), $($cmp)+)
+	};
+	(*$second:ident = $arg:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::assign($arg), $($cmp)+)
+	};
+	(*$second:ident += $arg:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::add($arg), $($cmp)+)
+	};
+	(*$second:ident |= $arg:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::or($arg), $($cmp)+)
+	};
+	(*$second:ident &= !$arg:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::and_not($arg), $($cmp)+)
+	};
+	(*$second:ident ^= $arg:expr; if old $($cmp:tt)+) => {
+		$crate::wake_op!(@cmp $crate::op::Op::xor($arg), $($cmp)+)
+	};
+	(@cmp $op:expr, == $value:expr) => {
+		$crate::wake_op!(@const $op, $crate::op::Cmp::eq($value))
+	};
+	(@cmp $op:expr, != $value:expr) => {
+		$crate::wake_op!(@const $op, $crate::op::Cmp::ne($value))
+	};
+	(@cmp $op:expr, <= $value:expr) => {
+		$crate::wake_op!(@const $op, $crate::op::Cmp::le($value))
+	};
+	(@cmp $op:expr, >= $value:expr) => {
+		$crate::wake_op!(@const $op, $crate::op::Cmp::ge($value))
+	};
+	(@cmp $op:expr, < $value:expr) => {
+		$crate::wake_op!(@const $op, $crate::op::Cmp::lt($value))
+	};
+	(@cmp $op:expr, > $value:expr) => {
+		$crate::wake_op!(@const $op, $crate::op::Cmp::gt($value))
+	};
+	(@const $op:expr, $cmp:expr) => {{
+		const OP_AND_CMP: $crate::op::OpAndCmp = $crate::op::OpAndCmp::new($op, $cmp);
+		OP_AND_CMP
+	}};
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Cmp, Op, OpAndCmp};
+	use crate::{Futex, Private};
+
+	#[test]
+	fn wake_op_macro() {
+		const ARG: u32 = 7;
+		assert_eq!(
+			wake_op!(*second = 1; if old == 2),
+			Op::assign(1) + Cmp::eq(2)
+		);
+		assert_eq!(wake_op!(*x += ARG; if old != 0), Op::add(7) + Cmp::ne(0));
+		assert_eq!(wake_op!(*x |= 3; if old < 4), Op::or(3) + Cmp::lt(4));
+		assert_eq!(wake_op!(*x &= !3; if old <= 4), Op::and_not(3) + Cmp::le(4));
+		assert_eq!(wake_op!(*x ^= ARG + 1; if old > 4), Op::xor(8) + Cmp::gt(4));
+		assert_eq!(
+			wake_op!(*x = 1 << 5; if old >= 4),
+			Op::assign_bit(5) + Cmp::ge(4)
+		);
+		assert_eq!(
+			wake_op!(*x += 1 << 5; if old == 0),
+			Op::add_bit(5) + Cmp::eq(0)
+		);
+		assert_eq!(
+			wake_op!(*x |= 1 << ARG; if old == 0),
+			Op::set_bit(7) + Cmp::eq(0)
+		);
+		assert_eq!(
+			wake_op!(*x &= !(1 << 5); if old == 0),
+			Op::clear_bit(5) + Cmp::eq(0)
+		);
+		assert_eq!(
+			wake_op!(*x ^= 1 << 5; if old == 0),
+			Op::toggle_bit(5) + Cmp::eq(0)
+		);
+	}
+
+	#[test]
+	fn wake_op_macro_does_what_it_says() {
+		let first = Futex::<Private>::new(0);
+		let second = Futex::<Private>::new(0b1);
+		first.wake_op(1, &second, wake_op!(*second |= 1 << 3; if old == 1), 1);
+		assert_eq!(second.value.into_inner(), 0b1001);
+	}
+
+	#[test]
+	fn display_round_trip() {
+		for &(op, _) in super::OPS.iter() {
+			for &(cmp, _) in super::CMPS.iter() {
+				for &arg in &[0, 1, 31, 4095] {
+					let v = Op::new(op, arg) + Cmp::new(cmp, 4095 - arg);
+					let s = v.to_string();
+					assert_eq!(s.parse::<OpAndCmp>(), Ok(v), "{}", s);
+					let (o, c) = v.split().unwrap();
+					assert_eq!(o.to_string().parse::<Op>(), Ok(o));
+					assert_eq!(c.to_string().parse::<Cmp>(), Ok(c));
+				}
+			}
+		}
+		assert_eq!(
+			wake_op!(*x |= 1 << 3; if old == 0).to_string(),
+			"Op::set_bit(3) + Cmp::eq(0)"
+		);
+	}
+
+	#[test]
+	fn parse_errors() {
+		use super::ParseOpError::*;
+		assert_eq!(
+			" Op::add( 1 )+Cmp::eq(2) ".parse(),
+			Ok(Op::add(1) + Cmp::eq(2))
+		);
+		assert_eq!("Op::add(1)".parse::<OpAndCmp>(), Err(Syntax));
+		assert_eq!("Cmp::add(1)".parse::<Cmp>(), Err(UnknownName));
+		assert_eq!("Op::mul(1)".parse::<Op>(), Err(UnknownName));
+		assert_eq!("Op::add(4096)".parse::<Op>(), Err(TooLarge));
+		assert_eq!("Op::add(-1)".parse::<Op>(), Err(Syntax));
+		assert_eq!("Op::add 1".parse::<Op>(), Err(Syntax));
+		// Invalid encodings are displayed, but can't be parsed back.
+		let invalid = OpAndCmp::from_raw_bits(0xF000_0000);
+		assert_eq!(invalid.to_string().parse::<OpAndCmp>(), Err(UnknownName));
+	}
+}
@@ -0,0 +1,172 @@
+use crate::op::OpAndCmp;
+use crate::timeout::Timeout;
+use crate::{Futex, WakeMask};
+use std::time::Duration;
+
+/// Options for [`Futex::wait_with`][crate::Futex::wait_with].
+///
+/// By default, the wait matches any `wake` call and has no timeout.
+#[derive(Clone, Copy)]
+pub struct WaitOptions {
+	pub(crate) expected_value: u32,
+	pub(crate) bitset: WakeMask,
+	pub(crate) timeout: Option<WaitTimeout>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum WaitTimeout {
+	Relative(Duration),
+	Absolute(i32, libc::timespec),
+}
+
+impl WaitOptions {
+	/// Wait only if the futex's value matches the expected value.
+	#[inline]
+	pub const fn new(expected_value: u32) -> Self {
+		Self {
+			expected_value,
+			bitset: WakeMask::ALL,
+			timeout: None,
+		}
+	}
+
+	/// Only wake up for `wake` calls matching this bitset.
+	///
+	/// See [`wait_bitset`][crate::Futex::wait_bitset].
+	#[inline]
+	pub const fn bitset(self, bitset: WakeMask) -> Self {
+		Self { bitset, ..self }
+	}
+
+	/// Stop waiting after the given duration, measured on the monotonic clock.
+	///
+	/// Replaces any previously set deadline.
+	#[inline]
+	pub const fn timeout(self, timeout: Duration) -> Self {
+		Self {
+			timeout: Some(WaitTimeout::Relative(timeout)),
+			..self
+		}
+	}
+
+	/// Stop waiting at the given point in time.
+	///
+	/// The clock is selected by the type of the deadline:
+	/// an [`Instant`][std::time::Instant] uses the monotonic clock,
+	/// and a [`SystemTime`][std::time::SystemTime] uses the real time clock.
+	///
+	/// Replaces any previously set timeout.
+	#[inline]
+	pub fn deadline(self, deadline: impl Timeout) -> Self {
+		let (clock, timespec) = deadline.as_timespec();
+		Self {
+			timeout: Some(WaitTimeout::Absolute(clock, timespec)),
+			..self
+		}
+	}
+}
+
+impl std::fmt::Debug for WaitOptions {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("WaitOptions");
+		d.field("expected_value", &self.expected_value);
+		d.field("bitset", &self.bitset);
+		match self.timeout {
+			None => {}
+			Some(WaitTimeout::Relative(timeout)) => {
+				d.field("timeout", &timeout);
+			}
+			Some(WaitTimeout::Absolute(clock, t)) => {
+				let clock = if clock == libc::FUTEX_CLOCK_REALTIME {
+					"realtime"
+				} else {
+					"monotonic"
+				};
+				d.field("clock", &clock);
+				d.field(
+					"deadline",
+					&Duration::new(t.tv_sec as u64, t.tv_nsec as u32),
+				);
+			}
+		}
+		d.finish()
+	}
+}
+
+/// Options for [`Futex::wake_with`][crate::Futex::wake_with].
+///
+/// By default, one waiter is woken up, regardless of its bitset.
+pub struct WakeOptions<'a, S> {
+	pub(crate) count: u32,
+	pub(crate) bitset: WakeMask,
+	pub(crate) op: Option<(&'a Futex<S>, OpAndCmp, u32)>,
+}
+
+impl<'a, S> WakeOptions<'a, S> {
+	/// Wake up one waiter.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			count: 1,
+			bitset: WakeMask::ALL,
+			op: None,
+		}
+	}
+
+	/// Wake up (at most) `n` waiters, rather than one.
+	///
+	/// See [`wake`][crate::Futex::wake].
+	#[inline]
+	pub const fn count(self, n: u32) -> Self {
+		Self { count: n, ..self }
+	}
+
+	/// Only wake up waiters matching this bitset.
+	///
+	/// See [`wake_bitset`][crate::Futex::wake_bitset].
+	/// This cannot be combined with an [`op`][WakeOptions::op].
+	#[inline]
+	pub const fn bitset(self, bitset: WakeMask) -> Self {
+		Self { bitset, ..self }
+	}
+
+	/// Also apply `op` to the `second` futex, and wake up `n2` of its waiters if its old value matches.
+	///
+	/// See [`wake_op`][crate::Futex::wake_op].
+	/// This cannot be combined with a [`bitset`][WakeOptions::bitset].
+	#[inline]
+	pub const fn op(self, second: &'a Futex<S>, op: OpAndCmp, n2: u32) -> Self {
+		Self {
+			op: Some((second, op, n2)),
+			..self
+		}
+	}
+}
+
+impl<S> Clone for WakeOptions<'_, S> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<S> Copy for WakeOptions<'_, S> {}
+
+impl<S> Default for WakeOptions<'_, S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for WakeOptions<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("WakeOptions");
+		d.field("count", &self.count);
+		d.field("bitset", &self.bitset);
+		if let Some((second, op, n2)) = self.op {
+			d.field("second", second);
+			d.field("op", &op);
+			d.field("n2", &n2);
+		}
+		d.finish()
+	}
+}
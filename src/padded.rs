@@ -0,0 +1,67 @@
+use std::ops::{Deref, DerefMut};
+
+/// Aligns a value to the size of a cache line, to avoid false sharing.
+///
+/// An array of futexes that are used by different threads, such as one
+/// lock per shard or one doorbell per queue, performs better if every futex
+/// is on its own cache line.
+///
+/// The alignment is 128 bytes on x86-64 (where the prefetcher pulls in pairs
+/// of cache lines) and AArch64, and 64 bytes on other platforms. The value
+/// is placed at the start, followed by padding, so the layout is the same
+/// in every process using it through shared memory.
+#[cfg_attr(
+	any(target_arch = "x86_64", target_arch = "aarch64"),
+	repr(C, align(128))
+)]
+#[cfg_attr(
+	not(any(target_arch = "x86_64", target_arch = "aarch64")),
+	repr(C, align(64))
+)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CachePadded<T> {
+	value: T,
+}
+
+impl<T> CachePadded<T> {
+	/// Wrap a value.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self { value }
+	}
+
+	/// Unwrap the value.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.value
+	}
+}
+
+impl<T> Deref for CachePadded<T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		&self.value
+	}
+}
+
+impl<T> DerefMut for CachePadded<T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		&mut self.value
+	}
+}
+
+impl<T> From<T> for CachePadded<T> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for CachePadded<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("CachePadded")
+			.field("value", &self.value)
+			.finish()
+	}
+}
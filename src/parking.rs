@@ -0,0 +1,340 @@
+//! Parking threads on arbitrary addresses.
+//!
+//! This is a global table of queues of parked threads, keyed by address,
+//! like WebKit's `ParkingLot`. It adds blocking to data structures whose
+//! state isn't a 32-bit futex: a thread parks on the address of some
+//! (atomic) variable, and another thread unparks it after changing that variable.
+//!
+//! Every parked thread sleeps on its own futex, so unparking one thread
+//! never wakes up any other thread, even if two addresses share a queue.
+//!
+//! [`park`], [`unpark_one`] and [`unpark_all`] cover the common cases.
+//! Like `parking_lot_core`, [`park_with`], [`unpark_one_with`],
+//! [`unpark_all_with`] and [`unpark_filter`] additionally pass tokens between
+//! the parking and unparking threads, and run callbacks while the queue is
+//! locked, for building custom synchronization primitives.
+
+use crate::sync::{Mutex, MutexGuard};
+use crate::sys::FutexCall;
+use crate::timeout::deadline;
+use crate::{CachePadded, Futex, Private, TimedWaitError, WakeMask};
+use std::ptr::addr_of;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicU32, AtomicUsize};
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+
+/// The thread is parked, or about to be.
+const PARKED: u32 = 0;
+/// The thread was unparked, and removed from the queue.
+const UNPARKED: u32 = 1;
+
+/// A value a parking thread leaves for the threads that unpark it.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct ParkToken(pub usize);
+
+/// A value an unparking thread passes to the thread it unparks.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct UnparkToken(pub usize);
+
+/// The result of parking a thread.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ParkResult {
+	/// The thread was parked, and then unparked by another thread with the given token.
+	Unparked(UnparkToken),
+	/// The validation function returned false, so the thread was not parked.
+	Invalid,
+	/// The timeout expired before the thread was unparked.
+	TimedOut,
+}
+
+impl ParkResult {
+	/// Returns true if the thread was unparked by another thread.
+	#[inline]
+	pub fn is_unparked(self) -> bool {
+		matches!(self, Self::Unparked(_))
+	}
+}
+
+/// The result of [`unpark_one_with`] or [`unpark_filter`], as passed to their callback.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct UnparkResult {
+	/// The number of threads that were unparked.
+	pub unparked_threads: usize,
+	/// Whether there are threads left parked on the same key.
+	pub have_more_threads: bool,
+}
+
+/// What [`unpark_filter`] should do with a parked thread.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterOp {
+	/// Unpark the thread, and continue with the next one.
+	Unpark,
+	/// Leave the thread parked, and continue with the next one.
+	Skip,
+	/// Leave this thread and all threads after it parked.
+	Stop,
+}
+
+/// A parked thread, living on the stack of that thread.
+struct Waiter {
+	key: usize,
+	park_token: ParkToken,
+	unpark_token: AtomicUsize,
+	futex: Futex<Private>,
+}
+
+/// A pointer to a [`Waiter`], only dereferenced while the bucket is locked,
+/// or by the thread that removed it from the queue.
+struct WaiterPtr(*const Waiter);
+
+unsafe impl Send for WaiterPtr {}
+
+struct Bucket {
+	queue: Mutex<Vec<WaiterPtr>>,
+}
+
+const BUCKETS: usize = 256;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const BUCKET: CachePadded<Bucket> = CachePadded::new(Bucket {
+	queue: Mutex::new(Vec::new()),
+});
+
+static TABLE: [CachePadded<Bucket>; BUCKETS] = [BUCKET; BUCKETS];
+
+/// The queue for the given key, locked.
+fn lock_bucket(key: usize) -> MutexGuard<'static, Vec<WaiterPtr>> {
+	// Fibonacci hashing, using the top 8 bits.
+	let i = ((key as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 56) as usize;
+	// A panic in a callback can't leave the queue in an inconsistent state.
+	TABLE[i]
+		.queue
+		.lock()
+		.unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Park the calling thread on `key`, until another thread unparks it.
+///
+/// `validate` is called while the queue for `key` is locked, so no thread
+/// can unpark `key` in the meantime. If it returns false, the thread is not
+/// parked and [`ParkResult::Invalid`] is returned. This is where the thread
+/// checks whether it still needs to wait, after which it cannot miss an
+/// unpark call.
+///
+/// `validate` must not park or unpark any threads itself.
+#[inline]
+pub fn park(key: usize, validate: impl FnOnce() -> bool) -> ParkResult {
+	park_with(key, validate, || {}, |_, _| {}, ParkToken::default(), None)
+}
+
+/// Park the calling thread on `key`, until another thread unparks it, or until the timeout expires.
+///
+/// See [`park`].
+#[inline]
+pub fn park_timeout(key: usize, validate: impl FnOnce() -> bool, timeout: Duration) -> ParkResult {
+	let deadline = deadline(timeout);
+	park_with(
+		key,
+		validate,
+		|| {},
+		|_, _| {},
+		ParkToken::default(),
+		deadline,
+	)
+}
+
+/// Park the calling thread on `key`, with callbacks and a token.
+///
+/// - `validate` is called while the queue for `key` is locked, as in [`park`].
+/// - `before_sleep` is called after the thread was added to the queue and
+///   the queue was unlocked, right before going to sleep. This is where a
+///   lock can be released, for example, when implementing a condition variable.
+/// - `timed_out` is called while the queue for `key` is locked, after the
+///   deadline passed and the thread was removed from the queue. Its
+///   arguments are the key, and whether this was the last thread parked on it.
+/// - `park_token` is visible to [`unpark_filter`] while the thread is parked.
+///
+/// None of the callbacks may park or unpark any threads themselves.
+pub fn park_with(
+	key: usize,
+	validate: impl FnOnce() -> bool,
+	before_sleep: impl FnOnce(),
+	timed_out: impl FnOnce(usize, bool),
+	park_token: ParkToken,
+	deadline: Option<Instant>,
+) -> ParkResult {
+	let waiter = Waiter {
+		key,
+		park_token,
+		unpark_token: AtomicUsize::new(0),
+		futex: Futex::new(PARKED),
+	};
+	{
+		let mut queue = lock_bucket(key);
+		if !validate() {
+			return ParkResult::Invalid;
+		}
+		queue.push(WaiterPtr(&waiter));
+	}
+
+	// If before_sleep panics, we must leave the queue before our stack frame disappears.
+	struct Leave<'a>(&'a Waiter);
+	impl Drop for Leave<'_> {
+		fn drop(&mut self) {
+			leave(self.0, |_, _| {});
+		}
+	}
+	let guard = Leave(&waiter);
+	before_sleep();
+	std::mem::forget(guard);
+
+	while waiter.futex.value.load(Acquire) == PARKED {
+		match deadline {
+			Some(deadline) => {
+				if let Err(TimedWaitError::TimedOut) =
+					waiter
+						.futex
+						.wait_bitset_until(PARKED, WakeMask::ALL, deadline)
+				{
+					break;
+				}
+			}
+			None => {
+				let _ = waiter.futex.wait(PARKED);
+			}
+		}
+	}
+	if waiter.futex.value.load(Acquire) == PARKED && leave(&waiter, timed_out) {
+		return ParkResult::TimedOut;
+	}
+	ParkResult::Unparked(UnparkToken(waiter.unpark_token.load(Relaxed)))
+}
+
+/// Remove a waiter from its queue, calling `timed_out`, unless an
+/// unparking thread already did so, in which case we wait until it unparks us.
+///
+/// Returns false if we were unparked.
+fn leave(waiter: &Waiter, timed_out: impl FnOnce(usize, bool)) -> bool {
+	let mut queue = lock_bucket(waiter.key);
+	match queue.iter().position(|w| std::ptr::eq(w.0, waiter)) {
+		Some(i) => {
+			queue.remove(i);
+			let key = waiter.key;
+			let last = !queue.iter().any(|w| unsafe { (*w.0).key } == key);
+			timed_out(key, last);
+			true
+		}
+		None => {
+			drop(queue);
+			while waiter.futex.value.load(Acquire) == PARKED {
+				let _ = waiter.futex.wait(PARKED);
+			}
+			false
+		}
+	}
+}
+
+/// Unpark a removed waiter.
+///
+/// After the store, the waiter might return and its memory might be reused,
+/// so it is woken up through a raw pointer, like [`Futex::wake`] on memory
+/// that might no longer be a futex: at worst, that is a spurious wake-up.
+unsafe fn unpark(waiter: *const Waiter, token: UnparkToken) {
+	(*waiter).unpark_token.store(token.0, Relaxed);
+	let futex: *const AtomicU32 = addr_of!((*waiter).futex.value);
+	(*futex).store(UNPARKED, Release);
+	let _ = FutexCall::new()
+		.futex_op(libc::FUTEX_WAKE + libc::FUTEX_PRIVATE_FLAG)
+		.uaddr(futex)
+		.val(1)
+		.call();
+}
+
+/// Unpark the thread that parked on `key` first.
+///
+/// Returns false if no thread was parked on `key`.
+#[inline]
+pub fn unpark_one(key: usize) -> bool {
+	unpark_one_with(key, |_| UnparkToken::default()).unparked_threads != 0
+}
+
+/// Unpark all threads parked on `key`.
+///
+/// Returns the number of threads that were unparked.
+#[inline]
+pub fn unpark_all(key: usize) -> usize {
+	unpark_all_with(key, UnparkToken::default())
+}
+
+/// Unpark the thread that parked on `key` first, passing it the token returned by `callback`.
+///
+/// `callback` is called while the queue for `key` is locked, also if no
+/// thread was unparked. It must not park or unpark any threads itself.
+pub fn unpark_one_with(
+	key: usize,
+	callback: impl FnOnce(UnparkResult) -> UnparkToken,
+) -> UnparkResult {
+	let mut first = true;
+	unpark_filter(
+		key,
+		|_| {
+			if std::mem::replace(&mut first, false) {
+				FilterOp::Unpark
+			} else {
+				FilterOp::Stop
+			}
+		},
+		callback,
+	)
+}
+
+/// Unpark all threads parked on `key`, passing them the given token.
+///
+/// Returns the number of threads that were unparked.
+#[inline]
+pub fn unpark_all_with(key: usize, token: UnparkToken) -> usize {
+	unpark_filter(key, |_| FilterOp::Unpark, |_| token).unparked_threads
+}
+
+/// Unpark the threads parked on `key` that `filter` selects, in the order they parked.
+///
+/// `filter` is called with the [`ParkToken`] of each thread parked on `key`,
+/// and `callback` is called once afterwards, to determine the token all
+/// unparked threads receive. Both are called while the queue for `key` is
+/// locked, and must not park or unpark any threads themselves.
+pub fn unpark_filter(
+	key: usize,
+	mut filter: impl FnMut(ParkToken) -> FilterOp,
+	callback: impl FnOnce(UnparkResult) -> UnparkToken,
+) -> UnparkResult {
+	let mut queue = lock_bucket(key);
+	let mut waiters = Vec::new();
+	let mut result = UnparkResult::default();
+	let mut stopped = false;
+	queue.retain(|w| {
+		let waiter = unsafe { &*w.0 };
+		if waiter.key != key {
+			return true;
+		}
+		if !stopped {
+			match filter(waiter.park_token) {
+				FilterOp::Unpark => {
+					waiters.push(w.0);
+					return false;
+				}
+				FilterOp::Skip => {}
+				FilterOp::Stop => stopped = true,
+			}
+		}
+		result.have_more_threads = true;
+		true
+	});
+	result.unparked_threads = waiters.len();
+	let token = callback(result);
+	drop(queue);
+	for &waiter in &waiters {
+		unsafe { unpark(waiter, token) };
+	}
+	result
+}
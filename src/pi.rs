@@ -0,0 +1,211 @@
+use crate::{PiFutex, PidNamespaceError, Scope};
+use std::cell::Cell;
+use std::io;
+use std::marker::PhantomData;
+use std::os::unix::fs::MetadataExt;
+use std::sync::Once;
+
+/// The way a [`PiFutex`][crate::PiFutex] was locked.
+#[must_use]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Acquired {
+	/// The futex was locked normally.
+	Clean,
+	/// The previous owner died while holding the lock, leaving the
+	/// [`OWNER_DIED`][crate::PiFutex::OWNER_DIED] bit set.
+	///
+	/// The state protected by the futex might be inconsistent, and should be
+	/// validated or repaired before it is used.
+	OwnerDied,
+}
+
+/// The lock of a [`PiFutex`], returned by [`PiFutex::lock_pi_guard`] and [`PiFutex::trylock_pi_guard`].
+///
+/// The futex is unlocked with `FUTEX_UNLOCK_PI` when this guard is dropped.
+/// Since the kernel only allows the owning thread to unlock a PI futex, the
+/// guard can't be sent to another thread.
+#[must_use = "if unused the PiFutex will immediately unlock"]
+pub struct PiLockGuard<'a, S: Scope> {
+	futex: &'a PiFutex<S>,
+	acquired: Acquired,
+	/// The futex is owned by the thread that locked it.
+	not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<S: Scope> Sync for PiLockGuard<'_, S> {}
+
+impl<'a, S: Scope> PiLockGuard<'a, S> {
+	#[inline]
+	pub(crate) fn new(futex: &'a PiFutex<S>, acquired: Acquired) -> Self {
+		Self {
+			futex,
+			acquired,
+			not_send: PhantomData,
+		}
+	}
+
+	/// Whether the futex was locked normally, or the previous owner died while holding it.
+	#[inline]
+	pub fn acquired(&self) -> Acquired {
+		self.acquired
+	}
+
+	/// The futex this guard holds the lock of.
+	#[inline]
+	pub fn futex(&self) -> &'a PiFutex<S> {
+		self.futex
+	}
+}
+
+impl<S: Scope> Drop for PiLockGuard<'_, S> {
+	#[inline]
+	fn drop(&mut self) {
+		let r = self.futex.unlock_pi();
+		debug_assert!(
+			r.is_ok(),
+			"PiLockGuard dropped by a thread that does not own the futex"
+		);
+	}
+}
+
+impl<S: Scope> std::fmt::Debug for PiLockGuard<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("PiLockGuard")
+			.field("futex", self.futex)
+			.field("acquired", &self.acquired)
+			.finish()
+	}
+}
+
+/// A decoded value of a [`PiFutex`][crate::PiFutex], as returned by [`PiFutex::state`][crate::PiFutex::state].
+///
+/// The value of a PI futex is the thread id of its owner (or zero if it is
+/// unlocked), plus the [`WAITERS`][crate::PiFutex::WAITERS] and
+/// [`OWNER_DIED`][crate::PiFutex::OWNER_DIED] bits, which the kernel sets.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PiState(u32);
+
+impl PiState {
+	/// Decode a raw PI futex value.
+	#[inline]
+	pub const fn from_raw(value: u32) -> Self {
+		Self(value)
+	}
+
+	/// The raw PI futex value.
+	#[inline]
+	pub const fn to_raw(self) -> u32 {
+		self.0
+	}
+
+	/// The thread id of the owner, or `None` if the futex is not locked.
+	#[inline]
+	pub const fn owner_tid(self) -> Option<u32> {
+		match self.0 & crate::PiFutex::<crate::Private>::TID_MASK {
+			0 => None,
+			tid => Some(tid),
+		}
+	}
+
+	/// Whether the futex is locked.
+	#[inline]
+	pub const fn is_locked(self) -> bool {
+		self.owner_tid().is_some()
+	}
+
+	/// Whether there might be threads waiting for the futex, such that it must be unlocked through the kernel.
+	#[inline]
+	pub const fn has_waiters(self) -> bool {
+		self.0 & crate::PiFutex::<crate::Private>::WAITERS != 0
+	}
+
+	/// Whether a previous owner died while holding the lock.
+	#[inline]
+	pub const fn owner_died(self) -> bool {
+		self.0 & crate::PiFutex::<crate::Private>::OWNER_DIED != 0
+	}
+}
+
+impl std::fmt::Debug for PiState {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("PiState")
+			.field("owner_tid", &self.owner_tid())
+			.field("has_waiters", &self.has_waiters())
+			.field("owner_died", &self.owner_died())
+			.finish()
+	}
+}
+
+thread_local! {
+	/// The cached thread id of this thread, or zero if not yet known.
+	static TID: Cell<u32> = const { Cell::new(0) };
+}
+
+/// The thread id of the calling thread, as stored in a locked [`PiFutex`][crate::PiFutex].
+///
+/// This is the kernel thread id returned by `gettid()`, in the PID namespace
+/// of the calling process. It is cached in a thread-local after the first
+/// call, and looked up again in the child after a `fork()`.
+#[inline]
+pub fn current_tid() -> u32 {
+	TID.with(|tid| match tid.get() {
+		0 => {
+			static AT_FORK: Once = Once::new();
+			// The child of a fork has the thread-local of the forking thread, but not its thread id.
+			AT_FORK.call_once(|| unsafe {
+				libc::pthread_atfork(None, None, Some(reset_tid));
+			});
+			let t = unsafe { libc::syscall(libc::SYS_gettid) as u32 };
+			tid.set(t);
+			t
+		}
+		t => t,
+	})
+}
+
+extern "C" fn reset_tid() {
+	TID.with(|tid| tid.set(0));
+}
+
+/// The PID namespace of a process, which must be the same for all processes sharing a [`PiFutex<Shared>`][crate::PiFutex].
+///
+/// A PI futex stores the thread id of its owner, which the kernel looks up
+/// in the PID namespace of the thread that waits for it. A thread has a
+/// different id in every PID namespace, so between processes in different
+/// PID namespaces, such as in different containers, the kernel finds the
+/// wrong thread or no thread at all, and priority inheritance silently
+/// breaks down.
+///
+/// To detect this, store [`PidNamespace::current`] next to the shared
+/// futexes when creating them, and [`check`][PidNamespace::check] it in
+/// every process that uses them. An all-zero `PidNamespace` does not match
+/// any namespace.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PidNamespace {
+	dev: u64,
+	ino: u64,
+}
+
+impl PidNamespace {
+	/// The PID namespace of the calling process, from `/proc/self/ns/pid`.
+	pub fn current() -> io::Result<Self> {
+		let meta = std::fs::metadata("/proc/self/ns/pid")?;
+		Ok(Self {
+			dev: meta.dev(),
+			ino: meta.ino(),
+		})
+	}
+
+	/// Check that the calling process is in this PID namespace.
+	///
+	/// Fails with a [`PidNamespaceError`] if it is not, or with another
+	/// error if `/proc/self/ns/pid` can't be read.
+	pub fn check(&self) -> io::Result<()> {
+		if Self::current()? == *self {
+			Ok(())
+		} else {
+			Err(PidNamespaceError::Mismatch.into())
+		}
+	}
+}
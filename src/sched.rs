@@ -0,0 +1,103 @@
+//! Scheduling policies and priorities of the calling thread.
+//!
+//! Priority inheritance through a [`PiFutex`][crate::PiFutex] only makes a
+//! difference when the threads involved run at different real-time
+//! priorities, under `SCHED_FIFO` or `SCHED_RR`. These functions set that up
+//! for the calling thread. Raising the priority requires `CAP_SYS_NICE` or a
+//! sufficient `RLIMIT_RTPRIO`.
+
+use std::io;
+use std::ops::RangeInclusive;
+
+/// A scheduling policy.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Policy {
+	/// `SCHED_OTHER`: the default time-sharing policy.
+	Other,
+	/// `SCHED_BATCH`: time-sharing, for CPU-intensive background work.
+	Batch,
+	/// `SCHED_IDLE`: only runs when nothing else wants to.
+	Idle,
+	/// `SCHED_FIFO`: real-time, running until it blocks or yields, or a higher priority thread is runnable.
+	Fifo,
+	/// `SCHED_RR`: real-time, like `Fifo`, but taking turns with threads of the same priority.
+	RoundRobin,
+}
+
+impl Policy {
+	fn to_raw(self) -> libc::c_int {
+		match self {
+			Self::Other => libc::SCHED_OTHER,
+			Self::Batch => libc::SCHED_BATCH,
+			Self::Idle => libc::SCHED_IDLE,
+			Self::Fifo => libc::SCHED_FIFO,
+			Self::RoundRobin => libc::SCHED_RR,
+		}
+	}
+
+	fn from_raw(policy: libc::c_int) -> io::Result<Self> {
+		match policy & !libc::SCHED_RESET_ON_FORK {
+			libc::SCHED_OTHER => Ok(Self::Other),
+			libc::SCHED_BATCH => Ok(Self::Batch),
+			libc::SCHED_IDLE => Ok(Self::Idle),
+			libc::SCHED_FIFO => Ok(Self::Fifo),
+			libc::SCHED_RR => Ok(Self::RoundRobin),
+			_ => Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"unsupported scheduling policy",
+			)),
+		}
+	}
+
+	/// Whether this is a real-time policy: `Fifo` or `RoundRobin`.
+	#[inline]
+	pub fn is_realtime(self) -> bool {
+		matches!(self, Self::Fifo | Self::RoundRobin)
+	}
+
+	/// The priorities that are valid for this policy.
+	///
+	/// This is `1..=99` for the real-time policies, and `0..=0` for the others.
+	pub fn priorities(self) -> RangeInclusive<i32> {
+		let min = unsafe { libc::sched_get_priority_min(self.to_raw()) };
+		let max = unsafe { libc::sched_get_priority_max(self.to_raw()) };
+		min..=max
+	}
+}
+
+/// The scheduling policy and priority of the calling thread.
+pub fn current() -> io::Result<(Policy, i32)> {
+	let mut policy = 0;
+	let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+	let e = unsafe { libc::pthread_getschedparam(libc::pthread_self(), &mut policy, &mut param) };
+	if e != 0 {
+		return Err(io::Error::from_raw_os_error(e));
+	}
+	Ok((Policy::from_raw(policy)?, param.sched_priority))
+}
+
+/// Set the scheduling policy and priority of the calling thread.
+///
+/// The priority must be in the range given by [`Policy::priorities`].
+pub fn set_current(policy: Policy, priority: i32) -> io::Result<()> {
+	let param = libc::sched_param {
+		sched_priority: priority,
+	};
+	let e = unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy.to_raw(), &param) };
+	if e != 0 {
+		return Err(io::Error::from_raw_os_error(e));
+	}
+	Ok(())
+}
+
+/// Run the calling thread under `SCHED_FIFO` with the given priority.
+#[inline]
+pub fn set_fifo(priority: i32) -> io::Result<()> {
+	set_current(Policy::Fifo, priority)
+}
+
+/// Run the calling thread under `SCHED_RR` with the given priority.
+#[inline]
+pub fn set_round_robin(priority: i32) -> io::Result<()> {
+	set_current(Policy::RoundRobin, priority)
+}
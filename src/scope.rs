@@ -7,6 +7,10 @@ pub struct Private(());
 pub struct Shared(());
 
 /// [`Private`] or [`Shared`].
+///
+/// # Safety
+///
+/// `futex_flag` must return either `FUTEX_PRIVATE_FLAG` or zero.
 pub unsafe trait Scope {
 	fn futex_flag() -> i32;
 }
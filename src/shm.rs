@@ -0,0 +1,1282 @@
+//! Memory shared between processes, for [`Shared`] futexes.
+//!
+//! A `Futex<Shared>`, or any of the [`sync`] primitives using
+//! one, only works between processes if it lives in memory that all of those
+//! processes have mapped. A [`SharedRegion`] is such memory, which remains
+//! shared between a process and its children created through `fork()`.
+//! A [`SharedBox`] lives in a memfd, which can also be mapped by unrelated
+//! processes that receive its file descriptor, for example through
+//! [`SharedBox::send`] over a Unix socket. A [`NamedRegion`] lives in a
+//! file under `/dev/shm`, which any process can open by its name, and a
+//! [`FileRegion`] lives in a regular file, such as a lock or state file of
+//! a long-running daemon. A [`MappingBuilder`] creates a [`Mapping`] with
+//! any of these backings, chosen at run time.
+//!
+//! A [`SharedArena`] divides such memory into many separately allocated
+//! values, referred to by offsets that are valid in every process.
+//!
+//! Types that can safely be placed in such memory implement [`SharedSafe`].
+//! The [`shared_layout!`][crate::shared_layout] macro declares a struct of
+//! such fields, and checks that it doesn't contain anything else.
+
+use crate::{sync, CachePadded, Futex, PiFutex, PidNamespace, Shared};
+use std::fs::OpenOptions;
+use std::io;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::ops::Deref;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::ptr::{null_mut, NonNull};
+use std::sync::atomic::{
+	AtomicBool, AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicU16, AtomicU32, AtomicU64,
+	AtomicU8,
+	Ordering::{Acquire, Relaxed, Release},
+};
+
+/// The size of the mapping for a `T`. A mapping can't be empty, even for a zero-sized `T`.
+fn len<T>() -> usize {
+	size_of::<T>().max(1)
+}
+
+/// Map enough shared memory for a `T`, anonymous or from a file.
+fn map<T>(fd: Option<BorrowedFd>) -> io::Result<NonNull<T>> {
+	let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+	assert!(
+		align_of::<T>() <= page_size,
+		"alignment of T exceeds the page size"
+	);
+	let (flags, fd) = match fd {
+		Some(fd) => (libc::MAP_SHARED, fd.as_raw_fd()),
+		None => (libc::MAP_SHARED | libc::MAP_ANONYMOUS, -1),
+	};
+	let ptr = unsafe {
+		libc::mmap(
+			null_mut(),
+			len::<T>(),
+			libc::PROT_READ | libc::PROT_WRITE,
+			flags,
+			fd,
+			0,
+		)
+	};
+	if ptr == libc::MAP_FAILED {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(unsafe { NonNull::new_unchecked(ptr.cast()) })
+}
+
+/// Unmap the memory of a `T` mapped by [`map`].
+///
+/// Aborts if a robust mutex in it is still on the calling thread's robust list.
+unsafe fn unmap<T>(ptr: NonNull<T>) {
+	#[cfg(target_pointer_width = "64")]
+	sync::abort_if_listed(ptr.as_ptr().cast(), len::<T>());
+	libc::munmap(ptr.as_ptr().cast(), len::<T>());
+}
+
+fn check(r: libc::c_int) -> io::Result<libc::c_int> {
+	if r == -1 {
+		Err(io::Error::last_os_error())
+	} else {
+		Ok(r)
+	}
+}
+
+/// A value of type `T` in an anonymous shared memory mapping.
+///
+/// The mapping is created with `MAP_SHARED | MAP_ANONYMOUS`, such that
+/// after a `fork()`, the parent and child process both see and modify the
+/// same value. Both processes then have their own `SharedRegion`, giving
+/// out references that are valid for as long as that `SharedRegion` lives.
+///
+/// Since other processes might still be using the value, dropping a
+/// `SharedRegion` only unmaps the memory in the current process, without
+/// dropping the value. Because the value is accessed by different
+/// processes, it must be [`SharedSafe`]: it can't contain anything that is
+/// only meaningful in one address space, such as pointers, nor any
+/// `Private` futexes.
+pub struct SharedRegion<T> {
+	ptr: NonNull<T>,
+}
+
+unsafe impl<T: Send + Sync> Send for SharedRegion<T> {}
+unsafe impl<T: Send + Sync> Sync for SharedRegion<T> {}
+
+impl<T> SharedRegion<T> {
+	/// A pointer to the value in the shared mapping.
+	#[inline]
+	pub fn as_ptr(&self) -> *const T {
+		self.ptr.as_ptr()
+	}
+}
+
+impl<T: SharedSafe> SharedRegion<T> {
+	/// Map a new shared region, and move `value` into it.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub fn new(value: T) -> io::Result<Self> {
+		let ptr = map::<T>(None)?;
+		unsafe { ptr.as_ptr().write(value) };
+		Ok(Self { ptr })
+	}
+
+	/// Map a new shared region, containing a zeroed `T`.
+	///
+	/// This initializes the value in place, without ever moving a `T`
+	/// through the stack, which matters for large values.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub fn zeroed() -> io::Result<Self> {
+		// A new anonymous mapping is already zeroed.
+		Ok(Self {
+			ptr: map::<T>(None)?,
+		})
+	}
+}
+
+impl<T> Deref for SharedRegion<T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { self.ptr.as_ref() }
+	}
+}
+
+impl<T> Drop for SharedRegion<T> {
+	fn drop(&mut self) {
+		unsafe { unmap(self.ptr) };
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for SharedRegion<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("SharedRegion")
+			.field("ptr", &self.ptr)
+			.field("value", &**self)
+			.finish()
+	}
+}
+
+/// A value of type `T` in a sealed memfd, which any process with the file descriptor can map.
+///
+/// The memfd is created with exactly the size of a `T`, and sealed with
+/// `F_SEAL_SHRINK` and `F_SEAL_GROW`, such that no process can change its
+/// size. Another process that receives the file descriptor (for example
+/// through a Unix socket, or by inheriting it) can map the same value with
+/// [`SharedBox::from_fd`], which checks the size and the seals.
+///
+/// Like with a [`SharedRegion`], dropping a `SharedBox` unmaps the memory
+/// and closes the file descriptor in the current process, without dropping
+/// the value, and the value must be [`SharedSafe`].
+pub struct SharedBox<T> {
+	ptr: NonNull<T>,
+	fd: OwnedFd,
+}
+
+unsafe impl<T: Send + Sync> Send for SharedBox<T> {}
+unsafe impl<T: Send + Sync> Sync for SharedBox<T> {}
+
+/// The seals that make sure a mapped memfd can't shrink or grow.
+const SEALS: libc::c_int = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW;
+
+/// Create a new memfd of the size of a `T`, sealed against shrinking and growing.
+fn memfd<T>() -> io::Result<OwnedFd> {
+	let fd = check(unsafe {
+		libc::memfd_create(
+			b"linux-futex\0".as_ptr().cast(),
+			libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+		)
+	})?;
+	let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+	check(unsafe { libc::ftruncate(fd.as_raw_fd(), len::<T>() as libc::off_t) })?;
+	check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_ADD_SEALS, SEALS | libc::F_SEAL_SEAL) })?;
+	Ok(fd)
+}
+
+impl<T> SharedBox<T> {
+	/// Create a new sealed memfd of the size of a `T`, mapped but still all zeros.
+	fn create() -> io::Result<Self> {
+		let fd = memfd::<T>()?;
+		let ptr = map::<T>(Some(fd.as_fd()))?;
+		Ok(Self { ptr, fd })
+	}
+
+	/// Map the value of a `SharedBox` created in this or another process.
+	///
+	/// Fails with [`InvalidData`][io::ErrorKind::InvalidData] if the file
+	/// does not have the size of a `T`, or is not sealed against shrinking
+	/// and growing.
+	///
+	/// # Safety
+	///
+	/// The file must contain a valid `T`, such as one created by
+	/// [`SharedBox::new`] for the same type `T`.
+	pub unsafe fn from_fd(fd: OwnedFd) -> io::Result<Self> {
+		let seals = check(libc::fcntl(fd.as_raw_fd(), libc::F_GET_SEALS))?;
+		if seals & SEALS != SEALS {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"memfd is not sealed against shrinking and growing",
+			));
+		}
+		let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+		check(libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()))?;
+		if stat.assume_init().st_size as u64 != len::<T>() as u64 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"memfd does not have the size of the shared type",
+			));
+		}
+		let ptr = map::<T>(Some(fd.as_fd()))?;
+		Ok(Self { ptr, fd })
+	}
+
+	/// Send the file descriptor of the memfd over a Unix socket.
+	///
+	/// The receiving process can map the same value with [`SharedBox::recv`].
+	/// See [`send_fd`].
+	pub fn send(&self, socket: &UnixStream) -> io::Result<()> {
+		send_fd(socket, self.fd.as_fd())
+	}
+
+	/// Receive the file descriptor of a memfd sent by [`SharedBox::send`], and map its value.
+	///
+	/// # Safety
+	///
+	/// The file must contain a valid `T`, as for [`SharedBox::from_fd`].
+	pub unsafe fn recv(socket: &UnixStream) -> io::Result<Self> {
+		Self::from_fd(recv_fd(socket)?)
+	}
+
+	/// A pointer to the value in the shared mapping.
+	#[inline]
+	pub fn as_ptr(&self) -> *const T {
+		self.ptr.as_ptr()
+	}
+
+	/// Unmap the value, returning the file descriptor of the memfd.
+	pub fn into_fd(self) -> OwnedFd {
+		let this = std::mem::ManuallyDrop::new(self);
+		unsafe {
+			unmap(this.ptr);
+			std::ptr::read(&this.fd)
+		}
+	}
+}
+
+impl<T: SharedSafe> SharedBox<T> {
+	/// Create a new sealed memfd, and move `value` into it.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub fn new(value: T) -> io::Result<Self> {
+		let this = Self::create()?;
+		unsafe { this.ptr.as_ptr().write(value) };
+		Ok(this)
+	}
+
+	/// Create a new sealed memfd, containing a zeroed `T`.
+	///
+	/// Like [`SharedRegion::zeroed`], this initializes the value in place.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub fn zeroed() -> io::Result<Self> {
+		// A new memfd is already zeroed.
+		Self::create()
+	}
+}
+
+impl<T> Deref for SharedBox<T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { self.ptr.as_ref() }
+	}
+}
+
+impl<T> AsFd for SharedBox<T> {
+	#[inline]
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.fd.as_fd()
+	}
+}
+
+impl<T> AsRawFd for SharedBox<T> {
+	#[inline]
+	fn as_raw_fd(&self) -> RawFd {
+		self.fd.as_raw_fd()
+	}
+}
+
+impl<T> Drop for SharedBox<T> {
+	fn drop(&mut self) {
+		unsafe { unmap(self.ptr) };
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for SharedBox<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("SharedBox")
+			.field("fd", &self.fd.as_raw_fd())
+			.field("value", &**self)
+			.finish()
+	}
+}
+
+/// Send a file descriptor over a Unix socket, using `SCM_RIGHTS`.
+///
+/// This sends a single byte of data, carrying the file descriptor, which
+/// [`recv_fd`] receives on the other end. This way, unrelated processes can
+/// share memory without agreeing on a path in the file system.
+pub fn send_fd(socket: &UnixStream, fd: BorrowedFd) -> io::Result<()> {
+	let mut byte = 0u8;
+	let mut iov = libc::iovec {
+		iov_base: (&mut byte as *mut u8).cast(),
+		iov_len: 1,
+	};
+	let mut control = [0u64; 4];
+	let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr().cast();
+	msg.msg_controllen = unsafe { libc::CMSG_SPACE(size_of::<RawFd>() as u32) } as _;
+	unsafe {
+		let cmsg = libc::CMSG_FIRSTHDR(&msg);
+		(*cmsg).cmsg_level = libc::SOL_SOCKET;
+		(*cmsg).cmsg_type = libc::SCM_RIGHTS;
+		(*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<RawFd>() as u32) as _;
+		libc::CMSG_DATA(cmsg)
+			.cast::<RawFd>()
+			.write_unaligned(fd.as_raw_fd());
+	}
+	loop {
+		match unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) } {
+			-1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+			-1 => return Err(io::Error::last_os_error()),
+			_ => return Ok(()),
+		}
+	}
+}
+
+/// Receive a file descriptor sent by [`send_fd`] over a Unix socket.
+///
+/// The file descriptor is received with `O_CLOEXEC` set. Fails with
+/// [`UnexpectedEof`][io::ErrorKind::UnexpectedEof] if the socket was
+/// closed, and with [`InvalidData`][io::ErrorKind::InvalidData] if the
+/// message did not carry exactly one file descriptor.
+pub fn recv_fd(socket: &UnixStream) -> io::Result<OwnedFd> {
+	let mut byte = 0u8;
+	let mut iov = libc::iovec {
+		iov_base: (&mut byte as *mut u8).cast(),
+		iov_len: 1,
+	};
+	let mut control = [0u64; 4];
+	let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.as_mut_ptr().cast();
+	msg.msg_controllen = std::mem::size_of_val(&control) as _;
+	let n = loop {
+		match unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) } {
+			-1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+			-1 => return Err(io::Error::last_os_error()),
+			n => break n,
+		}
+	};
+	let mut fds = Vec::new();
+	unsafe {
+		let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+		while !cmsg.is_null() {
+			if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+				let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+				let len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+				for i in 0..len / size_of::<RawFd>() {
+					// Take ownership right away, such that they are closed on error.
+					fds.push(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+				}
+			}
+			cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+		}
+	}
+	if n == 0 && fds.is_empty() {
+		return Err(io::ErrorKind::UnexpectedEof.into());
+	}
+	if fds.len() != 1 || msg.msg_flags & libc::MSG_CTRUNC != 0 {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			"expected a message with exactly one file descriptor",
+		));
+	}
+	Ok(fds.pop().unwrap())
+}
+
+/// The directory for named regions: the same tmpfs that `shm_open` uses.
+const SHM_DIR: &str = "/dev/shm";
+
+/// A value of type `T` in a file under `/dev/shm`, which any process can map by its name.
+///
+/// This gives shared futexes the discoverability of named POSIX semaphores:
+/// unrelated processes that agree on a name and a type `T` get the same
+/// value through [`NamedRegion::open_or_create`], without having to pass
+/// around a file descriptor.
+///
+/// The file is initialized before it becomes visible under its name: it is
+/// created and filled in under a temporary name, and then atomically linked
+/// to its final name. If multiple processes race to create the same
+/// region, exactly one of them wins, and all of them map the value that
+/// process created.
+///
+/// Like a file created by `shm_open`, the file remains until it is
+/// [removed][NamedRegion::remove], even when no process has it mapped.
+/// Dropping a `NamedRegion` only unmaps the memory in the current process,
+/// without dropping the value, and the value must be [`SharedSafe`].
+pub struct NamedRegion<T> {
+	ptr: NonNull<T>,
+}
+
+unsafe impl<T: Send + Sync> Send for NamedRegion<T> {}
+unsafe impl<T: Send + Sync> Sync for NamedRegion<T> {}
+
+/// Makes the temporary names of regions that are being created unique within this process.
+static TEMP_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+fn shm_path(name: &str) -> io::Result<PathBuf> {
+	// Names starting with a dot are used for the temporary files.
+	if name.is_empty() || name.contains('/') || name.starts_with('.') {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"invalid name for a shared memory region",
+		));
+	}
+	Ok(Path::new(SHM_DIR).join(name))
+}
+
+/// Removes a file when dropped, including on errors and panics.
+struct RemoveOnDrop<'a>(&'a Path);
+
+impl Drop for RemoveOnDrop<'_> {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(self.0);
+	}
+}
+
+/// Unmaps a mapping when dropped, unless it is forgotten.
+struct UnmapOnDrop<T>(NonNull<T>);
+
+impl<T> Drop for UnmapOnDrop<T> {
+	fn drop(&mut self) {
+		unsafe { unmap(self.0) };
+	}
+}
+
+/// Open the file at `path`, or create it with the value returned by `init`.
+///
+/// A new file is filled in under a temporary name in the same directory,
+/// and then atomically linked to `path`, such that other processes never
+/// see it uninitialized. If another process links its file first, that one
+/// is used instead.
+unsafe fn open_or_create_file<T>(
+	path: &Path,
+	mode: u32,
+	init: impl FnOnce() -> T,
+) -> io::Result<(NonNull<T>, OwnedFd)> {
+	match open_file(path) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+		result => return result,
+	}
+	let file_name = path
+		.file_name()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path does not name a file"))?;
+	let temp = path.with_file_name(format!(
+		".{}.{}.{}",
+		file_name.to_string_lossy(),
+		std::process::id(),
+		TEMP_COUNTER.fetch_add(1, Relaxed)
+	));
+	let file = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.create_new(true)
+		.mode(mode)
+		.open(&temp)?;
+	let _remove = RemoveOnDrop(&temp);
+	file.set_len(len::<T>() as u64)?;
+	let ptr = map::<T>(Some(file.as_fd()))?;
+	// Unmapped again if `init` panics, or if the file doesn't end up being used.
+	let mapping = UnmapOnDrop(ptr);
+	ptr.as_ptr().write(init());
+	match std::fs::hard_link(&temp, path) {
+		Ok(()) => {
+			std::mem::forget(mapping);
+			Ok((ptr, file.into()))
+		}
+		Err(e) => {
+			drop(mapping);
+			if e.kind() != io::ErrorKind::AlreadyExists {
+				return Err(e);
+			}
+			// Another process created it first. Use theirs.
+			open_file(path)
+		}
+	}
+}
+
+/// Open the existing file at `path`, which must have the size of a `T`.
+unsafe fn open_file<T>(path: &Path) -> io::Result<(NonNull<T>, OwnedFd)> {
+	let file = OpenOptions::new().read(true).write(true).open(path)?;
+	if file.metadata()?.len() != len::<T>() as u64 {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			"file does not have the size of the shared type",
+		));
+	}
+	let ptr = map::<T>(Some(file.as_fd()))?;
+	Ok((ptr, file.into()))
+}
+
+impl<T: SharedSafe> NamedRegion<T> {
+	/// Open the region with the given name, or create it with the value returned by `init`.
+	///
+	/// The name must not be empty, contain a `/`, or start with a `.`.
+	/// A newly created file is only accessible by the user that created it.
+	///
+	/// `init` is only called if this process creates the region, but might
+	/// be called even if another process wins the race to create it.
+	///
+	/// # Safety
+	///
+	/// If a file with this name exists, it must contain a valid `T`, such as
+	/// one created by `open_or_create` for the same type `T`, and no process
+	/// may change its size while it is mapped.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub unsafe fn open_or_create(name: &str, init: impl FnOnce() -> T) -> io::Result<Self> {
+		let (ptr, _) = open_or_create_file(&shm_path(name)?, 0o600, init)?;
+		Ok(Self { ptr })
+	}
+
+	/// Open an existing region with the given name.
+	///
+	/// Fails with [`NotFound`][io::ErrorKind::NotFound] if there is no
+	/// region with this name, and with [`InvalidData`][io::ErrorKind::InvalidData]
+	/// if the file does not have the size of a `T`.
+	///
+	/// # Safety
+	///
+	/// The file must contain a valid `T`, such as one created by
+	/// [`open_or_create`][NamedRegion::open_or_create] for the same type `T`,
+	/// and no process may change its size while it is mapped.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub unsafe fn open(name: &str) -> io::Result<Self> {
+		let (ptr, _) = open_file(&shm_path(name)?)?;
+		Ok(Self { ptr })
+	}
+}
+
+impl<T> NamedRegion<T> {
+	/// Remove the region with the given name.
+	///
+	/// Processes that have the region mapped can keep using it, but
+	/// processes that open the name afterwards get a new region.
+	pub fn remove(name: &str) -> io::Result<()> {
+		std::fs::remove_file(shm_path(name)?)
+	}
+
+	/// A pointer to the value in the shared mapping.
+	#[inline]
+	pub fn as_ptr(&self) -> *const T {
+		self.ptr.as_ptr()
+	}
+}
+
+impl<T> Deref for NamedRegion<T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { self.ptr.as_ref() }
+	}
+}
+
+impl<T> Drop for NamedRegion<T> {
+	fn drop(&mut self) {
+		unsafe { unmap(self.ptr) };
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for NamedRegion<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("NamedRegion")
+			.field("ptr", &self.ptr)
+			.field("value", &**self)
+			.finish()
+	}
+}
+
+/// The start of the file of a [`FileRegion`].
+#[repr(C)]
+struct FileHeader {
+	/// The [`SharedLayout::LAYOUT_HASH`] of the value.
+	layout_hash: u64,
+	/// Set once the value is fully initialized.
+	initialized: AtomicU32,
+}
+
+/// The contents of the file of a [`FileRegion`].
+#[repr(C)]
+struct FileContents<T> {
+	header: FileHeader,
+	value: T,
+}
+
+/// A value of type `T` in a regular file, which any process can map by its path.
+///
+/// The file starts with a small header, containing the
+/// [layout hash][SharedLayout::LAYOUT_HASH] of `T` and whether the value
+/// was initialized, followed by the value itself. The first process to open
+/// the file initializes it while holding an exclusive `flock` on it, such
+/// that other processes opening it at the same time wait until the value is
+/// ready. If that process dies during initialization, the next process to
+/// open the file initializes it again.
+///
+/// Unlike a [`NamedRegion`], the file can be on any file system, and keeps
+/// its contents across reboots if it is on persistent storage. The futexes
+/// in it are not reset at that point, so it should not contain locks that
+/// might be held when the system goes down, unless they are robust.
+///
+/// Dropping a `FileRegion` only unmaps the memory in the current process,
+/// without dropping the value.
+pub struct FileRegion<T> {
+	ptr: NonNull<FileContents<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for FileRegion<T> {}
+unsafe impl<T: Send + Sync> Sync for FileRegion<T> {}
+
+/// An exclusive `flock` on a file, released when dropped.
+struct Flock<'a>(&'a std::fs::File);
+
+impl<'a> Flock<'a> {
+	fn lock(file: &'a std::fs::File) -> io::Result<Self> {
+		loop {
+			match check(unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) }) {
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e),
+				Ok(_) => return Ok(Self(file)),
+			}
+		}
+	}
+}
+
+impl Drop for Flock<'_> {
+	fn drop(&mut self) {
+		unsafe { libc::flock(self.0.as_raw_fd(), libc::LOCK_UN) };
+	}
+}
+
+impl<T: SharedLayout> FileRegion<T> {
+	/// Open the file at `path`, creating and initializing it with the value returned by `init` if necessary.
+	///
+	/// A new file is created with mode `0o600`, before the umask is applied.
+	/// `init` is only called if the file is new, or if its initialization
+	/// did not finish before.
+	///
+	/// Fails with [`InvalidData`][io::ErrorKind::InvalidData] if the file
+	/// exists but does not have the right size, or was initialized with a
+	/// different layout of `T`.
+	///
+	/// # Safety
+	///
+	/// The file must only be modified through `FileRegion`s of the same type
+	/// `T`, and no process may change its size while it is mapped.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub unsafe fn open_or_create(
+		path: impl AsRef<Path>,
+		init: impl FnOnce() -> T,
+	) -> io::Result<Self> {
+		let file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.create(true)
+			.truncate(false)
+			.mode(0o600)
+			.open(path)?;
+		let lock = Flock::lock(&file)?;
+		let size = file.metadata()?.len();
+		if size == 0 {
+			file.set_len(len::<FileContents<T>>() as u64)?;
+		} else if size != len::<FileContents<T>>() as u64 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"file does not have the size of the shared type",
+			));
+		}
+		let ptr = map::<FileContents<T>>(Some(file.as_fd()))?;
+		let this = Self { ptr };
+		// The header is only accessed through raw pointers, as the value
+		// and the layout hash are written without any reference to them.
+		let header = std::ptr::addr_of_mut!((*ptr.as_ptr()).header);
+		let initialized = &*std::ptr::addr_of!((*header).initialized);
+		let layout_hash = std::ptr::addr_of_mut!((*header).layout_hash);
+		if initialized.load(Acquire) == 0 {
+			std::ptr::addr_of_mut!((*ptr.as_ptr()).value).write(init());
+			layout_hash.write(T::LAYOUT_HASH);
+			initialized.store(1, Release);
+		} else if layout_hash.read() != T::LAYOUT_HASH {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"file was created for a different layout of the shared type",
+			));
+		}
+		drop(lock);
+		Ok(this)
+	}
+}
+
+impl<T> FileRegion<T> {
+	/// A pointer to the value in the shared mapping.
+	#[inline]
+	pub fn as_ptr(&self) -> *const T {
+		unsafe { std::ptr::addr_of!((*self.ptr.as_ptr()).value) }
+	}
+}
+
+impl<T> Deref for FileRegion<T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &self.ptr.as_ref().value }
+	}
+}
+
+impl<T> Drop for FileRegion<T> {
+	fn drop(&mut self) {
+		unsafe { unmap(self.ptr) };
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for FileRegion<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("FileRegion")
+			.field("ptr", &self.as_ptr())
+			.field("value", &**self)
+			.finish()
+	}
+}
+
+/// The memory behind a [`Mapping`], selected with [`MappingBuilder::new`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backing {
+	/// An anonymous shared mapping, shared with child processes created
+	/// through `fork()`, like a [`SharedRegion`].
+	Anonymous,
+	/// A sealed memfd, which can be passed to other processes as a file
+	/// descriptor, like a [`SharedBox`].
+	Memfd,
+	/// A file under `/dev/shm` with the given name, which is where
+	/// `shm_open` puts its objects on Linux, like a [`NamedRegion`].
+	Shm(String),
+	/// A file at the given path.
+	File(PathBuf),
+}
+
+/// Creates a [`Mapping`] with any [`Backing`].
+///
+/// This allows choosing the kind of shared memory at run time, for example
+/// based on the environment the program is deployed in, while the rest of
+/// the program works with the same `Mapping<T>` type.
+///
+/// By default, a file or shared memory object that doesn't exist yet is
+/// created, with mode `0o600` (before the umask is applied). Files are
+/// initialized before they become visible under their name, in the same
+/// way as a [`NamedRegion`], so processes racing to create the same file
+/// all end up with the same value.
+#[derive(Clone, Debug)]
+pub struct MappingBuilder {
+	backing: Backing,
+	mode: u32,
+	create: bool,
+}
+
+impl MappingBuilder {
+	/// Create a builder for a mapping with the given backing.
+	#[inline]
+	pub fn new(backing: Backing) -> Self {
+		Self {
+			backing,
+			mode: 0o600,
+			create: true,
+		}
+	}
+
+	/// Set the mode of a newly created file or shared memory object.
+	#[inline]
+	pub fn mode(self, mode: u32) -> Self {
+		Self { mode, ..self }
+	}
+
+	/// Whether to create the file or shared memory object if it doesn't exist.
+	///
+	/// If this is `false`, [`build`][MappingBuilder::build] fails with
+	/// [`NotFound`][io::ErrorKind::NotFound] if it doesn't exist, and with
+	/// [`InvalidInput`][io::ErrorKind::InvalidInput] for anonymous and memfd
+	/// backings, which can't be opened.
+	#[inline]
+	pub fn create(self, create: bool) -> Self {
+		Self { create, ..self }
+	}
+
+	/// Create or open the mapping.
+	///
+	/// `init` is called to get the initial value if the memory is new.
+	/// For a file or shared memory object, it might be called even if
+	/// another process wins the race to create it.
+	///
+	/// Fails with [`InvalidData`][io::ErrorKind::InvalidData] if an existing
+	/// file does not have the size of a `T`.
+	///
+	/// # Safety
+	///
+	/// An existing file or shared memory object must contain a valid `T`,
+	/// such as one created by a `MappingBuilder` or [`NamedRegion`] for the
+	/// same type `T`, and no process may change its size while it is mapped.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub unsafe fn build<T: SharedSafe>(self, init: impl FnOnce() -> T) -> io::Result<Mapping<T>> {
+		let (ptr, fd) = match &self.backing {
+			Backing::Anonymous | Backing::Memfd if !self.create => {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					"anonymous and memfd mappings can't be opened",
+				));
+			}
+			Backing::Anonymous => {
+				let ptr = map::<T>(None)?;
+				ptr.as_ptr().write(init());
+				(ptr, None)
+			}
+			Backing::Memfd => {
+				let fd = memfd::<T>()?;
+				let ptr = map::<T>(Some(fd.as_fd()))?;
+				ptr.as_ptr().write(init());
+				(ptr, Some(fd))
+			}
+			Backing::Shm(name) => {
+				let path = shm_path(name)?;
+				let (ptr, fd) = if self.create {
+					open_or_create_file(&path, self.mode, init)?
+				} else {
+					open_file(&path)?
+				};
+				(ptr, Some(fd))
+			}
+			Backing::File(path) => {
+				let (ptr, fd) = if self.create {
+					open_or_create_file(path, self.mode, init)?
+				} else {
+					open_file(path)?
+				};
+				(ptr, Some(fd))
+			}
+		};
+		Ok(Mapping {
+			ptr,
+			fd,
+			backing: self.backing,
+		})
+	}
+}
+
+/// A value of type `T` in shared memory, created by a [`MappingBuilder`].
+///
+/// Like the other shared memory types, dropping a `Mapping` only unmaps the
+/// memory (and closes its file descriptor) in the current process, without
+/// dropping the value, and the value must be [`SharedSafe`].
+pub struct Mapping<T> {
+	ptr: NonNull<T>,
+	fd: Option<OwnedFd>,
+	backing: Backing,
+}
+
+unsafe impl<T: Send + Sync> Send for Mapping<T> {}
+unsafe impl<T: Send + Sync> Sync for Mapping<T> {}
+
+impl<T> Mapping<T> {
+	/// The backing of the mapping.
+	#[inline]
+	pub fn backing(&self) -> &Backing {
+		&self.backing
+	}
+
+	/// The file descriptor of the memory, or `None` for an anonymous mapping.
+	#[inline]
+	pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+		self.fd.as_ref().map(|fd| fd.as_fd())
+	}
+
+	/// A pointer to the value in the shared mapping.
+	#[inline]
+	pub fn as_ptr(&self) -> *const T {
+		self.ptr.as_ptr()
+	}
+}
+
+impl<T> Deref for Mapping<T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { self.ptr.as_ref() }
+	}
+}
+
+impl<T> Drop for Mapping<T> {
+	fn drop(&mut self) {
+		unsafe { unmap(self.ptr) };
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Mapping<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Mapping")
+			.field("backing", &self.backing)
+			.field("ptr", &self.ptr)
+			.field("value", &**self)
+			.finish()
+	}
+}
+
+/// A bump allocator for futexes, locks and other values, living inside a shared mapping.
+///
+/// The arena consists of `N` bytes, which are handed out from front to back
+/// by [`alloc`][SharedArena::alloc], in any process that has the arena
+/// mapped. An allocation is identified by its [`ArenaOffset`] from the start
+/// of the arena, rather than by a pointer, so it refers to the same value in
+/// every process, even if the mapping is at a different address. Offsets
+/// can themselves be stored in shared memory, to pass them to other processes.
+///
+/// Memory is never freed, other than by unmapping the whole arena. Only
+/// [`SharedSafe`] types can be allocated, which are zeroed on allocation.
+/// For fixed-size payload slots, allocate an array.
+///
+/// An arena consisting of only zero bytes is a valid empty arena.
+#[repr(C)]
+pub struct SharedArena<const N: usize> {
+	/// The number of bytes allocated.
+	used: AtomicU32,
+	data: std::cell::UnsafeCell<[u8; N]>,
+}
+
+unsafe impl<const N: usize> Sync for SharedArena<N> {}
+
+/// The offset of a value of type `T` in a [`SharedArena`].
+///
+/// This is the same in every process that maps the arena, and can be stored
+/// in shared memory itself.
+#[repr(transparent)]
+pub struct ArenaOffset<T> {
+	offset: u32,
+	phantom: PhantomData<fn() -> T>,
+}
+
+impl<const N: usize> SharedArena<N> {
+	/// Create a new empty arena.
+	///
+	/// # Panics
+	///
+	/// Panics if `N` does not fit in a `u32`.
+	#[inline]
+	pub const fn new() -> Self {
+		assert!(N <= u32::MAX as usize, "arena too large");
+		Self {
+			used: AtomicU32::new(0),
+			data: std::cell::UnsafeCell::new([0; N]),
+		}
+	}
+
+	/// The size of the arena in bytes: `N`.
+	#[inline]
+	pub const fn capacity(&self) -> usize {
+		N
+	}
+
+	/// The number of bytes that were allocated, including padding for alignment.
+	#[inline]
+	pub fn used(&self) -> usize {
+		self.used.load(Relaxed) as usize
+	}
+
+	/// Allocate a zeroed `T`, returning its offset, or `None` if there is not enough room left.
+	///
+	/// # Panics
+	///
+	/// Panics if `T` needs an alignment larger than the page size.
+	pub fn alloc<T: SharedSafe>(&self) -> Option<ArenaOffset<T>> {
+		// The mapping starts at a page boundary in every process, so aligning
+		// the address aligns it in every process.
+		let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+		assert!(
+			align_of::<T>() <= page_size,
+			"alignment of T exceeds the page size"
+		);
+		let base = self.data.get() as usize;
+		let mut used = self.used.load(Relaxed);
+		loop {
+			let start = (base + used as usize + align_of::<T>() - 1) & !(align_of::<T>() - 1);
+			let offset = start - base;
+			let end = offset.checked_add(size_of::<T>()).filter(|&end| end <= N)?;
+			match self
+				.used
+				.compare_exchange_weak(used, end as u32, Relaxed, Relaxed)
+			{
+				Ok(_) => {
+					return Some(ArenaOffset {
+						offset: offset as u32,
+						phantom: PhantomData,
+					})
+				}
+				Err(u) => used = u,
+			}
+		}
+	}
+
+	/// Get the value at the given offset.
+	///
+	/// # Safety
+	///
+	/// The offset must have been allocated from this arena, in this or
+	/// another process, for the same type `T`.
+	#[inline]
+	pub unsafe fn get<T>(&self, offset: ArenaOffset<T>) -> &T {
+		&*self
+			.data
+			.get()
+			.cast::<u8>()
+			.add(offset.offset as usize)
+			.cast()
+	}
+}
+
+impl<const N: usize> Default for SharedArena<N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<const N: usize> std::fmt::Debug for SharedArena<N> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("SharedArena")
+			.field("capacity", &N)
+			.field("used", &self.used())
+			.finish()
+	}
+}
+
+impl<T> ArenaOffset<T> {
+	/// The offset in bytes from the start of the arena's data.
+	#[inline]
+	pub fn to_raw(self) -> u32 {
+		self.offset
+	}
+
+	/// An offset from a value returned by [`to_raw`][ArenaOffset::to_raw].
+	#[inline]
+	pub fn from_raw(offset: u32) -> Self {
+		Self {
+			offset,
+			phantom: PhantomData,
+		}
+	}
+}
+
+impl<T> Clone for ArenaOffset<T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<T> Copy for ArenaOffset<T> {}
+
+impl<T> PartialEq for ArenaOffset<T> {
+	fn eq(&self, other: &Self) -> bool {
+		self.offset == other.offset
+	}
+}
+
+impl<T> Eq for ArenaOffset<T> {}
+
+impl<T> std::fmt::Debug for ArenaOffset<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_tuple("ArenaOffset").field(&self.offset).finish()
+	}
+}
+
+/// Types that can be placed in memory shared between processes.
+///
+/// This is implemented for integers, atomics, [`Shared`] futexes, and the
+/// [`sync`] primitives using them, and for arrays and
+/// [`CachePadded`] of such types. Structs of such fields can implement it
+/// through [`shared_layout!`][crate::shared_layout].
+///
+/// # Safety
+///
+/// The type must not contain anything that is only meaningful in one
+/// address space, such as pointers, references, file descriptors or
+/// [`Private`][crate::Private] futexes, and must not rely on being dropped.
+/// A value consisting of only zero bytes must be valid, since that is what
+/// new shared memory contains.
+pub unsafe trait SharedSafe {}
+
+/// A [`SharedSafe`] struct declared with [`shared_layout!`][crate::shared_layout].
+pub trait SharedLayout: SharedSafe {
+	/// A hash of the name, fields, field types, sizes and alignments of the struct.
+	///
+	/// Storing this next to the struct in shared memory allows a process
+	/// to detect that another process uses a different version of the
+	/// struct, before interpreting the memory as the wrong type.
+	const LAYOUT_HASH: u64;
+}
+
+macro_rules! impl_shared_safe {
+	($($t:ty),* $(,)?) => {
+		$(unsafe impl SharedSafe for $t {})*
+	};
+}
+
+impl_shared_safe! {
+	bool, u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64,
+	AtomicBool, AtomicU8, AtomicU16, AtomicU32, AtomicU64, AtomicI8, AtomicI16, AtomicI32, AtomicI64,
+	Futex<Shared>, PiFutex<Shared>, PidNamespace,
+	sync::Barrier<Shared>, sync::ChannelGroup<Shared>, sync::Condvar<Shared>, sync::Event<Shared>,
+	sync::EventCount<Shared>, sync::Latch<Shared>, sync::Notify<Shared>, sync::Once<Shared>,
+	sync::Semaphore<Shared>, sync::StampedLock<Shared>, sync::WaitGroup<Shared>,
+}
+
+#[cfg(target_pointer_width = "64")]
+impl_shared_safe! {
+	sync::SharedOnce,
+}
+
+unsafe impl<T: SharedSafe, const N: usize> SharedSafe for [T; N] {}
+unsafe impl<const N: usize> SharedSafe for SharedArena<N> {}
+unsafe impl<T> SharedSafe for ArenaOffset<T> {}
+unsafe impl<T: SharedSafe> SharedSafe for CachePadded<T> {}
+unsafe impl<T: SharedSafe> SharedSafe for sync::Mutex<T, Shared> {}
+unsafe impl<T: SharedSafe> SharedSafe for sync::PiMutex<T, Shared> {}
+unsafe impl<T: SharedSafe> SharedSafe for sync::RwLock<T, Shared> {}
+unsafe impl<T: SharedSafe> SharedSafe for sync::SharedMutex<T> {}
+unsafe impl<T: SharedSafe> SharedSafe for sync::PidMutex<T> {}
+unsafe impl<T: SharedSafe + Copy, const N: usize> SharedSafe for sync::SpscRing<T, N, Shared> {}
+unsafe impl<T: SharedSafe + Copy, const N: usize> SharedSafe for sync::Channel<T, N, Shared> {}
+#[cfg(target_pointer_width = "64")]
+unsafe impl<T: SharedSafe> SharedSafe for sync::RobustMutex<T> {}
+#[cfg(target_pointer_width = "64")]
+unsafe impl<T: SharedSafe> SharedSafe for sync::RobustPiMutex<T> {}
+#[cfg(target_pointer_width = "64")]
+unsafe impl<T: SharedSafe> SharedSafe for sync::SharedPiMutex<T> {}
+
+/// One step of the FNV-1a hash used for [`SharedLayout::LAYOUT_HASH`].
+#[doc(hidden)]
+pub const fn __layout_hash(mut hash: u64, bytes: &[u8]) -> u64 {
+	let mut i = 0;
+	while i < bytes.len() {
+		hash ^= bytes[i] as u64;
+		hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+		i += 1;
+	}
+	hash
+}
+
+/// Declare a `#[repr(C)]` struct for shared memory, checking that all its fields are [`SharedSafe`].
+///
+/// The struct implements [`SharedSafe`] and [`SharedLayout`], such that it
+/// can be created in place with [`SharedRegion::zeroed`] or
+/// [`SharedBox::zeroed`]. A field of any other type, such as a `String` or
+/// a `Futex<Private>`, results in a compilation error. Generic structs are
+/// not supported.
+#[macro_export]
+macro_rules! shared_layout {
+	(
+		$(#[$attr:meta])*
+		$vis:vis struct $name:ident {
+			$($(#[$field_attr:meta])* $field_vis:vis $field:ident : $ty:ty),* $(,)?
+		}
+	) => {
+		$(#[$attr])*
+		#[repr(C)]
+		$vis struct $name {
+			$($(#[$field_attr])* $field_vis $field: $ty,)*
+		}
+
+		const _: fn() = || {
+			fn assert_shared_safe<T: $crate::shm::SharedSafe>() {}
+			$(assert_shared_safe::<$ty>();)*
+		};
+
+		unsafe impl $crate::shm::SharedSafe for $name {}
+
+		impl $crate::shm::SharedLayout for $name {
+			const LAYOUT_HASH: u64 = {
+				let hash = $crate::shm::__layout_hash(0xcbf2_9ce4_8422_2325, stringify!($name).as_bytes());
+				$(
+					let hash = $crate::shm::__layout_hash(hash, stringify!($field).as_bytes());
+					let hash = $crate::shm::__layout_hash(hash, stringify!($ty).as_bytes());
+					let hash = $crate::shm::__layout_hash(hash, &(::core::mem::size_of::<$ty>() as u64).to_le_bytes());
+					let hash = $crate::shm::__layout_hash(hash, &(::core::mem::align_of::<$ty>() as u64).to_le_bytes());
+				)*
+				$crate::shm::__layout_hash(hash, &(::core::mem::size_of::<Self>() as u64).to_le_bytes())
+			};
+		}
+	};
+}
+
+#[cfg(test)]
+mod tests {
+	use super::NamedRegion;
+	use std::panic::{catch_unwind, AssertUnwindSafe};
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering::Relaxed;
+
+	#[test]
+	fn named_region() {
+		let name = format!("linux-futex-test-{}", std::process::id());
+		let a = unsafe { NamedRegion::open_or_create(&name, || AtomicU32::new(1)) }.unwrap();
+		let b =
+			unsafe { NamedRegion::<AtomicU32>::open_or_create(&name, || unreachable!()) }.unwrap();
+		a.store(2, Relaxed);
+		assert_eq!(b.load(Relaxed), 2);
+		NamedRegion::<AtomicU32>::remove(&name).unwrap();
+		assert!(unsafe { NamedRegion::<AtomicU32>::open(&name) }.is_err());
+	}
+
+	#[test]
+	fn named_region_init_panics() {
+		let name = format!("linux-futex-test-panic-{}", std::process::id());
+		let r = catch_unwind(AssertUnwindSafe(|| unsafe {
+			NamedRegion::<AtomicU32>::open_or_create(&name, || panic!("init failed"))
+		}));
+		assert!(r.is_err());
+		// Neither the file nor the temporary mapping of it is left behind.
+		assert!(unsafe { NamedRegion::<AtomicU32>::open(&name) }.is_err());
+		let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+		assert!(!maps.contains(&name), "{}", maps);
+	}
+}
@@ -0,0 +1,265 @@
+use super::mutex::RawMutex;
+use super::poison;
+use super::spin::{AdaptiveSpin, SpinPolicy};
+use crate::{Private, Scope};
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+/// A mutex that spins according to a [`SpinPolicy`] before going to sleep.
+///
+/// This uses the same protocol as [`Mutex`][super::Mutex], except that a
+/// thread that finds the mutex locked spins according to the policy, rather
+/// than for a fixed number of iterations, before waiting on the futex.
+///
+/// By default, it uses an [`AdaptiveSpin`] policy.
+///
+/// Like [`std::sync::Mutex`], the mutex is poisoned when a thread panics
+/// while holding it, after which locking it results in a [`PoisonError`].
+///
+/// An `AdaptiveMutex<T, Shared>` can be used between processes, if it is
+/// placed in shared memory, and the policy can be shared as well.
+#[repr(C)]
+pub struct AdaptiveMutex<T: ?Sized, S = Private, P = AdaptiveSpin> {
+	raw: RawMutex<S>,
+	poison: poison::Flag,
+	policy: P,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send, S, P: Send> Send for AdaptiveMutex<T, S, P> {}
+unsafe impl<T: ?Sized + Send, S, P: Sync> Sync for AdaptiveMutex<T, S, P> {}
+
+/// The lock of an [`AdaptiveMutex`]. The mutex is unlocked when this guard is dropped.
+#[must_use = "if unused the AdaptiveMutex will immediately unlock"]
+pub struct AdaptiveMutexGuard<'a, T: ?Sized, S: Scope = Private, P = AdaptiveSpin> {
+	mutex: &'a AdaptiveMutex<T, S, P>,
+	poison: poison::Guard,
+}
+
+unsafe impl<T: ?Sized + Sync, S: Scope, P: Sync> Sync for AdaptiveMutexGuard<'_, T, S, P> {}
+
+impl<T, S, P: Default> AdaptiveMutex<T, S, P> {
+	/// Create a new unlocked mutex containing `value`, with the default policy.
+	#[inline]
+	pub fn new(value: T) -> Self {
+		Self::with_policy(value, P::default())
+	}
+}
+
+impl<T, S, P> AdaptiveMutex<T, S, P> {
+	/// Create a new unlocked mutex containing `value`, with the given policy.
+	#[inline]
+	pub const fn with_policy(value: T, policy: P) -> Self {
+		Self {
+			raw: RawMutex::new(),
+			poison: poison::Flag::new(),
+			policy,
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the mutex, returning the data it protected.
+	///
+	/// If the mutex is poisoned, the data is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn into_inner(self) -> LockResult<T> {
+		let poisoned = self.poison.get();
+		let data = self.data.into_inner();
+		if poisoned {
+			Err(PoisonError::new(data))
+		} else {
+			Ok(data)
+		}
+	}
+}
+
+impl<T: ?Sized, S, P> AdaptiveMutex<T, S, P> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the mutex.
+	#[inline]
+	pub fn get_mut(&mut self) -> LockResult<&mut T> {
+		let data = self.data.get_mut();
+		self.poison.result(data)
+	}
+
+	/// Check whether the mutex is poisoned.
+	#[inline]
+	pub fn is_poisoned(&self) -> bool {
+		self.poison.get()
+	}
+
+	/// Clear the poisoned state of the mutex.
+	///
+	/// Use this after recovering the protected data from an inconsistent state.
+	#[inline]
+	pub fn clear_poison(&self) {
+		self.poison.clear();
+	}
+
+	/// The spin policy of this mutex.
+	#[inline]
+	pub fn policy(&self) -> &P {
+		&self.policy
+	}
+}
+
+impl<T: ?Sized, S: Scope, P: SpinPolicy> AdaptiveMutex<T, S, P> {
+	/// Lock the mutex, blocking until it is available.
+	///
+	/// Locking a mutex that is already locked by the calling thread results in a deadlock.
+	///
+	/// If the mutex is poisoned, the guard is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn lock(&self) -> LockResult<AdaptiveMutexGuard<'_, T, S, P>> {
+		self.raw.lock_with(&self.policy);
+		self.guard()
+	}
+
+	/// Lock the mutex if it is not locked, without blocking.
+	#[inline]
+	pub fn try_lock(&self) -> TryLockResult<AdaptiveMutexGuard<'_, T, S, P>> {
+		if self.raw.try_lock() {
+			Ok(self.guard()?)
+		} else {
+			Err(TryLockError::WouldBlock)
+		}
+	}
+
+	#[inline]
+	fn guard(&self) -> LockResult<AdaptiveMutexGuard<'_, T, S, P>> {
+		poison::map_result(self.poison.guard(), |poison| AdaptiveMutexGuard {
+			mutex: self,
+			poison,
+		})
+	}
+}
+
+impl<T: ?Sized, S: Scope, P> Deref for AdaptiveMutexGuard<'_, T, S, P> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope, P> DerefMut for AdaptiveMutexGuard<'_, T, S, P> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope, P> Drop for AdaptiveMutexGuard<'_, T, S, P> {
+	#[inline]
+	fn drop(&mut self) {
+		self.mutex.poison.done(&self.poison);
+		unsafe { self.mutex.raw.unlock() }
+	}
+}
+
+impl<T: Default, S, P: Default> Default for AdaptiveMutex<T, S, P> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T, S, P: Default> From<T> for AdaptiveMutex<T, S, P> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope, P: SpinPolicy + std::fmt::Debug> std::fmt::Debug
+	for AdaptiveMutex<T, S, P>
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("AdaptiveMutex");
+		d.field("scope", &std::any::type_name::<S>());
+		d.field("policy", &self.policy);
+		match self.try_lock() {
+			Ok(guard) => d.field("data", &&*guard),
+			Err(TryLockError::Poisoned(e)) => d.field("data", &&**e.get_ref()),
+			Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+		};
+		d.field("poisoned", &self.poison.get());
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope, P> std::fmt::Debug for AdaptiveMutexGuard<'_, T, S, P> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::AdaptiveMutex;
+	use crate::sync::{ExponentialSpin, FixedSpin};
+	use crate::{Private, Shared};
+	use std::sync::TryLockError;
+	use std::thread;
+
+	#[test]
+	fn contention() {
+		let mutex = AdaptiveMutex::<u32>::new(0);
+		thread::scope(|s| {
+			for _ in 0..8 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*mutex.lock().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(mutex.into_inner().unwrap(), 80_000);
+	}
+
+	#[test]
+	fn contention_with_other_policies() {
+		let fixed = AdaptiveMutex::<u32, Shared, _>::with_policy(0, FixedSpin(10));
+		let exponential = AdaptiveMutex::<u32, Private, _>::with_policy(0, ExponentialSpin(64));
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*fixed.lock().unwrap() += 1;
+						*exponential.lock().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(fixed.into_inner().unwrap(), 40_000);
+		assert_eq!(exponential.into_inner().unwrap(), 40_000);
+	}
+
+	#[test]
+	fn try_lock() {
+		let mutex = AdaptiveMutex::<u32>::new(1);
+		let guard = mutex.try_lock().unwrap();
+		assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+		drop(guard);
+		assert_eq!(*mutex.try_lock().unwrap(), 1);
+	}
+
+	#[test]
+	fn poisoning() {
+		let mutex = AdaptiveMutex::<u32>::new(1);
+		let r = thread::scope(|s| {
+			s.spawn(|| {
+				let mut guard = mutex.lock().unwrap();
+				*guard = 2;
+				panic!("poison");
+			})
+			.join()
+		});
+		assert!(r.is_err());
+		assert!(mutex.is_poisoned());
+		assert_eq!(*mutex.lock().unwrap_err().into_inner(), 2);
+		assert!(matches!(mutex.try_lock(), Err(TryLockError::Poisoned(_))));
+		mutex.clear_poison();
+		assert_eq!(mutex.into_inner().unwrap(), 2);
+	}
+}
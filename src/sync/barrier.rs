@@ -0,0 +1,252 @@
+use crate::timeout::deadline;
+use crate::{Futex, Private, Scope, TimedWaitError, WakeMask};
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::time::{Duration, Instant};
+
+/// Set in the generation when a party gave up waiting.
+const BROKEN: u32 = 1 << 31;
+
+/// A reusable barrier, blocking a fixed number of threads until all of them have arrived.
+///
+/// Threads wait on a generation counter, which the last thread to arrive
+/// increments before waking all others, such that the barrier can be reused
+/// for the next round right away.
+///
+/// A party that gives up waiting with [`wait_timeout`][Barrier::wait_timeout]
+/// breaks the barrier: all other parties waiting in the same round, and all
+/// parties arriving after that, get a [`BarrierError::Broken`], until the
+/// barrier is [reset][Barrier::reset]. This way, a party that never shows
+/// up doesn't leave the others blocked forever.
+///
+/// A `Barrier<Shared>` can be used to synchronize processes, if it is placed
+/// in shared memory.
+#[repr(C)]
+pub struct Barrier<S = Private> {
+	/// The generation, plus [`BROKEN`].
+	generation: Futex<S>,
+	arrived: AtomicU32,
+	parties: u32,
+}
+
+/// The result of [`Barrier::wait`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+	/// Returns true for exactly one of the threads of every round: the last one to arrive.
+	#[inline]
+	pub fn is_leader(self) -> bool {
+		self.0
+	}
+}
+
+/// The ways [`Barrier::wait_timeout`] can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BarrierError {
+	/// The timeout expired before all parties arrived, and the barrier is now broken.
+	TimedOut,
+	/// Another party gave up waiting, so the barrier is broken.
+	Broken,
+}
+
+impl std::fmt::Display for BarrierError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::TimedOut => "barrier wait timed out",
+			Self::Broken => "barrier is broken",
+		})
+	}
+}
+
+impl std::error::Error for BarrierError {}
+
+impl<S> Barrier<S> {
+	/// Create a new barrier for `parties` threads.
+	///
+	/// A barrier for zero or one threads never blocks.
+	#[inline]
+	pub const fn new(parties: u32) -> Self {
+		Self {
+			generation: Futex::new(0),
+			arrived: AtomicU32::new(0),
+			parties,
+		}
+	}
+
+	/// Returns true if a party gave up waiting, and the barrier was not reset since.
+	#[inline]
+	pub fn is_broken(&self) -> bool {
+		self.generation.value.load(Relaxed) & BROKEN != 0
+	}
+}
+
+impl<S: Scope> Barrier<S> {
+	/// Block until all parties have called `wait`.
+	///
+	/// # Panics
+	///
+	/// Panics if the barrier is broken.
+	pub fn wait(&self) -> BarrierWaitResult {
+		match self.wait_until(None) {
+			Ok(result) => result,
+			Err(_) => panic!("barrier is broken"),
+		}
+	}
+
+	/// Block until all parties have called `wait`, or until the timeout expires.
+	///
+	/// If the timeout expires first, the barrier is broken, which makes all
+	/// other waiting parties return [`BarrierError::Broken`].
+	pub fn wait_timeout(&self, timeout: Duration) -> Result<BarrierWaitResult, BarrierError> {
+		self.wait_until(deadline(timeout))
+	}
+
+	fn wait_until(&self, deadline: Option<Instant>) -> Result<BarrierWaitResult, BarrierError> {
+		let generation = self.generation.value.load(Acquire);
+		if generation & BROKEN != 0 {
+			return Err(BarrierError::Broken);
+		}
+		let arrived = self.arrived.fetch_add(1, AcqRel) + 1;
+		if arrived >= self.parties {
+			// Reset the counter for the next round before starting it.
+			self.arrived.store(0, Relaxed);
+			let next = generation.wrapping_add(1) & !BROKEN;
+			if self
+				.generation
+				.value
+				.compare_exchange(generation, next, Release, Relaxed)
+				.is_err()
+			{
+				// Someone gave up just before we arrived.
+				return Err(BarrierError::Broken);
+			}
+			self.generation.wake(u32::MAX);
+			return Ok(BarrierWaitResult(true));
+		}
+		loop {
+			let current = self.generation.value.load(Acquire);
+			if current == generation | BROKEN {
+				return Err(BarrierError::Broken);
+			} else if current != generation {
+				return Ok(BarrierWaitResult(false));
+			}
+			match deadline {
+				Some(deadline) => {
+					if let Err(TimedWaitError::TimedOut) =
+						self.generation
+							.wait_bitset_until(generation, WakeMask::ALL, deadline)
+					{
+						if self
+							.generation
+							.value
+							.compare_exchange(generation, generation | BROKEN, Relaxed, Relaxed)
+							.is_ok()
+						{
+							self.generation.wake(u32::MAX);
+							return Err(BarrierError::TimedOut);
+						}
+					}
+				}
+				None => {
+					let _ = self.generation.wait(generation);
+				}
+			}
+		}
+	}
+
+	/// Reset a broken barrier, such that it can be used again.
+	///
+	/// This should only be called when no parties are waiting on the barrier.
+	pub fn reset(&self) {
+		self.arrived.store(0, Relaxed);
+		let _ = self
+			.generation
+			.value
+			.fetch_update(Release, Relaxed, |g| Some(g.wrapping_add(1) & !BROKEN));
+		self.generation.wake(u32::MAX);
+	}
+}
+
+impl<S> std::fmt::Debug for Barrier<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Barrier")
+			.field("scope", &std::any::type_name::<S>())
+			.field("parties", &self.parties)
+			.field("broken", &self.is_broken())
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Barrier, BarrierError};
+	use crate::Private;
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn one_leader_per_round() {
+		let barrier = Barrier::<Private>::new(4);
+		let leaders = AtomicU32::new(0);
+		let arrived = AtomicU32::new(0);
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for round in 1..=100 {
+						arrived.fetch_add(1, Relaxed);
+						if barrier.wait().is_leader() {
+							leaders.fetch_add(1, Relaxed);
+						}
+						// Nobody starts the next round before everyone arrived in this one.
+						assert!(arrived.load(Relaxed) >= round * 4);
+						barrier.wait();
+					}
+				});
+			}
+		});
+		assert_eq!(leaders.load(Relaxed), 100);
+	}
+
+	#[test]
+	fn single_party_never_blocks() {
+		let barrier = Barrier::<Private>::new(1);
+		assert!(barrier.wait().is_leader());
+		assert!(barrier.wait().is_leader());
+		let barrier = Barrier::<Private>::new(0);
+		assert!(barrier.wait().is_leader());
+	}
+
+	#[test]
+	fn timeout_breaks_the_barrier() {
+		let barrier = Barrier::<Private>::new(3);
+		thread::scope(|s| {
+			let waiter = s.spawn(|| barrier.wait_timeout(Duration::from_secs(10)));
+			assert_eq!(
+				barrier.wait_timeout(Duration::from_millis(10)),
+				Err(BarrierError::TimedOut)
+			);
+			assert_eq!(waiter.join().unwrap(), Err(BarrierError::Broken));
+		});
+		assert!(barrier.is_broken());
+		assert_eq!(
+			barrier.wait_timeout(Duration::from_secs(10)),
+			Err(BarrierError::Broken)
+		);
+
+		barrier.reset();
+		assert!(!barrier.is_broken());
+		thread::scope(|s| {
+			let a = s.spawn(|| barrier.wait_timeout(Duration::from_secs(10)));
+			let b = s.spawn(|| barrier.wait_timeout(Duration::from_secs(10)));
+			let c = barrier.wait_timeout(Duration::from_secs(10)).unwrap();
+			let leaders = [a.join().unwrap().unwrap(), b.join().unwrap().unwrap(), c]
+				.iter()
+				.filter(|r| r.is_leader())
+				.count();
+			assert_eq!(leaders, 1);
+		});
+	}
+}
@@ -0,0 +1,156 @@
+use super::EventCount;
+use crate::timeout::deadline;
+use crate::{Private, Scope};
+use std::time::{Duration, Instant};
+
+/// A queue with non-blocking push and pop operations.
+///
+/// Implement this for a (lock-free) queue to make it blocking using [`Blocking`].
+pub trait NonBlockingQueue {
+	type Item;
+
+	/// Push an item, or give it back if the queue is full.
+	fn try_push(&self, item: Self::Item) -> Result<(), Self::Item>;
+
+	/// Pop an item, or return `None` if the queue is empty.
+	fn try_pop(&self) -> Option<Self::Item>;
+}
+
+/// Adds blocking push and pop operations to a [`NonBlockingQueue`].
+///
+/// Popping from an empty queue blocks until an item is pushed, and pushing
+/// onto a full queue blocks until an item is popped.
+///
+/// The blocking is implemented with an [`EventCount`] for each direction,
+/// so pushing and popping only cost a system call when another thread is
+/// blocked on the queue, which can only happen when it was empty or full.
+#[repr(C)]
+pub struct Blocking<Q, S = Private> {
+	not_empty: EventCount<S>,
+	not_full: EventCount<S>,
+	queue: Q,
+}
+
+impl<Q, S> Blocking<Q, S> {
+	/// Wrap a queue.
+	#[inline]
+	pub const fn new(queue: Q) -> Self {
+		Self {
+			not_empty: EventCount::new(),
+			not_full: EventCount::new(),
+			queue,
+		}
+	}
+
+	/// Get a reference to the wrapped queue.
+	///
+	/// Pushing or popping directly on the queue does not wake up blocked threads.
+	#[inline]
+	pub fn get_ref(&self) -> &Q {
+		&self.queue
+	}
+
+	/// Consume the adapter, returning the wrapped queue.
+	#[inline]
+	pub fn into_inner(self) -> Q {
+		self.queue
+	}
+}
+
+impl<Q: NonBlockingQueue, S: Scope> Blocking<Q, S> {
+	/// Push an item, or give it back if the queue is full, without blocking.
+	#[inline]
+	pub fn try_push(&self, item: Q::Item) -> Result<(), Q::Item> {
+		self.queue.try_push(item)?;
+		self.not_empty.notify_one();
+		Ok(())
+	}
+
+	/// Pop an item, or return `None` if the queue is empty, without blocking.
+	#[inline]
+	pub fn try_pop(&self) -> Option<Q::Item> {
+		let item = self.queue.try_pop()?;
+		self.not_full.notify_one();
+		Some(item)
+	}
+
+	/// Push an item, blocking while the queue is full.
+	pub fn push(&self, item: Q::Item) {
+		if self.push_until(item, None).is_err() {
+			unreachable!();
+		}
+	}
+
+	/// Push an item, blocking while the queue is full, or until the timeout expires.
+	///
+	/// Gives the item back if the timeout expired.
+	pub fn push_timeout(&self, item: Q::Item, timeout: Duration) -> Result<(), Q::Item> {
+		self.push_until(item, deadline(timeout))
+	}
+
+	fn push_until(&self, mut item: Q::Item, deadline: Option<Instant>) -> Result<(), Q::Item> {
+		loop {
+			item = match self.try_push(item) {
+				Ok(()) => return Ok(()),
+				Err(item) => item,
+			};
+			let key = self.not_full.prepare_wait();
+			item = match self.try_push(item) {
+				Ok(()) => {
+					self.not_full.cancel_wait();
+					return Ok(());
+				}
+				Err(item) => item,
+			};
+			if !self.not_full.commit_wait_until(key, deadline) {
+				return self.try_push(item);
+			}
+		}
+	}
+
+	/// Pop an item, blocking while the queue is empty.
+	pub fn pop(&self) -> Q::Item {
+		match self.pop_until(None) {
+			Some(item) => item,
+			None => unreachable!(),
+		}
+	}
+
+	/// Pop an item, blocking while the queue is empty, or until the timeout expires.
+	///
+	/// Returns `None` if the timeout expired.
+	pub fn pop_timeout(&self, timeout: Duration) -> Option<Q::Item> {
+		self.pop_until(deadline(timeout))
+	}
+
+	fn pop_until(&self, deadline: Option<Instant>) -> Option<Q::Item> {
+		loop {
+			if let Some(item) = self.try_pop() {
+				return Some(item);
+			}
+			let key = self.not_empty.prepare_wait();
+			if let Some(item) = self.try_pop() {
+				self.not_empty.cancel_wait();
+				return Some(item);
+			}
+			if !self.not_empty.commit_wait_until(key, deadline) {
+				return self.try_pop();
+			}
+		}
+	}
+}
+
+impl<Q: Default, S> Default for Blocking<Q, S> {
+	fn default() -> Self {
+		Self::new(Q::default())
+	}
+}
+
+impl<Q: std::fmt::Debug, S> std::fmt::Debug for Blocking<Q, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Blocking")
+			.field("scope", &std::any::type_name::<S>())
+			.field("queue", &self.queue)
+			.finish()
+	}
+}
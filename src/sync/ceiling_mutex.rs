@@ -0,0 +1,188 @@
+use super::mutex::RawMutex;
+use crate::{Private, Scope};
+use std::cell::UnsafeCell;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// A mutex that runs its owner at a fixed real-time priority: the priority ceiling protocol.
+///
+/// Locking raises the scheduling policy of the calling thread to
+/// `SCHED_FIFO` with the configured ceiling priority, if it isn't already
+/// running at that priority or higher, and unlocking restores the previous
+/// policy and priority. When the ceiling is the highest priority of all
+/// threads using the mutex, no thread holding it can be preempted by
+/// another thread that wants it, avoiding priority inversion without
+/// relying on the kernel's priority inheritance.
+///
+/// Changing the scheduling policy requires `CAP_SYS_NICE` or a sufficient
+/// `RLIMIT_RTPRIO`. Locking fails with an error if it is not permitted.
+///
+/// When holding multiple ceiling mutexes, they must be unlocked in the
+/// reverse order in which they were locked, for the priority to be restored correctly.
+///
+/// A `CeilingMutex<T, Shared>` can be used between processes, if it is
+/// placed in shared memory.
+#[repr(C)]
+pub struct CeilingMutex<T: ?Sized, S = Private> {
+	raw: RawMutex<S>,
+	ceiling: i32,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send, S> Send for CeilingMutex<T, S> {}
+unsafe impl<T: ?Sized + Send, S> Sync for CeilingMutex<T, S> {}
+
+/// The lock of a [`CeilingMutex`]. The mutex is unlocked when this guard is dropped.
+#[must_use = "if unused the CeilingMutex will immediately unlock"]
+pub struct CeilingMutexGuard<'a, T: ?Sized, S: Scope = Private> {
+	mutex: &'a CeilingMutex<T, S>,
+	/// The scheduling policy and parameters to restore, if they were changed.
+	previous: Option<(i32, libc::sched_param)>,
+	/// The priority belongs to the thread that locked the mutex.
+	not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync, S: Scope> Sync for CeilingMutexGuard<'_, T, S> {}
+
+impl<T, S> CeilingMutex<T, S> {
+	/// Create a new unlocked mutex containing `value`, with the given `SCHED_FIFO` priority as ceiling.
+	#[inline]
+	pub const fn new(value: T, ceiling: i32) -> Self {
+		Self {
+			raw: RawMutex::new(),
+			ceiling,
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the mutex, returning the data it protected.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.data.into_inner()
+	}
+}
+
+impl<T: ?Sized, S> CeilingMutex<T, S> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the mutex.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		self.data.get_mut()
+	}
+
+	/// The `SCHED_FIFO` priority a thread runs at while holding the mutex.
+	#[inline]
+	pub fn ceiling(&self) -> i32 {
+		self.ceiling
+	}
+}
+
+impl<T: ?Sized, S: Scope> CeilingMutex<T, S> {
+	/// Raise the priority of the calling thread to the ceiling, and lock the
+	/// mutex, blocking until it is available.
+	///
+	/// Fails if the priority of the calling thread could not be changed.
+	///
+	/// Locking a mutex that is already locked by the calling thread results in a deadlock.
+	pub fn lock(&self) -> io::Result<CeilingMutexGuard<'_, T, S>> {
+		let previous = self.raise()?;
+		self.raw.lock();
+		Ok(CeilingMutexGuard {
+			mutex: self,
+			previous,
+			not_send: PhantomData,
+		})
+	}
+
+	/// Lock the mutex if it is not locked, without blocking, raising the
+	/// priority of the calling thread to the ceiling.
+	///
+	/// Returns `Ok(None)` if the mutex is locked.
+	pub fn try_lock(&self) -> io::Result<Option<CeilingMutexGuard<'_, T, S>>> {
+		let previous = self.raise()?;
+		if self.raw.try_lock() {
+			Ok(Some(CeilingMutexGuard {
+				mutex: self,
+				previous,
+				not_send: PhantomData,
+			}))
+		} else {
+			restore(previous);
+			Ok(None)
+		}
+	}
+
+	/// Raise the priority of the calling thread to the ceiling, if it's lower.
+	///
+	/// Returns the previous policy and parameters, if they were changed.
+	fn raise(&self) -> io::Result<Option<(i32, libc::sched_param)>> {
+		unsafe {
+			let mut policy = 0;
+			let mut param: libc::sched_param = std::mem::zeroed();
+			let e = libc::pthread_getschedparam(libc::pthread_self(), &mut policy, &mut param);
+			if e != 0 {
+				return Err(io::Error::from_raw_os_error(e));
+			}
+			let realtime = policy == libc::SCHED_FIFO || policy == libc::SCHED_RR;
+			if realtime && param.sched_priority >= self.ceiling {
+				return Ok(None);
+			}
+			let ceiling = libc::sched_param {
+				sched_priority: self.ceiling,
+			};
+			let e = libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &ceiling);
+			if e != 0 {
+				return Err(io::Error::from_raw_os_error(e));
+			}
+			Ok(Some((policy, param)))
+		}
+	}
+}
+
+/// Restore the scheduling policy and parameters of the calling thread.
+fn restore(previous: Option<(i32, libc::sched_param)>) {
+	if let Some((policy, param)) = previous {
+		// Lowering the priority is always permitted.
+		unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy, &param) };
+	}
+}
+
+impl<T: ?Sized, S: Scope> Deref for CeilingMutexGuard<'_, T, S> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> DerefMut for CeilingMutexGuard<'_, T, S> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Drop for CeilingMutexGuard<'_, T, S> {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { self.mutex.raw.unlock() };
+		restore(self.previous);
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for CeilingMutex<T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("CeilingMutex")
+			.field("scope", &std::any::type_name::<S>())
+			.field("ceiling", &self.ceiling)
+			.finish_non_exhaustive()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for CeilingMutexGuard<'_, T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
@@ -0,0 +1,310 @@
+use super::{Blocking, NonBlockingQueue};
+use crate::{CachePadded, Private, Scope};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::time::Duration;
+
+/// A bounded lock-free queue, based on Dmitry Vyukov's bounded MPMC queue.
+///
+/// Every slot has a sequence number, telling which lap of the ring it is
+/// ready for. To make an all-zero queue valid, the sequence number of slot
+/// `i` is stored minus `i`.
+#[repr(C)]
+struct Queue<T, const N: usize> {
+	/// The position of the next message to receive.
+	head: CachePadded<AtomicU32>,
+	/// The position of the next message to send.
+	tail: CachePadded<AtomicU32>,
+	sequences: [AtomicU32; N],
+	messages: UnsafeCell<MaybeUninit<[T; N]>>,
+}
+
+impl<T: Copy, const N: usize> Queue<T, N> {
+	#[allow(clippy::declare_interior_mutable_const)]
+	const ZERO: AtomicU32 = AtomicU32::new(0);
+
+	const fn new() -> Self {
+		assert!(N.is_power_of_two(), "capacity must be a power of two");
+		assert!(N <= 1 << 31, "capacity too large");
+		Self {
+			head: CachePadded::new(AtomicU32::new(0)),
+			tail: CachePadded::new(AtomicU32::new(0)),
+			sequences: [Self::ZERO; N],
+			messages: UnsafeCell::new(MaybeUninit::uninit()),
+		}
+	}
+
+	#[inline]
+	fn sequence(&self, pos: u32) -> u32 {
+		let i = pos as usize & (N - 1);
+		self.sequences[i].load(Acquire).wrapping_add(i as u32)
+	}
+
+	#[inline]
+	fn set_sequence(&self, pos: u32, sequence: u32) {
+		let i = pos as usize & (N - 1);
+		self.sequences[i].store(sequence.wrapping_sub(i as u32), Release);
+	}
+
+	#[inline]
+	fn message(&self, pos: u32) -> *mut T {
+		let messages = self.messages.get().cast::<T>();
+		unsafe { messages.add(pos as usize & (N - 1)) }
+	}
+
+	fn len(&self) -> usize {
+		let head = self.head.load(Relaxed);
+		let tail = self.tail.load(Relaxed);
+		(tail.wrapping_sub(head) as usize).min(N)
+	}
+}
+
+impl<T: Copy, const N: usize> NonBlockingQueue for Queue<T, N> {
+	type Item = T;
+
+	fn try_push(&self, message: T) -> Result<(), T> {
+		let mut pos = self.tail.load(Relaxed);
+		loop {
+			let diff = self.sequence(pos).wrapping_sub(pos) as i32;
+			if diff == 0 {
+				match self
+					.tail
+					.compare_exchange_weak(pos, pos.wrapping_add(1), Relaxed, Relaxed)
+				{
+					Ok(_) => {
+						unsafe { self.message(pos).write(message) };
+						self.set_sequence(pos, pos.wrapping_add(1));
+						return Ok(());
+					}
+					Err(p) => pos = p,
+				}
+			} else if diff < 0 {
+				// The slot still holds a message from the previous lap: full.
+				return Err(message);
+			} else {
+				pos = self.tail.load(Relaxed);
+			}
+		}
+	}
+
+	fn try_pop(&self) -> Option<T> {
+		let mut pos = self.head.load(Relaxed);
+		loop {
+			let diff = self.sequence(pos).wrapping_sub(pos.wrapping_add(1)) as i32;
+			if diff == 0 {
+				match self
+					.head
+					.compare_exchange_weak(pos, pos.wrapping_add(1), Relaxed, Relaxed)
+				{
+					Ok(_) => {
+						let message = unsafe { self.message(pos).read() };
+						self.set_sequence(pos, pos.wrapping_add(N as u32));
+						return Some(message);
+					}
+					Err(p) => pos = p,
+				}
+			} else if diff < 0 {
+				// The slot was not written in this lap yet: empty.
+				return None;
+			} else {
+				pos = self.head.load(Relaxed);
+			}
+		}
+	}
+}
+
+/// A bounded multi-producer multi-consumer channel of `N` messages, blocking on futexes.
+///
+/// Any number of threads can send and receive messages at the same time.
+/// Sending and receiving are lock-free, and only block (or wake up another
+/// thread) when the channel is full or empty, through a [`Blocking`]
+/// adapter.
+///
+/// `N` must be a power of two, and at most 2<sup>31</sup>. The messages
+/// are `Copy`, and are never dropped.
+///
+/// A `Channel<T, N, Shared>` can be used between processes, if it is placed
+/// in shared memory, to exchange structured messages without sockets or
+/// serialization. The messages should then not contain anything that is
+/// only meaningful in one address space, such as pointers. A zeroed
+/// `Channel` is a valid empty channel. If a process dies while sending or
+/// receiving, the message it was working on can block the channel.
+#[repr(transparent)]
+pub struct Channel<T, const N: usize, S = Private> {
+	inner: Blocking<Queue<T, N>, S>,
+}
+
+unsafe impl<T: Send, const N: usize, S> Send for Channel<T, N, S> {}
+unsafe impl<T: Send, const N: usize, S> Sync for Channel<T, N, S> {}
+
+impl<T: Copy, const N: usize, S> Channel<T, N, S> {
+	/// Create a new empty channel.
+	///
+	/// # Panics
+	///
+	/// Panics if `N` is not a power of two, or larger than 2<sup>31</sup>.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			inner: Blocking::new(Queue::new()),
+		}
+	}
+
+	/// The number of messages the channel can hold: `N`.
+	#[inline]
+	pub const fn capacity(&self) -> usize {
+		N
+	}
+
+	/// The number of messages in the channel.
+	///
+	/// This is only a snapshot, as other threads might be sending or
+	/// receiving concurrently.
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.inner.get_ref().len()
+	}
+
+	/// Returns true if the channel is empty.
+	///
+	/// This is only a snapshot, like [`len`][Channel::len].
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+impl<T: Copy, const N: usize, S: Scope> Channel<T, N, S> {
+	/// Send a message, or give it back if the channel is full, without blocking.
+	#[inline]
+	pub fn try_send(&self, message: T) -> Result<(), T> {
+		self.inner.try_push(message)
+	}
+
+	/// Send a message, blocking while the channel is full.
+	#[inline]
+	pub fn send(&self, message: T) {
+		self.inner.push(message)
+	}
+
+	/// Send a message, blocking while the channel is full, or until the timeout expires.
+	///
+	/// Gives the message back if the timeout expired.
+	#[inline]
+	pub fn send_timeout(&self, message: T, timeout: Duration) -> Result<(), T> {
+		self.inner.push_timeout(message, timeout)
+	}
+
+	/// Receive a message, or return `None` if the channel is empty, without blocking.
+	#[inline]
+	pub fn try_recv(&self) -> Option<T> {
+		self.inner.try_pop()
+	}
+
+	/// Receive a message, blocking while the channel is empty.
+	#[inline]
+	pub fn recv(&self) -> T {
+		self.inner.pop()
+	}
+
+	/// Receive a message, blocking while the channel is empty, or until the timeout expires.
+	///
+	/// Returns `None` if the timeout expired.
+	#[inline]
+	pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+		self.inner.pop_timeout(timeout)
+	}
+}
+
+impl<T: Copy, const N: usize, S> Default for Channel<T, N, S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T, const N: usize, S> std::fmt::Debug for Channel<T, N, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let queue = self.inner.get_ref();
+		f.debug_struct("Channel")
+			.field("scope", &std::any::type_name::<S>())
+			.field("capacity", &N)
+			.field("head", &queue.head.load(Relaxed))
+			.field("tail", &queue.tail.load(Relaxed))
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Channel;
+	use crate::{Private, Shared};
+	use std::sync::atomic::AtomicU64;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn full_and_empty() {
+		let channel = Channel::<u32, 4, Private>::new();
+		assert!(channel.is_empty());
+		assert_eq!(channel.try_recv(), None);
+		for i in 0..4 {
+			channel.try_send(i).unwrap();
+		}
+		assert_eq!(channel.len(), 4);
+		assert_eq!(channel.try_send(4), Err(4));
+		assert_eq!(channel.send_timeout(4, Duration::from_millis(10)), Err(4));
+		for i in 0..4 {
+			assert_eq!(channel.try_recv(), Some(i));
+		}
+		assert_eq!(channel.recv_timeout(Duration::from_millis(10)), None);
+	}
+
+	#[test]
+	fn many_senders_and_receivers() {
+		let channel = Channel::<u64, 8, Shared>::new();
+		let sum = AtomicU64::new(0);
+		thread::scope(|s| {
+			for t in 0..4 {
+				let channel = &channel;
+				s.spawn(move || {
+					for i in 0..10_000 {
+						channel.send(t * 10_000 + i + 1);
+					}
+				});
+			}
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						sum.fetch_add(channel.recv(), Relaxed);
+					}
+				});
+			}
+		});
+		assert_eq!(sum.into_inner(), 40_000 * 40_001 / 2);
+		assert!(channel.is_empty());
+	}
+
+	#[test]
+	fn wakes_up_sleeping_ends() {
+		let channel = Channel::<u32, 2, Private>::new();
+		thread::scope(|s| {
+			let receiver = s.spawn(|| {
+				assert_eq!(channel.recv_timeout(Duration::from_secs(10)), Some(1));
+				thread::sleep(Duration::from_millis(10));
+				assert_eq!(channel.recv(), 2);
+				assert_eq!(channel.recv(), 3);
+				assert_eq!(channel.recv(), 4);
+			});
+			thread::sleep(Duration::from_millis(10));
+			channel.send(1);
+			channel.send(2);
+			channel.send(3);
+			// Full: waits for the receiver.
+			assert_eq!(channel.send_timeout(4, Duration::from_secs(10)), Ok(()));
+			receiver.join().unwrap();
+		});
+	}
+}
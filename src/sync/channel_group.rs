@@ -0,0 +1,139 @@
+use crate::timeout::deadline;
+use crate::{Futex, Private, Scope, TimedWaitError, WakeMask};
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Release};
+use std::time::{Duration, Instant};
+
+/// Up to 32 independent events, sharing a single futex.
+///
+/// Every bit of the futex is a channel, which is set by
+/// [`notify`][ChannelGroup::notify] and cleared by a thread that
+/// [waits][ChannelGroup::wait] for it. A waiting thread subscribes to any
+/// combination of channels by passing a mask, and is only woken up when one
+/// of those channels is notified, using the wake bitset of the futex.
+///
+/// This takes care of the details that are easy to get wrong when using
+/// [`Futex::wait_bitset`] and [`Futex::wake_bitset`] directly: a waiter
+/// never goes to sleep while one of its channels is already set, a
+/// notification is never lost when another channel changes concurrently,
+/// and the futex is only woken when a notification actually sets a bit.
+///
+/// A `ChannelGroup<Shared>` can be used between processes, if it is placed
+/// in shared memory.
+#[repr(transparent)]
+pub struct ChannelGroup<S = Private> {
+	futex: Futex<S>,
+}
+
+impl<S> ChannelGroup<S> {
+	/// Create a new channel group, with no channels set.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			futex: Futex::new(0),
+		}
+	}
+
+	/// The mask of a single channel, for `index` in `0..32`.
+	///
+	/// # Panics
+	///
+	/// Panics if `index` is 32 or more.
+	#[inline]
+	pub const fn channel(index: u32) -> u32 {
+		assert!(index < 32, "channel index out of range");
+		1 << index
+	}
+
+	/// The channels that are currently set.
+	#[inline]
+	pub fn pending(&self) -> u32 {
+		self.futex.value.load(Acquire)
+	}
+
+	/// Clear the given channels, without waiting, returning which of them were set.
+	#[inline]
+	pub fn take(&self, mask: u32) -> u32 {
+		self.futex.value.fetch_and(!mask, Acquire) & mask
+	}
+}
+
+impl<S: Scope> ChannelGroup<S> {
+	/// Set the given channels, waking up all threads waiting for any of them.
+	///
+	/// Threads waiting only for channels that were already set are not woken up,
+	/// nor are threads waiting only for other channels.
+	#[inline]
+	pub fn notify(&self, channels: u32) {
+		let old = self.futex.value.fetch_or(channels, Release);
+		if let Some(new_bits) = WakeMask::new(channels & !old) {
+			self.futex.wake_bitset(u32::MAX, new_bits);
+		}
+	}
+
+	/// Block until any of the channels in `mask` is set, then clear and return those channels.
+	///
+	/// # Panics
+	///
+	/// Panics if `mask` is zero, since that would wait forever.
+	pub fn wait(&self, mask: u32) -> u32 {
+		match self.wait_until(mask, None) {
+			Some(channels) => channels,
+			None => unreachable!(),
+		}
+	}
+
+	/// Block until any of the channels in `mask` is set, or until the timeout expires.
+	///
+	/// Returns the channels that were set and cleared, or `None` if the timeout expired.
+	///
+	/// # Panics
+	///
+	/// Panics if `mask` is zero.
+	pub fn wait_timeout(&self, mask: u32, timeout: Duration) -> Option<u32> {
+		self.wait_until(mask, deadline(timeout))
+	}
+
+	fn wait_until(&self, mask: u32, deadline: Option<Instant>) -> Option<u32> {
+		let bitset = WakeMask::new(mask).expect("cannot wait for an empty set of channels");
+		loop {
+			let value = self.futex.value.load(Acquire);
+			if value & mask != 0 {
+				let taken = self.futex.value.fetch_and(!mask, AcqRel) & mask;
+				if taken != 0 {
+					return Some(taken);
+				}
+				// Another thread took them first.
+				continue;
+			}
+			// If any bit changes before we sleep, including one of another
+			// channel, the futex value no longer matches and we try again.
+			match deadline {
+				Some(deadline) => {
+					if let Err(TimedWaitError::TimedOut) =
+						self.futex.wait_bitset_until(value, bitset, deadline)
+					{
+						return None;
+					}
+				}
+				None => {
+					let _ = self.futex.wait_bitset(value, bitset);
+				}
+			}
+		}
+	}
+}
+
+impl<S> Default for ChannelGroup<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for ChannelGroup<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("ChannelGroup")
+			.field("scope", &std::any::type_name::<S>())
+			.field("pending", &format_args!("{:#034b}", self.pending()))
+			.finish()
+	}
+}
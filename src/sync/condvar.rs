@@ -0,0 +1,259 @@
+use super::mutex::RawMutex;
+use super::poison;
+use super::MutexGuard;
+use crate::sys::{Error, FutexCall};
+use crate::{Futex, Private, Scope};
+use std::sync::atomic::AtomicIsize;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::LockResult;
+use std::time::Duration;
+
+/// A condition variable, to be used together with a [`Mutex`][super::Mutex].
+///
+/// [`notify_all`][Condvar::notify_all] wakes up only one waiter, and
+/// requeues all the others onto the mutex using `FUTEX_CMP_REQUEUE`, such
+/// that they are woken up one by one as the mutex becomes available,
+/// rather than all at once.
+///
+/// A condition variable must always be used with the same mutex, and both
+/// must be part of the same memory mapping when used between processes.
+#[repr(C)]
+pub struct Condvar<S = Private> {
+	futex: Futex<S>,
+	/// The address of the mutex's futex, relative to this condition variable.
+	///
+	/// Zero if the condition variable was never waited on.
+	mutex: AtomicIsize,
+}
+
+/// Whether a timed wait on a [`Condvar`] or [`PiCondvar`][super::PiCondvar] timed out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WaitTimeoutResult(pub(crate) bool);
+
+impl WaitTimeoutResult {
+	/// Returns true if the wait timed out.
+	#[inline]
+	pub fn timed_out(self) -> bool {
+		self.0
+	}
+}
+
+impl<S> Condvar<S> {
+	/// Create a new condition variable.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			futex: Futex::new(0),
+			mutex: AtomicIsize::new(0),
+		}
+	}
+}
+
+impl<S: Scope> Condvar<S> {
+	/// Unlock the mutex and wait until notified, after which the mutex is locked again.
+	///
+	/// Like most condition variables, this can wake up spuriously.
+	/// Use [`wait_while`][Condvar::wait_while] to wait for a condition.
+	///
+	/// If the mutex is poisoned, the guard is returned inside a [`PoisonError`][std::sync::PoisonError].
+	pub fn wait<'a, T: ?Sized>(
+		&self,
+		guard: MutexGuard<'a, T, S>,
+	) -> LockResult<MutexGuard<'a, T, S>> {
+		let mutex = guard.mutex;
+		let seq = self.prepare_wait(&mutex.raw);
+		drop(guard);
+		let _ = self.futex.wait(seq);
+		mutex.raw.lock_requeued();
+		unsafe { MutexGuard::new(mutex) }
+	}
+
+	/// Wait until notified, or until the timeout expires.
+	///
+	/// See [`wait`][Condvar::wait].
+	pub fn wait_timeout<'a, T: ?Sized>(
+		&self,
+		guard: MutexGuard<'a, T, S>,
+		timeout: Duration,
+	) -> LockResult<(MutexGuard<'a, T, S>, WaitTimeoutResult)> {
+		let mutex = guard.mutex;
+		let seq = self.prepare_wait(&mutex.raw);
+		drop(guard);
+		let r = self.futex.wait_for(seq, timeout);
+		mutex.raw.lock_requeued();
+		let timed_out = WaitTimeoutResult(r == Err(crate::TimedWaitError::TimedOut));
+		poison::map_result(unsafe { MutexGuard::new(mutex) }, |guard| {
+			(guard, timed_out)
+		})
+	}
+
+	/// Wait as long as the condition holds.
+	pub fn wait_while<'a, T: ?Sized>(
+		&self,
+		mut guard: MutexGuard<'a, T, S>,
+		mut condition: impl FnMut(&mut T) -> bool,
+	) -> LockResult<MutexGuard<'a, T, S>> {
+		while condition(&mut *guard) {
+			guard = self.wait(guard)?;
+		}
+		Ok(guard)
+	}
+
+	/// Wake up one waiting thread.
+	#[inline]
+	pub fn notify_one(&self) {
+		self.futex.value.fetch_add(1, Relaxed);
+		self.futex.wake(1);
+	}
+
+	/// Wake up all waiting threads.
+	///
+	/// Only one thread is woken up directly. The others are requeued onto the
+	/// mutex, and are woken up when it gets unlocked.
+	pub fn notify_all(&self) {
+		let mut seq = self.futex.value.fetch_add(1, Relaxed).wrapping_add(1);
+		let offset = self.mutex.load(Relaxed);
+		if offset == 0 {
+			// Nobody ever waited.
+			return;
+		}
+		let mutex = (self as *const Self as isize).wrapping_add(offset) as *const Futex<S>;
+		loop {
+			// We don't have a reference to the mutex, so we can't use Futex::cmp_requeue.
+			// If there are no waiters left, the mutex might not exist anymore.
+			// The kernel doesn't access it in that case, and neither do we.
+			let r = unsafe {
+				FutexCall::new()
+					.futex_op(libc::FUTEX_CMP_REQUEUE + S::futex_flag())
+					.uaddr(&self.futex.value)
+					.uaddr2(mutex as *const _)
+					.val(1)
+					.val2(i32::MAX as u32)
+					.val3(seq)
+					.call()
+			};
+			match r {
+				// Another thread notified in the meantime. Try again.
+				Err(Error(libc::EAGAIN)) => seq = self.futex.value.load(Relaxed),
+				Err(e) => e.panic("FUTEX_CMP_REQUEUE"),
+				Ok(_) => return,
+			}
+		}
+	}
+
+	/// Remember the mutex, and return the sequence number to wait for.
+	fn prepare_wait(&self, mutex: &RawMutex<S>) -> u32 {
+		let offset =
+			(&mutex.futex as *const Futex<S> as isize).wrapping_sub(self as *const Self as isize);
+		self.mutex.store(offset, Relaxed);
+		self.futex.value.load(Relaxed)
+	}
+}
+
+impl<S> Default for Condvar<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for Condvar<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Condvar")
+			.field("scope", &std::any::type_name::<S>())
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Condvar;
+	use crate::sync::Mutex;
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn notify_one() {
+		let mutex = Mutex::<bool>::new(false);
+		let condvar = Condvar::new();
+		thread::scope(|s| {
+			s.spawn(|| {
+				thread::sleep(Duration::from_millis(10));
+				*mutex.lock().unwrap() = true;
+				condvar.notify_one();
+			});
+			let guard = condvar.wait_while(mutex.lock().unwrap(), |ready| !*ready);
+			assert!(*guard.unwrap());
+		});
+	}
+
+	#[test]
+	fn notify_all_requeues_every_waiter() {
+		let mutex = Mutex::<(bool, u32)>::new((false, 0));
+		let condvar = Condvar::new();
+		thread::scope(|s| {
+			for _ in 0..8 {
+				s.spawn(|| {
+					let mut guard = mutex.lock().unwrap();
+					while !guard.0 {
+						guard = condvar.wait(guard).unwrap();
+					}
+					guard.1 += 1;
+				});
+			}
+			thread::sleep(Duration::from_millis(10));
+			mutex.lock().unwrap().0 = true;
+			condvar.notify_all();
+		});
+		assert_eq!(mutex.into_inner().unwrap(), (true, 8));
+	}
+
+	#[test]
+	fn wait_timeout() {
+		let mutex = Mutex::<()>::new(());
+		let condvar = Condvar::new();
+		let start = Instant::now();
+		let (guard, result) = condvar
+			.wait_timeout(mutex.lock().unwrap(), Duration::from_millis(20))
+			.unwrap();
+		assert!(result.timed_out());
+		assert!(start.elapsed() >= Duration::from_millis(20));
+		drop(guard);
+		assert!(mutex.try_lock().is_ok());
+	}
+
+	#[test]
+	fn wait_timeout_notified() {
+		let mutex = Mutex::<bool>::new(false);
+		let condvar = Condvar::new();
+		thread::scope(|s| {
+			let mut guard = mutex.lock().unwrap();
+			s.spawn(|| {
+				*mutex.lock().unwrap() = true;
+				condvar.notify_one();
+			});
+			while !*guard {
+				let (g, result) = condvar
+					.wait_timeout(guard, Duration::from_secs(10))
+					.unwrap();
+				assert!(!result.timed_out());
+				guard = g;
+			}
+		});
+	}
+
+	#[test]
+	fn wait_on_poisoned_mutex() {
+		let mutex = Mutex::<()>::new(());
+		let condvar = Condvar::new();
+		let _ = thread::scope(|s| {
+			s.spawn(|| {
+				let _guard = mutex.lock().unwrap();
+				panic!("poison");
+			})
+			.join()
+		});
+		let guard = mutex.lock().unwrap_err().into_inner();
+		let r = condvar.wait_timeout(guard, Duration::from_millis(1));
+		assert!(r.is_err());
+	}
+}
@@ -0,0 +1,144 @@
+use crate::timeout::deadline;
+use crate::{Futex, Private, Scope, TimedWaitError, WakeMask};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::time::{Duration, Instant};
+
+/// The event is set.
+const SET: u32 = 1;
+/// There might be threads waiting.
+const WAITING: u32 = 2;
+/// The other bits: the number of times the event was set, such that a
+/// waiter doesn't miss a [`Event::set`] followed quickly by a [`Event::reset`].
+const GENERATION: u32 = 4;
+
+/// A manual-reset event: a flag that threads can wait for, which can be set and cleared again.
+///
+/// [`wait`][Event::wait] blocks until [`set`][Event::set] is called, and
+/// keeps returning immediately until [`reset`][Event::reset] is called.
+/// Unlike a [`Latch`][super::Latch], an event can be reused, for signals
+/// such as "configuration reloaded" or "shutdown requested" that are sent
+/// to a group of workers more than once.
+///
+/// Every thread that is waiting when the event is set is woken up and
+/// returns, even if the event is reset again before it gets to run.
+/// Setting the event only wakes up the futex if there are waiting threads.
+///
+/// An `Event<Shared>` can be used between processes, if it is placed in
+/// shared memory. A zeroed `Event` is a valid event that is not set.
+#[repr(transparent)]
+pub struct Event<S = Private> {
+	futex: Futex<S>,
+}
+
+impl<S> Event<S> {
+	/// Create a new event that is not set.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			futex: Futex::new(0),
+		}
+	}
+
+	/// Check whether the event is set.
+	#[inline]
+	pub fn is_set(&self) -> bool {
+		self.futex.value.load(Acquire) & SET != 0
+	}
+
+	/// Clear the event, such that [`wait`][Event::wait] blocks again.
+	///
+	/// Returns true if the event was set.
+	#[inline]
+	pub fn reset(&self) -> bool {
+		self.futex.value.fetch_and(!SET, Relaxed) & SET != 0
+	}
+}
+
+impl<S: Scope> Event<S> {
+	/// Set the event, waking up all waiting threads.
+	///
+	/// Returns false if the event was already set, in which case this has no effect.
+	#[inline]
+	pub fn set(&self) -> bool {
+		let mut old = self.futex.value.load(Relaxed);
+		loop {
+			if old & SET != 0 {
+				return false;
+			}
+			let new = (old & !WAITING).wrapping_add(GENERATION) | SET;
+			match self
+				.futex
+				.value
+				.compare_exchange_weak(old, new, Release, Relaxed)
+			{
+				Ok(_) => break,
+				Err(v) => old = v,
+			}
+		}
+		if old & WAITING != 0 {
+			self.futex.wake(u32::MAX);
+		}
+		true
+	}
+
+	/// Wait until the event is set.
+	#[inline]
+	pub fn wait(&self) {
+		self.wait_until(None);
+	}
+
+	/// Wait until the event is set, or until the timeout expires.
+	///
+	/// Returns false if the timeout expired.
+	#[inline]
+	pub fn wait_timeout(&self, timeout: Duration) -> bool {
+		self.wait_until(deadline(timeout))
+	}
+
+	fn wait_until(&self, deadline: Option<Instant>) -> bool {
+		let generation = self.futex.value.load(Acquire) & !(SET | WAITING);
+		loop {
+			let v = self.futex.value.load(Acquire);
+			if v & SET != 0 || v & !(SET | WAITING) != generation {
+				return true;
+			}
+			if v & WAITING == 0
+				&& self
+					.futex
+					.value
+					.compare_exchange(v, v | WAITING, Relaxed, Relaxed)
+					.is_err()
+			{
+				continue;
+			}
+			match deadline {
+				Some(deadline) => {
+					if let Err(TimedWaitError::TimedOut) =
+						self.futex
+							.wait_bitset_until(v | WAITING, WakeMask::ALL, deadline)
+					{
+						return false;
+					}
+				}
+				None => {
+					let _ = self.futex.wait(v | WAITING);
+				}
+			}
+		}
+	}
+}
+
+impl<S> Default for Event<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for Event<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Event")
+			.field("scope", &std::any::type_name::<S>())
+			.field("set", &self.is_set())
+			.finish()
+	}
+}
@@ -0,0 +1,150 @@
+use crate::timeout::deadline;
+use crate::{Futex, Private, Scope, TimedWaitError, WakeMask};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, SeqCst};
+use std::sync::atomic::{fence, AtomicU32};
+use std::time::{Duration, Instant};
+
+/// Adds blocking to lock-free data structures, without a mutex.
+///
+/// A thread that wants to wait for a condition (for example, a queue being
+/// non-empty) does the following:
+///
+/// 1. Call [`prepare_wait`][EventCount::prepare_wait].
+/// 2. Check the condition.
+/// 3. If the condition holds, call [`cancel_wait`][EventCount::cancel_wait].
+///    Otherwise, call [`commit_wait`][EventCount::commit_wait] with the key
+///    from the first step, and start over after it returns.
+///
+/// A thread that makes the condition true calls
+/// [`notify_one`][EventCount::notify_one] or [`notify_all`][EventCount::notify_all]
+/// afterwards. Any notification after `prepare_wait` makes `commit_wait`
+/// return, so no notification can get lost between checking the condition and
+/// going to sleep.
+///
+/// Notifying is cheap when nobody is waiting: it only loads the number of
+/// waiters.
+///
+/// An `EventCount<Shared>` can be used between processes, if it is placed in
+/// shared memory.
+#[repr(C)]
+pub struct EventCount<S = Private> {
+	/// The epoch, incremented by every notification while there are waiters.
+	futex: Futex<S>,
+	/// The number of threads between `prepare_wait` and `commit_wait` or `cancel_wait`.
+	waiters: AtomicU32,
+}
+
+/// Returned by [`EventCount::prepare_wait`], to be passed to [`EventCount::commit_wait`].
+#[must_use = "a prepared wait must be committed or cancelled"]
+#[derive(Debug)]
+pub struct EventKey(u32);
+
+impl<S> EventCount<S> {
+	/// Create a new `EventCount`.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			futex: Futex::new(0),
+			waiters: AtomicU32::new(0),
+		}
+	}
+}
+
+impl<S: Scope> EventCount<S> {
+	/// Announce that this thread is about to check its condition, and might wait.
+	///
+	/// Must be followed by either [`commit_wait`][EventCount::commit_wait]
+	/// or [`cancel_wait`][EventCount::cancel_wait].
+	#[inline]
+	pub fn prepare_wait(&self) -> EventKey {
+		self.waiters.fetch_add(1, SeqCst);
+		EventKey(self.futex.value.load(SeqCst))
+	}
+
+	/// Don't wait after all, because the condition already holds.
+	#[inline]
+	pub fn cancel_wait(&self) {
+		self.waiters.fetch_sub(1, SeqCst);
+	}
+
+	/// Wait until notified, unless a notification already happened since the
+	/// [`prepare_wait`][EventCount::prepare_wait] that returned `key`.
+	#[inline]
+	pub fn commit_wait(&self, key: EventKey) {
+		self.commit_wait_until(key, None);
+	}
+
+	/// Wait until notified, or until the timeout expires.
+	///
+	/// See [`commit_wait`][EventCount::commit_wait].
+	///
+	/// Returns false if the timeout expired.
+	#[inline]
+	pub fn commit_wait_timeout(&self, key: EventKey, timeout: Duration) -> bool {
+		self.commit_wait_until(key, deadline(timeout))
+	}
+
+	pub(crate) fn commit_wait_until(&self, key: EventKey, deadline: Option<Instant>) -> bool {
+		let notified = loop {
+			if self.futex.value.load(Acquire) != key.0 {
+				break true;
+			}
+			match deadline {
+				Some(deadline) => {
+					if let Err(TimedWaitError::TimedOut) =
+						self.futex.wait_bitset_until(key.0, WakeMask::ALL, deadline)
+					{
+						break false;
+					}
+				}
+				None => {
+					let _ = self.futex.wait(key.0);
+				}
+			}
+		};
+		self.waiters.fetch_sub(1, SeqCst);
+		notified
+	}
+
+	/// Wake up one waiting thread.
+	///
+	/// All threads that are between [`prepare_wait`][EventCount::prepare_wait]
+	/// and [`commit_wait`][EventCount::commit_wait] will not go to sleep, but
+	/// only one thread that is already asleep is woken up.
+	#[inline]
+	pub fn notify_one(&self) {
+		self.notify(1);
+	}
+
+	/// Wake up all waiting threads.
+	#[inline]
+	pub fn notify_all(&self) {
+		self.notify(u32::MAX);
+	}
+
+	#[inline]
+	fn notify(&self, n: u32) {
+		// Order the caller's changes to the condition before the load of the
+		// number of waiters, matching the order in prepare_wait.
+		fence(SeqCst);
+		if self.waiters.load(Relaxed) > 0 {
+			self.futex.value.fetch_add(1, SeqCst);
+			self.futex.wake(n);
+		}
+	}
+}
+
+impl<S> Default for EventCount<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for EventCount<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("EventCount")
+			.field("scope", &std::any::type_name::<S>())
+			.field("waiters", &self.waiters.load(Relaxed))
+			.finish()
+	}
+}
@@ -0,0 +1,223 @@
+use crate::timeout::deadline;
+use crate::{Futex, Private, TimedWaitError, WakeMask};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, SeqCst};
+use std::time::{Duration, Instant};
+
+/// Nobody is waiting for a partner.
+const EMPTY: u32 = 0;
+/// A thread is putting its value in the first slot.
+const FILLING: u32 = 1;
+/// A thread is waiting for a partner, with its value in the first slot.
+const WAITING: u32 = 2;
+/// A partner took the value in the first slot, and is putting its own value in the second slot.
+const CLAIMED: u32 = 3;
+/// The partner's value is in the second slot, ready to be taken by the waiting thread.
+const DONE: u32 = 4;
+
+/// A rendezvous point where pairs of threads swap values.
+///
+/// The first thread to call [`exchange`][Exchanger::exchange] blocks until
+/// a second thread does the same, after which both return the value of the
+/// other. Any other threads arriving during an exchange wait until it's
+/// finished, and then pair up with each other.
+///
+/// This is useful for double buffering, where a producer hands a filled
+/// buffer to a consumer and gets an empty one back in return.
+pub struct Exchanger<T> {
+	state: Futex<Private>,
+	/// The number of threads that are (about to be) asleep on the futex.
+	sleepers: AtomicU32,
+	first: UnsafeCell<MaybeUninit<T>>,
+	second: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for Exchanger<T> {}
+unsafe impl<T: Send> Sync for Exchanger<T> {}
+
+impl<T> Exchanger<T> {
+	/// Create a new exchanger.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			state: Futex::new(EMPTY),
+			sleepers: AtomicU32::new(0),
+			first: UnsafeCell::new(MaybeUninit::uninit()),
+			second: UnsafeCell::new(MaybeUninit::uninit()),
+		}
+	}
+
+	/// Wait for a partner thread, and swap `value` for the value of the partner.
+	pub fn exchange(&self, value: T) -> T {
+		match self.exchange_until(value, None) {
+			Ok(value) => value,
+			Err(_) => unreachable!(),
+		}
+	}
+
+	/// Wait for a partner thread, and swap `value` for the value of the partner, or until the timeout expires.
+	///
+	/// If no partner arrived before the timeout expired, `value` is given back in the `Err`.
+	pub fn exchange_timeout(&self, value: T, timeout: Duration) -> Result<T, T> {
+		self.exchange_until(value, deadline(timeout))
+	}
+
+	fn exchange_until(&self, value: T, deadline: Option<Instant>) -> Result<T, T> {
+		loop {
+			let state = self.state.value.load(Acquire);
+			match state {
+				EMPTY => {
+					if self
+						.state
+						.value
+						.compare_exchange(EMPTY, FILLING, Acquire, Relaxed)
+						.is_ok()
+					{
+						unsafe { (*self.first.get()).write(value) };
+						self.set_state(WAITING);
+						return self.wait_for_partner(deadline);
+					}
+				}
+				WAITING => {
+					if self
+						.state
+						.value
+						.compare_exchange(WAITING, CLAIMED, Acquire, Relaxed)
+						.is_ok()
+					{
+						let theirs = unsafe { (*self.first.get()).assume_init_read() };
+						unsafe { (*self.second.get()).write(value) };
+						self.set_state(DONE);
+						return Ok(theirs);
+					}
+				}
+				_ => {
+					// Another pair is busy exchanging. Wait for them to finish.
+					if !self.wait(state, deadline) {
+						return Err(value);
+					}
+				}
+			}
+		}
+	}
+
+	/// Wait until a partner has put its value in the second slot, and take it.
+	///
+	/// Our value must be in the first slot.
+	fn wait_for_partner(&self, mut deadline: Option<Instant>) -> Result<T, T> {
+		loop {
+			match self.state.value.load(Acquire) {
+				DONE => break,
+				WAITING => {
+					if !self.wait(WAITING, deadline)
+						&& self
+							.state
+							.value
+							.compare_exchange(WAITING, EMPTY, Acquire, Relaxed)
+							.is_ok()
+					{
+						// Nobody came. Take our value back.
+						return Err(unsafe { (*self.first.get()).assume_init_read() });
+					}
+				}
+				CLAIMED => {
+					// A partner is just about to give us its value, so we no
+					// longer care about the timeout.
+					deadline = None;
+					self.wait(CLAIMED, None);
+				}
+				_ => unreachable!(),
+			}
+		}
+		let value = unsafe { (*self.second.get()).assume_init_read() };
+		self.set_state(EMPTY);
+		Ok(value)
+	}
+
+	/// Block while the state is `state`, until the deadline (if any).
+	///
+	/// Returns false if the deadline passed.
+	fn wait(&self, state: u32, deadline: Option<Instant>) -> bool {
+		self.sleepers.fetch_add(1, SeqCst);
+		let result = match deadline {
+			Some(deadline) => self.state.wait_bitset_until(state, WakeMask::ALL, deadline),
+			None => self.state.wait(state).map_err(TimedWaitError::from),
+		};
+		self.sleepers.fetch_sub(1, Relaxed);
+		result != Err(TimedWaitError::TimedOut)
+	}
+
+	fn set_state(&self, state: u32) {
+		self.state.value.store(state, SeqCst);
+		// Both the waiting thread and any threads waiting for their turn
+		// might be asleep, so wake them all.
+		if self.sleepers.load(SeqCst) != 0 {
+			self.state.wake(u32::MAX);
+		}
+	}
+}
+
+impl<T> Default for Exchanger<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T> std::fmt::Debug for Exchanger<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Exchanger")
+			.field("waiting", &(self.state.value.load(Relaxed) != EMPTY))
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Exchanger;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn swap() {
+		let exchanger = Exchanger::new();
+		thread::scope(|s| {
+			let other = s.spawn(|| exchanger.exchange(String::from("b")));
+			assert_eq!(exchanger.exchange(String::from("a")), "b");
+			assert_eq!(other.join().unwrap(), "a");
+		});
+	}
+
+	#[test]
+	fn many_pairs() {
+		let exchanger = Exchanger::new();
+		let results: Vec<usize> = thread::scope(|s| {
+			let threads: Vec<_> = (0..8)
+				.map(|i| {
+					let exchanger = &exchanger;
+					s.spawn(move || exchanger.exchange(i))
+				})
+				.collect();
+			threads.into_iter().map(|t| t.join().unwrap()).collect()
+		});
+		for (i, &j) in results.iter().enumerate() {
+			assert_ne!(i, j);
+			assert_eq!(results[j], i);
+		}
+	}
+
+	#[test]
+	fn timeout() {
+		let exchanger = Exchanger::new();
+		assert_eq!(
+			exchanger.exchange_timeout(1, Duration::from_millis(10)),
+			Err(1)
+		);
+		thread::scope(|s| {
+			let other = s.spawn(|| exchanger.exchange_timeout(2, Duration::from_secs(10)));
+			assert_eq!(exchanger.exchange(3), 2);
+			assert_eq!(other.join().unwrap(), Ok(3));
+		});
+	}
+}
@@ -0,0 +1,97 @@
+use crate::{Futex, Private, Scope};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::time::Duration;
+
+/// Not set, and nobody is waiting.
+const UNSET: u32 = 0;
+/// Not set, and there might be threads waiting.
+const WAITING: u32 = 1;
+/// Set.
+const SET: u32 = 2;
+
+/// A one-shot signal: once set, it stays set forever.
+///
+/// [`wait`][Latch::wait] blocks until [`set`][Latch::set] is called, and
+/// returns immediately afterwards. This is useful for flags such as
+/// "initialization finished" or "shutting down".
+///
+/// Setting the latch only wakes up the futex if there are waiting threads.
+///
+/// A `Latch<Shared>` can be used between processes, if it is placed in
+/// shared memory.
+#[repr(transparent)]
+pub struct Latch<S = Private> {
+	futex: Futex<S>,
+}
+
+impl<S> Latch<S> {
+	/// Create a new latch that is not set.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			futex: Futex::new(UNSET),
+		}
+	}
+
+	/// Check whether the latch is set.
+	#[inline]
+	pub fn is_set(&self) -> bool {
+		self.futex.value.load(Acquire) == SET
+	}
+}
+
+impl<S: Scope> Latch<S> {
+	/// Set the latch, waking up all waiting threads.
+	///
+	/// Setting a latch that is already set has no effect.
+	#[inline]
+	pub fn set(&self) {
+		if self.futex.value.swap(SET, Release) == WAITING {
+			self.futex.wake(u32::MAX);
+		}
+	}
+
+	/// Wait until the latch is set.
+	#[inline]
+	pub fn wait(&self) {
+		if !self.is_set() {
+			self.mark_waiting();
+			self.futex.wait_while(|v| v != SET);
+		}
+	}
+
+	/// Wait until the latch is set, or until the timeout expires.
+	///
+	/// Returns false if the timeout expired.
+	#[inline]
+	pub fn wait_timeout(&self, timeout: Duration) -> bool {
+		if self.is_set() {
+			return true;
+		}
+		self.mark_waiting();
+		self.futex.wait_while_for(timeout, |v| v != SET).is_ok()
+	}
+
+	#[inline]
+	fn mark_waiting(&self) {
+		let _ = self
+			.futex
+			.value
+			.compare_exchange(UNSET, WAITING, Relaxed, Relaxed);
+	}
+}
+
+impl<S> Default for Latch<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for Latch<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Latch")
+			.field("scope", &std::any::type_name::<S>())
+			.field("set", &self.is_set())
+			.finish()
+	}
+}
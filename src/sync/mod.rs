@@ -0,0 +1,123 @@
+//! Synchronization primitives built on futexes.
+//!
+//! Most primitives in this module are generic over the [`Scope`][crate::Scope]
+//! of the futexes they use. With [`Shared`][crate::Shared], they can be placed
+//! in memory shared between processes, as long as the data they protect does
+//! not contain anything that is only meaningful in one address space, such as
+//! pointers.
+//!
+//! [`futex_static!`][crate::futex_static] declares statics protected by
+//! one of the locks of this module.
+//!
+//! With the `lock_api` feature, `RawFutexMutex` and `RawFutexRwLock`
+//! implement the traits of the [`lock_api`](https://docs.rs/lock_api) crate.
+
+mod adaptive_mutex;
+mod barrier;
+mod blocking;
+mod ceiling_mutex;
+mod channel;
+mod channel_group;
+mod condvar;
+mod event;
+mod event_count;
+mod exchanger;
+mod latch;
+mod mutex;
+mod notify;
+mod once;
+mod parker;
+mod phaser;
+mod pi_condvar;
+mod pi_mutex;
+mod pid_mutex;
+mod poison;
+mod queue_lock;
+mod rate_limiter;
+#[cfg(feature = "lock_api")]
+mod raw;
+#[cfg(target_pointer_width = "64")]
+mod robust_list;
+#[cfg(target_pointer_width = "64")]
+mod robust_mutex;
+#[cfg(target_pointer_width = "64")]
+mod robust_pi_mutex;
+mod rwlock;
+mod semaphore;
+mod sharded_lock;
+mod shared_mutex;
+#[cfg(target_pointer_width = "64")]
+mod shared_once;
+#[cfg(target_pointer_width = "64")]
+mod shared_pi_mutex;
+mod spin;
+mod spsc;
+mod stamped_lock;
+mod wait_group;
+
+pub use adaptive_mutex::{AdaptiveMutex, AdaptiveMutexGuard};
+pub use barrier::{Barrier, BarrierError, BarrierWaitResult};
+pub use blocking::{Blocking, NonBlockingQueue};
+pub use ceiling_mutex::{CeilingMutex, CeilingMutexGuard};
+pub use channel::Channel;
+pub use channel_group::ChannelGroup;
+pub use condvar::{Condvar, WaitTimeoutResult};
+pub use event::Event;
+pub use event_count::{EventCount, EventKey};
+pub use exchanger::Exchanger;
+pub use latch::Latch;
+pub use mutex::{Mutex, MutexGuard};
+pub(crate) use mutex::{RawMutex, CONTENDED, UNLOCKED};
+pub use notify::Notify;
+pub use once::{Once, OnceCell, OnceState};
+pub use parker::{Parker, Unparker};
+pub use phaser::Phaser;
+pub use pi_condvar::PiCondvar;
+pub use pi_mutex::{PiMutex, PiMutexGuard};
+pub use pid_mutex::{PidMutex, PidMutexGuard};
+pub use queue_lock::{QueueLock, QueueLockGuard};
+pub use rate_limiter::RateLimiter;
+#[cfg(feature = "lock_api")]
+pub use raw::{RawFutexMutex, RawFutexRwLock};
+#[cfg(target_pointer_width = "64")]
+pub(crate) use robust_list::abort_if_listed;
+#[cfg(target_pointer_width = "64")]
+pub use robust_mutex::{LockState, RobustLockError, RobustMutex, RobustMutexGuard};
+#[cfg(target_pointer_width = "64")]
+pub use robust_pi_mutex::{RobustPiMutex, RobustPiMutexGuard};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard};
+pub use semaphore::Semaphore;
+pub(crate) use semaphore::MANY;
+pub use sharded_lock::{ShardedLock, ShardedLockReadGuard, ShardedLockWriteGuard};
+pub use shared_mutex::SharedMutex;
+#[cfg(target_pointer_width = "64")]
+pub use shared_once::SharedOnce;
+#[cfg(target_pointer_width = "64")]
+pub use shared_pi_mutex::SharedPiMutex;
+pub use spin::{AdaptiveSpin, ExponentialSpin, FixedSpin, SpinPolicy};
+pub use spsc::{SpscConsumer, SpscProducer, SpscRing};
+pub use stamped_lock::{Stamp, StampedLock, StampedReadGuard, StampedWriteGuard};
+pub use wait_group::{WaitGroup, WaitGroupError};
+
+/// Declare statics protected by a lock of this module.
+///
+/// Each static is written as `static NAME: Lock<T> = value;`, where `Lock` is
+/// the name of a lock of the [`sync`][crate::sync] module with a `const fn
+/// new(value: T)`, such as [`Mutex`] or [`RwLock`], and `value` is a constant
+/// expression of type `T`. The lock does not need to be imported. A
+/// [`Scope`][crate::Scope] can be given as a second parameter, as in
+/// `Mutex<T, Shared>`, but defaults to [`Private`][crate::Private].
+///
+/// Attributes, doc comments and visibility are kept.
+#[macro_export]
+macro_rules! futex_static {
+	($(
+		$(#[$attr:meta])*
+		$vis:vis static $name:ident : $lock:ident < $ty:ty $(, $scope:ty)? > = $init:expr;
+	)*) => {
+		$(
+			$(#[$attr])*
+			$vis static $name: $crate::sync::$lock<$ty $(, $scope)?> = $crate::sync::$lock::new($init);
+		)*
+	};
+}
@@ -0,0 +1,397 @@
+use super::poison;
+use super::spin::{FixedSpin, SpinPolicy};
+use crate::{Futex, Private, Scope};
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+/// The futex is not locked.
+pub(crate) const UNLOCKED: u32 = 0;
+/// The futex is locked, and there are no other threads waiting for it.
+const LOCKED: u32 = 1;
+/// The futex is locked, and there might be other threads waiting for it.
+pub(crate) const CONTENDED: u32 = 2;
+
+/// A mutex without any data, implementing the three-state futex protocol.
+#[repr(transparent)]
+pub(crate) struct RawMutex<S> {
+	pub(crate) futex: Futex<S>,
+}
+
+impl<S> RawMutex<S> {
+	#[inline]
+	pub(crate) const fn new() -> Self {
+		Self {
+			futex: Futex::new(UNLOCKED),
+		}
+	}
+}
+
+impl<S: Scope> RawMutex<S> {
+	#[inline]
+	pub(crate) fn try_lock(&self) -> bool {
+		self.futex
+			.value
+			.compare_exchange(UNLOCKED, LOCKED, Acquire, Relaxed)
+			.is_ok()
+	}
+
+	#[inline]
+	pub(crate) fn lock(&self) {
+		self.lock_with(&FixedSpin::new(100));
+	}
+
+	#[inline]
+	pub(crate) fn lock_with(&self, policy: &impl SpinPolicy) {
+		if !self.try_lock() {
+			self.lock_contended(policy);
+		}
+	}
+
+	#[cold]
+	fn lock_contended(&self, policy: &impl SpinPolicy) {
+		let mut attempt = 0;
+		let mut state = self.spin(policy, &mut attempt);
+
+		// Try to lock it without marking it as contended, if it's unlocked.
+		if state == UNLOCKED {
+			match self
+				.futex
+				.value
+				.compare_exchange(UNLOCKED, LOCKED, Acquire, Relaxed)
+			{
+				Ok(_) => return policy.acquired(attempt, false),
+				Err(s) => state = s,
+			}
+		}
+
+		let mut slept = false;
+		loop {
+			// Mark the mutex as contended, and take the lock if it was unlocked.
+			if state != CONTENDED && self.futex.value.swap(CONTENDED, Acquire) == UNLOCKED {
+				return policy.acquired(attempt, slept);
+			}
+			let _ = self.futex.wait(CONTENDED);
+			slept = true;
+			state = self.spin(policy, &mut attempt);
+		}
+	}
+
+	/// Lock the mutex, or give up once the deadline has passed.
+	///
+	/// Returns false if the deadline passed before the mutex could be locked.
+	#[cfg(feature = "lock_api")]
+	#[inline]
+	pub(crate) fn try_lock_until(&self, deadline: std::time::Instant) -> bool {
+		self.try_lock() || self.lock_contended_until(deadline)
+	}
+
+	#[cfg(feature = "lock_api")]
+	#[cold]
+	fn lock_contended_until(&self, deadline: std::time::Instant) -> bool {
+		while self.futex.value.swap(CONTENDED, Acquire) != UNLOCKED {
+			if let Err(crate::TimedWaitError::TimedOut) =
+				self.futex
+					.wait_bitset_until(CONTENDED, crate::WakeMask::ALL, deadline)
+			{
+				return false;
+			}
+		}
+		true
+	}
+
+	/// Lock the mutex, marking it as contended.
+	///
+	/// This is used after waiting on a [`Condvar`][super::Condvar], since other
+	/// waiters might have been requeued onto the mutex. Always marking the
+	/// mutex as contended makes sure those get woken up when we unlock it.
+	pub(crate) fn lock_requeued(&self) {
+		while self.futex.value.swap(CONTENDED, Acquire) != UNLOCKED {
+			let _ = self.futex.wait(CONTENDED);
+		}
+	}
+
+	/// Spin for as long as the policy allows, while the mutex is locked but not contended.
+	fn spin(&self, policy: &impl SpinPolicy, attempt: &mut u32) -> u32 {
+		loop {
+			let state = self.futex.value.load(Relaxed);
+			if state != LOCKED {
+				return state;
+			}
+			match policy.backoff(*attempt) {
+				Some(n) => {
+					for _ in 0..n {
+						std::hint::spin_loop();
+					}
+					*attempt += 1;
+				}
+				None => return state,
+			}
+		}
+	}
+
+	/// Unlock the mutex, waking up one waiter if there might be any.
+	///
+	/// The mutex must be locked.
+	#[inline]
+	pub(crate) unsafe fn unlock(&self) {
+		if self.futex.value.swap(UNLOCKED, Release) == CONTENDED {
+			self.futex.wake(1);
+		}
+	}
+}
+
+/// A mutual exclusion primitive protecting data of type `T`.
+///
+/// This is the classic three-state futex mutex: locking and unlocking without
+/// contention only takes a single atomic operation, and the futex is only
+/// woken up when another thread might be waiting.
+///
+/// Like [`std::sync::Mutex`], the mutex is poisoned when a thread panics
+/// while holding it, after which locking it results in a [`PoisonError`].
+///
+/// A `Mutex<T, Shared>` can be used between processes, if it is placed in
+/// shared memory.
+#[repr(C)]
+pub struct Mutex<T: ?Sized, S = Private> {
+	pub(crate) raw: RawMutex<S>,
+	poison: poison::Flag,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send, S> Send for Mutex<T, S> {}
+unsafe impl<T: ?Sized + Send, S> Sync for Mutex<T, S> {}
+
+/// The lock of a [`Mutex`]. The mutex is unlocked when this guard is dropped.
+#[must_use = "if unused the Mutex will immediately unlock"]
+pub struct MutexGuard<'a, T: ?Sized, S: Scope = Private> {
+	pub(crate) mutex: &'a Mutex<T, S>,
+	poison: poison::Guard,
+}
+
+unsafe impl<T: ?Sized + Sync, S: Scope> Sync for MutexGuard<'_, T, S> {}
+
+impl<T, S> Mutex<T, S> {
+	/// Create a new unlocked mutex containing `value`.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self {
+			raw: RawMutex::new(),
+			poison: poison::Flag::new(),
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the mutex, returning the data it protected.
+	///
+	/// If the mutex is poisoned, the data is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn into_inner(self) -> LockResult<T> {
+		let poisoned = self.poison.get();
+		let data = self.data.into_inner();
+		if poisoned {
+			Err(PoisonError::new(data))
+		} else {
+			Ok(data)
+		}
+	}
+}
+
+impl<T: ?Sized, S> Mutex<T, S> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the mutex.
+	#[inline]
+	pub fn get_mut(&mut self) -> LockResult<&mut T> {
+		let data = self.data.get_mut();
+		self.poison.result(data)
+	}
+
+	/// Check whether the mutex is poisoned.
+	#[inline]
+	pub fn is_poisoned(&self) -> bool {
+		self.poison.get()
+	}
+
+	/// Clear the poisoned state of the mutex.
+	///
+	/// Use this after recovering the protected data from an inconsistent state.
+	#[inline]
+	pub fn clear_poison(&self) {
+		self.poison.clear();
+	}
+}
+
+impl<T: ?Sized, S: Scope> Mutex<T, S> {
+	/// Lock the mutex, blocking until it is available.
+	///
+	/// Locking a mutex that is already locked by the calling thread results in a deadlock.
+	///
+	/// If the mutex is poisoned, the guard is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn lock(&self) -> LockResult<MutexGuard<'_, T, S>> {
+		self.raw.lock();
+		unsafe { MutexGuard::new(self) }
+	}
+
+	/// Lock the mutex if it is not locked, without blocking.
+	#[inline]
+	pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T, S>> {
+		if self.raw.try_lock() {
+			Ok(unsafe { MutexGuard::new(self) }?)
+		} else {
+			Err(TryLockError::WouldBlock)
+		}
+	}
+}
+
+impl<'a, T: ?Sized, S: Scope> MutexGuard<'a, T, S> {
+	/// The mutex must be locked by the calling thread.
+	#[inline]
+	pub(crate) unsafe fn new(mutex: &'a Mutex<T, S>) -> LockResult<Self> {
+		poison::map_result(mutex.poison.guard(), |poison| Self { mutex, poison })
+	}
+}
+
+impl<T: ?Sized, S: Scope> Deref for MutexGuard<'_, T, S> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> DerefMut for MutexGuard<'_, T, S> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Drop for MutexGuard<'_, T, S> {
+	#[inline]
+	fn drop(&mut self) {
+		self.mutex.poison.done(&self.poison);
+		unsafe { self.mutex.raw.unlock() }
+	}
+}
+
+impl<T: Default, S> Default for Mutex<T, S> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T, S> From<T> for Mutex<T, S> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for Mutex<T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("Mutex");
+		d.field("scope", &std::any::type_name::<S>());
+		match self.try_lock() {
+			Ok(guard) => d.field("data", &&*guard),
+			Err(TryLockError::Poisoned(e)) => d.field("data", &&**e.get_ref()),
+			Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+		};
+		d.field("poisoned", &self.poison.get());
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for MutexGuard<'_, T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Mutex;
+	use crate::Shared;
+	use std::sync::TryLockError;
+	use std::thread;
+
+	#[test]
+	fn contention() {
+		let mutex = Mutex::<u32>::new(0);
+		thread::scope(|s| {
+			for _ in 0..8 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*mutex.lock().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(mutex.into_inner().unwrap(), 80_000);
+	}
+
+	#[test]
+	fn contention_shared() {
+		let mutex = Mutex::<u32, Shared>::new(0);
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*mutex.lock().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(*mutex.lock().unwrap(), 40_000);
+	}
+
+	#[test]
+	fn try_lock() {
+		let mutex = Mutex::<u32>::new(1);
+		let guard = mutex.try_lock().unwrap();
+		assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+		thread::scope(|s| {
+			s.spawn(|| assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock))));
+		});
+		drop(guard);
+		assert_eq!(*mutex.try_lock().unwrap(), 1);
+	}
+
+	#[test]
+	fn poisoning() {
+		let mut mutex = Mutex::<u32>::new(1);
+		assert!(!mutex.is_poisoned());
+		let r = thread::scope(|s| {
+			s.spawn(|| {
+				let mut guard = mutex.lock().unwrap();
+				*guard = 2;
+				panic!("poison");
+			})
+			.join()
+		});
+		assert!(r.is_err());
+		assert!(mutex.is_poisoned());
+		assert_eq!(*mutex.lock().unwrap_err().into_inner(), 2);
+		assert!(matches!(mutex.try_lock(), Err(TryLockError::Poisoned(_))));
+		assert!(mutex.get_mut().is_err());
+		mutex.clear_poison();
+		assert!(!mutex.is_poisoned());
+		assert_eq!(*mutex.lock().unwrap(), 2);
+		assert_eq!(mutex.into_inner().unwrap(), 2);
+	}
+
+	#[test]
+	fn no_poison_without_panic_while_locked() {
+		let mutex = Mutex::<u32>::new(1);
+		let r = thread::scope(|s| {
+			s.spawn(|| {
+				drop(mutex.lock().unwrap());
+				panic!("not holding the lock");
+			})
+			.join()
+		});
+		assert!(r.is_err());
+		assert!(!mutex.is_poisoned());
+	}
+}
@@ -0,0 +1,196 @@
+use crate::timeout::deadline;
+use crate::{Futex, Private, Scope, TimedWaitError, WakeMask};
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, SeqCst};
+use std::time::{Duration, Instant};
+
+/// The lowest bit: a stored permit. The other bits: the generation, which is
+/// incremented by every [`Notify::notify_all`].
+const PERMIT: u32 = 1;
+const GENERATION: u32 = 2;
+
+/// Notifies waiting threads, without losing notifications that happen before a thread waits.
+///
+/// This is a blocking equivalent of `tokio::sync::Notify`:
+/// [`notify_one`][Notify::notify_one] either wakes up a waiting thread, or
+/// stores a permit that makes the next call to [`wait`][Notify::wait] return
+/// immediately. [`notify_all`][Notify::notify_all] wakes up all threads that
+/// are currently waiting, without storing a permit.
+///
+/// # Between processes
+///
+/// A `Notify<Shared>` can be used between processes, if it is placed in
+/// shared memory, such as to signal "work available" on a shared queue
+/// without a pipe or eventfd per waiter. The permit makes sure a process
+/// that notifies before the other process starts waiting is not missed.
+///
+/// A new `Notify` consists of only zero bytes, so a freshly created shared
+/// mapping contains a valid `Notify` without a permit. If a process dies
+/// while waiting, the `Notify` keeps working, but notifying then always
+/// takes a syscall, even when nobody is waiting anymore.
+#[repr(C)]
+pub struct Notify<S = Private> {
+	futex: Futex<S>,
+	waiters: AtomicU32,
+}
+
+impl<S> Notify<S> {
+	/// Create a new `Notify` without a stored permit.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			futex: Futex::new(0),
+			waiters: AtomicU32::new(0),
+		}
+	}
+}
+
+impl<S: Scope> Notify<S> {
+	/// Wait until notified, consuming the stored permit if there is one.
+	#[inline]
+	pub fn wait(&self) {
+		self.wait_deadline(None);
+	}
+
+	/// Wait until notified, or until the timeout expires.
+	///
+	/// Returns false if the timeout expired.
+	#[inline]
+	pub fn wait_timeout(&self, timeout: Duration) -> bool {
+		self.wait_deadline(deadline(timeout))
+	}
+
+	/// Wait until notified, or until the deadline passes.
+	///
+	/// Returns false if the deadline passed.
+	#[inline]
+	pub fn wait_until(&self, deadline: Instant) -> bool {
+		self.wait_deadline(Some(deadline))
+	}
+
+	fn wait_deadline(&self, deadline: Option<Instant>) -> bool {
+		let generation = self.futex.value.load(Acquire) & !PERMIT;
+		self.waiters.fetch_add(1, SeqCst);
+		let notified = loop {
+			let v = self.futex.value.load(SeqCst);
+			if v & !PERMIT != generation {
+				// Woken up by notify_all.
+				break true;
+			}
+			if v & PERMIT != 0 {
+				match self
+					.futex
+					.value
+					.compare_exchange(v, v & !PERMIT, Acquire, Relaxed)
+				{
+					Ok(_) => break true,
+					Err(_) => continue,
+				}
+			}
+			match deadline {
+				Some(deadline) => {
+					if let Err(TimedWaitError::TimedOut) =
+						self.futex.wait_bitset_until(v, WakeMask::ALL, deadline)
+					{
+						break false;
+					}
+				}
+				None => {
+					let _ = self.futex.wait(v);
+				}
+			}
+		};
+		self.waiters.fetch_sub(1, Relaxed);
+		notified
+	}
+
+	/// Wake up one waiting thread, or store a permit for the next one if nobody is waiting.
+	///
+	/// At most one permit is stored: notifying again before it is consumed has no effect.
+	#[inline]
+	pub fn notify_one(&self) {
+		if self.futex.value.fetch_or(PERMIT, SeqCst) & PERMIT == 0 && self.waiters.load(SeqCst) > 0
+		{
+			self.futex.wake(1);
+		}
+	}
+
+	/// Wake up all threads that are currently waiting, without storing a permit.
+	#[inline]
+	pub fn notify_all(&self) {
+		self.futex.value.fetch_add(GENERATION, SeqCst);
+		if self.waiters.load(SeqCst) > 0 {
+			self.futex.wake(u32::MAX);
+		}
+	}
+}
+
+impl<S> Default for Notify<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for Notify<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Notify")
+			.field("scope", &std::any::type_name::<S>())
+			.field("permit", &(self.futex.value.load(Relaxed) & PERMIT != 0))
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Notify;
+	use crate::Private;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn permit_is_stored() {
+		let notify = Notify::<Private>::new();
+		notify.notify_one();
+		notify.notify_one();
+		// Only one permit is stored.
+		assert!(notify.wait_timeout(Duration::ZERO));
+		assert!(!notify.wait_timeout(Duration::from_millis(10)));
+		// notify_all doesn't store a permit.
+		notify.notify_all();
+		assert!(!notify.wait_timeout(Duration::from_millis(10)));
+	}
+
+	#[test]
+	fn notify_one_wakes_one_waiter() {
+		let notify = Notify::<Private>::new();
+		thread::scope(|s| {
+			let waiters: Vec<_> = (0..2).map(|_| s.spawn(|| notify.wait())).collect();
+			while notify.waiters.load(Relaxed) < 2 {
+				thread::yield_now();
+			}
+			notify.notify_one();
+			while !waiters.iter().any(|w| w.is_finished()) {
+				thread::yield_now();
+			}
+			thread::sleep(Duration::from_millis(10));
+			assert_eq!(waiters.iter().filter(|w| w.is_finished()).count(), 1);
+			notify.notify_one();
+		});
+		assert!(!notify.wait_timeout(Duration::ZERO));
+	}
+
+	#[test]
+	fn notify_all_wakes_every_waiter() {
+		let notify = Notify::<Private>::new();
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| assert!(notify.wait_timeout(Duration::from_secs(10))));
+			}
+			while notify.waiters.load(Relaxed) < 4 {
+				thread::yield_now();
+			}
+			notify.notify_all();
+		});
+	}
+}
@@ -0,0 +1,325 @@
+use crate::{Futex, Private, Scope};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+const INCOMPLETE: u32 = 0;
+/// The initialization panicked.
+const POISONED: u32 = 1;
+/// A thread is running the initialization, and nobody is waiting for it.
+const RUNNING: u32 = 2;
+/// A thread is running the initialization, and others might be waiting for it.
+const QUEUED: u32 = 3;
+const COMPLETE: u32 = 4;
+
+/// A one-time initialization primitive.
+///
+/// Threads calling [`call_once`][Once::call_once] while another thread runs
+/// the initialization block on the futex until it is done.
+///
+/// A `Once<Shared>` can be used between processes, if it is placed in
+/// shared memory.
+#[repr(transparent)]
+pub struct Once<S = Private> {
+	futex: Futex<S>,
+}
+
+/// State passed to the closure given to [`Once::call_once_force`].
+#[derive(Debug)]
+pub struct OnceState {
+	pub(crate) poisoned: bool,
+}
+
+impl OnceState {
+	/// Returns true if a previous initialization panicked.
+	#[inline]
+	pub fn is_poisoned(&self) -> bool {
+		self.poisoned
+	}
+}
+
+/// Sets the state when dropped, and wakes up the waiters.
+struct CompletionGuard<'a, S: Scope> {
+	once: &'a Once<S>,
+	state: u32,
+}
+
+impl<S: Scope> Drop for CompletionGuard<'_, S> {
+	fn drop(&mut self) {
+		if self.once.futex.value.swap(self.state, Release) == QUEUED {
+			self.once.futex.wake(u32::MAX);
+		}
+	}
+}
+
+impl<S> Once<S> {
+	/// Create a new `Once` that has not run yet.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			futex: Futex::new(INCOMPLETE),
+		}
+	}
+
+	/// Returns true if the initialization completed successfully.
+	#[inline]
+	pub fn is_completed(&self) -> bool {
+		self.futex.value.load(Acquire) == COMPLETE
+	}
+}
+
+impl<S: Scope> Once<S> {
+	/// Run the closure if this is the first call, or wait for the first call to complete.
+	///
+	/// # Panics
+	///
+	/// Panics if a previous initialization panicked, poisoning the `Once`.
+	#[inline]
+	pub fn call_once(&self, f: impl FnOnce()) {
+		if !self.is_completed() {
+			self.call(false, |_| f());
+		}
+	}
+
+	/// Like [`call_once`][Once::call_once], but also runs the closure if a previous initialization panicked.
+	#[inline]
+	pub fn call_once_force(&self, f: impl FnOnce(&OnceState)) {
+		if !self.is_completed() {
+			self.call(true, f);
+		}
+	}
+
+	#[cold]
+	fn call(&self, ignore_poison: bool, f: impl FnOnce(&OnceState)) {
+		let mut state = self.futex.value.load(Acquire);
+		loop {
+			match state {
+				POISONED if !ignore_poison => panic!("Once instance has previously been poisoned"),
+				INCOMPLETE | POISONED => {
+					if let Err(s) = self
+						.futex
+						.value
+						.compare_exchange(state, RUNNING, Acquire, Acquire)
+					{
+						state = s;
+						continue;
+					}
+					let mut guard = CompletionGuard {
+						once: self,
+						state: POISONED,
+					};
+					f(&OnceState {
+						poisoned: state == POISONED,
+					});
+					guard.state = COMPLETE;
+					return;
+				}
+				RUNNING | QUEUED => {
+					if state == RUNNING {
+						if let Err(s) = self
+							.futex
+							.value
+							.compare_exchange(RUNNING, QUEUED, Relaxed, Acquire)
+						{
+							state = s;
+							continue;
+						}
+					}
+					let _ = self.futex.wait(QUEUED);
+					state = self.futex.value.load(Acquire);
+				}
+				_ => return,
+			}
+		}
+	}
+}
+
+impl<S> Default for Once<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for Once<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Once")
+			.field("scope", &std::any::type_name::<S>())
+			.field("completed", &self.is_completed())
+			.finish()
+	}
+}
+
+/// A cell that is written to only once.
+///
+/// Threads calling [`get_or_init`][OnceCell::get_or_init] while another
+/// thread initializes the cell block on the futex until it is done.
+///
+/// A `OnceCell<T, Shared>` can be used between processes, if it is placed in
+/// shared memory.
+#[repr(C)]
+pub struct OnceCell<T, S = Private> {
+	once: Once<S>,
+	value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send, S> Send for OnceCell<T, S> {}
+unsafe impl<T: Send + Sync, S> Sync for OnceCell<T, S> {}
+
+impl<T, S> OnceCell<T, S> {
+	/// Create a new empty cell.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			once: Once::new(),
+			value: UnsafeCell::new(MaybeUninit::uninit()),
+		}
+	}
+
+	/// Get the value, if the cell was initialized.
+	#[inline]
+	pub fn get(&self) -> Option<&T> {
+		if self.once.is_completed() {
+			Some(unsafe { (*self.value.get()).assume_init_ref() })
+		} else {
+			None
+		}
+	}
+
+	/// Get a mutable reference to the value, if the cell was initialized.
+	#[inline]
+	pub fn get_mut(&mut self) -> Option<&mut T> {
+		if self.once.is_completed() {
+			Some(unsafe { self.value.get_mut().assume_init_mut() })
+		} else {
+			None
+		}
+	}
+
+	/// Consume the cell, returning the value if it was initialized.
+	#[inline]
+	pub fn into_inner(self) -> Option<T> {
+		let this = std::mem::ManuallyDrop::new(self);
+		if this.once.is_completed() {
+			Some(unsafe { (*this.value.get()).assume_init_read() })
+		} else {
+			None
+		}
+	}
+}
+
+impl<T, S: Scope> OnceCell<T, S> {
+	/// Get the value, initializing it with `f` if the cell is empty.
+	///
+	/// If another thread is initializing the cell, this blocks until it is done.
+	///
+	/// # Panics
+	///
+	/// Panics if a previous initialization panicked, poisoning the cell.
+	#[inline]
+	pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+		self.once.call_once(|| unsafe {
+			(*self.value.get()).write(f());
+		});
+		unsafe { (*self.value.get()).assume_init_ref() }
+	}
+
+	/// Initialize the cell with `value`, if it was empty.
+	///
+	/// If the cell was already initialized, the value is given back as an error.
+	#[inline]
+	pub fn set(&self, value: T) -> Result<(), T> {
+		let mut value = Some(value);
+		self.get_or_init(|| value.take().unwrap());
+		match value {
+			None => Ok(()),
+			Some(value) => Err(value),
+		}
+	}
+}
+
+impl<T, S> Drop for OnceCell<T, S> {
+	fn drop(&mut self) {
+		if self.once.is_completed() {
+			unsafe { self.value.get_mut().assume_init_drop() }
+		}
+	}
+}
+
+impl<T, S> Default for OnceCell<T, S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T: std::fmt::Debug, S> std::fmt::Debug for OnceCell<T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("OnceCell");
+		d.field("scope", &std::any::type_name::<S>());
+		match self.get() {
+			Some(v) => d.field("value", v),
+			None => d.field("value", &format_args!("<uninit>")),
+		};
+		d.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Once, OnceCell};
+	use crate::Private;
+	use std::panic::{catch_unwind, AssertUnwindSafe};
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn runs_once() {
+		let once = Once::<Private>::new();
+		let runs = AtomicU32::new(0);
+		thread::scope(|s| {
+			for _ in 0..8 {
+				s.spawn(|| {
+					once.call_once(|| {
+						thread::sleep(Duration::from_millis(10));
+						runs.fetch_add(1, Relaxed);
+					});
+					// Everyone waits for the initialization to finish.
+					assert_eq!(runs.load(Relaxed), 1);
+				});
+			}
+		});
+		assert!(once.is_completed());
+		once.call_once(|| unreachable!());
+	}
+
+	#[test]
+	fn poisoning() {
+		let once = Once::<Private>::new();
+		let r = catch_unwind(AssertUnwindSafe(|| once.call_once(|| panic!("poison"))));
+		assert!(r.is_err());
+		assert!(!once.is_completed());
+		let r = catch_unwind(AssertUnwindSafe(|| once.call_once(|| {})));
+		assert!(r.is_err());
+		once.call_once_force(|state| assert!(state.is_poisoned()));
+		assert!(once.is_completed());
+	}
+
+	#[test]
+	fn cell() {
+		let cell = OnceCell::<String, Private>::new();
+		assert!(cell.get().is_none());
+		thread::scope(|s| {
+			for i in 0..8 {
+				let cell = &cell;
+				s.spawn(move || {
+					let value = cell.get_or_init(|| i.to_string());
+					assert_eq!(cell.get(), Some(value));
+				});
+			}
+		});
+		let value = cell.get().unwrap().clone();
+		assert_eq!(cell.set("other".to_string()), Err("other".to_string()));
+		assert_eq!(cell.into_inner(), Some(value));
+	}
+}
@@ -0,0 +1,147 @@
+use crate::{Futex, Private};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use std::sync::Arc;
+use std::time::Duration;
+
+const EMPTY: u32 = 0;
+const NOTIFIED: u32 = 1;
+const PARKED: u32 = u32::MAX;
+
+/// A thread parking primitive, similar to [`std::thread::park`].
+///
+/// Every `Parker` holds a token that is set by [`Unparker::unpark`] and
+/// consumed by [`park`][Parker::park]. Unparking before parking is never
+/// lost: the next call to `park` returns immediately.
+///
+/// A `Parker` can be moved to another thread, but only one thread can park on it.
+pub struct Parker {
+	unparker: Unparker,
+	_not_sync: PhantomData<Cell<()>>,
+}
+
+/// Unparks the thread of a [`Parker`].
+///
+/// Obtained through [`Parker::unparker`], and can be cloned and shared between threads.
+#[derive(Clone)]
+pub struct Unparker {
+	futex: Arc<Futex<Private>>,
+}
+
+impl Parker {
+	/// Create a new `Parker` without a token.
+	#[inline]
+	pub fn new() -> Self {
+		Self {
+			unparker: Unparker {
+				futex: Arc::new(Futex::new(EMPTY)),
+			},
+			_not_sync: PhantomData,
+		}
+	}
+
+	/// Block until the token is available, then consume it.
+	#[inline]
+	pub fn park(&self) {
+		let futex = &self.unparker.futex;
+		// EMPTY -> PARKED, or NOTIFIED -> EMPTY.
+		if futex.value.fetch_sub(1, Acquire) == NOTIFIED {
+			return;
+		}
+		loop {
+			let _ = futex.wait(PARKED);
+			if futex
+				.value
+				.compare_exchange(NOTIFIED, EMPTY, Acquire, Acquire)
+				.is_ok()
+			{
+				return;
+			}
+		}
+	}
+
+	/// Block until the token is available or the timeout expires, then consume the token if it was set.
+	///
+	/// This might also return early for other reasons.
+	#[inline]
+	pub fn park_timeout(&self, timeout: Duration) {
+		let futex = &self.unparker.futex;
+		if futex.value.fetch_sub(1, Acquire) == NOTIFIED {
+			return;
+		}
+		let _ = futex.wait_for(PARKED, timeout);
+		futex.value.swap(EMPTY, Acquire);
+	}
+
+	/// The [`Unparker`] for this `Parker`.
+	#[inline]
+	pub fn unparker(&self) -> &Unparker {
+		&self.unparker
+	}
+}
+
+impl Unparker {
+	/// Set the token, waking up the parked thread if there is one.
+	#[inline]
+	pub fn unpark(&self) {
+		if self.futex.value.swap(NOTIFIED, Release) == PARKED {
+			self.futex.wake(1);
+		}
+	}
+}
+
+impl Default for Parker {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl std::fmt::Debug for Parker {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Parker").finish_non_exhaustive()
+	}
+}
+
+impl std::fmt::Debug for Unparker {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Unparker").finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Parker;
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn token_is_not_lost() {
+		let parker = Parker::new();
+		parker.unparker().unpark();
+		parker.unparker().unpark();
+		// Only one token is stored.
+		parker.park();
+		let start = Instant::now();
+		parker.park_timeout(Duration::from_millis(20));
+		assert!(start.elapsed() >= Duration::from_millis(20));
+	}
+
+	#[test]
+	fn unpark_from_another_thread() {
+		let parker = Parker::new();
+		let unparker = parker.unparker().clone();
+		thread::scope(|s| {
+			s.spawn(move || {
+				for _ in 0..100 {
+					unparker.unpark();
+					thread::sleep(Duration::from_millis(1));
+				}
+			});
+			let start = Instant::now();
+			parker.park();
+			parker.park_timeout(Duration::from_secs(10));
+			assert!(start.elapsed() < Duration::from_secs(10));
+		});
+	}
+}
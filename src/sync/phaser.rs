@@ -0,0 +1,256 @@
+use crate::{Futex, Private, Scope};
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed};
+
+/// The maximum number of parties of a [`Phaser`].
+const MAX_PARTIES: u32 = 0xFFFF;
+
+#[inline]
+const fn pack(phase: u32, parties: u32, unarrived: u32) -> u64 {
+	(phase as u64) << 32 | (parties as u64) << 16 | unarrived as u64
+}
+
+#[inline]
+fn phase(state: u64) -> u32 {
+	(state >> 32) as u32
+}
+
+#[inline]
+fn parties(state: u64) -> u32 {
+	(state >> 16) as u32 & MAX_PARTIES
+}
+
+#[inline]
+fn unarrived(state: u64) -> u32 {
+	state as u32 & MAX_PARTIES
+}
+
+/// A reusable barrier with a changing number of parties, counting phases.
+///
+/// Every phase ends when all registered parties have arrived, after which
+/// the phase number is incremented (wrapping around) and the next phase
+/// starts right away. Unlike a [`Barrier`][super::Barrier], parties can
+/// [register][Phaser::register] and [deregister][Phaser::deregister] at any
+/// time, and can [arrive][Phaser::arrive] without waiting for the others.
+///
+/// The phase number, party count and the number of parties that did not
+/// arrive yet are stored together in a single 64-bit word. The futex holds
+/// a copy of the phase number, which waiting threads sleep on.
+///
+/// A `Phaser<Shared>` can be used to synchronize processes, if it is placed
+/// in shared memory.
+#[repr(C)]
+pub struct Phaser<S = Private> {
+	phase: Futex<S>,
+	state: AtomicU64,
+}
+
+impl<S> Phaser<S> {
+	/// Create a new phaser for `parties` parties, starting at phase zero.
+	///
+	/// # Panics
+	///
+	/// Panics if `parties` is more than 65535.
+	#[inline]
+	pub const fn new(parties: u32) -> Self {
+		assert!(parties <= MAX_PARTIES, "too many parties");
+		Self {
+			phase: Futex::new(0),
+			state: AtomicU64::new(pack(0, parties, parties)),
+		}
+	}
+
+	/// The current phase number.
+	#[inline]
+	pub fn phase(&self) -> u32 {
+		phase(self.state.load(Acquire))
+	}
+
+	/// The number of registered parties.
+	#[inline]
+	pub fn parties(&self) -> u32 {
+		parties(self.state.load(Relaxed))
+	}
+
+	/// The number of registered parties that did not yet arrive at the current phase.
+	#[inline]
+	pub fn unarrived(&self) -> u32 {
+		unarrived(self.state.load(Relaxed))
+	}
+}
+
+impl<S: Scope> Phaser<S> {
+	/// Register a new party, which then needs to arrive at the current phase as well.
+	///
+	/// Returns the current phase number.
+	///
+	/// # Panics
+	///
+	/// Panics if this would result in more than 65535 parties.
+	pub fn register(&self) -> u32 {
+		let state = self
+			.state
+			.fetch_update(AcqRel, Acquire, |s| {
+				(parties(s) < MAX_PARTIES).then(|| s + pack(0, 1, 1))
+			})
+			.expect("too many parties");
+		phase(state)
+	}
+
+	/// Arrive at the current phase, without waiting for the other parties.
+	///
+	/// Returns the phase number that was arrived at.
+	///
+	/// # Panics
+	///
+	/// Panics if all parties already arrived, which means there were more
+	/// calls to `arrive` than there are registered parties.
+	#[inline]
+	pub fn arrive(&self) -> u32 {
+		self.arrive_and(0)
+	}
+
+	/// Arrive at the current phase, and deregister a party, without waiting for the other parties.
+	///
+	/// Returns the phase number that was arrived at.
+	///
+	/// # Panics
+	///
+	/// Panics if all parties already arrived, or if there are no parties.
+	#[inline]
+	pub fn deregister(&self) -> u32 {
+		self.arrive_and(1)
+	}
+
+	/// Arrive at the current phase, and wait until all other parties have arrived too.
+	///
+	/// Returns the phase number that was arrived at.
+	/// The current phase is one more than that, when this returns.
+	pub fn arrive_and_wait(&self) -> u32 {
+		let phase = self.arrive();
+		self.wait_for_phase(phase);
+		phase
+	}
+
+	/// Wait until the phaser is no longer in the given phase.
+	///
+	/// Returns immediately if the phaser is already in another phase.
+	pub fn wait_for_phase(&self, phase: u32) {
+		while self.phase.value.load(Acquire) == phase {
+			let _ = self.phase.wait(phase);
+		}
+	}
+
+	/// Arrive at the current phase, while removing `deregister` parties.
+	fn arrive_and(&self, deregister: u32) -> u32 {
+		let state = self
+			.state
+			.fetch_update(AcqRel, Acquire, |s| {
+				if unarrived(s) == 0 || parties(s) < deregister {
+					return None;
+				}
+				let parties = parties(s) - deregister;
+				Some(if unarrived(s) == 1 {
+					// The last one to arrive starts the next phase.
+					pack(phase(s).wrapping_add(1), parties, parties)
+				} else {
+					pack(phase(s), parties, unarrived(s) - 1)
+				})
+			})
+			.expect("more arrivals than parties");
+		let phase = phase(state);
+		if unarrived(state) == 1 {
+			self.advance(phase.wrapping_add(1));
+		}
+		phase
+	}
+
+	/// Publish the new phase number in the futex, and wake up all waiters.
+	#[cold]
+	fn advance(&self, new: u32) {
+		// The next phase might already have ended before we get here,
+		// in which case we must not move the futex back to an older phase.
+		let _ = self.phase.value.fetch_update(AcqRel, Relaxed, |old| {
+			(new.wrapping_sub(old) as i32 > 0).then_some(new)
+		});
+		self.phase.wake(u32::MAX);
+	}
+}
+
+impl<S> std::fmt::Debug for Phaser<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let state = self.state.load(Relaxed);
+		f.debug_struct("Phaser")
+			.field("scope", &std::any::type_name::<S>())
+			.field("phase", &phase(state))
+			.field("parties", &parties(state))
+			.field("unarrived", &unarrived(state))
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Phaser;
+	use crate::{Private, Shared};
+	use std::panic::catch_unwind;
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::thread;
+
+	#[test]
+	fn register_arrive_deregister() {
+		let phaser = Phaser::<Private>::new(2);
+		assert_eq!(phaser.arrive(), 0);
+		assert_eq!(phaser.unarrived(), 1);
+		assert_eq!(phaser.register(), 0);
+		assert_eq!((phaser.parties(), phaser.unarrived()), (3, 2));
+		assert_eq!(phaser.deregister(), 0);
+		assert_eq!((phaser.parties(), phaser.unarrived()), (2, 1));
+		assert_eq!(phaser.arrive(), 0);
+		assert_eq!(phaser.phase(), 1);
+		assert_eq!((phaser.parties(), phaser.unarrived()), (2, 2));
+		// The last party to deregister ends the phase too.
+		assert_eq!(phaser.deregister(), 1);
+		assert_eq!(phaser.deregister(), 1);
+		assert_eq!((phaser.phase(), phaser.parties()), (2, 0));
+	}
+
+	#[test]
+	fn too_many_arrivals() {
+		let phaser = Phaser::<Private>::new(1);
+		assert_eq!(phaser.deregister(), 0);
+		assert!(catch_unwind(|| phaser.arrive()).is_err());
+		assert!(catch_unwind(|| phaser.deregister()).is_err());
+		assert_eq!((phaser.phase(), phaser.parties()), (1, 0));
+	}
+
+	#[test]
+	fn lockstep() {
+		let phaser = Phaser::<Shared>::new(4);
+		let arrived = AtomicU32::new(0);
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for round in 0..100 {
+						arrived.fetch_add(1, Relaxed);
+						assert_eq!(phaser.arrive_and_wait(), round);
+						assert!(arrived.load(Relaxed) >= 4 * (round + 1));
+					}
+				});
+			}
+		});
+		assert_eq!(phaser.phase(), 100);
+	}
+
+	#[test]
+	fn wait_for_phase() {
+		let phaser = Phaser::<Private>::new(1);
+		phaser.wait_for_phase(5);
+		thread::scope(|s| {
+			let waiter = s.spawn(|| phaser.wait_for_phase(0));
+			phaser.arrive();
+			waiter.join().unwrap();
+		});
+	}
+}
@@ -0,0 +1,182 @@
+use super::{poison, PiMutexGuard, WaitTimeoutResult};
+use crate::sys::{count, Error, FutexCall};
+use crate::timeout::deadline;
+use crate::{Futex, PiFutex, Private, RequeuePiError, Scope, TimedRequeuePiError};
+use std::sync::atomic::AtomicIsize;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::LockResult;
+use std::time::Duration;
+
+/// A condition variable for real-time use, to be used together with a [`PiMutex`][super::PiMutex].
+///
+/// Waiters wait with `FUTEX_WAIT_REQUEUE_PI`, and are woken up by requeueing
+/// them onto the mutex with `FUTEX_CMP_REQUEUE_PI`. The kernel then locks the
+/// mutex on their behalf, such that priority inheritance is never
+/// interrupted: a waiting thread is always either waiting for this condition
+/// variable or boosting the owner of the mutex.
+///
+/// A condition variable must always be used with the same mutex, and both
+/// must be part of the same memory mapping when used between processes.
+#[repr(C)]
+pub struct PiCondvar<S = Private> {
+	futex: Futex<S>,
+	/// The address of the mutex's futex, relative to this condition variable.
+	///
+	/// Zero if the condition variable was never waited on.
+	mutex: AtomicIsize,
+}
+
+impl<S> PiCondvar<S> {
+	/// Create a new condition variable.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			futex: Futex::new(0),
+			mutex: AtomicIsize::new(0),
+		}
+	}
+}
+
+impl<S: Scope> PiCondvar<S> {
+	/// Unlock the mutex and wait until notified, after which the mutex is locked again.
+	///
+	/// Like most condition variables, this can wake up spuriously.
+	/// Use [`wait_while`][PiCondvar::wait_while] to wait for a condition.
+	///
+	/// If the mutex is poisoned, the guard is returned inside a [`PoisonError`][std::sync::PoisonError].
+	pub fn wait<'a, T: ?Sized>(
+		&self,
+		guard: PiMutexGuard<'a, T, S>,
+	) -> LockResult<PiMutexGuard<'a, T, S>> {
+		let mutex = guard.mutex;
+		let seq = self.prepare_wait(&mutex.futex);
+		drop(guard);
+		match self.futex.wait_requeue_pi(seq, &mutex.futex) {
+			// We were requeued, and the kernel locked the mutex for us.
+			Ok(()) => mutex.acquired(mutex.futex.acquired()),
+			// We were notified before going to sleep, woken up without requeueing, or interrupted.
+			Err(RequeuePiError::TryAgain | RequeuePiError::Interrupted) => mutex.lock_raw(),
+		}
+		unsafe { PiMutexGuard::new(mutex) }
+	}
+
+	/// Wait until notified, or until the timeout expires.
+	///
+	/// See [`wait`][PiCondvar::wait].
+	pub fn wait_timeout<'a, T: ?Sized>(
+		&self,
+		guard: PiMutexGuard<'a, T, S>,
+		timeout: Duration,
+	) -> LockResult<(PiMutexGuard<'a, T, S>, WaitTimeoutResult)> {
+		let deadline = match deadline(timeout) {
+			Some(deadline) => deadline,
+			None => {
+				return poison::map_result(self.wait(guard), |guard| {
+					(guard, WaitTimeoutResult(false))
+				})
+			}
+		};
+		let mutex = guard.mutex;
+		let seq = self.prepare_wait(&mutex.futex);
+		drop(guard);
+		let timed_out = match self
+			.futex
+			.wait_requeue_pi_until(seq, &mutex.futex, deadline)
+		{
+			Ok(()) => {
+				mutex.acquired(mutex.futex.acquired());
+				false
+			}
+			Err(e) => {
+				mutex.lock_raw();
+				e == TimedRequeuePiError::TimedOut
+			}
+		};
+		poison::map_result(unsafe { PiMutexGuard::new(mutex) }, |guard| {
+			(guard, WaitTimeoutResult(timed_out))
+		})
+	}
+
+	/// Wait as long as the condition holds.
+	pub fn wait_while<'a, T: ?Sized>(
+		&self,
+		mut guard: PiMutexGuard<'a, T, S>,
+		mut condition: impl FnMut(&mut T) -> bool,
+	) -> LockResult<PiMutexGuard<'a, T, S>> {
+		while condition(&mut *guard) {
+			guard = self.wait(guard)?;
+		}
+		Ok(guard)
+	}
+
+	/// Wake up one waiting thread.
+	///
+	/// The thread is requeued onto the mutex, or gets the mutex directly if
+	/// it is unlocked.
+	#[inline]
+	pub fn notify_one(&self) {
+		self.notify(0);
+	}
+
+	/// Wake up all waiting threads.
+	///
+	/// Only one thread is woken up directly. The others are requeued onto the
+	/// mutex, and get it one by one, in priority order.
+	#[inline]
+	pub fn notify_all(&self) {
+		self.notify(u32::MAX);
+	}
+
+	fn notify(&self, n_requeue: u32) {
+		let mut seq = self.futex.value.fetch_add(1, Relaxed).wrapping_add(1);
+		let offset = self.mutex.load(Relaxed);
+		if offset == 0 {
+			// Nobody ever waited.
+			return;
+		}
+		let mutex = (self as *const Self as isize).wrapping_add(offset) as *const PiFutex<S>;
+		loop {
+			// We don't have a reference to the mutex, so we can't use Futex::cmp_requeue_pi.
+			// If there are no waiters left, the mutex might not exist anymore.
+			// The kernel doesn't access it in that case, and neither do we.
+			let r = unsafe {
+				FutexCall::new()
+					.futex_op(libc::FUTEX_CMP_REQUEUE_PI + S::futex_flag())
+					.uaddr(&self.futex.value)
+					.uaddr2(mutex as *const _)
+					.val(1)
+					.val2(count(n_requeue))
+					.val3(seq)
+					.call()
+			};
+			match r {
+				// Another thread notified in the meantime. Try again.
+				Err(Error(libc::EAGAIN)) => seq = self.futex.value.load(Relaxed),
+				Err(e) => e.panic("FUTEX_CMP_REQUEUE_PI"),
+				Ok(_) => return,
+			}
+		}
+	}
+
+	/// Remember the mutex, and return the sequence number to wait for.
+	fn prepare_wait(&self, mutex: &PiFutex<S>) -> u32 {
+		let offset =
+			(mutex as *const PiFutex<S> as isize).wrapping_sub(self as *const Self as isize);
+		self.mutex.store(offset, Relaxed);
+		self.futex.value.load(Relaxed)
+	}
+}
+
+impl<S> Default for PiCondvar<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for PiCondvar<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("PiCondvar")
+			.field("scope", &std::any::type_name::<S>())
+			.finish_non_exhaustive()
+	}
+}
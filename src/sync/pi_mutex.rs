@@ -0,0 +1,343 @@
+use super::poison;
+use crate::pi::current_tid;
+use crate::{Acquired, LockError, PiFutex, Private, Scope};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+/// A priority-inheritance mutex protecting data of type `T`.
+///
+/// This implements the protocol of [`PiFutex`]: without contention, the
+/// thread id of the owner is stored in the futex in user space. On
+/// contention, the kernel takes over with `FUTEX_LOCK_PI` and
+/// `FUTEX_UNLOCK_PI`, and boosts the priority of the owner to that of the
+/// highest priority waiter while it holds the lock.
+///
+/// As the lock is owned by a thread, the guard cannot be sent to another thread.
+///
+/// Like [`std::sync::Mutex`], the mutex is poisoned when a thread panics
+/// while holding it, after which locking it results in a [`PoisonError`].
+///
+/// This mutex is not on the robust list of its owner, so a thread that exits
+/// while holding it leaves it locked by a thread that no longer exists.
+/// Locking it after that panics, or blocks if the thread id was reused by
+/// another thread in the meantime. Use a
+/// [`RobustPiMutex`][super::RobustPiMutex] if the owner might die while
+/// holding the lock.
+///
+/// A `PiMutex<T, Shared>` can be used between processes, if it is placed in
+/// shared memory.
+#[repr(C)]
+pub struct PiMutex<T: ?Sized, S = Private> {
+	pub(crate) futex: PiFutex<S>,
+	poison: poison::Flag,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send, S> Send for PiMutex<T, S> {}
+unsafe impl<T: ?Sized + Send, S> Sync for PiMutex<T, S> {}
+
+/// The lock of a [`PiMutex`]. The mutex is unlocked when this guard is dropped.
+#[must_use = "if unused the PiMutex will immediately unlock"]
+pub struct PiMutexGuard<'a, T: ?Sized, S: Scope = Private> {
+	pub(crate) mutex: &'a PiMutex<T, S>,
+	poison: poison::Guard,
+	/// The lock must be unlocked by the thread that locked it.
+	not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync, S: Scope> Sync for PiMutexGuard<'_, T, S> {}
+
+impl<T, S> PiMutex<T, S> {
+	/// Create a new unlocked mutex containing `value`.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self {
+			futex: PiFutex::new(0),
+			poison: poison::Flag::new(),
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the mutex, returning the data it protected.
+	///
+	/// If the mutex is poisoned, the data is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn into_inner(self) -> LockResult<T> {
+		let poisoned = self.poison.get();
+		let data = self.data.into_inner();
+		if poisoned {
+			Err(PoisonError::new(data))
+		} else {
+			Ok(data)
+		}
+	}
+}
+
+impl<T: ?Sized, S> PiMutex<T, S> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the mutex.
+	#[inline]
+	pub fn get_mut(&mut self) -> LockResult<&mut T> {
+		let data = self.data.get_mut();
+		self.poison.result(data)
+	}
+
+	/// Check whether the mutex is poisoned.
+	#[inline]
+	pub fn is_poisoned(&self) -> bool {
+		self.poison.get()
+	}
+
+	/// Clear the poisoned state of the mutex.
+	///
+	/// Use this after recovering the protected data from an inconsistent state.
+	#[inline]
+	pub fn clear_poison(&self) {
+		self.poison.clear();
+	}
+}
+
+impl<T: ?Sized, S: Scope> PiMutex<T, S> {
+	/// Lock the mutex, blocking until it is available.
+	///
+	/// If the mutex is poisoned, the guard is returned inside a [`PoisonError`].
+	///
+	/// # Panics
+	///
+	/// Panics if the mutex is already locked by the calling thread, or by a
+	/// thread that exited without unlocking it.
+	#[inline]
+	pub fn lock(&self) -> LockResult<PiMutexGuard<'_, T, S>> {
+		self.lock_raw();
+		unsafe { PiMutexGuard::new(self) }
+	}
+
+	/// Lock the mutex if it is not locked, without blocking.
+	#[inline]
+	pub fn try_lock(&self) -> TryLockResult<PiMutexGuard<'_, T, S>> {
+		if self.try_lock_fast() || self.try_lock_slow() {
+			Ok(unsafe { PiMutexGuard::new(self) }?)
+		} else {
+			Err(TryLockError::WouldBlock)
+		}
+	}
+
+	#[inline]
+	pub(crate) fn lock_raw(&self) {
+		if !self.try_lock_fast() {
+			self.lock_contended();
+		}
+	}
+
+	#[inline]
+	fn try_lock_fast(&self) -> bool {
+		self.futex
+			.value
+			.compare_exchange(0, current_tid(), Acquire, Relaxed)
+			.is_ok()
+	}
+
+	/// Let the kernel try to lock it, if there is no owner but the futex isn't zero.
+	#[cold]
+	fn try_lock_slow(&self) -> bool {
+		if self.futex.value.load(Relaxed) & PiFutex::<S>::TID_MASK != 0 {
+			return false;
+		}
+		match self.futex.trylock_pi() {
+			Ok(acquired) => {
+				self.acquired(acquired);
+				true
+			}
+			Err(_) => false,
+		}
+	}
+
+	#[cold]
+	fn lock_contended(&self) {
+		loop {
+			match self.futex.lock_pi() {
+				Ok(acquired) => return self.acquired(acquired),
+				Err(LockError::TryAgain) => continue,
+				Err(LockError::Deadlock) => panic!("PiMutex already locked by the calling thread"),
+			}
+		}
+	}
+
+	#[inline]
+	pub(crate) fn acquired(&self, acquired: Acquired) {
+		if acquired == Acquired::OwnerDied {
+			self.poison.set();
+		}
+	}
+
+	/// Unlock the mutex, letting the kernel hand it over if there are waiters.
+	///
+	/// The mutex must be locked by the calling thread.
+	#[inline]
+	pub(crate) unsafe fn unlock(&self) {
+		if self
+			.futex
+			.value
+			.compare_exchange(current_tid(), 0, Release, Relaxed)
+			.is_err()
+		{
+			self.unlock_contended();
+		}
+	}
+
+	#[cold]
+	fn unlock_contended(&self) {
+		if self.futex.unlock_pi().is_err() {
+			panic!("PiMutex unlocked by a thread that does not own it");
+		}
+	}
+}
+
+impl<'a, T: ?Sized, S: Scope> PiMutexGuard<'a, T, S> {
+	/// The mutex must be locked by the calling thread.
+	#[inline]
+	pub(crate) unsafe fn new(mutex: &'a PiMutex<T, S>) -> LockResult<Self> {
+		poison::map_result(mutex.poison.guard(), |poison| Self {
+			mutex,
+			poison,
+			not_send: PhantomData,
+		})
+	}
+}
+
+impl<T: ?Sized, S: Scope> Deref for PiMutexGuard<'_, T, S> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> DerefMut for PiMutexGuard<'_, T, S> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Drop for PiMutexGuard<'_, T, S> {
+	#[inline]
+	fn drop(&mut self) {
+		self.mutex.poison.done(&self.poison);
+		unsafe { self.mutex.unlock() }
+	}
+}
+
+impl<T: Default, S> Default for PiMutex<T, S> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T, S> From<T> for PiMutex<T, S> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for PiMutex<T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("PiMutex");
+		d.field("scope", &std::any::type_name::<S>());
+		match self.try_lock() {
+			Ok(guard) => d.field("data", &&*guard),
+			Err(TryLockError::Poisoned(e)) => d.field("data", &&**e.get_ref()),
+			Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+		};
+		d.field("poisoned", &self.poison.get());
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for PiMutexGuard<'_, T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::PiMutex;
+	use crate::{Private, Shared};
+	use std::panic::{catch_unwind, AssertUnwindSafe};
+	use std::sync::TryLockError;
+	use std::thread;
+
+	#[test]
+	fn contention() {
+		let mutex = PiMutex::<u32, Private>::new(0);
+		thread::scope(|s| {
+			for _ in 0..8 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*mutex.lock().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(mutex.into_inner().unwrap(), 80_000);
+	}
+
+	#[test]
+	fn contention_shared() {
+		let mutex = PiMutex::<u32, Shared>::new(0);
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*mutex.lock().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(*mutex.lock().unwrap(), 40_000);
+	}
+
+	#[test]
+	fn try_lock() {
+		let mutex = PiMutex::<u32, Private>::new(1);
+		let guard = mutex.try_lock().unwrap();
+		thread::scope(|s| {
+			s.spawn(|| assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock))));
+		});
+		drop(guard);
+		assert_eq!(*mutex.try_lock().unwrap(), 1);
+	}
+
+	#[test]
+	fn relock_panics() {
+		let mutex = PiMutex::<u32, Private>::new(1);
+		let guard = mutex.lock().unwrap();
+		// Force the kernel path, which detects the deadlock.
+		let r = catch_unwind(AssertUnwindSafe(|| drop(mutex.lock())));
+		assert!(r.is_err());
+		drop(guard);
+		assert!(mutex.try_lock().is_ok());
+	}
+
+	#[test]
+	fn poisoning() {
+		let mutex = PiMutex::<u32, Private>::new(1);
+		let _ = thread::scope(|s| {
+			s.spawn(|| {
+				let mut guard = mutex.lock().unwrap();
+				*guard = 2;
+				panic!("poison");
+			})
+			.join()
+		});
+		assert!(mutex.is_poisoned());
+		assert_eq!(*mutex.lock().unwrap_err().into_inner(), 2);
+		mutex.clear_poison();
+		assert!(mutex.lock().is_ok());
+	}
+}
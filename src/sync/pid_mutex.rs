@@ -0,0 +1,402 @@
+use crate::timeout::deadline;
+use crate::{Futex, Shared, TimedWaitError, WakeMask};
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicU32, AtomicU64};
+use std::time::{Duration, Instant};
+
+/// Set in the futex if there might be processes waiting for the mutex.
+const WAITERS: u32 = 1 << 31;
+/// The bits of the futex containing the process id of the owner, or zero if unlocked.
+const PID_MASK: u32 = !WAITERS;
+
+/// A mutex for use between processes, which can be taken over from an owner process that no longer exists.
+///
+/// A [`RobustMutex`][super::RobustMutex] relies on the kernel to release the
+/// lock when its owner dies, which requires the owner to have registered
+/// its robust futex list. When that's not an option, this mutex offers an
+/// alternative: it stores the process id and the start time of the process
+/// that holds it, and [`try_steal_if_dead`][PidMutex::try_steal_if_dead]
+/// takes the lock over if that process no longer exists. The start time is
+/// what makes this safe when the process id has been reused by a new
+/// process in the meantime.
+///
+/// Nothing happens automatically when the owner dies: waiters keep waiting
+/// until another process steals the lock. A process that might have to take
+/// over should use [`lock_timeout`][PidMutex::lock_timeout], and try to
+/// steal the lock when it times out.
+///
+/// The owner is a process, not a thread: threads of the same process exclude
+/// each other like with a normal mutex, but the lock is only considered
+/// abandoned once the whole process is gone. A zombie process, which exited
+/// but was not yet reaped by its parent, counts as gone.
+///
+/// # Between processes
+///
+/// A `PidMutex` consisting of only zero bytes is a valid unlocked mutex.
+/// All processes using it must be in the same PID namespace, since process
+/// ids are meaningless in other namespaces (see
+/// [`PidNamespace`][crate::PidNamespace]), and must be able to read the
+/// `/proc/<pid>/stat` files of each other.
+#[repr(C)]
+pub struct PidMutex<T: ?Sized> {
+	/// The process id of the owner, plus the `WAITERS` bit.
+	futex: Futex<Shared>,
+	/// The start time of the owner plus one, or zero if it is not known yet.
+	start_time: AtomicU64,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for PidMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for PidMutex<T> {}
+
+/// The lock of a [`PidMutex`]. The mutex is unlocked when this guard is dropped.
+#[must_use = "if unused the PidMutex will immediately unlock"]
+pub struct PidMutexGuard<'a, T: ?Sized> {
+	mutex: &'a PidMutex<T>,
+}
+
+/// The process id of the calling process, and its start time plus one.
+///
+/// The start time is cached, and looked up again after a `fork()`.
+fn current_process() -> (u32, u64) {
+	static PID: AtomicU32 = AtomicU32::new(0);
+	static START_TIME: AtomicU64 = AtomicU64::new(0);
+	let pid = unsafe { libc::getpid() } as u32;
+	if PID.load(Acquire) == pid {
+		return (pid, START_TIME.load(Relaxed));
+	}
+	// If this fails, zero means the start time is unknown, which makes the lock unstealable.
+	let start_time = start_time("self").map_or(0, |t| t + 1);
+	START_TIME.store(start_time, Relaxed);
+	PID.store(pid, Release);
+	(pid, start_time)
+}
+
+/// The start time of the process, in clock ticks after boot, or `None` if it doesn't exist or is a zombie.
+fn start_time(pid: &str) -> Option<u64> {
+	let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+	// The second field is the name of the executable in parentheses, which can contain anything.
+	let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+	// The third field is the state, and the 22nd is the start time.
+	match fields.next()? {
+		"Z" | "X" | "x" => None,
+		_ => fields.nth(18)?.parse().ok(),
+	}
+}
+
+impl<T> PidMutex<T> {
+	/// Create a new unlocked mutex containing `value`.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self {
+			futex: Futex::new(0),
+			start_time: AtomicU64::new(0),
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the mutex, returning the data it protected.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.data.into_inner()
+	}
+}
+
+impl<T: ?Sized> PidMutex<T> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the mutex.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		self.data.get_mut()
+	}
+
+	/// The process id of the owner, or `None` if the mutex is not locked.
+	#[inline]
+	pub fn owner(&self) -> Option<u32> {
+		match self.futex.value.load(Relaxed) & PID_MASK {
+			0 => None,
+			pid => Some(pid),
+		}
+	}
+
+	/// Lock the mutex, blocking until it is available.
+	///
+	/// Locking a mutex that is already locked by the calling thread results
+	/// in a deadlock, and so does locking a mutex whose owner is gone, until
+	/// another process steals it.
+	pub fn lock(&self) -> PidMutexGuard<'_, T> {
+		let (pid, start_time) = current_process();
+		if !self.try_acquire(pid) {
+			self.lock_contended(pid, None);
+		}
+		self.acquired(start_time)
+	}
+
+	/// Lock the mutex if it is not locked, without blocking.
+	///
+	/// Returns `None` if the mutex is locked.
+	pub fn try_lock(&self) -> Option<PidMutexGuard<'_, T>> {
+		let (pid, start_time) = current_process();
+		if self.try_acquire(pid) {
+			Some(self.acquired(start_time))
+		} else {
+			None
+		}
+	}
+
+	/// Lock the mutex, blocking until it is available or the timeout expires.
+	///
+	/// Returns `None` if the timeout expired before the mutex could be locked.
+	/// At that point, [`try_steal_if_dead`][PidMutex::try_steal_if_dead] can
+	/// check whether the owner is gone.
+	pub fn lock_timeout(&self, timeout: Duration) -> Option<PidMutexGuard<'_, T>> {
+		let (pid, start_time) = current_process();
+		if self.try_acquire(pid) || self.lock_contended(pid, deadline(timeout)) {
+			Some(self.acquired(start_time))
+		} else {
+			None
+		}
+	}
+
+	/// Take over the lock if the process holding it no longer exists.
+	///
+	/// This checks that no process exists with the process id of the owner
+	/// and the same start time. If so, the lock is given to the calling
+	/// thread, as if the owner unlocked it and this thread locked it. The
+	/// protected data might be inconsistent, since the owner might have died
+	/// in the middle of modifying it.
+	///
+	/// Returns `None` if the mutex is unlocked, if its owner still exists, if
+	/// it can't be determined whether the owner exists, or if another process
+	/// took over first. An owner that died right after locking the mutex,
+	/// before it stored its start time, is only considered gone once no
+	/// process with its process id exists at all.
+	pub fn try_steal_if_dead(&self) -> Option<PidMutexGuard<'_, T>> {
+		let state = self.futex.value.load(Acquire);
+		let owner = state & PID_MASK;
+		if owner == 0 {
+			return None;
+		}
+		let owner_start_time = self.start_time.load(Relaxed);
+		match start_time(&owner.to_string()) {
+			None => {}
+			Some(t) if owner_start_time != 0 && t + 1 != owner_start_time => {}
+			Some(_) => return None,
+		}
+		// Claim the takeover, so only one process gets to steal from this owner.
+		if owner_start_time != 0
+			&& self
+				.start_time
+				.compare_exchange(owner_start_time, 0, Relaxed, Relaxed)
+				.is_err()
+		{
+			return None;
+		}
+		let (pid, start_time) = current_process();
+		let mut state = state;
+		loop {
+			match self.futex.value.compare_exchange_weak(
+				state,
+				pid | (state & WAITERS),
+				Acquire,
+				Acquire,
+			) {
+				Ok(_) => return Some(self.acquired(start_time)),
+				// The only other change the owner's state can see is a waiter arriving.
+				Err(s) if s & PID_MASK == owner => state = s,
+				Err(_) => return None,
+			}
+		}
+	}
+
+	#[inline]
+	fn try_acquire(&self, pid: u32) -> bool {
+		self.futex
+			.value
+			.compare_exchange(0, pid, Acquire, Relaxed)
+			.is_ok()
+	}
+
+	/// Returns false if the deadline passed before the mutex could be locked.
+	#[cold]
+	fn lock_contended(&self, pid: u32, deadline: Option<Instant>) -> bool {
+		let mut state = self.futex.value.load(Relaxed);
+		loop {
+			if state == 0 {
+				// Other processes might be waiting too, so keep the waiters bit set.
+				match self
+					.futex
+					.value
+					.compare_exchange(0, pid | WAITERS, Acquire, Relaxed)
+				{
+					Ok(_) => return true,
+					Err(s) => state = s,
+				}
+				continue;
+			}
+			if state & WAITERS == 0 {
+				if let Err(s) =
+					self.futex
+						.value
+						.compare_exchange(state, state | WAITERS, Relaxed, Relaxed)
+				{
+					state = s;
+					continue;
+				}
+			}
+			let r = match deadline {
+				Some(deadline) => {
+					self.futex
+						.wait_bitset_until(state | WAITERS, WakeMask::ALL, deadline)
+				}
+				None => self.futex.wait(state | WAITERS).map_err(Into::into),
+			};
+			if let Err(TimedWaitError::TimedOut) = r {
+				return false;
+			}
+			state = self.futex.value.load(Relaxed);
+		}
+	}
+
+	#[inline]
+	fn acquired(&self, start_time: u64) -> PidMutexGuard<'_, T> {
+		self.start_time.store(start_time, Relaxed);
+		PidMutexGuard { mutex: self }
+	}
+}
+
+impl<T: Default> Default for PidMutex<T> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T: ?Sized> Deref for PidMutexGuard<'_, T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized> DerefMut for PidMutexGuard<'_, T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized> Drop for PidMutexGuard<'_, T> {
+	#[inline]
+	fn drop(&mut self) {
+		let mutex = self.mutex;
+		// Cleared before unlocking, such that the next owner's start time is never mixed up with ours.
+		mutex.start_time.store(0, Relaxed);
+		if mutex.futex.value.swap(0, Release) & WAITERS != 0 {
+			mutex.futex.wake(1);
+		}
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for PidMutex<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("PidMutex");
+		d.field("owner", &self.owner());
+		match self.try_lock() {
+			Some(guard) => d.field("data", &&*guard),
+			None => d.field("data", &format_args!("<locked>")),
+		};
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for PidMutexGuard<'_, T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{start_time, PidMutex};
+	use std::process::Command;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	/// Pretend the mutex was locked by process `pid`, which started at `start_time`.
+	fn lock_as(mutex: &PidMutex<u32>, pid: u32, start_time: u64) {
+		mutex.futex.value.store(pid, Relaxed);
+		mutex.start_time.store(start_time + 1, Relaxed);
+	}
+
+	#[test]
+	fn contention() {
+		let mutex = PidMutex::new(0);
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*mutex.lock() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(mutex.into_inner(), 40_000);
+	}
+
+	#[test]
+	fn try_lock_and_timeout() {
+		let mutex = PidMutex::new(1);
+		let guard = mutex.try_lock().unwrap();
+		assert_eq!(mutex.owner(), Some(std::process::id()));
+		assert!(mutex.try_lock().is_none());
+		let start = Instant::now();
+		assert!(mutex.lock_timeout(Duration::from_millis(10)).is_none());
+		assert!(start.elapsed() >= Duration::from_millis(10));
+		drop(guard);
+		assert_eq!(mutex.owner(), None);
+		assert_eq!(*mutex.lock_timeout(Duration::from_secs(10)).unwrap(), 1);
+	}
+
+	#[test]
+	fn no_stealing_from_a_live_owner() {
+		let mutex = PidMutex::new(1);
+		assert!(mutex.try_steal_if_dead().is_none());
+		let _guard = mutex.lock();
+		thread::scope(|s| {
+			s.spawn(|| assert!(mutex.try_steal_if_dead().is_none()));
+		});
+	}
+
+	#[test]
+	fn steal_from_a_dead_owner() {
+		let mut child = Command::new("true").spawn().unwrap();
+		let pid = child.id();
+		child.wait().unwrap();
+		let mutex = PidMutex::new(1);
+		lock_as(&mutex, pid, 0);
+		assert!(mutex.try_lock().is_none());
+		let mut guard = mutex.try_steal_if_dead().unwrap();
+		*guard = 2;
+		assert_eq!(mutex.owner(), Some(std::process::id()));
+		drop(guard);
+		assert_eq!(*mutex.try_lock().unwrap(), 2);
+	}
+
+	#[test]
+	fn steal_after_pid_reuse() {
+		let mutex = PidMutex::new(1);
+		// Our own process id, but a process with another start time.
+		let t = start_time("self").unwrap();
+		lock_as(&mutex, std::process::id(), t + 1);
+		let _guard = mutex.try_steal_if_dead().unwrap();
+		// Now it's locked by us, which is still alive.
+		thread::scope(|s| {
+			s.spawn(|| assert!(mutex.try_steal_if_dead().is_none()));
+		});
+	}
+}
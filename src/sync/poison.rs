@@ -0,0 +1,80 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{LockResult, PoisonError};
+use std::thread;
+
+/// Tracks whether a thread panicked while holding a lock.
+///
+/// This is a plain flag in the lock itself, so it works between processes too.
+#[repr(transparent)]
+pub(crate) struct Flag {
+	failed: AtomicBool,
+}
+
+/// Remembers whether the thread was already panicking when it took the lock.
+pub(crate) struct Guard {
+	panicking: bool,
+}
+
+impl Flag {
+	#[inline]
+	pub(crate) const fn new() -> Self {
+		Self {
+			failed: AtomicBool::new(false),
+		}
+	}
+
+	/// To be called right after locking.
+	#[inline]
+	pub(crate) fn guard(&self) -> LockResult<Guard> {
+		let guard = Guard {
+			panicking: thread::panicking(),
+		};
+		if self.get() {
+			Err(PoisonError::new(guard))
+		} else {
+			Ok(guard)
+		}
+	}
+
+	/// To be called right before unlocking.
+	#[inline]
+	pub(crate) fn done(&self, guard: &Guard) {
+		if !guard.panicking && thread::panicking() {
+			self.set();
+		}
+	}
+
+	#[inline]
+	pub(crate) fn get(&self) -> bool {
+		self.failed.load(Relaxed)
+	}
+
+	#[inline]
+	pub(crate) fn set(&self) {
+		self.failed.store(true, Relaxed);
+	}
+
+	#[inline]
+	pub(crate) fn clear(&self) {
+		self.failed.store(false, Relaxed);
+	}
+
+	/// Wrap a value in a [`PoisonError`] if the flag is set.
+	#[inline]
+	pub(crate) fn result<T>(&self, value: T) -> LockResult<T> {
+		if self.get() {
+			Err(PoisonError::new(value))
+		} else {
+			Ok(value)
+		}
+	}
+}
+
+/// Apply `f` to the value inside a [`LockResult`], keeping it poisoned if it was.
+pub(crate) fn map_result<T, U>(result: LockResult<T>, f: impl FnOnce(T) -> U) -> LockResult<U> {
+	match result {
+		Ok(t) => Ok(f(t)),
+		Err(e) => Err(PoisonError::new(f(e.into_inner()))),
+	}
+}
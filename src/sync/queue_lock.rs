@@ -0,0 +1,343 @@
+use super::poison;
+use crate::sys::FutexCall;
+use crate::{Futex, Private};
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{addr_of, null_mut, NonNull};
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicPtr, AtomicU32};
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+/// The node's thread is waiting for the lock, and might be spinning.
+const WAITING: u32 = 0;
+/// The node's thread is waiting for the lock, and is (about to be) asleep on the futex.
+const SLEEPING: u32 = 1;
+/// The lock was handed over to the node's thread.
+const GRANTED: u32 = 2;
+
+/// The number of times to check the node before going to sleep.
+const SPIN: u32 = 1000;
+
+/// A queue entry, one per thread holding or waiting for the lock.
+///
+/// Aligned to a cache line, so waiting threads don't disturb each other.
+#[repr(align(64))]
+struct Node {
+	next: AtomicPtr<Node>,
+	state: Futex<Private>,
+}
+
+/// An MCS queue lock protecting data of type `T`.
+///
+/// Waiting threads form a queue, in which every thread spins on and then
+/// sleeps on the futex in its own queue node, rather than all threads
+/// hammering the same word. The lock is handed over directly to the next
+/// thread in the queue, in the order in which they arrived.
+///
+/// This performs better than a [`Mutex`][super::Mutex] under heavy
+/// contention, but worse without contention, as every lock operation
+/// allocates a queue node.
+///
+/// Like [`std::sync::Mutex`], the lock is poisoned when a thread panics
+/// while holding it, after which locking it results in a [`PoisonError`].
+///
+/// As the queue consists of pointers, this lock can only be used within a
+/// single process.
+pub struct QueueLock<T: ?Sized> {
+	tail: AtomicPtr<Node>,
+	poison: poison::Flag,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for QueueLock<T> {}
+unsafe impl<T: ?Sized + Send> Sync for QueueLock<T> {}
+
+/// The lock of a [`QueueLock`]. The lock is released when this guard is dropped.
+#[must_use = "if unused the QueueLock will immediately unlock"]
+pub struct QueueLockGuard<'a, T: ?Sized> {
+	lock: &'a QueueLock<T>,
+	/// Our node, allocated by [`new_node`] and freed when the guard is dropped.
+	node: NonNull<Node>,
+	poison: poison::Guard,
+}
+
+unsafe impl<T: ?Sized + Send> Send for QueueLockGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for QueueLockGuard<'_, T> {}
+
+impl<T> QueueLock<T> {
+	/// Create a new unlocked queue lock containing `value`.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self {
+			tail: AtomicPtr::new(null_mut()),
+			poison: poison::Flag::new(),
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the lock, returning the data it protected.
+	///
+	/// If the lock is poisoned, the data is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn into_inner(self) -> LockResult<T> {
+		let poisoned = self.poison.get();
+		let data = self.data.into_inner();
+		if poisoned {
+			Err(PoisonError::new(data))
+		} else {
+			Ok(data)
+		}
+	}
+}
+
+impl<T: ?Sized> QueueLock<T> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the lock.
+	#[inline]
+	pub fn get_mut(&mut self) -> LockResult<&mut T> {
+		let data = self.data.get_mut();
+		self.poison.result(data)
+	}
+
+	/// Check whether the lock is poisoned.
+	#[inline]
+	pub fn is_poisoned(&self) -> bool {
+		self.poison.get()
+	}
+
+	/// Clear the poisoned state of the lock.
+	///
+	/// Use this after recovering the protected data from an inconsistent state.
+	#[inline]
+	pub fn clear_poison(&self) {
+		self.poison.clear();
+	}
+
+	/// Lock, blocking until it is this thread's turn.
+	///
+	/// If the lock is poisoned, the guard is returned inside a [`PoisonError`].
+	pub fn lock(&self) -> LockResult<QueueLockGuard<'_, T>> {
+		let node = new_node(WAITING);
+		let prev = self.tail.swap(node.as_ptr(), AcqRel);
+		if !prev.is_null() {
+			// Link ourselves behind the previous node, and wait for our turn.
+			unsafe { (*prev).next.store(node.as_ptr(), Release) };
+			wait_for_turn(unsafe { node.as_ref() });
+		}
+		self.guard(node)
+	}
+
+	/// Lock, if nobody holds or waits for the lock.
+	pub fn try_lock(&self) -> TryLockResult<QueueLockGuard<'_, T>> {
+		if !self.tail.load(Relaxed).is_null() {
+			return Err(TryLockError::WouldBlock);
+		}
+		let node = new_node(GRANTED);
+		match self
+			.tail
+			.compare_exchange(null_mut(), node.as_ptr(), Acquire, Relaxed)
+		{
+			Ok(_) => Ok(self.guard(node)?),
+			Err(_) => {
+				drop(unsafe { Box::from_raw(node.as_ptr()) });
+				Err(TryLockError::WouldBlock)
+			}
+		}
+	}
+
+	#[inline]
+	fn guard(&self, node: NonNull<Node>) -> LockResult<QueueLockGuard<'_, T>> {
+		poison::map_result(self.poison.guard(), |poison| QueueLockGuard {
+			lock: self,
+			node,
+			poison,
+		})
+	}
+}
+
+fn new_node(state: u32) -> NonNull<Node> {
+	let node = Box::new(Node {
+		next: AtomicPtr::new(null_mut()),
+		state: Futex::new(state),
+	});
+	unsafe { NonNull::new_unchecked(Box::into_raw(node)) }
+}
+
+#[cold]
+fn wait_for_turn(node: &Node) {
+	for _ in 0..SPIN {
+		if node.state.value.load(Acquire) == GRANTED {
+			return;
+		}
+		std::hint::spin_loop();
+	}
+	if node
+		.state
+		.value
+		.compare_exchange(WAITING, SLEEPING, Acquire, Acquire)
+		.is_ok()
+	{
+		while node.state.value.load(Acquire) != GRANTED {
+			let _ = node.state.wait(SLEEPING);
+		}
+	}
+}
+
+impl<T: ?Sized> Drop for QueueLockGuard<'_, T> {
+	fn drop(&mut self) {
+		self.lock.poison.done(&self.poison);
+		unsafe {
+			unlock(&self.lock.tail, self.node.as_ptr());
+			drop(Box::from_raw(self.node.as_ptr()));
+		}
+	}
+}
+
+/// Hand the lock over to the next node in the queue, or unlock it if there is none.
+unsafe fn unlock(tail: &AtomicPtr<Node>, node: *mut Node) {
+	let mut next = (*node).next.load(Acquire);
+	if next.is_null() {
+		// We're the last one in the queue, unless another thread is
+		// just about to link itself behind us.
+		if tail
+			.compare_exchange(node, null_mut(), Release, Relaxed)
+			.is_ok()
+		{
+			return;
+		}
+		loop {
+			next = (*node).next.load(Acquire);
+			if !next.is_null() {
+				break;
+			}
+			std::hint::spin_loop();
+		}
+	}
+	// Once the lock is granted, the next thread might free its node at
+	// any point, so we only keep a raw pointer to wake it up afterwards.
+	let futex: *const AtomicU32 = addr_of!((*next).state.value);
+	if (*futex).swap(GRANTED, Release) == SLEEPING {
+		let _ = FutexCall::new()
+			.futex_op(libc::FUTEX_WAKE + libc::FUTEX_PRIVATE_FLAG)
+			.uaddr(futex)
+			.val(1)
+			.call();
+	}
+}
+
+impl<T: ?Sized> Deref for QueueLockGuard<'_, T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.lock.data.get() }
+	}
+}
+
+impl<T: ?Sized> DerefMut for QueueLockGuard<'_, T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.lock.data.get() }
+	}
+}
+
+impl<T: Default> Default for QueueLock<T> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T> From<T> for QueueLock<T> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for QueueLock<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("QueueLock");
+		match self.try_lock() {
+			Ok(guard) => d.field("data", &&*guard),
+			Err(TryLockError::Poisoned(e)) => d.field("data", &&**e.get_ref()),
+			Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+		};
+		d.field("poisoned", &self.poison.get());
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for QueueLockGuard<'_, T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::QueueLock;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::sync::TryLockError;
+	use std::thread;
+
+	#[test]
+	fn contention() {
+		let lock = QueueLock::new(0);
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..1000 {
+						*lock.lock().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(lock.into_inner().unwrap(), 4000);
+	}
+
+	#[test]
+	fn handed_over_in_order() {
+		let lock = QueueLock::new(Vec::new());
+		let guard = lock.lock().unwrap();
+		thread::scope(|s| {
+			for i in 0..4 {
+				let lock = &lock;
+				let tail = lock.tail.load(Relaxed);
+				s.spawn(move || lock.lock().unwrap().push(i));
+				// Wait until the thread is in the queue.
+				while lock.tail.load(Relaxed) == tail {
+					thread::yield_now();
+				}
+			}
+			drop(guard);
+		});
+		assert_eq!(lock.into_inner().unwrap(), [0, 1, 2, 3]);
+	}
+
+	#[test]
+	fn try_lock() {
+		let lock = QueueLock::new(1);
+		let guard = lock.try_lock().unwrap();
+		assert!(matches!(lock.try_lock(), Err(TryLockError::WouldBlock)));
+		drop(guard);
+		assert_eq!(*lock.try_lock().unwrap(), 1);
+	}
+
+	#[test]
+	fn poisoning() {
+		let lock = QueueLock::new(1);
+		let r = thread::scope(|s| {
+			s.spawn(|| {
+				let mut guard = lock.lock().unwrap();
+				*guard = 2;
+				panic!("poison");
+			})
+			.join()
+		});
+		assert!(r.is_err());
+		assert!(lock.is_poisoned());
+		assert_eq!(*lock.lock().unwrap_err().into_inner(), 2);
+		assert!(matches!(lock.try_lock(), Err(TryLockError::Poisoned(_))));
+		lock.clear_poison();
+		assert_eq!(lock.into_inner().unwrap(), 2);
+	}
+}
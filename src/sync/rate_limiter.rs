@@ -0,0 +1,186 @@
+use crate::{Futex, Private, WakeMask};
+use std::convert::TryFrom;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::{Duration, Instant};
+
+/// A token bucket rate limiter.
+///
+/// Tokens are added at a fixed rate, one per `interval`, up to a maximum of
+/// `burst` tokens. Acquiring tokens blocks until enough have accumulated.
+///
+/// Internally, this doesn't keep track of the number of tokens, but of the
+/// point in time at which the bucket will be full again (the 'theoretical
+/// arrival time' of the generic cell rate algorithm). Acquiring tokens moves
+/// that point forward, and a thread that acquires more tokens than are
+/// available reserves them right away, before sleeping until they have
+/// accumulated. That way, threads get their tokens in the order in which
+/// they asked for them, without any thread having to refill the bucket.
+///
+/// Waiting threads sleep on a futex with a timeout, which no thread ever
+/// wakes up, so no timer thread is involved.
+pub struct RateLimiter {
+	base: Instant,
+	/// Nanoseconds after `base` at which the bucket will be full.
+	full_at: AtomicU64,
+	/// Nanoseconds per token.
+	interval: u64,
+	burst: u64,
+	sleep: Futex<Private>,
+}
+
+impl RateLimiter {
+	/// Create a rate limiter adding a token every `interval`, holding at most `burst` tokens.
+	///
+	/// The bucket starts out full.
+	pub fn new(interval: Duration, burst: u32) -> Self {
+		Self {
+			base: Instant::now(),
+			full_at: AtomicU64::new(0),
+			interval: u64::try_from(interval.as_nanos()).unwrap_or(u64::MAX),
+			burst: u64::from(burst),
+			sleep: Futex::new(0),
+		}
+	}
+
+	/// Create a rate limiter adding `rate` tokens per second, holding at most `burst` tokens.
+	///
+	/// # Panics
+	///
+	/// Panics if `rate` is zero.
+	pub fn per_second(rate: u32, burst: u32) -> Self {
+		assert!(rate != 0, "rate must be nonzero");
+		Self::new(Duration::from_secs(1) / rate, burst)
+	}
+
+	/// Take `n` tokens, blocking until they are available.
+	pub fn acquire(&self, n: u32) {
+		if let Some(ready) = self.reserve(n, u64::MAX) {
+			self.sleep_until(ready);
+		}
+	}
+
+	/// Take `n` tokens, blocking until they are available, unless that would take longer than `timeout`.
+	///
+	/// Returns false, without taking any tokens, if the tokens would not be
+	/// available before the timeout expires. In that case, this returns
+	/// immediately rather than after the timeout.
+	pub fn acquire_timeout(&self, n: u32, timeout: Duration) -> bool {
+		let timeout = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+		match self.reserve(n, timeout) {
+			Some(ready) => {
+				self.sleep_until(ready);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Take `n` tokens, if they are available, without blocking.
+	#[inline]
+	pub fn try_acquire(&self, n: u32) -> bool {
+		self.reserve(n, 0).is_some()
+	}
+
+	/// Reserve `n` tokens, if they'd be available within `max_wait` nanoseconds.
+	///
+	/// Returns the time at which the reserved tokens will be available,
+	/// or `None` if nothing was reserved because of `max_wait`.
+	fn reserve(&self, n: u32, max_wait: u64) -> Option<u64> {
+		let now = self.now();
+		let cost = u64::from(n).saturating_mul(self.interval);
+		let tolerance = self.burst.saturating_mul(self.interval);
+		let mut ready = now;
+		self.full_at
+			.fetch_update(Relaxed, Relaxed, |full_at| {
+				let new = full_at.max(now).saturating_add(cost);
+				ready = new.saturating_sub(tolerance);
+				let wait = ready.saturating_sub(now);
+				(wait <= max_wait).then_some(new)
+			})
+			.ok()
+			.map(|_| ready)
+	}
+
+	/// Nanoseconds since `base`.
+	fn now(&self) -> u64 {
+		u64::try_from(self.base.elapsed().as_nanos()).unwrap_or(u64::MAX)
+	}
+
+	/// Sleep until `ready` nanoseconds after `base`.
+	fn sleep_until(&self, ready: u64) {
+		match self.base.checked_add(Duration::from_nanos(ready)) {
+			Some(deadline) => {
+				while Instant::now() < deadline {
+					let _ = self.sleep.wait_bitset_until(0, WakeMask::ALL, deadline);
+				}
+			}
+			// A deadline too large to represent means sleeping forever.
+			None => loop {
+				let _ = self.sleep.wait(0);
+			},
+		}
+	}
+}
+
+impl std::fmt::Debug for RateLimiter {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("RateLimiter")
+			.field("interval", &Duration::from_nanos(self.interval))
+			.field("burst", &self.burst)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::RateLimiter;
+	use std::panic::catch_unwind;
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn burst_then_wait() {
+		let limiter = RateLimiter::new(Duration::from_millis(20), 2);
+		assert!(limiter.try_acquire(2));
+		assert!(!limiter.try_acquire(1));
+		let start = Instant::now();
+		limiter.acquire(1);
+		assert!(start.elapsed() >= Duration::from_millis(15));
+	}
+
+	#[test]
+	fn acquire_timeout() {
+		let limiter = RateLimiter::new(Duration::from_millis(100), 1);
+		assert!(limiter.acquire_timeout(1, Duration::ZERO));
+		let start = Instant::now();
+		// Returns right away, rather than sleeping until the token is there.
+		assert!(!limiter.acquire_timeout(1, Duration::from_millis(10)));
+		assert!(start.elapsed() < Duration::from_millis(90));
+		// Nothing was reserved, so the next token is still due in less than 150ms.
+		assert!(limiter.acquire_timeout(1, Duration::from_millis(150)));
+		assert!(!limiter.try_acquire(1));
+	}
+
+	#[test]
+	fn limits_the_rate_across_threads() {
+		let limiter = RateLimiter::per_second(1000, 1);
+		let start = Instant::now();
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..10 {
+						limiter.acquire(1);
+					}
+				});
+			}
+		});
+		// The first token was available right away.
+		assert!(start.elapsed() >= Duration::from_millis(39));
+	}
+
+	#[test]
+	fn zero_rate() {
+		assert!(catch_unwind(|| RateLimiter::per_second(0, 1)).is_err());
+	}
+}
@@ -0,0 +1,174 @@
+use super::mutex::RawMutex;
+use super::rwlock::RawRwLock;
+use crate::timeout::deadline;
+use crate::{Private, Scope};
+use lock_api::GuardSend;
+use std::time::{Duration, Instant};
+
+/// The raw lock of a [`Mutex`][super::Mutex], for use with [`lock_api`].
+///
+/// `lock_api::Mutex<RawFutexMutex, T>` is a mutex with all the features of
+/// `lock_api`, such as mapped guards and timed locking, but without
+/// poisoning.
+///
+/// Only available with the `lock_api` feature.
+#[repr(transparent)]
+pub struct RawFutexMutex<S = Private> {
+	raw: RawMutex<S>,
+}
+
+/// The raw lock of a [`RwLock`][super::RwLock], for use with [`lock_api`].
+///
+/// `lock_api::RwLock<RawFutexRwLock, T>` is a reader-writer lock with all
+/// the features of `lock_api`, including upgradable reads and downgrading,
+/// but without poisoning.
+///
+/// Only available with the `lock_api` feature.
+#[repr(transparent)]
+pub struct RawFutexRwLock<S = Private> {
+	raw: RawRwLock<S>,
+}
+
+unsafe impl<S: Scope> lock_api::RawMutex for RawFutexMutex<S> {
+	#[allow(clippy::declare_interior_mutable_const)]
+	const INIT: Self = Self {
+		raw: RawMutex::new(),
+	};
+
+	type GuardMarker = GuardSend;
+
+	#[inline]
+	fn lock(&self) {
+		self.raw.lock();
+	}
+
+	#[inline]
+	fn try_lock(&self) -> bool {
+		self.raw.try_lock()
+	}
+
+	#[inline]
+	unsafe fn unlock(&self) {
+		self.raw.unlock();
+	}
+}
+
+unsafe impl<S: Scope> lock_api::RawMutexTimed for RawFutexMutex<S> {
+	type Duration = Duration;
+	type Instant = Instant;
+
+	#[inline]
+	fn try_lock_for(&self, timeout: Duration) -> bool {
+		match deadline(timeout) {
+			Some(deadline) => self.raw.try_lock_until(deadline),
+			None => {
+				self.raw.lock();
+				true
+			}
+		}
+	}
+
+	#[inline]
+	fn try_lock_until(&self, deadline: Instant) -> bool {
+		self.raw.try_lock_until(deadline)
+	}
+}
+
+unsafe impl<S: Scope> lock_api::RawRwLock for RawFutexRwLock<S> {
+	#[allow(clippy::declare_interior_mutable_const)]
+	const INIT: Self = Self {
+		raw: RawRwLock::new(),
+	};
+
+	type GuardMarker = GuardSend;
+
+	#[inline]
+	fn lock_shared(&self) {
+		self.raw.read();
+	}
+
+	#[inline]
+	fn try_lock_shared(&self) -> bool {
+		self.raw.try_read()
+	}
+
+	#[inline]
+	unsafe fn unlock_shared(&self) {
+		self.raw.read_unlock();
+	}
+
+	#[inline]
+	fn lock_exclusive(&self) {
+		self.raw.write();
+	}
+
+	#[inline]
+	fn try_lock_exclusive(&self) -> bool {
+		self.raw.try_write()
+	}
+
+	#[inline]
+	unsafe fn unlock_exclusive(&self) {
+		self.raw.write_unlock();
+	}
+}
+
+unsafe impl<S: Scope> lock_api::RawRwLockDowngrade for RawFutexRwLock<S> {
+	#[inline]
+	unsafe fn downgrade(&self) {
+		self.raw.downgrade();
+	}
+}
+
+unsafe impl<S: Scope> lock_api::RawRwLockUpgrade for RawFutexRwLock<S> {
+	#[inline]
+	fn lock_upgradable(&self) {
+		self.raw.upgradable_read();
+	}
+
+	#[inline]
+	fn try_lock_upgradable(&self) -> bool {
+		self.raw.try_upgradable_read()
+	}
+
+	#[inline]
+	unsafe fn unlock_upgradable(&self) {
+		self.raw.upgradable_read_unlock();
+	}
+
+	#[inline]
+	unsafe fn upgrade(&self) {
+		self.raw.upgrade();
+	}
+
+	#[inline]
+	unsafe fn try_upgrade(&self) -> bool {
+		self.raw.try_upgrade()
+	}
+}
+
+unsafe impl<S: Scope> lock_api::RawRwLockUpgradeDowngrade for RawFutexRwLock<S> {
+	#[inline]
+	unsafe fn downgrade_upgradable(&self) {
+		self.raw.downgrade_upgradable();
+	}
+
+	#[inline]
+	unsafe fn downgrade_to_upgradable(&self) {
+		self.raw.downgrade_to_upgradable();
+	}
+}
+
+impl<S> std::fmt::Debug for RawFutexMutex<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("RawFutexMutex")
+			.field("futex", &self.raw.futex)
+			.finish()
+	}
+}
+
+impl<S> std::fmt::Debug for RawFutexRwLock<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("RawFutexRwLock").finish_non_exhaustive()
+	}
+}
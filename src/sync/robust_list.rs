@@ -0,0 +1,193 @@
+//! Linking futexes into the calling thread's robust futex list.
+//!
+//! When a thread exits, the kernel walks its robust list, and marks every
+//! futex on it that is still locked by that thread with `FUTEX_OWNER_DIED`,
+//! waking up a waiter. There is only one list per thread, which glibc
+//! registers for its own robust `pthread_mutex_t`s, so our entries are
+//! linked into that same list, using the same layout as glibc: a doubly
+//! linked list of pointers to the `next` field of each entry, with the
+//! `prev` field right before it, and the futex at a fixed offset. Pointers
+//! to entries for PI futexes have their lowest bit set.
+
+use crate::{PiFutex, Shared};
+use std::cell::Cell;
+use std::mem::size_of;
+use std::ptr::{addr_of_mut, null_mut};
+use std::sync::atomic::AtomicPtr;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// The offset of the futex relative to the `next` field of an entry, as
+/// used by glibc's `pthread_mutex_t` on 64-bit platforms.
+pub(crate) const FUTEX_OFFSET: isize = -32;
+
+/// The list links of an entry, placed 24 bytes after the futex.
+#[repr(C)]
+pub(crate) struct Links {
+	prev: AtomicPtr<()>,
+	next: AtomicPtr<()>,
+}
+
+impl Links {
+	pub(crate) const fn new() -> Self {
+		Self {
+			prev: AtomicPtr::new(null_mut()),
+			next: AtomicPtr::new(null_mut()),
+		}
+	}
+
+	fn next_ptr(&self) -> *mut () {
+		&self.next as *const AtomicPtr<()> as *mut ()
+	}
+
+	/// The pointer to this entry, marked as a PI futex if `pi` is true.
+	fn entry(&self, pi: bool) -> *mut () {
+		(self.next_ptr() as usize | pi as usize) as *mut ()
+	}
+}
+
+/// `struct robust_list_head` from the kernel.
+#[repr(C)]
+struct Head {
+	list: *mut (),
+	futex_offset: isize,
+	list_op_pending: *mut (),
+}
+
+/// A [`Head`] with room for the `prev` field that list operations write to
+/// when the head is the neighbour of an entry, like glibc's `robust_prev`.
+#[repr(C)]
+struct OwnHead {
+	prev: *mut (),
+	head: Head,
+}
+
+thread_local! {
+	static HEAD: Cell<*mut Head> = const { Cell::new(null_mut()) };
+}
+
+/// The robust list of the calling thread, registering one if it doesn't have one yet.
+///
+/// # Panics
+///
+/// Panics if the thread already has a robust list with a different layout.
+fn head() -> *mut Head {
+	HEAD.with(|h| {
+		if h.get().is_null() {
+			h.set(unsafe { get_or_register() });
+		}
+		h.get()
+	})
+}
+
+#[cold]
+unsafe fn get_or_register() -> *mut Head {
+	let mut head: *mut Head = null_mut();
+	let mut len = 0usize;
+	if libc::syscall(libc::SYS_get_robust_list, 0, &mut head, &mut len) != 0 || head.is_null() {
+		// Nobody registered a list for this thread. Register our own.
+		// This is never freed, as the kernel walks it when the thread exits.
+		let own = Box::leak(Box::new(OwnHead {
+			prev: null_mut(),
+			head: Head {
+				list: null_mut(),
+				futex_offset: FUTEX_OFFSET,
+				list_op_pending: null_mut(),
+			},
+		}));
+		head = addr_of_mut!(own.head);
+		(*head).list = head as *mut ();
+		let r = libc::syscall(libc::SYS_set_robust_list, head, size_of::<Head>());
+		assert_eq!(r, 0, "set_robust_list failed");
+	}
+	assert_eq!(
+		(*head).futex_offset,
+		FUTEX_OFFSET,
+		"robust futex list of this thread has an unsupported layout"
+	);
+	head
+}
+
+/// Mark an entry as about to be locked or unlocked, so the kernel also
+/// checks its futex if the thread dies before the operation completes.
+///
+/// `pi` tells the kernel whether the futex is a PI futex.
+#[inline]
+pub(crate) fn set_pending(links: &Links, pi: bool) {
+	unsafe { (*head()).list_op_pending = links.entry(pi) };
+}
+
+#[inline]
+pub(crate) fn clear_pending() {
+	unsafe { (*head()).list_op_pending = null_mut() };
+}
+
+/// Add an entry to the front of the calling thread's robust list.
+///
+/// The entry's futex must be locked by the calling thread.
+/// `pi` tells the kernel whether it is a PI futex.
+#[inline]
+pub(crate) unsafe fn enqueue(links: &Links, pi: bool) {
+	let head = head();
+	let first = (*head).list;
+	links.next.store(first, Relaxed);
+	links.prev.store(head as *mut (), Relaxed);
+	*prev_of(first) = links.next_ptr();
+	(*head).list = links.entry(pi);
+}
+
+/// Remove an entry from the calling thread's robust list.
+///
+/// The entry must be on the calling thread's robust list.
+#[inline]
+pub(crate) unsafe fn dequeue(links: &Links) {
+	let next = links.next.load(Relaxed);
+	let prev = links.prev.load(Relaxed);
+	*prev_of(next) = prev;
+	*((prev as usize & !1) as *mut *mut ()) = next;
+}
+
+/// Abort if a robust futex with value `value` is about to be freed while locked.
+///
+/// A locked futex is on the robust list of its owner, so freeing it would
+/// leave a dangling entry that the next list operation writes through.
+/// That can only happen if its guard was leaked. Panicking would not help,
+/// since the memory is freed during unwinding either way.
+#[inline]
+pub(crate) fn abort_if_locked(value: u32) {
+	if value & PiFutex::<Shared>::TID_MASK != 0 {
+		abort("robust mutex dropped while locked");
+	}
+}
+
+/// Abort if the calling thread's robust list has an entry in the `len` bytes at `start`.
+///
+/// Call this before unmapping memory, for the same reason as [`abort_if_locked`].
+pub(crate) fn abort_if_listed(start: *const u8, len: usize) {
+	let head = HEAD.with(|h| h.get());
+	if head.is_null() {
+		// This thread never locked a robust futex of ours.
+		return;
+	}
+	unsafe {
+		let mut entry = (*head).list;
+		while entry != head as *mut () {
+			let next = (entry as usize & !1) as *const *mut ();
+			if (next as usize).wrapping_sub(start as usize) < len {
+				abort("robust mutex unmapped while locked");
+			}
+			entry = *next;
+		}
+	}
+}
+
+#[cold]
+fn abort(msg: &str) -> ! {
+	eprintln!("{}", msg);
+	std::process::abort();
+}
+
+/// The `prev` field of the entry (or head) with the given `next` field.
+#[inline]
+unsafe fn prev_of(next: *mut ()) -> *mut *mut () {
+	((next as usize & !1) - size_of::<*mut ()>()) as *mut *mut ()
+}
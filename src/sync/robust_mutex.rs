@@ -0,0 +1,525 @@
+use super::robust_list::{self, Links};
+use crate::pi::current_tid;
+use crate::{Futex, NotRecoverableError, PiFutex, Shared};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+const WAITERS: u32 = PiFutex::<Shared>::WAITERS;
+const OWNER_DIED: u32 = PiFutex::<Shared>::OWNER_DIED;
+const TID_MASK: u32 = PiFutex::<Shared>::TID_MASK;
+
+/// The protected state is consistent.
+pub(super) const CONSISTENT: u32 = 0;
+/// An owner died, and the protected state was not made consistent yet.
+pub(super) const INCONSISTENT: u32 = 1;
+/// An owner died, and the next owner unlocked without making the state consistent.
+pub(super) const NOT_RECOVERABLE: u32 = 2;
+
+/// The futex and list links, laid out like the start of glibc's `pthread_mutex_t`.
+#[repr(C)]
+struct RawRobustMutex {
+	/// The thread id of the owner, plus the `WAITERS` and `OWNER_DIED` bits.
+	futex: Futex<Shared>,
+	state: AtomicU32,
+	_reserved: [u32; 4],
+	links: Links,
+}
+
+/// A robust mutex protecting data of type `T`, which detects when its owner died.
+///
+/// The mutex is linked into the robust futex list of the thread holding it.
+/// If that thread exits (or its process dies) without unlocking it, the
+/// kernel marks the mutex as such and wakes up a waiter. The next thread to
+/// lock it then gets a [`RobustLockError::OwnerDied`], with a guard that
+/// gives access to the possibly inconsistent data. After restoring the
+/// invariants of the data, it marks the state as consistent with
+/// [`make_consistent`][RobustMutexGuard::make_consistent]. If it unlocks
+/// without doing so, the mutex becomes unusable, and every later attempt
+/// to lock it results in [`RobustLockError::NotRecoverable`].
+/// [`lock_with_recovery`][RobustMutex::lock_with_recovery] wraps this in a
+/// single call.
+///
+/// A locked mutex is linked into the robust list of its owner, so it must
+/// stay in place until it is unlocked. Dropping or unmapping a mutex while
+/// it is locked, which is only possible after leaking its guard (for example
+/// with [`mem::forget`][std::mem::forget]), aborts the process.
+///
+/// # Recovering after a crash
+///
+/// Placed in shared memory, this is how a surviving process takes over
+/// from a process that crashed while holding the lock:
+///
+/// 1. The kernel unlocks the mutex when the owner dies, marking it as
+///    abandoned, and wakes up one waiting thread. Without waiters, the
+///    mutex stays abandoned until it is locked again.
+///    [`state`][RobustMutex::state] shows this without locking.
+/// 2. The next thread to lock it, in any process, gets
+///    [`RobustLockError::OwnerDied`] with a guard, and is the only one
+///    that gets to repair the data.
+/// 3. After repairing the data, it calls
+///    [`make_consistent`][RobustMutexGuard::make_consistent], and the
+///    mutex is usable again for everyone once the guard is dropped.
+///
+/// If the repairing thread dies as well, the next thread to lock gets
+/// `OwnerDied` again. If it gives up without calling `make_consistent`, all
+/// waiting and future lockers get [`RobustLockError::NotRecoverable`], since
+/// there is no safe way to continue.
+///
+/// The robust list is shared with glibc, and this type uses the layout
+/// glibc uses for its own robust mutexes on 64-bit platforms.
+///
+/// As the kernel always wakes up waiters on a robust futex as shared
+/// futexes, this mutex always uses a [`Futex<Shared>`][Futex]. It can be
+/// used between processes, if it is placed in shared memory.
+#[repr(C)]
+pub struct RobustMutex<T: ?Sized> {
+	raw: RawRobustMutex,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RobustMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for RobustMutex<T> {}
+
+/// The lock of a [`RobustMutex`]. The mutex is unlocked when this guard is dropped.
+#[must_use = "if unused the RobustMutex will immediately unlock"]
+pub struct RobustMutexGuard<'a, T: ?Sized> {
+	mutex: &'a RobustMutex<T>,
+	/// The mutex is on the robust list of the thread that locked it.
+	not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RobustMutexGuard<'_, T> {}
+
+/// The ways locking a [`RobustMutex`] can fail.
+pub enum RobustLockError<G> {
+	/// The previous owner died while holding the lock.
+	///
+	/// The lock was acquired, but the protected data might be inconsistent.
+	/// Restore its invariants, and then call
+	/// [`make_consistent`][RobustMutexGuard::make_consistent], or the mutex
+	/// becomes unrecoverable when the guard is dropped.
+	OwnerDied(G),
+	/// A previous owner died while holding the lock, and the protected data
+	/// was never made consistent again.
+	NotRecoverable,
+}
+
+/// The state of the data protected by a [`RobustMutex`], as returned by [`RobustMutex::state`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LockState {
+	/// No owner died, or the data was made consistent again afterwards.
+	Consistent,
+	/// An owner died while holding the lock, and the data was not made
+	/// consistent yet. The next thread to lock the mutex gets
+	/// [`RobustLockError::OwnerDied`].
+	Abandoned,
+	/// An owner died, and the data was never made consistent again.
+	NotRecoverable,
+}
+
+impl<T> RobustMutex<T> {
+	/// Create a new unlocked mutex containing `value`.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self {
+			raw: RawRobustMutex {
+				futex: Futex::new(0),
+				state: AtomicU32::new(CONSISTENT),
+				_reserved: [0; 4],
+				links: Links::new(),
+			},
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the mutex, returning the data it protected.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.data.into_inner()
+	}
+}
+
+impl<T: ?Sized> RobustMutex<T> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the mutex.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		self.data.get_mut()
+	}
+
+	/// Check whether an owner died, without locking the mutex.
+	///
+	/// This is only a snapshot: an owner might die, or another thread might
+	/// recover the data, right after this returns.
+	pub fn state(&self) -> LockState {
+		match self.raw.state.load(Relaxed) {
+			NOT_RECOVERABLE => LockState::NotRecoverable,
+			INCONSISTENT => LockState::Abandoned,
+			_ if self.raw.futex.value.load(Relaxed) & OWNER_DIED != 0 => LockState::Abandoned,
+			_ => LockState::Consistent,
+		}
+	}
+
+	/// Lock the mutex, blocking until it is available.
+	///
+	/// Locking a mutex that is already locked by the calling thread results in a deadlock.
+	pub fn lock(
+		&self,
+	) -> Result<RobustMutexGuard<'_, T>, RobustLockError<RobustMutexGuard<'_, T>>> {
+		robust_list::set_pending(&self.raw.links, false);
+		let owner_died = self.raw.lock();
+		self.acquired(owner_died)
+	}
+
+	/// Lock the mutex if it is not locked, without blocking.
+	///
+	/// Returns `None` if the mutex is locked.
+	pub fn try_lock(
+		&self,
+	) -> Option<Result<RobustMutexGuard<'_, T>, RobustLockError<RobustMutexGuard<'_, T>>>> {
+		robust_list::set_pending(&self.raw.links, false);
+		match self.raw.try_lock() {
+			Some(owner_died) => Some(self.acquired(owner_died)),
+			None => {
+				robust_list::clear_pending();
+				None
+			}
+		}
+	}
+
+	/// Lock the mutex, calling `recover` on the data first if the previous owner died.
+	///
+	/// After `recover` returns, the state is marked as consistent again.
+	/// If `recover` panics, the mutex is unlocked without marking it as
+	/// consistent, making it unrecoverable.
+	pub fn lock_with_recovery(
+		&self,
+		recover: impl FnOnce(&mut T),
+	) -> Result<RobustMutexGuard<'_, T>, NotRecoverableError> {
+		match self.lock() {
+			Ok(guard) => Ok(guard),
+			Err(RobustLockError::OwnerDied(mut guard)) => {
+				recover(&mut *guard);
+				RobustMutexGuard::make_consistent(&guard);
+				Ok(guard)
+			}
+			Err(RobustLockError::NotRecoverable) => Err(NotRecoverableError::NotRecoverable),
+		}
+	}
+
+	/// Finish locking, after the futex was locked by the calling thread.
+	fn acquired(
+		&self,
+		owner_died: bool,
+	) -> Result<RobustMutexGuard<'_, T>, RobustLockError<RobustMutexGuard<'_, T>>> {
+		unsafe { robust_list::enqueue(&self.raw.links, false) };
+		robust_list::clear_pending();
+		let guard = RobustMutexGuard {
+			mutex: self,
+			not_send: PhantomData,
+		};
+		if owner_died {
+			self.raw.state.store(INCONSISTENT, Relaxed);
+		}
+		match self.raw.state.load(Relaxed) {
+			CONSISTENT => Ok(guard),
+			INCONSISTENT => Err(RobustLockError::OwnerDied(guard)),
+			// Dropping the guard unlocks the mutex again.
+			_ => Err(RobustLockError::NotRecoverable),
+		}
+	}
+}
+
+impl RawRobustMutex {
+	/// Lock the futex. Returns true if the previous owner died.
+	#[inline]
+	fn lock(&self) -> bool {
+		match self.try_lock() {
+			Some(owner_died) => owner_died,
+			None => self.lock_contended(),
+		}
+	}
+
+	#[inline]
+	fn try_lock(&self) -> Option<bool> {
+		let mut v = self.futex.value.load(Relaxed);
+		while v & TID_MASK == 0 {
+			// Unlocked, possibly by the kernel after the owner died.
+			match self.futex.value.compare_exchange_weak(
+				v,
+				current_tid() | (v & WAITERS),
+				Acquire,
+				Relaxed,
+			) {
+				Ok(_) => return Some(v & OWNER_DIED != 0),
+				Err(e) => v = e,
+			}
+		}
+		None
+	}
+
+	#[cold]
+	fn lock_contended(&self) -> bool {
+		let tid = current_tid();
+		let mut v = self.futex.value.load(Relaxed);
+		loop {
+			if v & TID_MASK == 0 {
+				// There might be other waiters, since we've been waiting.
+				match self
+					.futex
+					.value
+					.compare_exchange(v, tid | WAITERS, Acquire, Relaxed)
+				{
+					Ok(_) => return v & OWNER_DIED != 0,
+					Err(e) => v = e,
+				}
+				continue;
+			}
+			if v & WAITERS == 0 {
+				if let Err(e) = self
+					.futex
+					.value
+					.compare_exchange(v, v | WAITERS, Relaxed, Relaxed)
+				{
+					v = e;
+					continue;
+				}
+			}
+			let _ = self.futex.wait(v | WAITERS);
+			v = self.futex.value.load(Relaxed);
+		}
+	}
+
+	/// Unlock the futex, and remove it from the robust list.
+	///
+	/// The futex must be locked by the calling thread.
+	#[inline]
+	unsafe fn unlock(&self) {
+		let n = if self.state.load(Relaxed) == INCONSISTENT {
+			// Nobody made the state consistent. Wake everyone to tell them.
+			self.state.store(NOT_RECOVERABLE, Relaxed);
+			u32::MAX
+		} else {
+			1
+		};
+		robust_list::set_pending(&self.links, false);
+		robust_list::dequeue(&self.links);
+		if self.futex.value.swap(0, Release) & WAITERS != 0 {
+			self.futex.wake(n);
+		}
+		robust_list::clear_pending();
+	}
+}
+
+impl Drop for RawRobustMutex {
+	fn drop(&mut self) {
+		robust_list::abort_if_locked(*self.futex.value.get_mut());
+	}
+}
+
+impl<T: ?Sized> RobustMutexGuard<'_, T> {
+	/// Mark the state protected by the mutex as consistent again,
+	/// after the previous owner died.
+	///
+	/// This is an associated function, to not conflict with methods on `T`.
+	#[inline]
+	pub fn make_consistent(this: &Self) {
+		let _ = this
+			.mutex
+			.raw
+			.state
+			.compare_exchange(INCONSISTENT, CONSISTENT, Relaxed, Relaxed);
+	}
+}
+
+impl<T: ?Sized> Deref for RobustMutexGuard<'_, T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized> DerefMut for RobustMutexGuard<'_, T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized> Drop for RobustMutexGuard<'_, T> {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { self.mutex.raw.unlock() }
+	}
+}
+
+impl<T: Default> Default for RobustMutex<T> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T> From<T> for RobustMutex<T> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for RobustMutex<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("RobustMutex");
+		match self.try_lock() {
+			Some(Ok(guard)) => d.field("data", &&*guard),
+			Some(Err(RobustLockError::OwnerDied(guard))) => d.field("data", &&*guard),
+			Some(Err(RobustLockError::NotRecoverable)) => {
+				d.field("data", &format_args!("<not recoverable>"))
+			}
+			None => d.field("data", &format_args!("<locked>")),
+		};
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for RobustMutexGuard<'_, T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+impl<G> std::fmt::Debug for RobustLockError<G> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::OwnerDied(_) => f.write_str("OwnerDied(..)"),
+			Self::NotRecoverable => f.write_str("NotRecoverable"),
+		}
+	}
+}
+
+impl<G> std::fmt::Display for RobustLockError<G> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::OwnerDied(_) => "previous owner of the lock died",
+			Self::NotRecoverable => "lock is not recoverable",
+		})
+	}
+}
+
+impl<G> std::error::Error for RobustLockError<G> {}
+
+#[cfg(test)]
+mod tests {
+	use super::{LockState, RobustLockError, RobustMutex, RobustMutexGuard};
+	use crate::sync::Latch;
+	use crate::{NotRecoverableError, Private};
+	use std::thread;
+	use std::time::Duration;
+
+	/// Lock the mutex on a thread that exits without unlocking it.
+	fn abandon(mutex: &RobustMutex<u32>, value: u32) {
+		thread::scope(|s| {
+			// Only an explicit join waits until the thread has really exited,
+			// after the kernel walked its robust list.
+			s.spawn(|| {
+				let mut guard = mutex.lock().ok().unwrap();
+				*guard = value;
+				std::mem::forget(guard);
+			})
+			.join()
+			.unwrap();
+		});
+	}
+
+	#[test]
+	fn contention() {
+		let mutex = RobustMutex::new(0u32);
+		thread::scope(|s| {
+			for _ in 0..8 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*mutex.lock().ok().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(mutex.into_inner(), 80_000);
+	}
+
+	#[test]
+	fn try_lock() {
+		let mutex = RobustMutex::new(0u32);
+		let guard = mutex.try_lock().unwrap().ok().unwrap();
+		thread::scope(|s| {
+			s.spawn(|| assert!(mutex.try_lock().is_none()));
+		});
+		drop(guard);
+		assert!(mutex.try_lock().unwrap().is_ok());
+	}
+
+	#[test]
+	fn owner_died() {
+		let mutex = RobustMutex::new(0u32);
+		abandon(&mutex, 1);
+		assert_eq!(mutex.state(), LockState::Abandoned);
+		match mutex.lock() {
+			Err(RobustLockError::OwnerDied(guard)) => {
+				assert_eq!(*guard, 1);
+				RobustMutexGuard::make_consistent(&guard);
+			}
+			r => panic!("unexpected result: {:?}", r.map(|_| ())),
+		}
+		assert_eq!(mutex.state(), LockState::Consistent);
+		assert!(mutex.lock().is_ok());
+	}
+
+	#[test]
+	fn not_recoverable() {
+		let mutex = RobustMutex::new(0u32);
+		abandon(&mutex, 1);
+		// Give up without making the state consistent.
+		assert!(matches!(mutex.lock(), Err(RobustLockError::OwnerDied(_))));
+		assert_eq!(mutex.state(), LockState::NotRecoverable);
+		assert!(matches!(mutex.lock(), Err(RobustLockError::NotRecoverable)));
+		assert!(matches!(
+			mutex.try_lock(),
+			Some(Err(RobustLockError::NotRecoverable))
+		));
+		assert_eq!(
+			mutex.lock_with_recovery(|_| unreachable!()).err(),
+			Some(NotRecoverableError::NotRecoverable)
+		);
+	}
+
+	#[test]
+	fn lock_with_recovery() {
+		let mutex = RobustMutex::new(0u32);
+		assert_eq!(*mutex.lock_with_recovery(|_| unreachable!()).unwrap(), 0);
+		abandon(&mutex, 1);
+		let guard = mutex.lock_with_recovery(|value| *value = 2).unwrap();
+		assert_eq!(*guard, 2);
+		drop(guard);
+		assert_eq!(mutex.state(), LockState::Consistent);
+	}
+
+	#[test]
+	fn owner_died_while_others_wait() {
+		let mutex = RobustMutex::new(0u32);
+		let locked = Latch::<Private>::new();
+		thread::scope(|s| {
+			let owner = s.spawn(|| {
+				let guard = mutex.lock().ok().unwrap();
+				locked.set();
+				thread::sleep(Duration::from_millis(10));
+				std::mem::forget(guard);
+			});
+			locked.wait();
+			let waiter = s.spawn(|| mutex.lock_with_recovery(|value| *value = 1).map(|g| *g));
+			owner.join().unwrap();
+			assert_eq!(waiter.join().unwrap(), Ok(1));
+		});
+	}
+}
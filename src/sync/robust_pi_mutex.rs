@@ -0,0 +1,399 @@
+use super::robust_list::{self, Links};
+use super::robust_mutex::{CONSISTENT, INCONSISTENT, NOT_RECOVERABLE};
+use super::{LockState, RobustLockError};
+use crate::pi::current_tid;
+use crate::{Acquired, LockError, NotRecoverableError, PiFutex, Shared};
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+/// The PI futex and list links, laid out like the start of glibc's `pthread_mutex_t`.
+#[repr(C)]
+struct RawRobustPiMutex {
+	futex: PiFutex<Shared>,
+	state: AtomicU32,
+	_reserved: [u32; 4],
+	links: Links,
+}
+
+/// A robust mutex with priority inheritance, protecting data of type `T`.
+///
+/// This combines a [`PiMutex`][super::PiMutex] and a
+/// [`RobustMutex`][super::RobustMutex], like a `pthread_mutex_t` with both
+/// `PTHREAD_PRIO_INHERIT` and `PTHREAD_MUTEX_ROBUST`. Threads waiting for
+/// it lend their priority to its owner through `FUTEX_LOCK_PI`, and the
+/// mutex is linked into the robust futex list of the owning thread, marked
+/// as a PI futex. If the owner exits (or its process dies) without
+/// unlocking, the kernel hands the mutex to the highest priority waiter,
+/// and the next thread to lock it gets a [`RobustLockError::OwnerDied`].
+///
+/// Recovering works exactly like for a `RobustMutex`: restore the
+/// invariants of the data, and call
+/// [`make_consistent`][RobustPiMutexGuard::make_consistent] before
+/// unlocking, or the mutex becomes unrecoverable.
+///
+/// Like a `RobustMutex`, dropping or unmapping this mutex while it is
+/// locked, after leaking its guard, aborts the process.
+///
+/// Like a `RobustMutex`, this mutex always uses a
+/// [`PiFutex<Shared>`][PiFutex], and can be used between processes in the
+/// same PID namespace, if it is placed in shared memory.
+#[repr(C)]
+pub struct RobustPiMutex<T: ?Sized> {
+	raw: RawRobustPiMutex,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RobustPiMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for RobustPiMutex<T> {}
+
+/// The lock of a [`RobustPiMutex`]. The mutex is unlocked when this guard is dropped.
+#[must_use = "if unused the RobustPiMutex will immediately unlock"]
+pub struct RobustPiMutexGuard<'a, T: ?Sized> {
+	mutex: &'a RobustPiMutex<T>,
+	/// The mutex is owned by, and on the robust list of, the thread that locked it.
+	not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for RobustPiMutexGuard<'_, T> {}
+
+impl<T> RobustPiMutex<T> {
+	/// Create a new unlocked mutex containing `value`.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self {
+			raw: RawRobustPiMutex {
+				futex: PiFutex::new(0),
+				state: AtomicU32::new(CONSISTENT),
+				_reserved: [0; 4],
+				links: Links::new(),
+			},
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the mutex, returning the data it protected.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.data.into_inner()
+	}
+}
+
+impl<T: ?Sized> RobustPiMutex<T> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the mutex.
+	#[inline]
+	pub fn get_mut(&mut self) -> &mut T {
+		self.data.get_mut()
+	}
+
+	/// Check whether an owner died, without locking the mutex.
+	///
+	/// This is only a snapshot: an owner might die, or another thread might
+	/// recover the data, right after this returns.
+	pub fn state(&self) -> LockState {
+		match self.raw.state.load(Relaxed) {
+			NOT_RECOVERABLE => LockState::NotRecoverable,
+			INCONSISTENT => LockState::Abandoned,
+			_ if self.raw.futex.state().owner_died() => LockState::Abandoned,
+			_ => LockState::Consistent,
+		}
+	}
+
+	/// Lock the mutex, blocking until it is available.
+	///
+	/// # Panics
+	///
+	/// Panics if the mutex is already locked by the calling thread.
+	pub fn lock(
+		&self,
+	) -> Result<RobustPiMutexGuard<'_, T>, RobustLockError<RobustPiMutexGuard<'_, T>>> {
+		robust_list::set_pending(&self.raw.links, true);
+		let acquired = if self.raw.try_lock_fast() {
+			Acquired::Clean
+		} else {
+			self.raw.lock_contended()
+		};
+		self.acquired(acquired)
+	}
+
+	/// Lock the mutex if it is not locked, without blocking.
+	///
+	/// Returns `None` if the mutex is locked.
+	pub fn try_lock(
+		&self,
+	) -> Option<Result<RobustPiMutexGuard<'_, T>, RobustLockError<RobustPiMutexGuard<'_, T>>>> {
+		robust_list::set_pending(&self.raw.links, true);
+		if self.raw.try_lock_fast() {
+			return Some(self.acquired(Acquired::Clean));
+		}
+		// Let the kernel try, if there is no owner but the futex isn't zero.
+		if !self.raw.futex.state().is_locked() {
+			if let Ok(acquired) = self.raw.futex.trylock_pi() {
+				return Some(self.acquired(acquired));
+			}
+		}
+		robust_list::clear_pending();
+		None
+	}
+
+	/// Lock the mutex, calling `recover` on the data first if the previous owner died.
+	///
+	/// After `recover` returns, the state is marked as consistent again.
+	/// If `recover` panics, the mutex is unlocked without marking it as
+	/// consistent, making it unrecoverable.
+	pub fn lock_with_recovery(
+		&self,
+		recover: impl FnOnce(&mut T),
+	) -> Result<RobustPiMutexGuard<'_, T>, NotRecoverableError> {
+		match self.lock() {
+			Ok(guard) => Ok(guard),
+			Err(RobustLockError::OwnerDied(mut guard)) => {
+				recover(&mut *guard);
+				RobustPiMutexGuard::make_consistent(&guard);
+				Ok(guard)
+			}
+			Err(RobustLockError::NotRecoverable) => Err(NotRecoverableError::NotRecoverable),
+		}
+	}
+
+	/// Finish locking, after the futex was locked by the calling thread.
+	fn acquired(
+		&self,
+		acquired: Acquired,
+	) -> Result<RobustPiMutexGuard<'_, T>, RobustLockError<RobustPiMutexGuard<'_, T>>> {
+		unsafe { robust_list::enqueue(&self.raw.links, true) };
+		robust_list::clear_pending();
+		let guard = RobustPiMutexGuard {
+			mutex: self,
+			not_send: PhantomData,
+		};
+		if acquired == Acquired::OwnerDied {
+			self.raw.state.store(INCONSISTENT, Relaxed);
+		}
+		match self.raw.state.load(Relaxed) {
+			CONSISTENT => Ok(guard),
+			INCONSISTENT => Err(RobustLockError::OwnerDied(guard)),
+			// Dropping the guard unlocks the mutex again.
+			_ => Err(RobustLockError::NotRecoverable),
+		}
+	}
+}
+
+impl RawRobustPiMutex {
+	#[inline]
+	fn try_lock_fast(&self) -> bool {
+		self.futex
+			.value
+			.compare_exchange(0, current_tid(), Acquire, Relaxed)
+			.is_ok()
+	}
+
+	#[cold]
+	fn lock_contended(&self) -> Acquired {
+		loop {
+			match self.futex.lock_pi() {
+				Ok(acquired) => return acquired,
+				Err(LockError::TryAgain) => continue,
+				Err(LockError::Deadlock) => {
+					robust_list::clear_pending();
+					panic!("RobustPiMutex already locked by the calling thread");
+				}
+			}
+		}
+	}
+
+	/// Unlock the futex, and remove it from the robust list.
+	///
+	/// The futex must be locked by the calling thread.
+	#[inline]
+	unsafe fn unlock(&self) {
+		if self.state.load(Relaxed) == INCONSISTENT {
+			// Nobody made the state consistent. Every waiter will get the
+			// lock in turn, see this, and unlock it again.
+			self.state.store(NOT_RECOVERABLE, Relaxed);
+		}
+		robust_list::set_pending(&self.links, true);
+		robust_list::dequeue(&self.links);
+		if self
+			.futex
+			.value
+			.compare_exchange(current_tid(), 0, Release, Relaxed)
+			.is_err()
+		{
+			// There are waiters, or the owner died bit is set. Let the kernel handle it.
+			let _ = self.futex.unlock_pi();
+		}
+		robust_list::clear_pending();
+	}
+}
+
+impl Drop for RawRobustPiMutex {
+	fn drop(&mut self) {
+		robust_list::abort_if_locked(*self.futex.value.get_mut());
+	}
+}
+
+impl<T: ?Sized> RobustPiMutexGuard<'_, T> {
+	/// Mark the state protected by the mutex as consistent again,
+	/// after the previous owner died.
+	///
+	/// This is an associated function, to not conflict with methods on `T`.
+	#[inline]
+	pub fn make_consistent(this: &Self) {
+		let _ = this
+			.mutex
+			.raw
+			.state
+			.compare_exchange(INCONSISTENT, CONSISTENT, Relaxed, Relaxed);
+	}
+}
+
+impl<T: ?Sized> Deref for RobustPiMutexGuard<'_, T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized> DerefMut for RobustPiMutexGuard<'_, T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.mutex.data.get() }
+	}
+}
+
+impl<T: ?Sized> Drop for RobustPiMutexGuard<'_, T> {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { self.mutex.raw.unlock() }
+	}
+}
+
+impl<T: Default> Default for RobustPiMutex<T> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T> From<T> for RobustPiMutex<T> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for RobustPiMutex<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("RobustPiMutex");
+		match self.try_lock() {
+			Some(Ok(guard)) => d.field("data", &&*guard),
+			Some(Err(RobustLockError::OwnerDied(guard))) => d.field("data", &&*guard),
+			Some(Err(RobustLockError::NotRecoverable)) => {
+				d.field("data", &format_args!("<not recoverable>"))
+			}
+			None => d.field("data", &format_args!("<locked>")),
+		};
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for RobustPiMutexGuard<'_, T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{RobustPiMutex, RobustPiMutexGuard};
+	use crate::sync::{Latch, LockState, RobustLockError};
+	use crate::Private;
+	use std::panic::{catch_unwind, AssertUnwindSafe};
+	use std::thread;
+	use std::time::Duration;
+
+	/// Lock the mutex on a thread that exits without unlocking it.
+	fn abandon(mutex: &RobustPiMutex<u32>, value: u32) {
+		thread::scope(|s| {
+			// Only an explicit join waits until the thread has really exited,
+			// after the kernel walked its robust list.
+			s.spawn(|| {
+				let mut guard = mutex.lock().ok().unwrap();
+				*guard = value;
+				std::mem::forget(guard);
+			})
+			.join()
+			.unwrap();
+		});
+	}
+
+	#[test]
+	fn contention() {
+		let mutex = RobustPiMutex::new(0u32);
+		thread::scope(|s| {
+			for _ in 0..8 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						*mutex.lock().ok().unwrap() += 1;
+					}
+				});
+			}
+		});
+		assert_eq!(mutex.into_inner(), 80_000);
+	}
+
+	#[test]
+	fn try_lock_and_relock() {
+		let mutex = RobustPiMutex::new(0u32);
+		let guard = mutex.try_lock().unwrap().ok().unwrap();
+		thread::scope(|s| {
+			s.spawn(|| assert!(mutex.try_lock().is_none()));
+		});
+		let r = catch_unwind(AssertUnwindSafe(|| drop(mutex.lock())));
+		assert!(r.is_err());
+		drop(guard);
+		assert!(mutex.try_lock().unwrap().is_ok());
+	}
+
+	#[test]
+	fn owner_died() {
+		let mutex = RobustPiMutex::new(0u32);
+		abandon(&mutex, 1);
+		assert_eq!(mutex.state(), LockState::Abandoned);
+		match mutex.try_lock() {
+			Some(Err(RobustLockError::OwnerDied(guard))) => {
+				assert_eq!(*guard, 1);
+				RobustPiMutexGuard::make_consistent(&guard);
+			}
+			r => panic!("unexpected result: {:?}", r.map(|r| r.map(|_| ()))),
+		}
+		assert_eq!(mutex.state(), LockState::Consistent);
+		assert!(mutex.lock().is_ok());
+
+		abandon(&mutex, 2);
+		assert!(matches!(mutex.lock(), Err(RobustLockError::OwnerDied(_))));
+		assert!(matches!(mutex.lock(), Err(RobustLockError::NotRecoverable)));
+	}
+
+	#[test]
+	fn owner_died_while_others_wait() {
+		let mutex = RobustPiMutex::new(0u32);
+		let locked = Latch::<Private>::new();
+		thread::scope(|s| {
+			let owner = s.spawn(|| {
+				let guard = mutex.lock().ok().unwrap();
+				locked.set();
+				thread::sleep(Duration::from_millis(10));
+				std::mem::forget(guard);
+			});
+			locked.wait();
+			let waiter = s.spawn(|| mutex.lock_with_recovery(|value| *value = 1).map(|g| *g));
+			owner.join().unwrap();
+			assert_eq!(waiter.join().unwrap(), Ok(1));
+		});
+	}
+}
@@ -0,0 +1,997 @@
+use super::poison;
+use crate::{Futex, Private, Scope, WakeMask};
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+/// The lower 28 bits: the number of readers, or [`WRITE_LOCKED`].
+const MASK: u32 = (1 << 28) - 1;
+const READ_LOCKED: u32 = 1;
+const WRITE_LOCKED: u32 = MASK;
+const MAX_READERS: u32 = MASK - 1;
+/// One of the readers holds an upgradable read lock.
+const UPGRADABLE: u32 = 1 << 28;
+/// The upgradable reader is waiting on the [`UPGRADE`] channel for the other readers to leave.
+const UPGRADING: u32 = 1 << 29;
+/// There might be readers waiting on the [`READERS`] channel.
+const READERS_WAITING: u32 = 1 << 30;
+/// There might be writers waiting on the [`WRITERS`] channel.
+const WRITERS_WAITING: u32 = 1 << 31;
+
+/// The wake bitset channel readers wait on.
+const READERS: WakeMask = WakeMask::bit(0);
+/// The wake bitset channel writers and upgradable readers wait on.
+const WRITERS: WakeMask = WakeMask::bit(1);
+/// The wake bitset channel an upgrading reader waits on.
+const UPGRADE: WakeMask = WakeMask::bit(2);
+
+#[inline]
+fn is_unlocked(state: u32) -> bool {
+	state & MASK == 0
+}
+
+#[inline]
+fn is_write_locked(state: u32) -> bool {
+	state & MASK == WRITE_LOCKED
+}
+
+#[inline]
+fn has_readers_waiting(state: u32) -> bool {
+	state & READERS_WAITING != 0
+}
+
+#[inline]
+fn has_writers_waiting(state: u32) -> bool {
+	state & WRITERS_WAITING != 0
+}
+
+#[inline]
+fn is_read_lockable(state: u32) -> bool {
+	// Readers don't overtake waiting writers, to avoid starving writers.
+	state & MASK < MAX_READERS
+		&& !has_readers_waiting(state)
+		&& !has_writers_waiting(state)
+		&& state & UPGRADING == 0
+}
+
+#[inline]
+fn is_upgradable_lockable(state: u32) -> bool {
+	state & MASK < MAX_READERS && state & (UPGRADABLE | UPGRADING) == 0
+}
+
+/// A reader-writer lock without any data.
+///
+/// Readers and writers wait on the same futex, but on different wake
+/// bitset channels, such that they can be woken up independently.
+#[repr(transparent)]
+pub(crate) struct RawRwLock<S> {
+	futex: Futex<S>,
+}
+
+impl<S> RawRwLock<S> {
+	#[inline]
+	pub(crate) const fn new() -> Self {
+		Self {
+			futex: Futex::new(0),
+		}
+	}
+}
+
+impl<S: Scope> RawRwLock<S> {
+	#[inline]
+	pub(crate) fn try_read(&self) -> bool {
+		self.futex
+			.value
+			.fetch_update(Acquire, Relaxed, |s| {
+				is_read_lockable(s).then(|| s + READ_LOCKED)
+			})
+			.is_ok()
+	}
+
+	#[inline]
+	pub(crate) fn read(&self) {
+		let state = self.futex.value.load(Relaxed);
+		if !is_read_lockable(state)
+			|| self
+				.futex
+				.value
+				.compare_exchange_weak(state, state + READ_LOCKED, Acquire, Relaxed)
+				.is_err()
+		{
+			self.read_contended();
+		}
+	}
+
+	#[cold]
+	fn read_contended(&self) {
+		let mut state = self.spin_read();
+		loop {
+			if is_read_lockable(state) {
+				match self.futex.value.compare_exchange_weak(
+					state,
+					state + READ_LOCKED,
+					Acquire,
+					Relaxed,
+				) {
+					Ok(_) => return,
+					Err(s) => {
+						state = s;
+						continue;
+					}
+				}
+			}
+
+			if state & MASK == MAX_READERS {
+				panic!("too many active read locks on RwLock");
+			}
+
+			// Make sure the readers waiting bit is set before going to sleep.
+			if !has_readers_waiting(state) {
+				if let Err(s) = self.futex.value.compare_exchange(
+					state,
+					state | READERS_WAITING,
+					Relaxed,
+					Relaxed,
+				) {
+					state = s;
+					continue;
+				}
+			}
+
+			let _ = self.futex.wait_bitset(state | READERS_WAITING, READERS);
+
+			state = self.spin_read();
+		}
+	}
+
+	/// Unlock a read lock.
+	///
+	/// The lock must be read-locked by the caller.
+	#[inline]
+	pub(crate) unsafe fn read_unlock(&self) {
+		let state = self.futex.value.fetch_sub(READ_LOCKED, Release) - READ_LOCKED;
+
+		// Readers only wait when there's a writer (waiting), so if there are
+		// readers waiting, there must also be writers waiting.
+		if is_unlocked(state) && has_writers_waiting(state) {
+			self.wake_writer_or_readers(state);
+		} else if state & MASK == READ_LOCKED && state & UPGRADING != 0 {
+			// Only the upgrading reader is left.
+			self.futex.wake_bitset(1, UPGRADE);
+		}
+	}
+
+	#[inline]
+	pub(crate) fn try_upgradable_read(&self) -> bool {
+		self.futex
+			.value
+			.fetch_update(Acquire, Relaxed, |s| {
+				(is_read_lockable(s) && s & UPGRADABLE == 0).then(|| s + READ_LOCKED + UPGRADABLE)
+			})
+			.is_ok()
+	}
+
+	#[inline]
+	pub(crate) fn upgradable_read(&self) {
+		if !self.try_upgradable_read() {
+			self.lock_contended(is_upgradable_lockable, READ_LOCKED + UPGRADABLE);
+		}
+	}
+
+	/// Unlock an upgradable read lock.
+	///
+	/// The lock must be upgradable-read-locked by the caller.
+	#[inline]
+	pub(crate) unsafe fn upgradable_read_unlock(&self) {
+		let state = self
+			.futex
+			.value
+			.fetch_sub(READ_LOCKED + UPGRADABLE, Release)
+			- (READ_LOCKED + UPGRADABLE);
+		if is_unlocked(state) && has_writers_waiting(state) {
+			self.wake_writer_or_readers(state);
+		}
+	}
+
+	/// Upgrade an upgradable read lock to a write lock, waiting for the other readers to leave.
+	///
+	/// The lock must be upgradable-read-locked by the caller.
+	pub(crate) unsafe fn upgrade(&self) {
+		let mut state = self.futex.value.load(Relaxed);
+		loop {
+			if state & MASK == READ_LOCKED {
+				// We're the only reader left.
+				let new = (state & !(MASK | UPGRADABLE | UPGRADING)) | WRITE_LOCKED;
+				match self
+					.futex
+					.value
+					.compare_exchange_weak(state, new, Acquire, Relaxed)
+				{
+					Ok(_) => return,
+					Err(s) => state = s,
+				}
+				continue;
+			}
+			// Stop new readers from coming in, and wait for the others to leave.
+			if state & UPGRADING == 0 {
+				if let Err(s) =
+					self.futex
+						.value
+						.compare_exchange(state, state | UPGRADING, Relaxed, Relaxed)
+				{
+					state = s;
+					continue;
+				}
+			}
+			let _ = self.futex.wait_bitset(state | UPGRADING, UPGRADE);
+			state = self.futex.value.load(Relaxed);
+		}
+	}
+
+	/// Upgrade an upgradable read lock to a write lock, if there are no other readers.
+	///
+	/// The lock must be upgradable-read-locked by the caller.
+	#[inline]
+	pub(crate) unsafe fn try_upgrade(&self) -> bool {
+		self.futex
+			.value
+			.fetch_update(Acquire, Relaxed, |s| {
+				(s & MASK == READ_LOCKED).then_some((s & !(MASK | UPGRADABLE)) | WRITE_LOCKED)
+			})
+			.is_ok()
+	}
+
+	/// Downgrade an upgradable read lock to a normal read lock.
+	///
+	/// The lock must be upgradable-read-locked by the caller.
+	#[inline]
+	pub(crate) unsafe fn downgrade_upgradable(&self) {
+		let state = self.futex.value.fetch_sub(UPGRADABLE, Release) - UPGRADABLE;
+		if has_writers_waiting(state) {
+			// An upgradable reader might be waiting.
+			self.futex.wake_bitset(1, WRITERS);
+		}
+	}
+
+	/// Downgrade a write lock to a read lock, waking up waiting readers.
+	///
+	/// The lock must be write-locked by the caller.
+	#[inline]
+	pub(crate) unsafe fn downgrade(&self) {
+		let state = self
+			.futex
+			.value
+			.fetch_sub(WRITE_LOCKED - READ_LOCKED, Release)
+			- (WRITE_LOCKED - READ_LOCKED);
+		if has_readers_waiting(state)
+			&& self.futex.value.fetch_and(!READERS_WAITING, Relaxed) & READERS_WAITING != 0
+		{
+			self.futex.wake_bitset(u32::MAX, READERS);
+		}
+	}
+
+	/// Downgrade a write lock to an upgradable read lock, waking up waiting readers.
+	///
+	/// The lock must be write-locked by the caller.
+	#[cfg(feature = "lock_api")]
+	#[inline]
+	pub(crate) unsafe fn downgrade_to_upgradable(&self) {
+		let delta = (READ_LOCKED + UPGRADABLE).wrapping_sub(WRITE_LOCKED);
+		let state = self
+			.futex
+			.value
+			.fetch_add(delta, Release)
+			.wrapping_add(delta);
+		if has_readers_waiting(state)
+			&& self.futex.value.fetch_and(!READERS_WAITING, Relaxed) & READERS_WAITING != 0
+		{
+			self.futex.wake_bitset(u32::MAX, READERS);
+		}
+	}
+
+	#[inline]
+	pub(crate) fn try_write(&self) -> bool {
+		self.futex
+			.value
+			.fetch_update(Acquire, Relaxed, |s| {
+				is_unlocked(s).then(|| s + WRITE_LOCKED)
+			})
+			.is_ok()
+	}
+
+	#[inline]
+	pub(crate) fn write(&self) {
+		if self
+			.futex
+			.value
+			.compare_exchange_weak(0, WRITE_LOCKED, Acquire, Relaxed)
+			.is_err()
+		{
+			self.lock_contended(is_unlocked, WRITE_LOCKED);
+		}
+	}
+
+	/// Lock for writing, or for upgradable reading, by adding `add` once `lockable`.
+	#[cold]
+	fn lock_contended(&self, lockable: fn(u32) -> bool, add: u32) {
+		let mut state = self.spin_write(lockable);
+
+		// Once we've slept, other writers might be sleeping too, so we keep
+		// the writers waiting bit set when we take the lock.
+		let mut other_writers_waiting = 0;
+
+		loop {
+			if lockable(state) {
+				match self.futex.value.compare_exchange_weak(
+					state,
+					(state + add) | other_writers_waiting,
+					Acquire,
+					Relaxed,
+				) {
+					Ok(_) => return,
+					Err(s) => {
+						state = s;
+						continue;
+					}
+				}
+			}
+
+			// Make sure the writers waiting bit is set before going to sleep.
+			if !has_writers_waiting(state) {
+				if let Err(s) = self.futex.value.compare_exchange(
+					state,
+					state | WRITERS_WAITING,
+					Relaxed,
+					Relaxed,
+				) {
+					state = s;
+					continue;
+				}
+			}
+
+			other_writers_waiting = WRITERS_WAITING;
+
+			let _ = self.futex.wait_bitset(state | WRITERS_WAITING, WRITERS);
+
+			state = self.spin_write(lockable);
+		}
+	}
+
+	/// Unlock a write lock.
+	///
+	/// The lock must be write-locked by the caller.
+	#[inline]
+	pub(crate) unsafe fn write_unlock(&self) {
+		let state = self.futex.value.fetch_sub(WRITE_LOCKED, Release) - WRITE_LOCKED;
+		if has_readers_waiting(state) || has_writers_waiting(state) {
+			self.wake_writer_or_readers(state);
+		}
+	}
+
+	/// Unlock all locks, and wake up all waiters.
+	///
+	/// Nobody may hold any lock.
+	pub(crate) unsafe fn force_unlock(&self) {
+		self.futex.value.store(0, Release);
+		self.futex.wake(u32::MAX);
+	}
+
+	/// Wake up a writer if there is one waiting, or all readers otherwise.
+	///
+	/// The waiting bits are cleared before waking, such that a thread that was
+	/// about to wait will see a different value and won't go to sleep.
+	#[cold]
+	fn wake_writer_or_readers(&self, mut state: u32) {
+		debug_assert!(is_unlocked(state));
+
+		if state == WRITERS_WAITING {
+			match self
+				.futex
+				.value
+				.compare_exchange(state, 0, Relaxed, Relaxed)
+			{
+				Ok(_) => {
+					self.futex.wake_bitset(1, WRITERS);
+					return;
+				}
+				Err(s) => state = s,
+			}
+		}
+
+		if state == READERS_WAITING + WRITERS_WAITING {
+			if self
+				.futex
+				.value
+				.compare_exchange(state, READERS_WAITING, Relaxed, Relaxed)
+				.is_err()
+			{
+				// The lock got locked. The new owner will wake up the waiters.
+				return;
+			}
+			if self.futex.wake_bitset(1, WRITERS) > 0 {
+				return;
+			}
+			// No writer was actually sleeping. Wake up the readers instead.
+			state = READERS_WAITING;
+		}
+
+		if state == READERS_WAITING
+			&& self
+				.futex
+				.value
+				.compare_exchange(state, 0, Relaxed, Relaxed)
+				.is_ok()
+		{
+			self.futex.wake_bitset(u32::MAX, READERS);
+		}
+	}
+
+	fn spin_until(&self, f: impl Fn(u32) -> bool) -> u32 {
+		let mut spin = 100;
+		loop {
+			let state = self.futex.value.load(Relaxed);
+			if f(state) || spin == 0 {
+				return state;
+			}
+			std::hint::spin_loop();
+			spin -= 1;
+		}
+	}
+
+	/// Spin while write-locked, as long as nobody is waiting.
+	fn spin_read(&self) -> u32 {
+		self.spin_until(|s| !is_write_locked(s) || has_readers_waiting(s) || has_writers_waiting(s))
+	}
+
+	/// Spin while not lockable, as long as no writers are waiting.
+	fn spin_write(&self, lockable: fn(u32) -> bool) -> u32 {
+		self.spin_until(|s| lockable(s) || has_writers_waiting(s))
+	}
+}
+
+/// A reader-writer lock protecting data of type `T`.
+///
+/// Any number of readers or at most one writer can hold the lock at the same time.
+///
+/// Readers and writers are woken up through separate wake bitset channels on
+/// the same futex, such that unlocking never wakes up readers when only a
+/// writer can make progress. Waiting writers are preferred over new readers.
+///
+/// One reader at a time can hold an [upgradable][RwLock::upgradable_read]
+/// read lock, which can be upgraded to a write lock without unlocking.
+/// A write lock can be downgraded to a read lock.
+///
+/// Like [`std::sync::RwLock`], the lock is poisoned when a thread panics
+/// while holding a write lock, after which locking it results in a [`PoisonError`].
+///
+/// # Between processes
+///
+/// A `RwLock<T, Shared>` can be used between processes, if it is placed in
+/// shared memory. A new lock consists of only zero bytes, other than the
+/// data it protects. Waiting writers are preferred over new readers in
+/// every process, so a steady stream of readers from one process can't
+/// starve a writer in another.
+///
+/// The lock only counts readers, without keeping track of who they are.
+/// If a process dies while holding a read or write lock, that lock is never
+/// released, and writers (or all threads, for a write lock) block forever.
+/// When the death of a process holding the lock must be survived, use a
+/// [`RobustMutex`][super::RobustMutex] instead, which the kernel unlocks
+/// when its owner dies. Otherwise, once the application knows that none of
+/// the remaining threads holds the lock, for example because all
+/// processes that used it were restarted, it can release the locks of the
+/// dead processes with [`force_unlock`][RwLock::force_unlock], after
+/// checking the protected data for a partial write.
+#[repr(C)]
+pub struct RwLock<T: ?Sized, S = Private> {
+	raw: RawRwLock<S>,
+	poison: poison::Flag,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send, S> Send for RwLock<T, S> {}
+unsafe impl<T: ?Sized + Send + Sync, S> Sync for RwLock<T, S> {}
+
+/// A read lock of a [`RwLock`]. The lock is released when this guard is dropped.
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockReadGuard<'a, T: ?Sized, S: Scope = Private> {
+	lock: &'a RwLock<T, S>,
+}
+
+/// A write lock of a [`RwLock`]. The lock is released when this guard is dropped.
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockWriteGuard<'a, T: ?Sized, S: Scope = Private> {
+	lock: &'a RwLock<T, S>,
+	poison: poison::Guard,
+}
+
+unsafe impl<T: ?Sized + Sync, S: Scope> Sync for RwLockWriteGuard<'_, T, S> {}
+
+/// An upgradable read lock of a [`RwLock`]. The lock is released when this guard is dropped.
+///
+/// Only one upgradable read lock can exist at a time, but it can coexist
+/// with normal read locks. It can be upgraded to a write lock without
+/// releasing the lock in between.
+#[must_use = "if unused the RwLock will immediately unlock"]
+pub struct RwLockUpgradableReadGuard<'a, T: ?Sized, S: Scope = Private> {
+	lock: &'a RwLock<T, S>,
+}
+
+impl<T, S> RwLock<T, S> {
+	/// Create a new unlocked reader-writer lock containing `value`.
+	#[inline]
+	pub const fn new(value: T) -> Self {
+		Self {
+			raw: RawRwLock::new(),
+			poison: poison::Flag::new(),
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the lock, returning the data it protected.
+	///
+	/// If the lock is poisoned, the data is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn into_inner(self) -> LockResult<T> {
+		let poisoned = self.poison.get();
+		let data = self.data.into_inner();
+		if poisoned {
+			Err(PoisonError::new(data))
+		} else {
+			Ok(data)
+		}
+	}
+}
+
+impl<T: ?Sized, S> RwLock<T, S> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the lock.
+	#[inline]
+	pub fn get_mut(&mut self) -> LockResult<&mut T> {
+		let data = self.data.get_mut();
+		self.poison.result(data)
+	}
+
+	/// Check whether the lock is poisoned.
+	#[inline]
+	pub fn is_poisoned(&self) -> bool {
+		self.poison.get()
+	}
+
+	/// Clear the poisoned state of the lock.
+	///
+	/// Use this after recovering the protected data from an inconsistent state.
+	#[inline]
+	pub fn clear_poison(&self) {
+		self.poison.clear();
+	}
+}
+
+impl<T: ?Sized, S: Scope> RwLock<T, S> {
+	/// Release all read and write locks, and wake up all waiting threads.
+	///
+	/// This is for recovering from a process that died while holding the lock.
+	///
+	/// # Safety
+	///
+	/// No thread, in any process, may hold a lock on this `RwLock` anymore.
+	#[inline]
+	pub unsafe fn force_unlock(&self) {
+		self.raw.force_unlock();
+	}
+
+	/// Lock for reading, blocking until no writer holds or waits for the lock.
+	///
+	/// If the lock is poisoned, the guard is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T, S>> {
+		self.raw.read();
+		self.poison.result(RwLockReadGuard { lock: self })
+	}
+
+	/// Lock for reading, if that's possible without blocking.
+	#[inline]
+	pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T, S>> {
+		if self.raw.try_read() {
+			Ok(self.poison.result(RwLockReadGuard { lock: self })?)
+		} else {
+			Err(TryLockError::WouldBlock)
+		}
+	}
+
+	/// Lock for upgradable reading, blocking until no writer or other
+	/// upgradable reader holds or waits for the lock.
+	///
+	/// If the lock is poisoned, the guard is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn upgradable_read(&self) -> LockResult<RwLockUpgradableReadGuard<'_, T, S>> {
+		self.raw.upgradable_read();
+		self.poison.result(RwLockUpgradableReadGuard { lock: self })
+	}
+
+	/// Lock for upgradable reading, if that's possible without blocking.
+	#[inline]
+	pub fn try_upgradable_read(&self) -> TryLockResult<RwLockUpgradableReadGuard<'_, T, S>> {
+		if self.raw.try_upgradable_read() {
+			Ok(self
+				.poison
+				.result(RwLockUpgradableReadGuard { lock: self })?)
+		} else {
+			Err(TryLockError::WouldBlock)
+		}
+	}
+
+	/// Lock for writing, blocking until the lock is available.
+	///
+	/// If the lock is poisoned, the guard is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T, S>> {
+		self.raw.write();
+		self.write_guard()
+	}
+
+	/// Lock for writing, if that's possible without blocking.
+	#[inline]
+	pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T, S>> {
+		if self.raw.try_write() {
+			Ok(self.write_guard()?)
+		} else {
+			Err(TryLockError::WouldBlock)
+		}
+	}
+
+	#[inline]
+	fn write_guard(&self) -> LockResult<RwLockWriteGuard<'_, T, S>> {
+		poison::map_result(self.poison.guard(), |poison| RwLockWriteGuard {
+			lock: self,
+			poison,
+		})
+	}
+}
+
+impl<'a, T: ?Sized, S: Scope> RwLockUpgradableReadGuard<'a, T, S> {
+	/// Upgrade to a write lock, blocking until all other readers have released the lock.
+	///
+	/// New readers are held off while waiting.
+	///
+	/// If the lock is poisoned, which can happen if it already was when the
+	/// upgradable read lock was taken, the guard is returned inside a [`PoisonError`].
+	///
+	/// This is an associated function, to not conflict with methods on `T`.
+	pub fn upgrade(this: Self) -> LockResult<RwLockWriteGuard<'a, T, S>> {
+		let lock = this.lock;
+		std::mem::forget(this);
+		unsafe { lock.raw.upgrade() };
+		lock.write_guard()
+	}
+
+	/// Upgrade to a write lock if there are no other readers, without blocking.
+	///
+	/// If that's not possible, the upgradable read guard is given back.
+	/// If the lock is poisoned, the write guard is returned inside a [`PoisonError`].
+	///
+	/// This is an associated function, to not conflict with methods on `T`.
+	pub fn try_upgrade(this: Self) -> Result<LockResult<RwLockWriteGuard<'a, T, S>>, Self> {
+		if unsafe { this.lock.raw.try_upgrade() } {
+			let lock = this.lock;
+			std::mem::forget(this);
+			Ok(lock.write_guard())
+		} else {
+			Err(this)
+		}
+	}
+
+	/// Downgrade to a normal read lock, allowing another upgradable reader in.
+	///
+	/// This is an associated function, to not conflict with methods on `T`.
+	pub fn downgrade(this: Self) -> RwLockReadGuard<'a, T, S> {
+		let lock = this.lock;
+		std::mem::forget(this);
+		unsafe { lock.raw.downgrade_upgradable() };
+		RwLockReadGuard { lock }
+	}
+}
+
+impl<'a, T: ?Sized, S: Scope> RwLockWriteGuard<'a, T, S> {
+	/// Downgrade to a read lock, without letting a writer in.
+	///
+	/// Waiting readers are woken up.
+	///
+	/// This is an associated function, to not conflict with methods on `T`.
+	pub fn downgrade(this: Self) -> RwLockReadGuard<'a, T, S> {
+		let lock = this.lock;
+		lock.poison.done(&this.poison);
+		std::mem::forget(this);
+		unsafe { lock.raw.downgrade() };
+		RwLockReadGuard { lock }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Deref for RwLockReadGuard<'_, T, S> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.lock.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Deref for RwLockWriteGuard<'_, T, S> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.lock.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> DerefMut for RwLockWriteGuard<'_, T, S> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.lock.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Drop for RwLockReadGuard<'_, T, S> {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { self.lock.raw.read_unlock() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Deref for RwLockUpgradableReadGuard<'_, T, S> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.lock.data.get() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Drop for RwLockUpgradableReadGuard<'_, T, S> {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { self.lock.raw.upgradable_read_unlock() }
+	}
+}
+
+impl<T: ?Sized, S: Scope> Drop for RwLockWriteGuard<'_, T, S> {
+	#[inline]
+	fn drop(&mut self) {
+		self.lock.poison.done(&self.poison);
+		unsafe { self.lock.raw.write_unlock() }
+	}
+}
+
+impl<T: Default, S> Default for RwLock<T, S> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T, S> From<T> for RwLock<T, S> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for RwLock<T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("RwLock");
+		d.field("scope", &std::any::type_name::<S>());
+		match self.try_read() {
+			Ok(guard) => d.field("data", &&*guard),
+			Err(TryLockError::Poisoned(e)) => d.field("data", &&**e.get_ref()),
+			Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+		};
+		d.field("poisoned", &self.poison.get());
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for RwLockReadGuard<'_, T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug
+	for RwLockUpgradableReadGuard<'_, T, S>
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug, S: Scope> std::fmt::Debug for RwLockWriteGuard<'_, T, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard};
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::sync::TryLockError;
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn readers_share_the_lock() {
+		let lock = RwLock::<u32>::new(1);
+		let a = lock.read().unwrap();
+		let b = lock.try_read().unwrap();
+		assert_eq!(*a + *b, 2);
+		assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+		drop((a, b));
+		assert!(lock.try_write().is_ok());
+	}
+
+	#[test]
+	fn writer_excludes_everyone() {
+		let lock = RwLock::<u32>::new(1);
+		let guard = lock.write().unwrap();
+		assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+		assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+		thread::scope(|s| {
+			s.spawn(|| assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock))));
+		});
+		drop(guard);
+		assert!(lock.try_read().is_ok());
+	}
+
+	#[test]
+	fn contention() {
+		let lock = RwLock::<(u32, u32)>::new((0, 0));
+		thread::scope(|s| {
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..5_000 {
+						let mut guard = lock.write().unwrap();
+						guard.0 += 1;
+						guard.1 += 1;
+					}
+				});
+				s.spawn(|| {
+					for _ in 0..5_000 {
+						let guard = lock.read().unwrap();
+						assert_eq!(guard.0, guard.1);
+					}
+				});
+			}
+		});
+		assert_eq!(lock.into_inner().unwrap(), (20_000, 20_000));
+	}
+
+	#[test]
+	fn waiting_writer_holds_off_new_readers() {
+		let lock = RwLock::<u32>::new(0);
+		let written = AtomicU32::new(0);
+		thread::scope(|s| {
+			let reader = lock.read().unwrap();
+			let writer = s.spawn(|| {
+				*lock.write().unwrap() = 1;
+				written.store(1, Relaxed);
+			});
+			// Once the writer is waiting, new readers have to wait too.
+			let start = Instant::now();
+			while let Ok(guard) = lock.try_read() {
+				drop(guard);
+				assert!(start.elapsed() < Duration::from_secs(10));
+				thread::sleep(Duration::from_millis(1));
+			}
+			assert_eq!(written.load(Relaxed), 0);
+			drop(reader);
+			writer.join().unwrap();
+			assert_eq!(*lock.read().unwrap(), 1);
+		});
+	}
+
+	#[test]
+	fn poisoning() {
+		let lock = RwLock::<u32>::new(1);
+		let _ = thread::scope(|s| {
+			s.spawn(|| {
+				let _guard = lock.read().unwrap();
+				panic!("a panicking reader doesn't poison");
+			})
+			.join()
+		});
+		assert!(!lock.is_poisoned());
+		let _ = thread::scope(|s| {
+			s.spawn(|| {
+				let mut guard = lock.write().unwrap();
+				*guard = 3;
+				panic!("a panicking writer poisons");
+			})
+			.join()
+		});
+		assert!(lock.is_poisoned());
+		assert_eq!(*lock.read().unwrap_err().into_inner(), 3);
+		assert!(matches!(lock.try_write(), Err(TryLockError::Poisoned(_))));
+		lock.clear_poison();
+		assert_eq!(*lock.write().unwrap(), 3);
+	}
+
+	#[test]
+	fn upgradable_read_coexists_with_readers() {
+		let lock = RwLock::<u32>::new(1);
+		let upgradable = lock.upgradable_read().unwrap();
+		let reader = lock.try_read().unwrap();
+		assert!(matches!(
+			lock.try_upgradable_read(),
+			Err(TryLockError::WouldBlock)
+		));
+		assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+		assert_eq!(*upgradable + *reader, 2);
+		// Another reader keeps the upgrade from succeeding.
+		let upgradable = RwLockUpgradableReadGuard::try_upgrade(upgradable).unwrap_err();
+		drop(reader);
+		let mut writer = RwLockUpgradableReadGuard::try_upgrade(upgradable)
+			.unwrap()
+			.unwrap();
+		*writer = 2;
+		assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+		drop(writer);
+		assert_eq!(*lock.read().unwrap(), 2);
+	}
+
+	#[test]
+	fn upgrading_a_poisoned_lock() {
+		let lock = RwLock::<u32>::new(1);
+		let _ = thread::scope(|s| {
+			s.spawn(|| {
+				let _guard = lock.write().unwrap();
+				panic!("poison the lock");
+			})
+			.join()
+		});
+		let upgradable = lock.upgradable_read().unwrap_err().into_inner();
+		let writer = RwLockUpgradableReadGuard::upgrade(upgradable).unwrap_err();
+		drop(writer);
+		let upgradable = lock.upgradable_read().unwrap_err().into_inner();
+		let writer = RwLockUpgradableReadGuard::try_upgrade(upgradable).unwrap();
+		assert!(writer.is_err());
+		drop(writer);
+		assert!(lock.is_poisoned());
+	}
+
+	#[test]
+	fn upgrade_waits_for_readers() {
+		let lock = RwLock::<u32>::new(0);
+		let released = AtomicU32::new(0);
+		thread::scope(|s| {
+			let reader = lock.read().unwrap();
+			let upgrader = s.spawn(|| {
+				let upgradable = lock.upgradable_read().unwrap();
+				let mut writer = RwLockUpgradableReadGuard::upgrade(upgradable).unwrap();
+				assert_eq!(released.load(Relaxed), 1);
+				*writer = 1;
+			});
+			thread::sleep(Duration::from_millis(10));
+			released.store(1, Relaxed);
+			drop(reader);
+			upgrader.join().unwrap();
+		});
+		assert_eq!(lock.into_inner().unwrap(), 1);
+	}
+
+	#[test]
+	fn downgrade() {
+		let lock = RwLock::<u32>::new(0);
+		let mut writer = lock.write().unwrap();
+		*writer = 1;
+		let reader = RwLockWriteGuard::downgrade(writer);
+		assert_eq!(*lock.try_read().unwrap(), 1);
+		assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+		drop(reader);
+
+		let upgradable = lock.upgradable_read().unwrap();
+		let reader = RwLockUpgradableReadGuard::downgrade(upgradable);
+		// Another upgradable reader is let in after downgrading.
+		assert!(lock.try_upgradable_read().is_ok());
+		drop(reader);
+		assert!(lock.try_write().is_ok());
+	}
+}
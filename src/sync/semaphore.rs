@@ -0,0 +1,280 @@
+use crate::timeout::deadline;
+use crate::{Futex, Private, Scope, TimedWaitError, WakeMask};
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, SeqCst};
+use std::time::{Duration, Instant};
+
+/// Added to the waiter count by a thread waiting for more than one permit.
+pub(crate) const MANY: u32 = 1 << 16;
+
+/// A counting semaphore.
+///
+/// The number of available permits is stored in a single futex word, such
+/// that acquiring and releasing without contention doesn't need any syscall.
+/// Releasing permits only wakes up as many waiters as there were permits
+/// released, unless there are waiters that need more than one permit.
+///
+/// # Between processes
+///
+/// A `Semaphore<Shared>` can be used between processes, if it is placed in
+/// shared memory, as a replacement for a POSIX or System V semaphore:
+/// [`release`][Semaphore::release] corresponds to `sem_post`,
+/// [`acquire`][Semaphore::acquire] to `sem_wait`, and
+/// [`acquire_until`][Semaphore::acquire_until] to `sem_timedwait`, except that
+/// the deadline is measured on the monotonic clock, so it is not affected by
+/// changes to the system time.
+///
+/// A semaphore with zero permits consists of only zero bytes, so a freshly
+/// created shared mapping contains a valid semaphore without any permits.
+/// If a process dies while waiting, the semaphore keeps working, but its
+/// waiter stays counted forever. For a waiter of a single permit, that only
+/// means every release makes a `FUTEX_WAKE` syscall, even if nobody is
+/// waiting. For a waiter of more than one permit (or once enough dead waiters
+/// have piled up), every release will wake up all waiters.
+#[repr(C)]
+pub struct Semaphore<S = Private> {
+	pub(crate) permits: Futex<S>,
+	/// The number of waiting threads, plus [`MANY`] for each of those that
+	/// needs more than one permit.
+	pub(crate) waiters: AtomicU32,
+}
+
+impl<S> Semaphore<S> {
+	/// Create a new semaphore with the given number of available permits.
+	#[inline]
+	pub const fn new(permits: u32) -> Self {
+		Self {
+			permits: Futex::new(permits),
+			waiters: AtomicU32::new(0),
+		}
+	}
+
+	/// The number of permits that are currently available.
+	#[inline]
+	pub fn available_permits(&self) -> u32 {
+		self.permits.value.load(Relaxed)
+	}
+}
+
+impl<S: Scope> Semaphore<S> {
+	/// Acquire a permit, blocking until one is available.
+	#[inline]
+	pub fn acquire(&self) {
+		self.acquire_many(1);
+	}
+
+	/// Acquire `n` permits at once, blocking until enough are available.
+	#[inline]
+	pub fn acquire_many(&self, n: u32) {
+		if !self.try_acquire_many(n) {
+			self.acquire_contended(n, None);
+		}
+	}
+
+	/// Acquire a permit, blocking until one is available, or until the timeout expires.
+	///
+	/// Returns false if the timeout expired.
+	#[inline]
+	pub fn acquire_timeout(&self, timeout: Duration) -> bool {
+		self.acquire_many_timeout(1, timeout)
+	}
+
+	/// Acquire `n` permits at once, blocking until enough are available, or until the timeout expires.
+	///
+	/// Returns false if the timeout expired, in which case no permits were acquired.
+	#[inline]
+	pub fn acquire_many_timeout(&self, n: u32, timeout: Duration) -> bool {
+		self.try_acquire_many(n) || self.acquire_contended(n, deadline(timeout))
+	}
+
+	/// Acquire a permit, blocking until one is available, or until the deadline passes.
+	///
+	/// Returns false if the deadline passed.
+	#[inline]
+	pub fn acquire_until(&self, deadline: Instant) -> bool {
+		self.acquire_many_until(1, deadline)
+	}
+
+	/// Acquire `n` permits at once, blocking until enough are available, or until the deadline passes.
+	///
+	/// Returns false if the deadline passed, in which case no permits were acquired.
+	#[inline]
+	pub fn acquire_many_until(&self, n: u32, deadline: Instant) -> bool {
+		self.try_acquire_many(n) || self.acquire_contended(n, Some(deadline))
+	}
+
+	/// Acquire a permit, if one is available, without blocking.
+	#[inline]
+	pub fn try_acquire(&self) -> bool {
+		self.try_acquire_many(1)
+	}
+
+	/// Acquire `n` permits, if enough are available, without blocking.
+	#[inline]
+	pub fn try_acquire_many(&self, n: u32) -> bool {
+		self.permits
+			.value
+			.fetch_update(Acquire, Relaxed, |p| p.checked_sub(n))
+			.is_ok()
+	}
+
+	/// Wait for `n` permits, until the deadline (if any).
+	#[cold]
+	fn acquire_contended(&self, n: u32, deadline: Option<Instant>) -> bool {
+		let w = if n == 1 { 1 } else { MANY };
+		self.waiters.fetch_add(w, SeqCst);
+		let acquired = loop {
+			let p = self.permits.value.load(SeqCst);
+			if p >= n {
+				if self
+					.permits
+					.value
+					.compare_exchange(p, p - n, Acquire, Relaxed)
+					.is_ok()
+				{
+					break true;
+				}
+				continue;
+			}
+			match deadline {
+				Some(deadline) => {
+					if let Err(TimedWaitError::TimedOut) =
+						self.permits.wait_bitset_until(p, WakeMask::ALL, deadline)
+					{
+						break false;
+					}
+				}
+				None => {
+					let _ = self.permits.wait(p);
+				}
+			}
+		};
+		self.waiters.fetch_sub(w, Relaxed);
+		acquired
+	}
+
+	/// Release a permit, waking up a waiter if there is one.
+	///
+	/// # Panics
+	///
+	/// Panics if this would make the number of available permits overflow a `u32`.
+	#[inline]
+	pub fn release(&self) {
+		self.release_many(1);
+	}
+
+	/// Release `n` permits, waking up as many waiters as necessary.
+	///
+	/// # Panics
+	///
+	/// Panics if this would make the number of available permits overflow a `u32`.
+	#[inline]
+	pub fn release_many(&self, n: u32) {
+		self.permits
+			.value
+			.fetch_update(SeqCst, Relaxed, |p| p.checked_add(n))
+			.expect("Semaphore permit count overflow");
+		let waiters = self.waiters.load(SeqCst);
+		if waiters >= MANY {
+			// Someone needs more than one permit, so we can't know who to wake up.
+			self.permits.wake(u32::MAX);
+		} else if waiters > 0 {
+			self.permits.wake(n);
+		}
+	}
+}
+
+impl<S> std::fmt::Debug for Semaphore<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Semaphore")
+			.field("scope", &std::any::type_name::<S>())
+			.field("permits", &self.available_permits())
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Semaphore;
+	use crate::Private;
+	use std::panic::catch_unwind;
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::thread;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn permits() {
+		let semaphore = Semaphore::<Private>::new(3);
+		assert!(semaphore.try_acquire_many(2));
+		assert!(!semaphore.try_acquire_many(2));
+		assert!(semaphore.try_acquire());
+		assert!(!semaphore.try_acquire());
+		semaphore.release_many(2);
+		assert_eq!(semaphore.available_permits(), 2);
+	}
+
+	#[test]
+	fn limits_concurrency() {
+		let semaphore = Semaphore::<Private>::new(2);
+		let inside = AtomicU32::new(0);
+		thread::scope(|s| {
+			for _ in 0..8 {
+				s.spawn(|| {
+					for _ in 0..100 {
+						semaphore.acquire();
+						assert!(inside.fetch_add(1, Relaxed) < 2);
+						thread::yield_now();
+						inside.fetch_sub(1, Relaxed);
+						semaphore.release();
+					}
+				});
+			}
+		});
+		assert_eq!(semaphore.available_permits(), 2);
+	}
+
+	#[test]
+	fn acquire_many_waits_for_enough_permits() {
+		let semaphore = Semaphore::<Private>::new(0);
+		thread::scope(|s| {
+			let waiter = s.spawn(|| semaphore.acquire_many(3));
+			for _ in 0..3 {
+				thread::sleep(Duration::from_millis(5));
+				assert!(!waiter.is_finished());
+				semaphore.release();
+			}
+			waiter.join().unwrap();
+		});
+		assert_eq!(semaphore.available_permits(), 0);
+	}
+
+	#[test]
+	fn release_overflow() {
+		let semaphore = Semaphore::<Private>::new(u32::MAX - 1);
+		semaphore.release();
+		assert!(catch_unwind(|| semaphore.release()).is_err());
+		assert!(catch_unwind(|| semaphore.release_many(2)).is_err());
+		assert_eq!(semaphore.available_permits(), u32::MAX);
+	}
+
+	#[test]
+	fn acquire_timeout() {
+		let semaphore = Semaphore::<Private>::new(1);
+		let start = Instant::now();
+		assert!(!semaphore.acquire_many_timeout(2, Duration::from_millis(20)));
+		assert!(start.elapsed() >= Duration::from_millis(20));
+		assert_eq!(semaphore.available_permits(), 1);
+		assert!(semaphore.acquire_timeout(Duration::from_millis(20)));
+		thread::scope(|s| {
+			s.spawn(|| {
+				thread::sleep(Duration::from_millis(5));
+				semaphore.release();
+			});
+			assert!(semaphore.acquire_timeout(Duration::from_secs(10)));
+		});
+		// A timeout too large for a deadline means no timeout.
+		semaphore.release();
+		assert!(semaphore.acquire_timeout(Duration::MAX));
+	}
+}
@@ -0,0 +1,350 @@
+use super::poison;
+use super::rwlock::RawRwLock;
+use crate::{CachePadded, Private};
+use std::cell::{Cell, UnsafeCell};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{LockResult, PoisonError, TryLockError, TryLockResult};
+
+/// A reader-writer lock protecting data of type `T`, with a separate lock word per shard.
+///
+/// Every thread is assigned a shard, and only locks that shard for
+/// reading. Readers on different threads therefore don't write to the same
+/// cache line, which makes read locking scale nearly linearly with the
+/// number of threads. Writers lock all shards, one after the other, which
+/// makes write locking a lot more expensive than for a [`RwLock`][super::RwLock].
+///
+/// Every shard is a futex, which readers of that shard and writers block on.
+///
+/// Like [`std::sync::RwLock`], the lock is poisoned when a thread panics
+/// while holding a write lock, after which locking it results in a [`PoisonError`].
+///
+/// As the shards are allocated separately, this lock can only be used
+/// within a single process.
+pub struct ShardedLock<T: ?Sized> {
+	shards: Box<[CachePadded<RawRwLock<Private>>]>,
+	poison: poison::Flag,
+	data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for ShardedLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for ShardedLock<T> {}
+
+/// A read lock of a [`ShardedLock`]. The lock is released when this guard is dropped.
+#[must_use = "if unused the ShardedLock will immediately unlock"]
+pub struct ShardedLockReadGuard<'a, T: ?Sized> {
+	lock: &'a ShardedLock<T>,
+	shard: &'a RawRwLock<Private>,
+}
+
+/// A write lock of a [`ShardedLock`]. The lock is released when this guard is dropped.
+#[must_use = "if unused the ShardedLock will immediately unlock"]
+pub struct ShardedLockWriteGuard<'a, T: ?Sized> {
+	lock: &'a ShardedLock<T>,
+	poison: poison::Guard,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for ShardedLockWriteGuard<'_, T> {}
+
+/// The index of the shard of the current thread, modulo the number of shards.
+fn thread_index() -> usize {
+	static NEXT: AtomicUsize = AtomicUsize::new(0);
+	thread_local! {
+		static INDEX: Cell<usize> = const { Cell::new(usize::MAX) };
+	}
+	INDEX.with(|index| {
+		if index.get() == usize::MAX {
+			index.set(NEXT.fetch_add(1, Relaxed));
+		}
+		index.get()
+	})
+}
+
+impl<T> ShardedLock<T> {
+	/// Create a new unlocked sharded lock containing `value`, with a shard per available CPU.
+	pub fn new(value: T) -> Self {
+		let shards = std::thread::available_parallelism().map_or(8, |n| n.get());
+		Self::with_shards(value, shards)
+	}
+
+	/// Create a new unlocked sharded lock containing `value`, with the given number of shards.
+	///
+	/// # Panics
+	///
+	/// Panics if `shards` is zero.
+	pub fn with_shards(value: T, shards: usize) -> Self {
+		assert!(shards != 0, "a ShardedLock needs at least one shard");
+		Self {
+			shards: (0..shards)
+				.map(|_| CachePadded::new(RawRwLock::new()))
+				.collect(),
+			poison: poison::Flag::new(),
+			data: UnsafeCell::new(value),
+		}
+	}
+
+	/// Consume the lock, returning the data it protected.
+	///
+	/// If the lock is poisoned, the data is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn into_inner(self) -> LockResult<T> {
+		let poisoned = self.poison.get();
+		let data = self.data.into_inner();
+		if poisoned {
+			Err(PoisonError::new(data))
+		} else {
+			Ok(data)
+		}
+	}
+}
+
+impl<T: ?Sized> ShardedLock<T> {
+	/// Get a mutable reference to the protected data.
+	///
+	/// No locking is necessary, since this requires exclusive access to the lock.
+	#[inline]
+	pub fn get_mut(&mut self) -> LockResult<&mut T> {
+		let data = self.data.get_mut();
+		self.poison.result(data)
+	}
+
+	/// Check whether the lock is poisoned.
+	#[inline]
+	pub fn is_poisoned(&self) -> bool {
+		self.poison.get()
+	}
+
+	/// Clear the poisoned state of the lock.
+	///
+	/// Use this after recovering the protected data from an inconsistent state.
+	#[inline]
+	pub fn clear_poison(&self) {
+		self.poison.clear();
+	}
+
+	/// The number of shards.
+	#[inline]
+	pub fn shards(&self) -> usize {
+		self.shards.len()
+	}
+
+	#[inline]
+	fn shard(&self) -> &RawRwLock<Private> {
+		&self.shards[thread_index() % self.shards.len()]
+	}
+
+	/// Lock the shard of the current thread for reading, blocking until no writer holds or waits for it.
+	///
+	/// If the lock is poisoned, the guard is returned inside a [`PoisonError`].
+	#[inline]
+	pub fn read(&self) -> LockResult<ShardedLockReadGuard<'_, T>> {
+		let shard = self.shard();
+		shard.read();
+		self.poison
+			.result(ShardedLockReadGuard { lock: self, shard })
+	}
+
+	/// Lock the shard of the current thread for reading, if that's possible without blocking.
+	#[inline]
+	pub fn try_read(&self) -> TryLockResult<ShardedLockReadGuard<'_, T>> {
+		let shard = self.shard();
+		if shard.try_read() {
+			Ok(self
+				.poison
+				.result(ShardedLockReadGuard { lock: self, shard })?)
+		} else {
+			Err(TryLockError::WouldBlock)
+		}
+	}
+
+	/// Lock all shards for writing, blocking until they are available.
+	///
+	/// If the lock is poisoned, the guard is returned inside a [`PoisonError`].
+	pub fn write(&self) -> LockResult<ShardedLockWriteGuard<'_, T>> {
+		// All writers lock the shards in the same order, so they can't deadlock.
+		for shard in self.shards.iter() {
+			shard.write();
+		}
+		self.write_guard()
+	}
+
+	/// Lock all shards for writing, if that's possible without blocking.
+	pub fn try_write(&self) -> TryLockResult<ShardedLockWriteGuard<'_, T>> {
+		for (i, shard) in self.shards.iter().enumerate() {
+			if !shard.try_write() {
+				for shard in self.shards[..i].iter().rev() {
+					unsafe { shard.write_unlock() };
+				}
+				return Err(TryLockError::WouldBlock);
+			}
+		}
+		Ok(self.write_guard()?)
+	}
+
+	#[inline]
+	fn write_guard(&self) -> LockResult<ShardedLockWriteGuard<'_, T>> {
+		poison::map_result(self.poison.guard(), |poison| ShardedLockWriteGuard {
+			lock: self,
+			poison,
+		})
+	}
+}
+
+impl<T: ?Sized> Deref for ShardedLockReadGuard<'_, T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.lock.data.get() }
+	}
+}
+
+impl<T: ?Sized> Deref for ShardedLockWriteGuard<'_, T> {
+	type Target = T;
+	#[inline]
+	fn deref(&self) -> &T {
+		unsafe { &*self.lock.data.get() }
+	}
+}
+
+impl<T: ?Sized> DerefMut for ShardedLockWriteGuard<'_, T> {
+	#[inline]
+	fn deref_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.lock.data.get() }
+	}
+}
+
+impl<T: ?Sized> Drop for ShardedLockReadGuard<'_, T> {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { self.shard.read_unlock() }
+	}
+}
+
+impl<T: ?Sized> Drop for ShardedLockWriteGuard<'_, T> {
+	fn drop(&mut self) {
+		self.lock.poison.done(&self.poison);
+		for shard in self.lock.shards.iter().rev() {
+			unsafe { shard.write_unlock() };
+		}
+	}
+}
+
+impl<T: Default> Default for ShardedLock<T> {
+	fn default() -> Self {
+		Self::new(T::default())
+	}
+}
+
+impl<T> From<T> for ShardedLock<T> {
+	fn from(value: T) -> Self {
+		Self::new(value)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for ShardedLock<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let mut d = f.debug_struct("ShardedLock");
+		d.field("shards", &self.shards.len());
+		match self.try_read() {
+			Ok(guard) => d.field("data", &&*guard),
+			Err(TryLockError::Poisoned(e)) => d.field("data", &&**e.get_ref()),
+			Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+		};
+		d.field("poisoned", &self.poison.get());
+		d.finish()
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for ShardedLockReadGuard<'_, T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+impl<T: ?Sized + std::fmt::Debug> std::fmt::Debug for ShardedLockWriteGuard<'_, T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		std::fmt::Debug::fmt(&**self, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ShardedLock;
+	use std::sync::TryLockError;
+	use std::thread;
+
+	#[test]
+	fn readers_and_writers() {
+		let lock = ShardedLock::with_shards((0u32, 0u32), 4);
+		thread::scope(|s| {
+			for _ in 0..2 {
+				s.spawn(|| {
+					for _ in 0..1000 {
+						let mut guard = lock.write().unwrap();
+						guard.0 += 1;
+						thread::yield_now();
+						guard.1 += 1;
+					}
+				});
+			}
+			for _ in 0..6 {
+				s.spawn(|| {
+					for _ in 0..1000 {
+						let guard = lock.read().unwrap();
+						assert_eq!(guard.0, guard.1);
+					}
+				});
+			}
+		});
+		assert_eq!(lock.into_inner().unwrap(), (2000, 2000));
+	}
+
+	#[test]
+	fn try_lock() {
+		let lock = ShardedLock::with_shards(1, 3);
+		assert_eq!(lock.shards(), 3);
+		let reader = lock.try_read().unwrap();
+		// Readers of other threads use other shards, but still block writers.
+		thread::scope(|s| {
+			s.spawn(|| {
+				assert_eq!(*lock.try_read().unwrap(), 1);
+				assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+			});
+		});
+		assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+		drop(reader);
+		let writer = lock.try_write().unwrap();
+		assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+		drop(writer);
+		// A failed try_write didn't leave any shard locked.
+		assert!(lock.try_write().is_ok());
+	}
+
+	#[test]
+	fn poisoning() {
+		let lock = ShardedLock::new(1);
+		let r = thread::scope(|s| {
+			s.spawn(|| {
+				let _guard = lock.read().unwrap();
+				panic!("a panicking reader doesn't poison");
+			})
+			.join()
+		});
+		assert!(r.is_err());
+		assert!(!lock.is_poisoned());
+		let r = thread::scope(|s| {
+			s.spawn(|| {
+				let mut guard = lock.write().unwrap();
+				*guard = 2;
+				panic!("a panicking writer poisons");
+			})
+			.join()
+		});
+		assert!(r.is_err());
+		assert!(lock.is_poisoned());
+		assert_eq!(*lock.read().unwrap_err().into_inner(), 2);
+		lock.clear_poison();
+		assert_eq!(lock.into_inner().unwrap(), 2);
+	}
+}
@@ -0,0 +1,135 @@
+use super::Mutex;
+use crate::{Futex, Shared};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering::{Acquire, Release};
+
+/// Nobody initialized the mutex yet. A freshly mapped region is all zeros, so this must be zero.
+const UNINITIALIZED: u32 = 0;
+/// A thread, possibly in another process, is running the initializer.
+const INITIALIZING: u32 = 1;
+/// The mutex is initialized.
+const READY: u32 = 2;
+
+/// A [`Mutex`] meant to be placed in shared memory, which is initialized in place exactly once.
+///
+/// A `SharedMutex` consisting of only zero bytes is valid and uninitialized,
+/// which is exactly what a freshly created shared mapping (such as a new
+/// memfd or `MAP_ANONYMOUS` mapping) contains. Any process that has the
+/// region mapped can call [`get_or_init`][SharedMutex::get_or_init] to get
+/// the mutex, initializing it if no other process did so yet.
+///
+/// The initialization state is a separate futex, going from
+/// `Uninitialized` to `Initializing` to `Ready`. Only the process that
+/// moves it from `Uninitialized` to `Initializing` runs the constructor.
+/// Other processes calling `get_or_init` in the meantime sleep on the
+/// futex until the mutex is `Ready`. If the constructor panics, the state
+/// is reset to `Uninitialized`, and the next caller tries again.
+///
+/// Like the other types meant for shared memory, the data is never dropped,
+/// and should not contain anything that is only meaningful in one address
+/// space, such as pointers.
+#[repr(C)]
+pub struct SharedMutex<T> {
+	state: Futex<Shared>,
+	mutex: UnsafeCell<MaybeUninit<Mutex<T, Shared>>>,
+}
+
+unsafe impl<T: Send> Send for SharedMutex<T> {}
+unsafe impl<T: Send> Sync for SharedMutex<T> {}
+
+impl<T> SharedMutex<T> {
+	/// Create a new uninitialized `SharedMutex`.
+	///
+	/// This is equivalent to zeroed memory.
+	#[inline]
+	pub const fn uninit() -> Self {
+		Self {
+			state: Futex::new(UNINITIALIZED),
+			mutex: UnsafeCell::new(MaybeUninit::uninit()),
+		}
+	}
+
+	/// Get the mutex, if it is initialized.
+	#[inline]
+	pub fn get(&self) -> Option<&Mutex<T, Shared>> {
+		if self.state.value.load(Acquire) == READY {
+			Some(unsafe { self.get_unchecked() })
+		} else {
+			None
+		}
+	}
+
+	/// Check whether the mutex is initialized.
+	#[inline]
+	pub fn is_initialized(&self) -> bool {
+		self.state.value.load(Acquire) == READY
+	}
+
+	/// Get the mutex, initializing it with the value returned by `f` if no
+	/// process did so yet.
+	///
+	/// If another process is initializing the mutex, this blocks until it's done.
+	pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &Mutex<T, Shared> {
+		match self.get() {
+			Some(mutex) => mutex,
+			None => self.init(f),
+		}
+	}
+
+	#[cold]
+	fn init(&self, f: impl FnOnce() -> T) -> &Mutex<T, Shared> {
+		let mut f = Some(f);
+		loop {
+			match self
+				.state
+				.value
+				.compare_exchange(UNINITIALIZED, INITIALIZING, Acquire, Acquire)
+			{
+				Ok(_) => {
+					// If the constructor panics, give the next caller a chance.
+					struct Reset<'a>(&'a Futex<Shared>);
+					impl Drop for Reset<'_> {
+						fn drop(&mut self) {
+							self.0.value.store(UNINITIALIZED, Release);
+							self.0.wake(u32::MAX);
+						}
+					}
+					let reset = Reset(&self.state);
+					let value = (f.take().unwrap())();
+					unsafe { (*self.mutex.get()).write(Mutex::new(value)) };
+					std::mem::forget(reset);
+					self.state.value.store(READY, Release);
+					self.state.wake(u32::MAX);
+					return unsafe { self.get_unchecked() };
+				}
+				Err(READY) => return unsafe { self.get_unchecked() },
+				Err(INITIALIZING) => {
+					let _ = self.state.wait(INITIALIZING);
+				}
+				Err(_) => panic!("SharedMutex is corrupted"),
+			}
+		}
+	}
+
+	/// The mutex must be initialized.
+	#[inline]
+	unsafe fn get_unchecked(&self) -> &Mutex<T, Shared> {
+		(*self.mutex.get()).assume_init_ref()
+	}
+}
+
+impl<T> Default for SharedMutex<T> {
+	fn default() -> Self {
+		Self::uninit()
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for SharedMutex<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self.get() {
+			Some(mutex) => f.debug_tuple("SharedMutex").field(mutex).finish(),
+			None => f.write_str("SharedMutex(<uninitialized>)"),
+		}
+	}
+}
@@ -0,0 +1,136 @@
+use super::{OnceState, RobustLockError, RobustMutex, RobustMutexGuard};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+const INCOMPLETE: u32 = 0;
+/// The initialization panicked, or the process running it died.
+const POISONED: u32 = 1;
+const COMPLETE: u32 = 2;
+
+/// A one-time initialization primitive for memory shared between processes.
+///
+/// Exactly one of the processes calling [`call_once`][SharedOnce::call_once]
+/// runs the closure. The others block until it is done.
+///
+/// The initialization runs while holding a [`RobustMutex`], which the other
+/// processes block on. If the initializing process dies before finishing,
+/// the kernel hands the mutex to one of the waiting processes, which then
+/// marks the `SharedOnce` as poisoned, just like when the closure panics.
+///
+/// A `SharedOnce` consisting of only zero bytes is valid and has not run
+/// yet, so it can be used in a freshly created shared mapping.
+///
+/// While the closure runs, the `RobustMutex` is on the robust list of the
+/// thread running it. Like a `RobustMutex`, a `SharedOnce` that is dropped
+/// or unmapped in that state, such as a copy inherited by a child process
+/// through `fork()` in the middle of an initialization, aborts the process.
+#[repr(C)]
+pub struct SharedOnce {
+	state: AtomicU32,
+	lock: RobustMutex<()>,
+}
+
+// Poisoning takes care of panics during initialization.
+impl UnwindSafe for SharedOnce {}
+impl RefUnwindSafe for SharedOnce {}
+
+impl SharedOnce {
+	/// Create a new `SharedOnce` that has not run yet.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			state: AtomicU32::new(INCOMPLETE),
+			lock: RobustMutex::new(()),
+		}
+	}
+
+	/// Returns true if the initialization completed successfully.
+	#[inline]
+	pub fn is_completed(&self) -> bool {
+		self.state.load(Acquire) == COMPLETE
+	}
+
+	/// Returns true if an initialization panicked or its process died.
+	#[inline]
+	pub fn is_poisoned(&self) -> bool {
+		self.state.load(Relaxed) == POISONED
+	}
+
+	/// Run the closure if this is the first call, or wait for the first call to complete.
+	///
+	/// # Panics
+	///
+	/// Panics if a previous initialization panicked or its process died,
+	/// poisoning the `SharedOnce`.
+	#[inline]
+	pub fn call_once(&self, f: impl FnOnce()) {
+		if !self.is_completed() {
+			self.call(false, |_| f());
+		}
+	}
+
+	/// Like [`call_once`][SharedOnce::call_once], but also runs the closure if
+	/// a previous initialization panicked or its process died.
+	#[inline]
+	pub fn call_once_force(&self, f: impl FnOnce(&OnceState)) {
+		if !self.is_completed() {
+			self.call(true, f);
+		}
+	}
+
+	#[cold]
+	fn call(&self, ignore_poison: bool, f: impl FnOnce(&OnceState)) {
+		let _guard = match self.lock.lock() {
+			Ok(guard) => guard,
+			Err(RobustLockError::OwnerDied(guard)) => {
+				// The process running the initialization died.
+				self.state.store(POISONED, Relaxed);
+				RobustMutexGuard::make_consistent(&guard);
+				guard
+			}
+			Err(RobustLockError::NotRecoverable) => unreachable!(),
+		};
+		let state = self.state.load(Acquire);
+		match state {
+			COMPLETE => return,
+			POISONED if !ignore_poison => {
+				panic!("SharedOnce instance has previously been poisoned")
+			}
+			_ => {}
+		}
+		// Dropped before the lock guard, so the state is set before unlocking.
+		struct Completion<'a> {
+			state: &'a AtomicU32,
+			value: u32,
+		}
+		impl Drop for Completion<'_> {
+			fn drop(&mut self) {
+				self.state.store(self.value, Release);
+			}
+		}
+		let mut completion = Completion {
+			state: &self.state,
+			value: POISONED,
+		};
+		f(&OnceState {
+			poisoned: state == POISONED,
+		});
+		completion.value = COMPLETE;
+	}
+}
+
+impl Default for SharedOnce {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl std::fmt::Debug for SharedOnce {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("SharedOnce")
+			.field("completed", &self.is_completed())
+			.field("poisoned", &self.is_poisoned())
+			.finish()
+	}
+}
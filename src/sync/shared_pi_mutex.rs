@@ -0,0 +1,190 @@
+use super::RobustPiMutex;
+use crate::{Futex, PidNamespace, Shared};
+use std::cell::UnsafeCell;
+use std::io;
+use std::mem::MaybeUninit;
+use std::sync::atomic::Ordering::{Acquire, Release};
+
+/// Nobody initialized the mutex yet. A freshly mapped region is all zeros, so this must be zero.
+const UNINITIALIZED: u32 = 0;
+/// A thread, possibly in another process, is running the initializer.
+const INITIALIZING: u32 = 1;
+/// The mutex is initialized.
+const READY: u32 = 2;
+
+/// A priority inheriting mutex for real-time processes cooperating over shared memory.
+///
+/// This puts together everything needed to use a PI mutex between
+/// processes: like a [`SharedMutex`][super::SharedMutex], it is valid when
+/// zeroed and initialized in place by the first process to use it, and the
+/// mutex itself is a [`RobustPiMutex`], which maintains the thread id and
+/// waiters bit of the PI protocol, and recovers when its owner dies.
+///
+/// Since the futex contains thread ids, all processes must be in the same
+/// PID namespace. The namespace of the process that initializes the mutex
+/// is stored next to it, and every process checks that it is in the same
+/// one when it gets the mutex through [`get_or_init`][SharedPiMutex::get_or_init]
+/// or [`get`][SharedPiMutex::get]. Those calls read `/proc/self/ns/pid`, so
+/// a real-time thread should get the mutex once, and keep the reference.
+///
+/// # Between processes
+///
+/// A `SharedPiMutex` consisting of only zero bytes is valid and
+/// uninitialized. If the owner of the mutex dies, the next thread to lock
+/// it gets a [`RobustLockError::OwnerDied`][super::RobustLockError::OwnerDied].
+/// If a process dies while running the initializer, the mutex stays
+/// uninitialized forever, just like a `SharedMutex`.
+///
+/// Like the [`RobustPiMutex`] inside it, dropping or unmapping a
+/// `SharedPiMutex` while the mutex is locked, after leaking its guard,
+/// aborts the process.
+#[repr(C)]
+pub struct SharedPiMutex<T> {
+	state: Futex<Shared>,
+	namespace: UnsafeCell<MaybeUninit<PidNamespace>>,
+	mutex: UnsafeCell<MaybeUninit<RobustPiMutex<T>>>,
+}
+
+unsafe impl<T: Send> Send for SharedPiMutex<T> {}
+unsafe impl<T: Send> Sync for SharedPiMutex<T> {}
+
+impl<T> SharedPiMutex<T> {
+	/// Create a new uninitialized `SharedPiMutex`.
+	///
+	/// This is equivalent to zeroed memory.
+	#[inline]
+	pub const fn uninit() -> Self {
+		Self {
+			state: Futex::new(UNINITIALIZED),
+			namespace: UnsafeCell::new(MaybeUninit::uninit()),
+			mutex: UnsafeCell::new(MaybeUninit::uninit()),
+		}
+	}
+
+	/// Check whether the mutex is initialized.
+	#[inline]
+	pub fn is_initialized(&self) -> bool {
+		self.state.value.load(Acquire) == READY
+	}
+
+	/// The PID namespace of the processes using the mutex, if it is initialized.
+	#[inline]
+	pub fn namespace(&self) -> Option<PidNamespace> {
+		if self.is_initialized() {
+			Some(unsafe { (*self.namespace.get()).assume_init() })
+		} else {
+			None
+		}
+	}
+
+	/// Get the mutex, if it is initialized.
+	///
+	/// Fails with a [`PidNamespaceError`][crate::PidNamespaceError] if the
+	/// calling process is in a different PID namespace than the process that
+	/// initialized the mutex.
+	pub fn get(&self) -> io::Result<Option<&RobustPiMutex<T>>> {
+		if self.is_initialized() {
+			unsafe { self.get_checked() }.map(Some)
+		} else {
+			Ok(None)
+		}
+	}
+
+	/// Get the mutex, initializing it with the value returned by `f` if no
+	/// process did so yet.
+	///
+	/// If another process is initializing the mutex, this blocks until it's done.
+	///
+	/// Fails with a [`PidNamespaceError`][crate::PidNamespaceError] if the
+	/// calling process is in a different PID namespace than the process that
+	/// initialized the mutex.
+	pub fn get_or_init(&self, f: impl FnOnce() -> T) -> io::Result<&RobustPiMutex<T>> {
+		match self.get()? {
+			Some(mutex) => Ok(mutex),
+			None => self.init(f),
+		}
+	}
+
+	#[cold]
+	fn init(&self, f: impl FnOnce() -> T) -> io::Result<&RobustPiMutex<T>> {
+		let mut f = Some(f);
+		loop {
+			match self
+				.state
+				.value
+				.compare_exchange(UNINITIALIZED, INITIALIZING, Acquire, Acquire)
+			{
+				Ok(_) => {
+					// If the constructor panics or fails, give the next caller a chance.
+					struct Reset<'a>(&'a Futex<Shared>);
+					impl Drop for Reset<'_> {
+						fn drop(&mut self) {
+							self.0.value.store(UNINITIALIZED, Release);
+							self.0.wake(u32::MAX);
+						}
+					}
+					let reset = Reset(&self.state);
+					let namespace = PidNamespace::current()?;
+					let value = (f.take().unwrap())();
+					unsafe {
+						(*self.namespace.get()).write(namespace);
+						(*self.mutex.get()).write(RobustPiMutex::new(value));
+					}
+					std::mem::forget(reset);
+					self.state.value.store(READY, Release);
+					self.state.wake(u32::MAX);
+					return Ok(unsafe { self.get_unchecked() });
+				}
+				Err(READY) => return unsafe { self.get_checked() },
+				Err(INITIALIZING) => {
+					let _ = self.state.wait(INITIALIZING);
+				}
+				Err(_) => panic!("SharedPiMutex is corrupted"),
+			}
+		}
+	}
+
+	/// Check the PID namespace, and get the mutex.
+	///
+	/// The mutex must be initialized.
+	unsafe fn get_checked(&self) -> io::Result<&RobustPiMutex<T>> {
+		(*self.namespace.get()).assume_init_ref().check()?;
+		Ok(self.get_unchecked())
+	}
+
+	/// The mutex must be initialized.
+	#[inline]
+	unsafe fn get_unchecked(&self) -> &RobustPiMutex<T> {
+		(*self.mutex.get()).assume_init_ref()
+	}
+}
+
+impl<T> Drop for SharedPiMutex<T> {
+	fn drop(&mut self) {
+		if *self.state.value.get_mut() == READY {
+			// Aborts if the mutex is still locked through a leaked guard.
+			unsafe { self.mutex.get_mut().assume_init_drop() };
+		}
+	}
+}
+
+impl<T> Default for SharedPiMutex<T> {
+	fn default() -> Self {
+		Self::uninit()
+	}
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for SharedPiMutex<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		if self.is_initialized() {
+			f.debug_struct("SharedPiMutex")
+				.field("namespace", unsafe {
+					(*self.namespace.get()).assume_init_ref()
+				})
+				.field("mutex", unsafe { self.get_unchecked() })
+				.finish()
+		} else {
+			f.write_str("SharedPiMutex(<uninitialized>)")
+		}
+	}
+}
@@ -0,0 +1,121 @@
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// Decides how long a thread spins on a lock before going to sleep on its futex.
+///
+/// Used by [`AdaptiveMutex`][super::AdaptiveMutex].
+pub trait SpinPolicy {
+	/// The number of [`spin_loop`][std::hint::spin_loop] iterations before the
+	/// next check of the lock, or `None` to stop spinning and go to sleep.
+	///
+	/// `attempt` counts the checks of the lock during this acquisition, starting at zero.
+	fn backoff(&self, attempt: u32) -> Option<u32>;
+
+	/// Called after the lock was acquired, with the number of checks of the
+	/// lock before it was acquired, and whether the thread had to sleep.
+	#[inline]
+	fn acquired(&self, attempts: u32, slept: bool) {
+		let _ = (attempts, slept);
+	}
+}
+
+/// Check the lock a fixed number of times before going to sleep.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedSpin(pub u32);
+
+impl FixedSpin {
+	#[inline]
+	pub const fn new(attempts: u32) -> Self {
+		Self(attempts)
+	}
+}
+
+impl Default for FixedSpin {
+	fn default() -> Self {
+		Self(100)
+	}
+}
+
+impl SpinPolicy for FixedSpin {
+	#[inline]
+	fn backoff(&self, attempt: u32) -> Option<u32> {
+		(attempt < self.0).then_some(1)
+	}
+}
+
+/// Check the lock a fixed number of times before going to sleep, doubling
+/// the time between the checks every time.
+#[derive(Clone, Copy, Debug)]
+pub struct ExponentialSpin(pub u32);
+
+impl ExponentialSpin {
+	#[inline]
+	pub const fn new(attempts: u32) -> Self {
+		Self(attempts)
+	}
+}
+
+impl Default for ExponentialSpin {
+	fn default() -> Self {
+		Self(10)
+	}
+}
+
+impl SpinPolicy for ExponentialSpin {
+	#[inline]
+	fn backoff(&self, attempt: u32) -> Option<u32> {
+		(attempt < self.0).then(|| 1 << attempt.min(16))
+	}
+}
+
+/// Adapt the number of checks of the lock to how long it took to acquire the lock recently.
+///
+/// This keeps a running average of the number of checks it took to acquire
+/// the lock, and spins for up to twice that (plus a small constant), limited
+/// to a maximum. If a lock is usually held only briefly, this will spin long
+/// enough to avoid sleeping, while threads stop wasting time spinning on a
+/// lock that is usually held for long.
+///
+/// This is the same heuristic as glibc's `PTHREAD_MUTEX_ADAPTIVE_NP`.
+#[derive(Debug)]
+pub struct AdaptiveSpin {
+	average: AtomicU32,
+	max: u32,
+}
+
+impl AdaptiveSpin {
+	/// Create a new adaptive policy that never checks the lock more than `max` times.
+	#[inline]
+	pub const fn new(max: u32) -> Self {
+		Self {
+			average: AtomicU32::new(0),
+			max,
+		}
+	}
+}
+
+impl Default for AdaptiveSpin {
+	fn default() -> Self {
+		Self::new(100)
+	}
+}
+
+impl SpinPolicy for AdaptiveSpin {
+	#[inline]
+	fn backoff(&self, attempt: u32) -> Option<u32> {
+		let limit = self
+			.average
+			.load(Relaxed)
+			.saturating_mul(2)
+			.saturating_add(10)
+			.min(self.max);
+		(attempt < limit).then_some(1)
+	}
+
+	#[inline]
+	fn acquired(&self, attempts: u32, _slept: bool) {
+		let average = self.average.load(Relaxed) as i64;
+		let average = average + (attempts.min(self.max) as i64 - average) / 8;
+		self.average.store(average as u32, Relaxed);
+	}
+}
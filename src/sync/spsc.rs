@@ -0,0 +1,362 @@
+use crate::timeout::deadline;
+use crate::{CachePadded, Futex, Private, Scope, TimedWaitError, WakeMask};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, SeqCst};
+use std::time::{Duration, Instant};
+
+/// One end of the ring: the index it's at, and whether the other end sleeps on it.
+#[repr(C)]
+struct End<S> {
+	/// Free-running index, only ever incremented by the owner of this end.
+	index: Futex<S>,
+	/// Set by the other end before it sleeps on `index`.
+	waiting: AtomicU32,
+}
+
+impl<S> End<S> {
+	const fn new() -> Self {
+		Self {
+			index: Futex::new(0),
+			waiting: AtomicU32::new(0),
+		}
+	}
+}
+
+impl<S: Scope> End<S> {
+	/// Move the index forward, waking the other end if it is sleeping on it.
+	#[inline]
+	fn advance(&self, index: u32) {
+		self.index.value.store(index, SeqCst);
+		if self.waiting.load(SeqCst) != 0 {
+			self.waiting.store(0, Relaxed);
+			self.index.wake(1);
+		}
+	}
+
+	/// Sleep until the index is no longer `index`, or until the deadline.
+	///
+	/// Returns false if the deadline passed.
+	#[inline]
+	fn wait(&self, index: u32, deadline: Option<Instant>) -> bool {
+		self.waiting.store(1, SeqCst);
+		if self.index.value.load(SeqCst) != index {
+			return true;
+		}
+		match deadline {
+			Some(deadline) => !matches!(
+				self.index.wait_bitset_until(index, WakeMask::ALL, deadline),
+				Err(TimedWaitError::TimedOut)
+			),
+			None => {
+				let _ = self.index.wait(index);
+				true
+			}
+		}
+	}
+}
+
+/// A single-producer single-consumer ring buffer of `N` records, blocking on futexes.
+///
+/// The producer and the consumer each own one index into the buffer, which
+/// is also the futex the other side sleeps on: the consumer sleeps on the
+/// producer's index when the ring is empty, and the producer sleeps on the
+/// consumer's index when the ring is full. A push only costs a system call
+/// when the consumer is sleeping, which only happens on the transition from
+/// empty to non-empty, and a pop only costs one when the producer is
+/// sleeping, on the transition from full to not full.
+///
+/// There may only be one producer and one consumer at a time, which is why
+/// [`producer`][SpscRing::producer] and [`consumer`][SpscRing::consumer]
+/// are unsafe. Typically, each of them is used by a different process.
+///
+/// `N` must be a power of two, and at most 2<sup>31</sup>. The records are
+/// `Copy`, and are never dropped.
+///
+/// An `SpscRing<T, N, Shared>` can be used between processes, if it is
+/// placed in shared memory. A zeroed `SpscRing` is a valid empty ring.
+#[repr(C)]
+pub struct SpscRing<T, const N: usize, S = Private> {
+	/// Index of the next record to pop.
+	head: CachePadded<End<S>>,
+	/// Index of the next record to push.
+	tail: CachePadded<End<S>>,
+	buffer: UnsafeCell<MaybeUninit<[T; N]>>,
+}
+
+unsafe impl<T: Send, const N: usize, S> Send for SpscRing<T, N, S> {}
+unsafe impl<T: Send, const N: usize, S> Sync for SpscRing<T, N, S> {}
+
+/// The producing end of an [`SpscRing`].
+pub struct SpscProducer<'a, T, const N: usize, S = Private> {
+	ring: &'a SpscRing<T, N, S>,
+}
+
+/// The consuming end of an [`SpscRing`].
+pub struct SpscConsumer<'a, T, const N: usize, S = Private> {
+	ring: &'a SpscRing<T, N, S>,
+}
+
+impl<T: Copy, const N: usize, S> SpscRing<T, N, S> {
+	/// Create a new empty ring.
+	///
+	/// # Panics
+	///
+	/// Panics if `N` is not a power of two, or larger than 2<sup>31</sup>.
+	#[inline]
+	pub const fn new() -> Self {
+		assert!(N.is_power_of_two(), "capacity must be a power of two");
+		assert!(N <= 1 << 31, "capacity too large");
+		Self {
+			head: CachePadded::new(End::new()),
+			tail: CachePadded::new(End::new()),
+			buffer: UnsafeCell::new(MaybeUninit::uninit()),
+		}
+	}
+
+	/// The number of records the ring can hold: `N`.
+	#[inline]
+	pub const fn capacity(&self) -> usize {
+		N
+	}
+
+	/// The number of records in the ring.
+	///
+	/// This is only a snapshot, as the producer and consumer might be
+	/// changing it concurrently.
+	#[inline]
+	pub fn len(&self) -> usize {
+		let head = self.head.index.value.load(Acquire);
+		let tail = self.tail.index.value.load(Acquire);
+		tail.wrapping_sub(head) as usize
+	}
+
+	/// Returns true if the ring is empty.
+	///
+	/// This is only a snapshot, like [`len`][SpscRing::len].
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Get the producing end of the ring.
+	///
+	/// # Safety
+	///
+	/// There must not be another `SpscProducer` for this ring at the same
+	/// time, in this or any other process.
+	#[inline]
+	pub unsafe fn producer(&self) -> SpscProducer<'_, T, N, S> {
+		SpscProducer { ring: self }
+	}
+
+	/// Get the consuming end of the ring.
+	///
+	/// # Safety
+	///
+	/// There must not be another `SpscConsumer` for this ring at the same
+	/// time, in this or any other process.
+	#[inline]
+	pub unsafe fn consumer(&self) -> SpscConsumer<'_, T, N, S> {
+		SpscConsumer { ring: self }
+	}
+
+	#[inline]
+	fn slot(&self, index: u32) -> *mut T {
+		let buffer = self.buffer.get().cast::<T>();
+		unsafe { buffer.add(index as usize & (N - 1)) }
+	}
+}
+
+impl<T: Copy, const N: usize, S: Scope> SpscProducer<'_, T, N, S> {
+	/// Push a record, or give it back if the ring is full, without blocking.
+	#[inline]
+	pub fn try_push(&mut self, value: T) -> Result<(), T> {
+		let tail = self.ring.tail.index.value.load(Relaxed);
+		let head = self.ring.head.index.value.load(Acquire);
+		if tail.wrapping_sub(head) as usize == N {
+			return Err(value);
+		}
+		self.push_at(tail, value);
+		Ok(())
+	}
+
+	/// Push a record, blocking while the ring is full.
+	pub fn push(&mut self, value: T) {
+		if self.push_until(value, None).is_err() {
+			unreachable!();
+		}
+	}
+
+	/// Push a record, blocking while the ring is full, or until the timeout expires.
+	///
+	/// Gives the record back if the timeout expired.
+	pub fn push_timeout(&mut self, value: T, timeout: Duration) -> Result<(), T> {
+		self.push_until(value, deadline(timeout))
+	}
+
+	fn push_until(&mut self, value: T, deadline: Option<Instant>) -> Result<(), T> {
+		let tail = self.ring.tail.index.value.load(Relaxed);
+		loop {
+			let head = self.ring.head.index.value.load(Acquire);
+			if (tail.wrapping_sub(head) as usize) < N {
+				break;
+			}
+			if !self.ring.head.wait(head, deadline) {
+				return Err(value);
+			}
+		}
+		self.push_at(tail, value);
+		Ok(())
+	}
+
+	#[inline]
+	fn push_at(&mut self, tail: u32, value: T) {
+		unsafe { self.ring.slot(tail).write(value) };
+		self.ring.tail.advance(tail.wrapping_add(1));
+	}
+}
+
+impl<T: Copy, const N: usize, S: Scope> SpscConsumer<'_, T, N, S> {
+	/// Pop a record, or return `None` if the ring is empty, without blocking.
+	#[inline]
+	pub fn try_pop(&mut self) -> Option<T> {
+		let head = self.ring.head.index.value.load(Relaxed);
+		let tail = self.ring.tail.index.value.load(Acquire);
+		if head == tail {
+			return None;
+		}
+		Some(self.pop_at(head))
+	}
+
+	/// Pop a record, blocking while the ring is empty.
+	pub fn pop(&mut self) -> T {
+		match self.pop_until(None) {
+			Some(value) => value,
+			None => unreachable!(),
+		}
+	}
+
+	/// Pop a record, blocking while the ring is empty, or until the timeout expires.
+	///
+	/// Returns `None` if the timeout expired.
+	pub fn pop_timeout(&mut self, timeout: Duration) -> Option<T> {
+		self.pop_until(deadline(timeout))
+	}
+
+	fn pop_until(&mut self, deadline: Option<Instant>) -> Option<T> {
+		let head = self.ring.head.index.value.load(Relaxed);
+		loop {
+			let tail = self.ring.tail.index.value.load(Acquire);
+			if tail != head {
+				break;
+			}
+			if !self.ring.tail.wait(tail, deadline) {
+				return None;
+			}
+		}
+		Some(self.pop_at(head))
+	}
+
+	#[inline]
+	fn pop_at(&mut self, head: u32) -> T {
+		let value = unsafe { self.ring.slot(head).read() };
+		self.ring.head.advance(head.wrapping_add(1));
+		value
+	}
+}
+
+impl<T: Copy, const N: usize, S> Default for SpscRing<T, N, S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<T, const N: usize, S> std::fmt::Debug for SpscRing<T, N, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("SpscRing")
+			.field("scope", &std::any::type_name::<S>())
+			.field("capacity", &N)
+			.field("head", &self.head.index.value)
+			.field("tail", &self.tail.index.value)
+			.finish()
+	}
+}
+
+impl<T, const N: usize, S> std::fmt::Debug for SpscProducer<'_, T, N, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("SpscProducer").finish_non_exhaustive()
+	}
+}
+
+impl<T, const N: usize, S> std::fmt::Debug for SpscConsumer<'_, T, N, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("SpscConsumer").finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::SpscRing;
+	use crate::{Private, Shared};
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn full_and_empty() {
+		let ring = SpscRing::<u32, 4, Private>::new();
+		let (mut producer, mut consumer) = unsafe { (ring.producer(), ring.consumer()) };
+		assert!(ring.is_empty());
+		assert_eq!(consumer.try_pop(), None);
+		for i in 0..4 {
+			producer.try_push(i).unwrap();
+		}
+		assert_eq!(ring.len(), 4);
+		assert_eq!(producer.try_push(4), Err(4));
+		assert_eq!(producer.push_timeout(4, Duration::from_millis(10)), Err(4));
+		for i in 0..4 {
+			assert_eq!(consumer.try_pop(), Some(i));
+		}
+		assert_eq!(consumer.pop_timeout(Duration::from_millis(10)), None);
+	}
+
+	#[test]
+	fn blocking_in_order() {
+		let ring = SpscRing::<u64, 8, Shared>::new();
+		let (mut producer, mut consumer) = unsafe { (ring.producer(), ring.consumer()) };
+		thread::scope(|s| {
+			s.spawn(move || {
+				for i in 0..100_000 {
+					producer.push(i);
+				}
+			});
+			for i in 0..100_000 {
+				assert_eq!(consumer.pop(), i);
+			}
+		});
+		assert!(ring.is_empty());
+	}
+
+	#[test]
+	fn wakes_up_sleeping_ends() {
+		let ring = SpscRing::<u32, 2, Private>::new();
+		let (mut producer, mut consumer) = unsafe { (ring.producer(), ring.consumer()) };
+		thread::scope(|s| {
+			let c = s.spawn(move || {
+				assert_eq!(consumer.pop_timeout(Duration::from_secs(10)), Some(1));
+				thread::sleep(Duration::from_millis(10));
+				assert_eq!(consumer.pop(), 2);
+				assert_eq!(consumer.pop(), 3);
+				assert_eq!(consumer.pop(), 4);
+			});
+			thread::sleep(Duration::from_millis(10));
+			producer.push(1);
+			producer.push(2);
+			producer.push(3);
+			// Full: waits for the consumer.
+			assert_eq!(producer.push_timeout(4, Duration::from_secs(10)), Ok(()));
+			c.join().unwrap();
+		});
+	}
+}
@@ -0,0 +1,269 @@
+use super::rwlock::RawRwLock;
+use crate::{Private, Scope};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::sync::atomic::{fence, AtomicU32};
+
+/// A stamp of a [`StampedLock`], used to validate an optimistic read.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Stamp(u32);
+
+/// A reader-writer lock that also supports optimistic reads.
+///
+/// An optimistic read takes no lock at all: it only obtains a [`Stamp`],
+/// reads the protected state, and then [validates][StampedLock::validate]
+/// the stamp to check that no writer was active in the meantime. This makes
+/// reading completely free of syscalls and of writes to shared memory, as
+/// long as there are no writers. If validation fails, the reader can retry,
+/// or fall back to a normal (pessimistic) read lock.
+///
+/// Writers block both readers and other writers, by sleeping on a futex,
+/// just like a [`RwLock`][super::RwLock].
+///
+/// Like the stamped lock in Java, this lock does not contain the data it
+/// protects. Since optimistic readers can observe the state while a writer
+/// is modifying it, that state needs to consist of atomics, which can be
+/// accessed with [`Relaxed`] ordering. Any values read optimistically must
+/// not be acted upon before the stamp was validated.
+///
+/// A `StampedLock<Shared>` can be used between processes, if it is placed
+/// in shared memory.
+#[repr(C)]
+pub struct StampedLock<S = Private> {
+	raw: RawRwLock<S>,
+	/// Incremented when a write lock is acquired and when it is released,
+	/// such that it is odd exactly while the lock is write locked.
+	sequence: AtomicU32,
+}
+
+/// A read lock of a [`StampedLock`]. The lock is released when this guard is dropped.
+#[must_use = "if unused the StampedLock will immediately unlock"]
+pub struct StampedReadGuard<'a, S: Scope = Private> {
+	lock: &'a StampedLock<S>,
+}
+
+/// A write lock of a [`StampedLock`]. The lock is released when this guard is dropped.
+#[must_use = "if unused the StampedLock will immediately unlock"]
+pub struct StampedWriteGuard<'a, S: Scope = Private> {
+	lock: &'a StampedLock<S>,
+}
+
+impl<S> StampedLock<S> {
+	/// Create a new unlocked stamped lock.
+	#[inline]
+	pub const fn new() -> Self {
+		Self {
+			raw: RawRwLock::new(),
+			sequence: AtomicU32::new(0),
+		}
+	}
+
+	/// Start an optimistic read, without locking.
+	///
+	/// Returns `None` if the lock is currently write locked.
+	#[inline]
+	pub fn try_optimistic_read(&self) -> Option<Stamp> {
+		let sequence = self.sequence.load(Acquire);
+		(sequence & 1 == 0).then_some(Stamp(sequence))
+	}
+
+	/// Check that the lock was not write locked since the given stamp was obtained.
+	///
+	/// If this returns true, everything read since the call to
+	/// [`try_optimistic_read`][Self::try_optimistic_read] that returned the
+	/// stamp was consistent.
+	#[inline]
+	pub fn validate(&self, stamp: Stamp) -> bool {
+		fence(Acquire);
+		self.sequence.load(Relaxed) == stamp.0
+	}
+}
+
+impl<S: Scope> StampedLock<S> {
+	/// Run `f` as an optimistic read, falling back to a read lock if that fails.
+	///
+	/// `f` is called once without any lock, and is called a second time
+	/// while holding a read lock if a writer interfered with the first call.
+	/// Only the result of a consistent call is returned.
+	#[inline]
+	pub fn optimistic_read<R>(&self, mut f: impl FnMut() -> R) -> R {
+		if let Some(stamp) = self.try_optimistic_read() {
+			let r = f();
+			if self.validate(stamp) {
+				return r;
+			}
+		}
+		let _guard = self.read();
+		f()
+	}
+
+	/// Lock for reading, blocking until no writer holds or waits for the lock.
+	#[inline]
+	pub fn read(&self) -> StampedReadGuard<'_, S> {
+		self.raw.read();
+		StampedReadGuard { lock: self }
+	}
+
+	/// Lock for reading, if no writer holds or waits for the lock, without blocking.
+	#[inline]
+	pub fn try_read(&self) -> Option<StampedReadGuard<'_, S>> {
+		self.raw.try_read().then(|| StampedReadGuard { lock: self })
+	}
+
+	/// Lock for writing, blocking until the lock is available.
+	///
+	/// This invalidates all stamps of optimistic reads that are in progress.
+	#[inline]
+	pub fn write(&self) -> StampedWriteGuard<'_, S> {
+		self.raw.write();
+		unsafe { self.write_locked() }
+	}
+
+	/// Lock for writing, if the lock is not locked, without blocking.
+	#[inline]
+	pub fn try_write(&self) -> Option<StampedWriteGuard<'_, S>> {
+		self.raw.try_write().then(|| unsafe { self.write_locked() })
+	}
+
+	/// Lock for writing, if the lock is not locked and the stamp is still valid, without blocking.
+	///
+	/// This can be used to turn an optimistic read into a write, if nothing
+	/// was modified since the stamp was obtained.
+	#[inline]
+	pub fn try_write_if_valid(&self, stamp: Stamp) -> Option<StampedWriteGuard<'_, S>> {
+		if !self.raw.try_write() {
+			return None;
+		}
+		if self.sequence.load(Relaxed) != stamp.0 {
+			unsafe { self.raw.write_unlock() };
+			return None;
+		}
+		Some(unsafe { self.write_locked() })
+	}
+
+	/// Mark the lock as write locked, invalidating all stamps.
+	///
+	/// The lock must be write locked by the calling thread.
+	#[inline]
+	unsafe fn write_locked(&self) -> StampedWriteGuard<'_, S> {
+		self.sequence.fetch_add(1, Relaxed);
+		// Make sure optimistic readers that see any of our writes also see the new sequence number.
+		fence(Release);
+		StampedWriteGuard { lock: self }
+	}
+}
+
+impl<S: Scope> Drop for StampedReadGuard<'_, S> {
+	#[inline]
+	fn drop(&mut self) {
+		unsafe { self.lock.raw.read_unlock() }
+	}
+}
+
+impl<S: Scope> Drop for StampedWriteGuard<'_, S> {
+	#[inline]
+	fn drop(&mut self) {
+		self.lock.sequence.fetch_add(1, Release);
+		unsafe { self.lock.raw.write_unlock() }
+	}
+}
+
+impl<S> Default for StampedLock<S> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<S> std::fmt::Debug for StampedLock<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("StampedLock")
+			.field("scope", &std::any::type_name::<S>())
+			.field("write_locked", &(self.sequence.load(Relaxed) & 1 != 0))
+			.finish()
+	}
+}
+
+impl<S: Scope> std::fmt::Debug for StampedReadGuard<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("StampedReadGuard").finish_non_exhaustive()
+	}
+}
+
+impl<S: Scope> std::fmt::Debug for StampedWriteGuard<'_, S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("StampedWriteGuard").finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::StampedLock;
+	use crate::{Private, Shared};
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::thread;
+
+	#[test]
+	fn optimistic_read() {
+		let lock = StampedLock::<Private>::new();
+		let stamp = lock.try_optimistic_read().unwrap();
+		let reader = lock.read();
+		assert!(lock.validate(stamp));
+		drop(reader);
+		let stamp = lock.try_optimistic_read().unwrap();
+		let writer = lock.write();
+		assert!(lock.try_optimistic_read().is_none());
+		assert!(!lock.validate(stamp));
+		drop(writer);
+		assert!(!lock.validate(stamp));
+		assert!(lock.validate(lock.try_optimistic_read().unwrap()));
+	}
+
+	#[test]
+	fn try_write_if_valid() {
+		let lock = StampedLock::<Private>::new();
+		let stamp = lock.try_optimistic_read().unwrap();
+		let writer = lock.try_write_if_valid(stamp).unwrap();
+		assert!(lock.try_read().is_none());
+		assert!(lock.try_write().is_none());
+		drop(writer);
+		// The stamp is stale now.
+		assert!(lock.try_write_if_valid(stamp).is_none());
+		let stamp = lock.try_optimistic_read().unwrap();
+		let reader = lock.read();
+		assert!(lock.try_write_if_valid(stamp).is_none());
+		drop(reader);
+		// Failing didn't leave the lock locked, or invalidate the stamp.
+		assert!(lock.try_write_if_valid(stamp).is_some());
+	}
+
+	#[test]
+	fn readers_never_see_a_torn_write() {
+		let lock = StampedLock::<Shared>::new();
+		let a = AtomicU32::new(0);
+		let b = AtomicU32::new(0);
+		thread::scope(|s| {
+			for _ in 0..2 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						let _guard = lock.write();
+						let v = a.load(Relaxed) + 1;
+						a.store(v, Relaxed);
+						thread::yield_now();
+						b.store(v, Relaxed);
+					}
+				});
+			}
+			for _ in 0..4 {
+				s.spawn(|| {
+					for _ in 0..10_000 {
+						let (x, y) = lock.optimistic_read(|| (a.load(Relaxed), b.load(Relaxed)));
+						assert_eq!(x, y);
+						let _guard = lock.read();
+						assert_eq!(a.load(Relaxed), b.load(Relaxed));
+					}
+				});
+			}
+		});
+		assert_eq!(a.into_inner(), 20_000);
+	}
+}
@@ -0,0 +1,248 @@
+use crate::timeout::deadline;
+use crate::{Futex, Private, Scope, TimedWaitError, WakeMask};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::time::{Duration, Instant};
+
+/// There might be threads waiting for the count to reach zero.
+const WAITING: u32 = 1 << 31;
+const COUNT: u32 = !WAITING;
+
+/// Waits for a number of tasks to finish, similar to Go's `sync.WaitGroup`.
+///
+/// The counter is increased with [`add`][WaitGroup::add], decreased with
+/// [`done`][WaitGroup::done], and [`wait`][WaitGroup::wait] blocks until it
+/// reaches zero. All waiters are woken up at once, and only if there were any.
+///
+/// A `WaitGroup<Shared>` can be used between processes, if it is placed in
+/// shared memory, such that a coordinator process can wait for a number of
+/// worker processes. If a worker might exit without calling `done`, the
+/// coordinator can use [`wait_for_workers`][WaitGroup::wait_for_workers]
+/// to detect that, instead of waiting forever.
+#[repr(transparent)]
+pub struct WaitGroup<S = Private> {
+	futex: Futex<S>,
+}
+
+/// The ways [`WaitGroup::wait_for_workers`] can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WaitGroupError {
+	/// The timeout expired before the count reached zero.
+	TimedOut,
+	/// More workers exited than there were `done` calls, so the count will never reach zero.
+	Abandoned,
+}
+
+impl std::fmt::Display for WaitGroupError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::TimedOut => "wait group wait timed out",
+			Self::Abandoned => "a worker exited without calling done",
+		})
+	}
+}
+
+impl std::error::Error for WaitGroupError {}
+
+/// How often [`WaitGroup::wait_for_workers`] checks whether the workers are still alive.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+impl<S> WaitGroup<S> {
+	/// Create a new wait group with the given initial count.
+	///
+	/// # Panics
+	///
+	/// Panics if the count does not fit in 31 bits.
+	#[inline]
+	pub const fn new(count: u32) -> Self {
+		assert!(count & WAITING == 0, "WaitGroup count overflow");
+		Self {
+			futex: Futex::new(count),
+		}
+	}
+
+	/// The current count.
+	#[inline]
+	pub fn count(&self) -> u32 {
+		self.futex.value.load(Relaxed) & COUNT
+	}
+
+	/// Increase the count by `n`.
+	///
+	/// # Panics
+	///
+	/// Panics if the count would no longer fit in 31 bits.
+	#[inline]
+	pub fn add(&self, n: u32) {
+		let r = self.futex.value.fetch_update(Relaxed, Relaxed, |v| {
+			(v & COUNT)
+				.checked_add(n)
+				.filter(|c| c & WAITING == 0)
+				.map(|c| c | (v & WAITING))
+		});
+		if r.is_err() {
+			panic!("WaitGroup count overflow");
+		}
+	}
+}
+
+impl<S: Scope> WaitGroup<S> {
+	/// Decrease the count by one, waking up all waiters if it reaches zero.
+	///
+	/// # Panics
+	///
+	/// Panics if the count was already zero.
+	#[inline]
+	pub fn done(&self) {
+		let v = self.futex.value.fetch_sub(1, Release);
+		if v & COUNT == 0 {
+			// Undo, to not disturb the waiting bit.
+			self.futex.value.fetch_add(1, Relaxed);
+			panic!("WaitGroup count underflow");
+		}
+		if v == 1 | WAITING {
+			self.futex.value.fetch_and(!WAITING, Relaxed);
+			self.futex.wake(u32::MAX);
+		}
+	}
+
+	/// Block until the count is zero.
+	#[inline]
+	pub fn wait(&self) {
+		self.wait_until(None);
+	}
+
+	/// Block until the count is zero, or until the timeout expires.
+	///
+	/// Returns false if the timeout expired.
+	#[inline]
+	pub fn wait_timeout(&self, timeout: Duration) -> bool {
+		self.wait_until(deadline(timeout))
+	}
+
+	/// Block until the count is zero, or until it can't reach zero anymore
+	/// because a worker process exited without calling `done`.
+	///
+	/// `workers` are the process ids of all processes that will call
+	/// [`done`][WaitGroup::done], each once. Each time the count does not
+	/// reach zero within a short interval, this checks how many of them are
+	/// still running. If the count is larger than that, a worker must have
+	/// exited without calling `done`, and this returns
+	/// [`WaitGroupError::Abandoned`].
+	///
+	/// The workers must not have been reaped yet (with `waitpid`) when this
+	/// is called, since their process ids might be reused. Processes are
+	/// tracked with pidfds, which needs Linux 5.3 or later. Processes that
+	/// can't be tracked are counted as exited.
+	pub fn wait_for_workers(&self, workers: &[u32]) -> Result<(), WaitGroupError> {
+		self.wait_for_workers_until(workers, None)
+	}
+
+	/// Like [`wait_for_workers`][WaitGroup::wait_for_workers], but giving up
+	/// with [`WaitGroupError::TimedOut`] if the timeout expires first.
+	pub fn wait_for_workers_timeout(
+		&self,
+		workers: &[u32],
+		timeout: Duration,
+	) -> Result<(), WaitGroupError> {
+		self.wait_for_workers_until(workers, deadline(timeout))
+	}
+
+	fn wait_for_workers_until(
+		&self,
+		workers: &[u32],
+		deadline: Option<Instant>,
+	) -> Result<(), WaitGroupError> {
+		let pidfds: Vec<Option<OwnedFd>> = workers.iter().map(|&pid| pidfd_open(pid)).collect();
+		loop {
+			let count = self.count();
+			if count == 0 {
+				return Ok(());
+			}
+			if count as usize > running(&pidfds) {
+				return Err(WaitGroupError::Abandoned);
+			}
+			let now = Instant::now();
+			let next = match deadline {
+				Some(deadline) if deadline <= now => return Err(WaitGroupError::TimedOut),
+				Some(deadline) => deadline.min(now + POLL_INTERVAL),
+				None => now + POLL_INTERVAL,
+			};
+			if self.wait_until(Some(next)) {
+				return Ok(());
+			}
+		}
+	}
+
+	fn wait_until(&self, deadline: Option<Instant>) -> bool {
+		loop {
+			let v = self.futex.value.load(Acquire);
+			if v & COUNT == 0 {
+				return true;
+			}
+			if v & WAITING == 0
+				&& self
+					.futex
+					.value
+					.compare_exchange(v, v | WAITING, Relaxed, Relaxed)
+					.is_err()
+			{
+				continue;
+			}
+			match deadline {
+				Some(deadline) => {
+					if let Err(TimedWaitError::TimedOut) =
+						self.futex
+							.wait_bitset_until(v | WAITING, WakeMask::ALL, deadline)
+					{
+						return self.count() == 0;
+					}
+				}
+				None => {
+					let _ = self.futex.wait(v | WAITING);
+				}
+			}
+		}
+	}
+}
+
+/// Get a pidfd for the process, or `None` if that's not possible.
+fn pidfd_open(pid: u32) -> Option<OwnedFd> {
+	match unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) } {
+		-1 => None,
+		fd => Some(unsafe { OwnedFd::from_raw_fd(fd as i32) }),
+	}
+}
+
+/// The number of processes that are still running. A pidfd becomes readable when its process exits.
+fn running(pidfds: &[Option<OwnedFd>]) -> usize {
+	let mut fds: Vec<libc::pollfd> = pidfds
+		.iter()
+		.flatten()
+		.map(|fd| libc::pollfd {
+			fd: fd.as_raw_fd(),
+			events: libc::POLLIN,
+			revents: 0,
+		})
+		.collect();
+	if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, 0) } == -1 {
+		// Can't tell. Assume they're all still running.
+		return fds.len();
+	}
+	fds.iter().filter(|fd| fd.revents == 0).count()
+}
+
+impl<S> Default for WaitGroup<S> {
+	fn default() -> Self {
+		Self::new(0)
+	}
+}
+
+impl<S> std::fmt::Debug for WaitGroup<S> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("WaitGroup")
+			.field("scope", &std::any::type_name::<S>())
+			.field("count", &self.count())
+			.finish()
+	}
+}
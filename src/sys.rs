@@ -89,3 +89,9 @@ impl Error {
 		panic!("{}: {}", name, std::io::Error::from_raw_os_error(self.0));
 	}
 }
+
+/// Limit a number of waiters to `i32::MAX`, the largest count the kernel accepts.
+#[inline]
+pub fn count(n: u32) -> u32 {
+	n.min(i32::MAX as u32)
+}
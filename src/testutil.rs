@@ -0,0 +1,279 @@
+//! Helpers for testing code that uses [`Shared`][crate::Shared] futexes between processes.
+//!
+//! [`fork`] runs a closure in a child process, which shares any
+//! [`SharedRegion`][crate::shm::SharedRegion] that was mapped before the
+//! fork with its parent. The parent then [joins][Child::join] the child,
+//! with a timeout to catch deadlocks, and checks its exit status.
+//!
+//! Only available with the `testutil` feature.
+
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+
+/// The exit code of a child process whose closure panicked, like that of a Rust program that panics.
+const PANIC_EXIT_CODE: i32 = 101;
+
+/// Run `f` in a forked child process.
+///
+/// The child exits with code 0 when `f` returns, and with code 101 if it
+/// panics. It never returns from this function, and does not run any
+/// destructors or exit handlers of the parent.
+///
+/// # Safety
+///
+/// As with any `fork()` in a multi-threaded process, only the calling
+/// thread exists in the child, and locks held by other threads at the time
+/// of the fork remain locked forever in the child. This includes locks
+/// inside the memory allocator and the standard library, such as the lock
+/// of standard output.
+///
+/// So if the calling process has other threads, `f` may only do what is
+/// async-signal-safe: futex operations and atomic operations on shared
+/// memory are fine, but `f` must not allocate, print, or panic, since
+/// panicking allocates and unwinds. Tests run by the standard test harness
+/// are in such a multi-threaded process. Check the results in the parent,
+/// after joining the child.
+pub unsafe fn fork(f: impl FnOnce()) -> io::Result<Child> {
+	match libc::fork() {
+		-1 => Err(io::Error::last_os_error()),
+		0 => {
+			let code = match catch_unwind(AssertUnwindSafe(f)) {
+				Ok(()) => 0,
+				Err(_) => PANIC_EXIT_CODE,
+			};
+			libc::_exit(code)
+		}
+		pid => Ok(Child { pid: Some(pid) }),
+	}
+}
+
+/// A child process created by [`fork`].
+///
+/// A child that is not joined is killed and reaped when this is dropped,
+/// such that a failing test doesn't leave processes behind.
+#[derive(Debug)]
+pub struct Child {
+	/// `None` once the child has been reaped.
+	pid: Option<libc::pid_t>,
+}
+
+impl Child {
+	/// The process id of the child.
+	#[inline]
+	pub fn pid(&self) -> u32 {
+		self.pid.unwrap() as u32
+	}
+
+	/// Wait for the child to exit.
+	pub fn join(mut self) -> io::Result<ExitStatus> {
+		self.wait(0).map(|status| status.unwrap())
+	}
+
+	/// Wait for the child to exit, or until the timeout expires.
+	///
+	/// If the timeout expires, the child is killed, and this fails with
+	/// [`TimedOut`][io::ErrorKind::TimedOut].
+	pub fn join_timeout(mut self, timeout: Duration) -> io::Result<ExitStatus> {
+		let start = Instant::now();
+		loop {
+			if let Some(status) = self.wait(libc::WNOHANG)? {
+				return Ok(status);
+			}
+			if start.elapsed() >= timeout {
+				self.kill()?;
+				return Err(io::Error::new(
+					io::ErrorKind::TimedOut,
+					"child process did not exit in time",
+				));
+			}
+			std::thread::sleep(Duration::from_millis(1));
+		}
+	}
+
+	/// Wait for the child to exit, and panic unless it exited successfully within the timeout.
+	#[track_caller]
+	pub fn assert_success(self, timeout: Duration) {
+		match self.join_timeout(timeout) {
+			Ok(status) if status.success() => {}
+			Ok(status) => panic!("child process failed: {}", status),
+			Err(e) => panic!("child process failed: {}", e),
+		}
+	}
+
+	/// Kill the child with `SIGKILL`, and wait for it to exit.
+	///
+	/// This is useful to test what happens when a process dies at a
+	/// specific point, such as while holding a lock.
+	pub fn kill(&mut self) -> io::Result<()> {
+		if let Some(pid) = self.pid {
+			if unsafe { libc::kill(pid, libc::SIGKILL) } == -1 {
+				return Err(io::Error::last_os_error());
+			}
+			self.wait(0)?;
+		}
+		Ok(())
+	}
+
+	/// `waitpid` for the child, returning `None` if it is still running with `WNOHANG`.
+	fn wait(&mut self, flags: libc::c_int) -> io::Result<Option<ExitStatus>> {
+		let pid = self.pid.expect("child process already reaped");
+		let mut status = 0;
+		loop {
+			match unsafe { libc::waitpid(pid, &mut status, flags) } {
+				-1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+				-1 => return Err(io::Error::last_os_error()),
+				0 => return Ok(None),
+				_ => {
+					self.pid = None;
+					return Ok(Some(ExitStatus::from_raw(status)));
+				}
+			}
+		}
+	}
+}
+
+impl Drop for Child {
+	fn drop(&mut self) {
+		let _ = self.kill();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::fork;
+	use crate::shm::SharedRegion;
+	use crate::sync::{Barrier, Condvar, Latch, Mutex, Semaphore};
+	use crate::Shared;
+	use std::io;
+	use std::sync::atomic::AtomicU32;
+	use std::sync::atomic::Ordering::Relaxed;
+	use std::time::Duration;
+
+	const TIMEOUT: Duration = Duration::from_secs(10);
+
+	#[test]
+	fn mutex() {
+		let counter = SharedRegion::new(Mutex::<u32, Shared>::new(0)).unwrap();
+		let increment = || {
+			for _ in 0..1000 {
+				if let Ok(mut n) = counter.lock() {
+					*n += 1;
+				}
+			}
+		};
+		let children = (0..4)
+			.map(|_| unsafe { fork(increment) }.unwrap())
+			.collect::<Vec<_>>();
+		increment();
+		for child in children {
+			child.assert_success(TIMEOUT);
+		}
+		assert_eq!(*counter.lock().unwrap(), 5000);
+	}
+
+	#[test]
+	fn condvar() {
+		let ready = SharedRegion::new(Mutex::<bool, Shared>::new(false)).unwrap();
+		let condvar = SharedRegion::new(Condvar::<Shared>::new()).unwrap();
+		let woken = SharedRegion::new(AtomicU32::new(0)).unwrap();
+		let child = unsafe {
+			fork(|| {
+				if let Ok(guard) = ready.lock() {
+					let _ = condvar.wait_while(guard, |ready| !*ready);
+					woken.store(1, Relaxed);
+				}
+			})
+		}
+		.unwrap();
+		std::thread::sleep(Duration::from_millis(10));
+		*ready.lock().unwrap() = true;
+		condvar.notify_all();
+		child.assert_success(TIMEOUT);
+		assert_eq!(woken.load(Relaxed), 1);
+	}
+
+	#[test]
+	fn semaphore_and_latch() {
+		let semaphore = SharedRegion::new(Semaphore::<Shared>::new(0)).unwrap();
+		let done = SharedRegion::new(Latch::<Shared>::new()).unwrap();
+		let child = unsafe {
+			fork(|| {
+				semaphore.acquire_many(2);
+				done.set();
+			})
+		}
+		.unwrap();
+		semaphore.release();
+		assert!(!done.wait_timeout(Duration::from_millis(10)));
+		semaphore.release();
+		assert!(done.wait_timeout(TIMEOUT));
+		child.assert_success(TIMEOUT);
+		assert_eq!(semaphore.available_permits(), 0);
+	}
+
+	#[test]
+	fn barrier() {
+		let barrier = SharedRegion::new(Barrier::<Shared>::new(3)).unwrap();
+		let leaders = SharedRegion::new(AtomicU32::new(0)).unwrap();
+		let wait = || {
+			if barrier.wait().is_leader() {
+				leaders.fetch_add(1, Relaxed);
+			}
+		};
+		let children = (0..2)
+			.map(|_| unsafe { fork(wait) }.unwrap())
+			.collect::<Vec<_>>();
+		wait();
+		for child in children {
+			child.assert_success(TIMEOUT);
+		}
+		assert_eq!(leaders.load(Relaxed), 1);
+	}
+
+	#[cfg(target_pointer_width = "64")]
+	#[test]
+	fn robust_mutex_owner_killed() {
+		use crate::sync::{LockState, RobustLockError, RobustMutex, RobustMutexGuard};
+		let mutex = SharedRegion::new(RobustMutex::new(0u32)).unwrap();
+		let locked = SharedRegion::new(Latch::<Shared>::new()).unwrap();
+		let mut child = unsafe {
+			fork(|| {
+				if let Ok(mut guard) = mutex.lock() {
+					*guard = 1;
+					locked.set();
+					loop {
+						libc::pause();
+					}
+				}
+			})
+		}
+		.unwrap();
+		assert!(locked.wait_timeout(TIMEOUT));
+		child.kill().unwrap();
+		assert_eq!(mutex.state(), LockState::Abandoned);
+		match mutex.lock() {
+			Err(RobustLockError::OwnerDied(guard)) => {
+				assert_eq!(*guard, 1);
+				RobustMutexGuard::make_consistent(&guard);
+			}
+			r => panic!("unexpected result: {:?}", r.map(|_| ())),
+		}
+		assert_eq!(mutex.state(), LockState::Consistent);
+		assert!(mutex.lock().is_ok());
+	}
+
+	#[test]
+	fn join_timeout_kills_child() {
+		let child = unsafe {
+			fork(|| loop {
+				libc::pause();
+			})
+		}
+		.unwrap();
+		let e = child.join_timeout(Duration::from_millis(10)).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+	}
+}
@@ -2,8 +2,13 @@ use libc::{c_long, time_t};
 use std::time::{Duration, Instant, SystemTime};
 
 /// A point in time on either the monotonic clock ([`Instant`]) or real time clock ([`SystemTime`]).
+///
+/// # Safety
+///
+/// `as_timespec` must return either zero or `FUTEX_CLOCK_REALTIME` as clock flag.
 pub unsafe trait Timeout {
 	#[doc(hidden)]
+	#[allow(clippy::wrong_self_convention)]
 	fn as_timespec(self) -> (i32, libc::timespec);
 }
 
@@ -29,6 +34,15 @@ unsafe impl Timeout for SystemTime {
 	}
 }
 
+/// The point in time `timeout` from now, on the monotonic clock.
+///
+/// Returns `None` if that is too far in the future to represent, which
+/// callers treat as no timeout at all: a timeout that large is never reached.
+#[inline]
+pub(crate) fn deadline(timeout: Duration) -> Option<Instant> {
+	Instant::now().checked_add(timeout)
+}
+
 #[inline]
 pub(crate) fn as_timespec(d: Duration) -> libc::timespec {
 	libc::timespec {
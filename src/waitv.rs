@@ -0,0 +1,133 @@
+use crate::sys::Error;
+use crate::{Futex, Scope, TimedWaitError, Timeout, WaitError};
+use std::marker::PhantomData;
+use std::ptr::null;
+use std::sync::atomic::AtomicU32;
+
+/// `FUTEX2_SIZE_U32`: all futexes of this crate are 32 bits.
+const FUTEX2_SIZE_U32: u32 = 0x02;
+
+/// The maximum number of futexes in a single [`wait_any`] call.
+pub const WAITV_MAX: usize = 128;
+
+/// A futex and its expected value, to wait on with [`wait_any`].
+///
+/// This has the layout of the kernel's `struct futex_waitv`, such that a
+/// slice of these is passed to the kernel as is.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct WaitvEntry<'a> {
+	val: u64,
+	uaddr: u64,
+	flags: u32,
+	_reserved: u32,
+	futex: PhantomData<&'a AtomicU32>,
+}
+
+unsafe impl Send for WaitvEntry<'_> {}
+unsafe impl Sync for WaitvEntry<'_> {}
+
+impl<'a> WaitvEntry<'a> {
+	/// Wait on `futex`, as long as it has the expected value.
+	///
+	/// Futexes of different [`Scope`]s can be combined in a single wait.
+	#[inline]
+	pub fn new<S: Scope>(futex: &'a Futex<S>, expected_value: u32) -> Self {
+		Self {
+			val: expected_value as u64,
+			uaddr: &futex.value as *const AtomicU32 as u64,
+			// FUTEX2_PRIVATE has the same value as FUTEX_PRIVATE_FLAG.
+			flags: FUTEX2_SIZE_U32 | S::futex_flag() as u32,
+			_reserved: 0,
+			futex: PhantomData,
+		}
+	}
+
+	/// The expected value.
+	#[inline]
+	pub fn expected_value(&self) -> u32 {
+		self.val as u32
+	}
+
+	pub(crate) fn futex_ptr(&self) -> *const AtomicU32 {
+		self.uaddr as *const AtomicU32
+	}
+
+	/// The private flag, for the other futex operations on this futex.
+	pub(crate) fn futex_flag(&self) -> i32 {
+		(self.flags & !FUTEX2_SIZE_U32) as i32
+	}
+}
+
+impl std::fmt::Debug for WaitvEntry<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("WaitvEntry")
+			.field("futex", &self.futex_ptr())
+			.field("expected_value", &self.expected_value())
+			.finish()
+	}
+}
+
+/// Wait until any of the futexes is awoken by a `wake` call.
+///
+/// This uses `futex_waitv`, available since Linux 5.16, and returns the
+/// index of the futex that was woken up.
+///
+/// The thread will only be sent to sleep if all futexes have their expected
+/// value. Otherwise, it returns directly with [`WaitError::WrongValue`].
+///
+/// # Panics
+///
+/// Panics if `entries` is empty or has more than [`WAITV_MAX`] entries, or
+/// if the kernel does not support `futex_waitv`.
+#[inline]
+pub fn wait_any(entries: &[WaitvEntry]) -> Result<usize, WaitError> {
+	match unsafe { waitv(entries, None) } {
+		Ok(i) => Ok(i),
+		Err(Error(libc::EAGAIN)) => Err(WaitError::WrongValue),
+		Err(Error(libc::EINTR)) => Err(WaitError::Interrupted),
+		Err(e) => e.panic("futex_waitv"),
+	}
+}
+
+/// Wait until any of the futexes is awoken by a `wake` call, or until the timeout expires.
+///
+/// See [`wait_any`].
+#[inline]
+pub fn wait_any_until(
+	entries: &[WaitvEntry],
+	timeout: impl Timeout,
+) -> Result<usize, TimedWaitError> {
+	match unsafe { waitv(entries, Some(timeout.as_timespec())) } {
+		Ok(i) => Ok(i),
+		Err(Error(libc::EAGAIN)) => Err(TimedWaitError::WrongValue),
+		Err(Error(libc::EINTR)) => Err(TimedWaitError::Interrupted),
+		Err(Error(libc::ETIMEDOUT)) => Err(TimedWaitError::TimedOut),
+		Err(e) => e.panic("futex_waitv"),
+	}
+}
+
+/// Call `futex_waitv`, with an absolute timeout from [`Timeout::as_timespec`].
+pub(crate) unsafe fn waitv(
+	entries: &[WaitvEntry],
+	timeout: Option<(i32, libc::timespec)>,
+) -> Result<usize, Error> {
+	let (clock, timespec) = match &timeout {
+		Some((libc::FUTEX_CLOCK_REALTIME, t)) => (libc::CLOCK_REALTIME, t as *const libc::timespec),
+		Some((_, t)) => (libc::CLOCK_MONOTONIC, t as *const libc::timespec),
+		None => (libc::CLOCK_MONOTONIC, null()),
+	};
+	let r = libc::syscall(
+		libc::SYS_futex_waitv,
+		entries.as_ptr(),
+		entries.len() as libc::c_uint,
+		0 as libc::c_uint,
+		timespec,
+		clock,
+	);
+	if r < 0 {
+		Err(Error(*libc::__errno_location()))
+	} else {
+		Ok(r as usize)
+	}
+}
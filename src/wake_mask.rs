@@ -0,0 +1,122 @@
+use std::num::NonZeroU32;
+use std::ops::BitOr;
+
+/// The bitset of a [`wait_bitset`][crate::Futex::wait_bitset] or
+/// [`wake_bitset`][crate::Futex::wake_bitset] call.
+///
+/// A waiter is woken up by a `wake_bitset` call if their masks have at
+/// least one 1-bit in common. The kernel rejects an empty mask, so a
+/// `WakeMask` is never zero.
+///
+/// Each bit can be used as a separate channel, to wake up only the waiters
+/// of that channel:
+/// `const READERS: WakeMask = WakeMask::bit(0);`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct WakeMask(NonZeroU32);
+
+impl WakeMask {
+	/// The mask with all bits set, which matches any other mask.
+	///
+	/// This is the mask used by the plain `wait` and `wake` operations.
+	pub const ALL: Self = Self::bit_range(0, 32);
+
+	/// A mask of the given bits, or `None` if `bits` is zero.
+	#[inline]
+	pub const fn new(bits: u32) -> Option<Self> {
+		match NonZeroU32::new(bits) {
+			Some(bits) => Some(Self(bits)),
+			None => None,
+		}
+	}
+
+	/// A mask of the given (non-zero) bits.
+	#[inline]
+	pub const fn from_nonzero(bits: NonZeroU32) -> Self {
+		Self(bits)
+	}
+
+	/// A mask with only bit `n` set.
+	///
+	/// # Panics
+	///
+	/// Panics if `n` is 32 or more.
+	#[inline]
+	pub const fn bit(n: u32) -> Self {
+		assert!(n < 32, "WakeMask bit out of range");
+		Self::bit_range(n, n + 1)
+	}
+
+	/// The bits of this mask.
+	#[inline]
+	pub const fn bits(self) -> u32 {
+		self.0.get()
+	}
+
+	/// The mask with the bits of both masks set.
+	#[inline]
+	pub const fn union(self, other: Self) -> Self {
+		match Self::new(self.bits() | other.bits()) {
+			Some(mask) => mask,
+			None => unreachable!(),
+		}
+	}
+
+	/// Whether a waiter with this mask is woken up by a wake with the other mask.
+	#[inline]
+	pub const fn matches(self, other: Self) -> bool {
+		self.bits() & other.bits() != 0
+	}
+
+	/// The mask of bits `start..end`, which must not be empty.
+	const fn bit_range(start: u32, end: u32) -> Self {
+		let bits = (u32::MAX >> (32 - (end - start))) << start;
+		match Self::new(bits) {
+			Some(mask) => mask,
+			None => unreachable!(),
+		}
+	}
+}
+
+impl Default for WakeMask {
+	/// [`WakeMask::ALL`].
+	#[inline]
+	fn default() -> Self {
+		Self::ALL
+	}
+}
+
+impl BitOr for WakeMask {
+	type Output = Self;
+	#[inline]
+	fn bitor(self, other: Self) -> Self {
+		self.union(other)
+	}
+}
+
+impl From<NonZeroU32> for WakeMask {
+	#[inline]
+	fn from(bits: NonZeroU32) -> Self {
+		Self(bits)
+	}
+}
+
+impl From<WakeMask> for NonZeroU32 {
+	#[inline]
+	fn from(mask: WakeMask) -> Self {
+		mask.0
+	}
+}
+
+impl From<WakeMask> for u32 {
+	#[inline]
+	fn from(mask: WakeMask) -> Self {
+		mask.bits()
+	}
+}
+
+impl std::fmt::Debug for WakeMask {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "WakeMask({:#x})", self.bits())
+	}
+}